@@ -0,0 +1,219 @@
+pub mod access_control {
+    use cosmwasm_std::{
+        Addr, Deps, DepsMut, Empty, Env, MessageInfo, Order, Response, StdResult, Storage,
+    };
+    use cw_storage_plus::Bound;
+
+    use crate::error::ContractError;
+    use crate::events::leverage_events::LeverageEvent;
+    use crate::msg::RoleMembersResponse;
+    use crate::state::{Role, LEVERAGE_CONTRACT_OWNER, PAUSED, PENDING_OWNER, ROLE_MEMBERS};
+
+    const DEFAULT_LIMIT: u32 = 10;
+    const MAX_LIMIT: u32 = 30;
+
+    /**
+     * The owner holds every role, other addresses only the roles granted to them.
+     */
+    pub fn has_role(storage: &dyn Storage, role: Role, address: &Addr) -> StdResult<bool> {
+        if LEVERAGE_CONTRACT_OWNER.load(storage)? == *address {
+            return Ok(true);
+        }
+
+        Ok(ROLE_MEMBERS.has(storage, (role.as_str(), address)))
+    }
+
+    pub fn assert_role(
+        storage: &dyn Storage,
+        role: Role,
+        address: &Addr,
+    ) -> Result<(), ContractError> {
+        match has_role(storage, role, address) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(ContractError::Unauthorized {}),
+            Err(err) => Err(ContractError::GenericError {
+                error: err.to_string(),
+            }),
+        }
+    }
+
+    pub fn assert_owner(storage: &dyn Storage, address: &Addr) -> Result<(), ContractError> {
+        match LEVERAGE_CONTRACT_OWNER.load(storage) {
+            Ok(owner) if owner == *address => Ok(()),
+            Ok(_) => Err(ContractError::Unauthorized {}),
+            Err(err) => Err(ContractError::GenericError {
+                error: err.to_string(),
+            }),
+        }
+    }
+
+    pub fn assert_not_paused(storage: &dyn Storage) -> Result<(), ContractError> {
+        if PAUSED.may_load(storage)?.unwrap_or(false) {
+            return Err(ContractError::Paused {});
+        }
+        Ok(())
+    }
+
+    /**
+     * Admins manage every role except their own, which only the owner hands out.
+     */
+    fn assert_can_manage(
+        storage: &dyn Storage,
+        role: Role,
+        sender: &Addr,
+    ) -> Result<(), ContractError> {
+        match role {
+            Role::Admin => assert_owner(storage, sender),
+            _ => assert_role(storage, Role::Admin, sender),
+        }
+    }
+
+    pub fn grant_role(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        role: Role,
+        address: String,
+    ) -> Result<Response, ContractError> {
+        assert_can_manage(deps.storage, role, &info.sender)?;
+
+        let address = deps.api.addr_validate(&address)?;
+        ROLE_MEMBERS.save(deps.storage, (role.as_str(), &address), &Empty {})?;
+
+        Ok(Response::new()
+            .add_attribute("method", "grant_role")
+            .add_attribute("role", role.as_str())
+            .add_attribute("address", address.clone())
+            .add_event(LeverageEvent::RoleGranted { role, address }.into()))
+    }
+
+    pub fn revoke_role(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        role: Role,
+        address: String,
+    ) -> Result<Response, ContractError> {
+        assert_can_manage(deps.storage, role, &info.sender)?;
+
+        let address = deps.api.addr_validate(&address)?;
+        if !ROLE_MEMBERS.has(deps.storage, (role.as_str(), &address)) {
+            return Err(ContractError::RoleNotGranted {});
+        }
+        ROLE_MEMBERS.remove(deps.storage, (role.as_str(), &address));
+
+        Ok(Response::new()
+            .add_attribute("method", "revoke_role")
+            .add_attribute("role", role.as_str())
+            .add_attribute("address", address.clone())
+            .add_event(LeverageEvent::RoleRevoked { role, address }.into()))
+    }
+
+    /**
+     * First step of the ownership transfer, the new owner has to accept it.
+     */
+    pub fn propose_ownership(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        new_owner: String,
+    ) -> Result<Response, ContractError> {
+        assert_owner(deps.storage, &info.sender)?;
+
+        let new_owner = deps.api.addr_validate(&new_owner)?;
+        PENDING_OWNER.save(deps.storage, &new_owner)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "propose_ownership")
+            .add_attribute("pending_owner", new_owner.clone())
+            .add_event(
+                LeverageEvent::OwnershipProposed {
+                    pending_owner: new_owner,
+                }
+                .into(),
+            ))
+    }
+
+    pub fn accept_ownership(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+    ) -> Result<Response, ContractError> {
+        let pending_owner = match PENDING_OWNER.may_load(deps.storage)? {
+            Some(pending_owner) => pending_owner,
+            None => return Err(ContractError::NoPendingOwner {}),
+        };
+
+        if pending_owner != info.sender {
+            return Err(ContractError::Unauthorized {});
+        }
+
+        LEVERAGE_CONTRACT_OWNER.save(deps.storage, &pending_owner)?;
+        PENDING_OWNER.remove(deps.storage);
+
+        Ok(Response::new()
+            .add_attribute("method", "accept_ownership")
+            .add_attribute("owner", pending_owner.clone())
+            .add_event(
+                LeverageEvent::OwnershipAccepted {
+                    owner: pending_owner,
+                }
+                .into(),
+            ))
+    }
+
+    pub fn pause(deps: DepsMut, _env: Env, info: MessageInfo) -> Result<Response, ContractError> {
+        assert_role(deps.storage, Role::Guardian, &info.sender)?;
+
+        PAUSED.save(deps.storage, &true)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "pause")
+            .add_attribute("sender", info.sender.clone())
+            .add_event(
+                LeverageEvent::Paused {
+                    sender: info.sender,
+                }
+                .into(),
+            ))
+    }
+
+    /**
+     * Guardians can only stop the protocol, resuming it is up to the admins.
+     */
+    pub fn unpause(deps: DepsMut, _env: Env, info: MessageInfo) -> Result<Response, ContractError> {
+        assert_role(deps.storage, Role::Admin, &info.sender)?;
+
+        PAUSED.save(deps.storage, &false)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "unpause")
+            .add_attribute("sender", info.sender.clone())
+            .add_event(
+                LeverageEvent::Unpaused {
+                    sender: info.sender,
+                }
+                .into(),
+            ))
+    }
+
+    pub fn fetch_role_members(
+        deps: Deps,
+        _env: Env,
+        role: Role,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    ) -> StdResult<RoleMembersResponse> {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+        let start_after = start_after.map(Addr::unchecked);
+        let start = start_after.as_ref().map(Bound::exclusive);
+
+        let members = ROLE_MEMBERS
+            .prefix(role.as_str())
+            .keys(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .collect::<StdResult<Vec<Addr>>>()?;
+
+        Ok(RoleMembersResponse { role, members })
+    }
+}
@@ -0,0 +1,83 @@
+pub mod price_aggregator {
+    use cosmwasm_std::Decimal;
+
+    use crate::state::{PriceSource, TokenInfo};
+    use crate::ContractError;
+
+    pub const MAX_AGGREGATED_SOURCES: usize = 5;
+
+    pub fn validate_source(
+        sources: &[PriceSource],
+        max_deviation: Decimal,
+        min_sources: u32,
+    ) -> Result<(), ContractError> {
+        let nested = sources
+            .iter()
+            .any(|source| matches!(source, PriceSource::Aggregate { .. }));
+        if sources.len() < 2 || sources.len() > MAX_AGGREGATED_SOURCES || nested {
+            return Err(ContractError::InvalidAggregateSources {});
+        }
+        if max_deviation.is_zero() || max_deviation > Decimal::one() {
+            return Err(ContractError::InvalidDeviation {});
+        }
+        if min_sources < 2 || min_sources as usize > sources.len() {
+            return Err(ContractError::InvalidMinSources {});
+        }
+        Ok(())
+    }
+
+    /**
+     * Median of the sources with a fresh price.
+     *
+     * Stale and unavailable sources are left out, so the others carry on while
+     * the primary feed is stale. Any other error of a source fails the price.
+     * At least `min_sources` sources need a price, and when one of them is more
+     * than `max_deviation` away from their median the sources disagree and the
+     * token isn't priced at all.
+     */
+    pub fn aggregate(
+        token_info: &TokenInfo,
+        prices: Vec<Result<Decimal, ContractError>>,
+        max_deviation: Decimal,
+        min_sources: u32,
+    ) -> Result<Decimal, ContractError> {
+        let mut fresh = vec![];
+        for price in prices {
+            match price {
+                Ok(price) => fresh.push(price),
+                Err(ContractError::StalePrice { .. } | ContractError::PriceNotAvailable { .. }) => {
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        if fresh.len() < min_sources as usize {
+            return Err(ContractError::PriceNotAvailable {
+                token: token_info.token.clone(),
+            });
+        }
+
+        let median = median(&mut fresh);
+        for price in fresh {
+            let deviation = price.abs_diff(median) / median;
+            if deviation > max_deviation {
+                return Err(ContractError::PriceDeviation {
+                    token: token_info.token.clone(),
+                });
+            }
+        }
+        Ok(median)
+    }
+
+    // The mean of the middle two for an even count, the prices are never empty.
+    // `usize::is_multiple_of` would need Rust 1.87.
+    #[allow(clippy::manual_is_multiple_of)]
+    fn median(prices: &mut [Decimal]) -> Decimal {
+        prices.sort();
+        let middle = prices.len() / 2;
+        if prices.len() % 2 == 0 {
+            (prices[middle - 1] + prices[middle]) / Decimal::from_ratio(2u128, 1u128)
+        } else {
+            prices[middle]
+        }
+    }
+}
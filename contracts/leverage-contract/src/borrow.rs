@@ -1,16 +1,65 @@
 pub mod borrow_leverage {
 
+    use crate::access::access_control;
     use crate::error::ContractError;
+    use crate::events::leverage_events::LeverageEvent;
+    use crate::incentives::incentives_module;
+    use crate::interest::interest_module;
+    use crate::listing::token_listing;
+    use crate::market::market_module;
     use crate::msg::TokenData;
-    use crate::state::{USER_VTOKEN_BALANCE, WRAPPED_TOKEN_BALANCE, WRAPPED_TOKEN_BORROW_BALANCE};
-    
-    use cosmwasm_std::{DepsMut, Env, MessageInfo, Response, Uint128};
+    use crate::operator::operator_approvals;
+    use crate::oracle::price_oracle;
+    use crate::referral::referral_program;
+    use crate::state::{
+        OperatorPermission, BORROWERS, BORROW_INTEREST, FEE_CONFIG, TOTAL_BORROWED, TOTAL_VTOKENS,
+        TOTAL_WRAPPED, USER_VTOKEN_BALANCE, WRAPPED_TOKEN_BALANCE, WRAPPED_TOKEN_BORROW_BALANCE,
+    };
+
+    use cosmwasm_std::{DepsMut, Empty, Env, MessageInfo, Response, Uint128};
+
+    /**
+     * Borrows on an account of the sender, or of the wallet the sender is an
+     * approved operator of.
+     */
+    pub fn borrow_for_account(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        token_data: TokenData,
+    ) -> Result<Response, ContractError> {
+        let operator = info.sender.clone();
+        let info = operator_approvals::acting_account(
+            deps.as_ref(),
+            &env,
+            info,
+            &token_data,
+            OperatorPermission::Borrow,
+        )?;
+        let response = borrow(deps, env, info, token_data.clone())?;
+
+        Ok(match token_data.on_behalf_of {
+            Some(owner) if owner != operator => response.add_attribute("operator", operator),
+            _ => response,
+        })
+    }
+
     pub fn borrow(
         deps: DepsMut,
-        _env: Env,
+        env: Env,
         info: MessageInfo,
         token_data: TokenData,
     ) -> Result<Response, ContractError> {
+        access_control::assert_not_paused(deps.storage)?;
+        let token_info = token_listing::load_active_token(deps.storage, &token_data.token_address)?;
+
+        if token_data.token_amount.is_zero() {
+            return Err(ContractError::ZeroAmount {});
+        }
+
+        // New debt needs a trusted price, repaying never does
+        price_oracle::query_price(deps.as_ref(), &env, &token_info)?;
+
         let wrapped_token = match WRAPPED_TOKEN_BALANCE
             .may_load(deps.storage, (&token_data.token_address, &info.sender))
         {
@@ -28,15 +77,56 @@ pub mod borrow_leverage {
             return Err(ContractError::InsufficientUnmintedToken {});
         }
 
+        // Settle the interest of the current principal before it grows
+        interest_module::accrue_interest(
+            deps.storage,
+            &token_data.token_address,
+            &info.sender,
+            env.block.time,
+        )?;
+        incentives_module::update_user_rewards(
+            deps.storage,
+            &token_data.token_address,
+            &info.sender,
+            env.block.time,
+        )?;
+
+        // The borrow fee is owed on top of the principal, like interest
+        let fees = FEE_CONFIG.may_load(deps.storage)?.unwrap_or_default();
+        let fee = match token_data.token_amount.checked_mul_floor(fees.borrow_fee) {
+            Ok(data) => data,
+            Err(_) => return Err(ContractError::Overflow {}),
+        };
+        BORROW_INTEREST.update(
+            deps.storage,
+            (&token_data.token_address, &info.sender),
+            |opt_interest| -> Result<Uint128, ContractError> {
+                match opt_interest {
+                    Some(interest) => match interest.checked_add(fee) {
+                        Ok(data) => Ok(data),
+                        Err(_) => Err(ContractError::Overflow {}),
+                    },
+                    None => Ok(fee),
+                }
+            },
+        )?;
+        referral_program::share_fees(
+            deps.storage,
+            &info.sender,
+            &token_data.token_address,
+            fee,
+            Uint128::zero(),
+        )?;
+
         // Update user's wrapped token balance by subtracting borrowed amount
-        WRAPPED_TOKEN_BALANCE.update(
+        let wrapped_balance = WRAPPED_TOKEN_BALANCE.update(
             deps.storage,
             (&token_data.token_address, &info.sender),
             |opt_wrapped_token_balance| -> Result<Uint128, ContractError> {
                 match opt_wrapped_token_balance {
                     Some(data) => match data.checked_sub(token_data.token_amount) {
                         Ok(wrapped_token_balance) => Ok(wrapped_token_balance),
-                        Err(_) => return Err(ContractError::Overflow {}),
+                        Err(_) => Err(ContractError::Overflow {}),
                     },
                     None => Err(ContractError::UpdateWrapTokenErr {}),
                 }
@@ -44,33 +134,70 @@ pub mod borrow_leverage {
         )?;
 
         // Update user's wrapped borrow balance by adding borrowed amount
-        WRAPPED_TOKEN_BORROW_BALANCE.update(
+        let borrow_balance = WRAPPED_TOKEN_BORROW_BALANCE.update(
             deps.storage,
             (&token_data.token_address, &info.sender),
             |opt_borrow_balance| -> Result<Uint128, ContractError> {
                 match opt_borrow_balance {
                     Some(data) => match data.checked_add(token_data.token_amount) {
                         Ok(borror_balance) => Ok(borror_balance),
-                        Err(_) => return Err(ContractError::Overflow {}),
+                        Err(_) => Err(ContractError::Overflow {}),
                     },
                     None => Ok(token_data.token_amount),
                 }
             },
         )?;
 
-        USER_VTOKEN_BALANCE.update(
+        let vtoken_balance = USER_VTOKEN_BALANCE.update(
             deps.storage,
             (&token_data.token_address, &info.sender),
             |opt_borrow_balance| -> Result<Uint128, ContractError> {
                 match opt_borrow_balance {
                     Some(data) => match data.checked_add(token_data.token_amount) {
                         Ok(borror_balance) => Ok(borror_balance),
-                        Err(_) => return Err(ContractError::Overflow {}),
+                        Err(_) => Err(ContractError::Overflow {}),
                     },
                     None => Ok(token_data.token_amount),
                 }
             },
         )?;
-        Ok(Response::new().add_attribute("method", "borrow"))
+
+        market_module::decrease_total(
+            deps.storage,
+            &TOTAL_WRAPPED,
+            &token_data.token_address,
+            token_data.token_amount,
+        )?;
+        market_module::increase_total(
+            deps.storage,
+            &TOTAL_BORROWED,
+            &token_data.token_address,
+            token_data.token_amount,
+        )?;
+        market_module::increase_total(
+            deps.storage,
+            &TOTAL_VTOKENS,
+            &token_data.token_address,
+            token_data.token_amount,
+        )?;
+        market_module::assert_borrow_cap(deps.storage, &token_data.token_address)?;
+
+        BORROWERS.save(deps.storage, &info.sender, &Empty {})?;
+
+        Ok(Response::new()
+            .add_attribute("method", "borrow")
+            .add_attribute("fee", fee)
+            .add_event(
+                LeverageEvent::Borrow {
+                    user: info.sender,
+                    token: token_data.token_address.to_string(),
+                    amount: token_data.token_amount,
+                    fee,
+                    borrow_balance,
+                    wrapped_balance,
+                    vtoken_balance,
+                }
+                .into(),
+            ))
     }
 }
@@ -2,9 +2,17 @@ pub mod burn_tokens {
 
     use cosmwasm_std::{DepsMut, Env, MessageInfo, Response, Uint128};
 
+    use crate::access::access_control;
     use crate::error::ContractError;
+    use crate::events::leverage_events::LeverageEvent;
+    use crate::incentives::incentives_module;
+    use crate::interest::interest_module;
+    use crate::market::market_module;
     use crate::msg::TokenData;
-    use crate::state::{TOKEN_BALANCE, USER_VTOKEN_BALANCE, WRAPPED_TOKEN_BALANCE, WRAPPED_TOKEN_BORROW_BALANCE};
+    use crate::state::{
+        RESERVE_BALANCE, TOKEN_BALANCE, TOTAL_COLLATERAL, TOTAL_VTOKENS, TOTAL_WRAPPED,
+        USER_VTOKEN_BALANCE, WRAPPED_TOKEN_BALANCE, WRAPPED_TOKEN_BORROW_BALANCE,
+    };
     /**
      * Function to burn vToken and receive underlying assets.
      *
@@ -15,6 +23,7 @@ pub mod burn_tokens {
      * 3. Loads the user's profit balance and checks if it is sufficient for the burning.
      * 4. If the profit balance is less than the vToken amount, returns an error indicating insufficient balance.
      * 5. Calculates the amount of underlying USDC tokens to be received based on the vToken amount.
+     *    It is paid out of the token's reserve, so only repaid or topped up tokens back it.
      * 6. Updates the user's token balance by adding the received USDC amount.
      * 7. Updates the user's unminted token balance by adding the vToken amount.
      * 8. Returns a response indicating success.
@@ -26,15 +35,22 @@ pub mod burn_tokens {
      * @param _v_token_data.token_amount Amount of vTokens to be burned.
      * @return A response object indicating success or failure.
      */
-
     pub fn burn(
         deps: DepsMut,
-        _env: Env,
+        env: Env,
         info: MessageInfo,
-        token_data: TokenData
+        token_data: TokenData,
     ) -> Result<Response, ContractError> {
+        access_control::assert_not_paused(deps.storage)?;
+
+        if token_data.token_amount.is_zero() {
+            return Err(ContractError::ZeroAmount {});
+        }
+
         // Load the user's borrow balance from storage
-        let user_borrow_balance = match WRAPPED_TOKEN_BORROW_BALANCE.may_load(deps.storage, (&token_data.token_address, &info.sender)) {
+        let user_borrow_balance = match WRAPPED_TOKEN_BORROW_BALANCE
+            .may_load(deps.storage, (&token_data.token_address, &info.sender))
+        {
             Ok(opt_data) => match opt_data {
                 Some(data) => data,
                 None => Uint128::zero(),
@@ -43,33 +59,63 @@ pub mod burn_tokens {
         };
 
         // If user's borrow balance is greater than zero, return an error
-        if user_borrow_balance.gt(&Uint128::zero()) {
+        // Interest still owed counts as borrowed as well
+        let user_borrow_interest = interest_module::pending_interest(
+            deps.storage,
+            &token_data.token_address,
+            &info.sender,
+            env.block.time,
+        )?;
+
+        if user_borrow_balance.gt(&Uint128::zero()) || user_borrow_interest.gt(&Uint128::zero()) {
             return Err(ContractError::PayBorrowAmount {});
         }
 
         // Load the user's profit balance from storage
-        let user_vtoken_balance =
-            match USER_VTOKEN_BALANCE.may_load(deps.storage, (&token_data.token_address, &info.sender)) {
-                Ok(opt_data) => match opt_data {
-                    Some(data) => data,
-                    None => Uint128::zero(),
-                },
-                Err(_) => return Err(ContractError::ProfitBalanceLoadError {}),
-            };
+        let user_vtoken_balance = match USER_VTOKEN_BALANCE
+            .may_load(deps.storage, (&token_data.token_address, &info.sender))
+        {
+            Ok(opt_data) => match opt_data {
+                Some(data) => data,
+                None => Uint128::zero(),
+            },
+            Err(_) => return Err(ContractError::ProfitBalanceLoadError {}),
+        };
 
         if user_vtoken_balance.lt(&token_data.token_amount) {
-            return Err(ContractError::InsufficientBalance {})
+            return Err(ContractError::InsufficientBalance {});
         }
 
         // Calculate the equivalent USDC amount based on the VToken amount burned (assuming 10:1 ratio)
-        let user_collateral_amount = match token_data.token_amount.checked_div(Uint128::from(10u128)) {
-            Ok(data) => data,
-            Err(_) => return Err(ContractError::Overflow {}),
-        };
-        
+        let user_collateral_amount =
+            match token_data.token_amount.checked_div(Uint128::from(10u128)) {
+                Ok(data) => data,
+                Err(_) => return Err(ContractError::Overflow {}),
+            };
+
+        // The new collateral has to be backed by real tokens in the reserve
+        let reserve = RESERVE_BALANCE
+            .may_load(deps.storage, &token_data.token_address)?
+            .unwrap_or_default();
+        if reserve.lt(&user_collateral_amount) {
+            return Err(ContractError::InsufficientReserve {});
+        }
+        market_module::decrease_total(
+            deps.storage,
+            &RESERVE_BALANCE,
+            &token_data.token_address,
+            user_collateral_amount,
+        )?;
+
+        incentives_module::update_user_rewards(
+            deps.storage,
+            &token_data.token_address,
+            &info.sender,
+            env.block.time,
+        )?;
 
         // Update user's token balance by adding the calculated USDC amount
-        TOKEN_BALANCE.update(
+        let collateral_balance = TOKEN_BALANCE.update(
             deps.storage,
             (&token_data.token_address, &info.sender),
             |opt_balance| -> Result<Uint128, ContractError> {
@@ -84,7 +130,7 @@ pub mod burn_tokens {
         )?;
 
         // Update user's unminted token balance by adding the VToken amount burned
-        WRAPPED_TOKEN_BALANCE.update(
+        let wrapped_balance = WRAPPED_TOKEN_BALANCE.update(
             deps.storage,
             (&token_data.token_address, &info.sender),
             |opt_balance| -> Result<Uint128, ContractError> {
@@ -98,7 +144,7 @@ pub mod burn_tokens {
             },
         )?;
 
-        USER_VTOKEN_BALANCE.update(
+        let vtoken_balance = USER_VTOKEN_BALANCE.update(
             deps.storage,
             (&token_data.token_address, &info.sender),
             |opt_balance| -> Result<Uint128, ContractError> {
@@ -107,11 +153,42 @@ pub mod burn_tokens {
                         Ok(data) => Ok(data),
                         Err(_) => Err(ContractError::Overflow {}),
                     },
-                    None => return Err(ContractError::GenericError { error: String::from("doesn't have v tokens") }),
+                    None => Err(ContractError::GenericError {
+                        error: String::from("doesn't have v tokens"),
+                    }),
                 }
-            }
+            },
         )?;
 
-        Ok(Response::new())
+        market_module::increase_total(
+            deps.storage,
+            &TOTAL_COLLATERAL,
+            &token_data.token_address,
+            user_collateral_amount,
+        )?;
+        market_module::increase_total(
+            deps.storage,
+            &TOTAL_WRAPPED,
+            &token_data.token_address,
+            token_data.token_amount,
+        )?;
+        market_module::decrease_total(
+            deps.storage,
+            &TOTAL_VTOKENS,
+            &token_data.token_address,
+            token_data.token_amount,
+        )?;
+
+        Ok(Response::new().add_attribute("method", "burn").add_event(
+            LeverageEvent::Burn {
+                user: info.sender,
+                token: token_data.token_address.to_string(),
+                amount: token_data.token_amount,
+                collateral_balance,
+                wrapped_balance,
+                vtoken_balance,
+            }
+            .into(),
+        ))
     }
 }
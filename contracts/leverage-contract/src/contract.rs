@@ -5,16 +5,33 @@ use cosmwasm_std::{
 };
 use cw2::set_contract_version;
 
+use crate::access::access_control;
 use crate::borrow::borrow_leverage;
 use crate::burn::burn_tokens;
+use crate::deleverage::auto_deleverage;
 use crate::deposit::deposit_collateral;
 use crate::error::ContractError;
 use crate::exchange::exchange_tokens;
 use crate::execute::execute_module;
-use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg};
+use crate::ibc_hooks::ibc_hook_deposit;
+use crate::incentives::incentives_module;
+use crate::liquidate::liquidate_leverage;
+use crate::listing::token_listing;
+use crate::market::market_module;
+use crate::migrate::contract_migration;
+use crate::msg::{ExecuteMsg, InitialToken, InstantiateMsg, MigrateMsg, QueryMsg, SudoMsg};
+use crate::operator::operator_approvals;
+use crate::oracle::price_oracle;
 use crate::query::query_module;
+use crate::receive::receive_cw20;
+use crate::referral::referral_program;
 use crate::repay::repay_leverage;
-use crate::state::{LEVERAGE_CONTRACT_OWNER, LISTED_TOKEN};
+use crate::simulate::simulation_module;
+use crate::state::{FeeConfig, LEVERAGE_CONTRACT_OWNER, TIMELOCK_DELAY};
+use crate::sub_account::sub_accounts;
+use crate::sudo::sudo_module;
+use crate::timelock::timelock_module;
+use crate::valuation::valuation_module;
 use crate::withdraw::withdraw_collateral;
 
 const CONTRACT_NAME: &str = "crates.io:leverage-contract";
@@ -22,28 +39,104 @@ const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
-    deps: DepsMut,
+    mut deps: DepsMut,
     _env: Env,
     info: MessageInfo,
     _msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
-    println!("inst: {}", _msg.token_contract_address);
 
-    let mut token_list: Vec<String> = Vec::new();
-    token_list.push(_msg.token_contract_address);
-    LISTED_TOKEN.save(deps.storage, &token_list)?;
-
-    LEVERAGE_CONTRACT_OWNER.save(deps.storage, &info.sender)?;
+    let owner = match _msg.owner {
+        Some(owner) => deps.api.addr_validate(&owner)?,
+        None => info.sender,
+    };
+    LEVERAGE_CONTRACT_OWNER.save(deps.storage, &owner)?;
+    set_up_markets(
+        deps.branch(),
+        &_env,
+        _msg.tokens,
+        _msg.fees,
+        _msg.timelock_delay,
+    )?;
 
     Ok(Response::new()
         .add_attribute("method", "instantiate")
-        .add_attribute("owner", info.sender))
+        .add_attribute("owner", owner))
+}
+
+// Lists the tokens and sets the fees and the timelock delay
+fn set_up_markets(
+    mut deps: DepsMut,
+    env: &Env,
+    tokens: Vec<InitialToken>,
+    fees: Option<FeeConfig>,
+    timelock_delay: Option<u64>,
+) -> Result<(), ContractError> {
+    for token in tokens {
+        token_listing::list_token(
+            deps.branch(),
+            env.clone(),
+            token.token_address.clone(),
+            token.asset,
+            token.decimals,
+            token.symbol,
+        )?;
+
+        if let Some(source) = token.price_source {
+            price_oracle::set_price_source(
+                deps.branch(),
+                env.clone(),
+                Addr::unchecked(token.token_address),
+                source,
+            )?;
+        }
+    }
+
+    execute_module::update_fees(deps.branch(), env.clone(), fees.unwrap_or_default())?;
+    let timelock_delay = timelock_delay.unwrap_or(timelock_module::DEFAULT_TIMELOCK_DELAY);
+    timelock_module::assert_timelock_delay(timelock_delay)?;
+    TIMELOCK_DELAY.save(deps.storage, &timelock_delay)?;
+    Ok(())
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn migrate(_deps: DepsMut, _env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
-    match msg {}
+pub fn migrate(mut deps: DepsMut, env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+    match msg {
+        MigrateMsg::FromFirstRelease {
+            tokens,
+            fees,
+            timelock_delay,
+        } => {
+            contract_migration::take_legacy_listing(deps.storage, &tokens)?;
+            set_up_markets(deps.branch(), &env, tokens, fees, timelock_delay)?;
+            contract_migration::rebuild_market_state(deps.storage, env.block.time)?;
+            set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+            Ok(Response::new()
+                .add_attribute("method", "migrate")
+                .add_attribute("version", CONTRACT_VERSION))
+        }
+    }
+}
+
+/// Entry point of chain governance, nothing in here checks the sender
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn sudo(deps: DepsMut, env: Env, msg: SudoMsg) -> Result<Response, ContractError> {
+    match msg {
+        SudoMsg::ForcePause {} => sudo_module::force_pause(deps, env),
+        SudoMsg::ReplaceOwner { new_owner } => sudo_module::replace_owner(deps, env, new_owner),
+        SudoMsg::UpdatePriceSources { sources } => {
+            sudo_module::update_price_sources(deps, env, sources)
+        }
+        SudoMsg::ForceSettle {
+            user_address,
+            account_id,
+        } => sudo_module::force_settle(deps, env, user_address, account_id),
+        SudoMsg::AutoDeleverage {
+            token_address,
+            limit,
+        } => sudo_module::auto_deleverage(deps, env, token_address, limit),
+    }
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
@@ -54,23 +147,148 @@ pub fn execute(
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::ListTokenOnLeverage { token_address } => {
-            execute_module::list_token_on_leverage(_deps, _env, _info, token_address)
-        }
         ExecuteMsg::Receive(cw20_receive_msg) => {
-            deposit_collateral::fungible_token(_deps, _env, _info, cw20_receive_msg)
+            receive_cw20::receive(_deps, _env, _info, cw20_receive_msg)
+        }
+        ExecuteMsg::DepositNative {
+            token_address,
+            referral_code,
+            account_id,
+        } => deposit_collateral::native_token(
+            _deps,
+            _env,
+            sub_accounts::sender_account(_info, account_id)?,
+            Addr::unchecked(token_address),
+            referral_code,
+        ),
+        ExecuteMsg::IbcHookDeposit {
+            channel,
+            original_sender,
+            borrow_amount,
+            account_id,
+        } => ibc_hook_deposit::deposit(
+            _deps,
+            _env,
+            _info,
+            channel,
+            original_sender,
+            borrow_amount,
+            account_id,
+        ),
+        ExecuteMsg::RepayNative {
+            token_address,
+            account_id,
+        } => repay_leverage::repay_native(
+            _deps,
+            _env,
+            sub_accounts::sender_account(_info, account_id)?,
+            Addr::unchecked(token_address),
+        ),
+        ExecuteMsg::LiquidateNative {
+            token_address,
+            user_address,
+            collateral_token,
+            account_id,
+        } => liquidate_leverage::liquidate_native(
+            _deps,
+            _env,
+            _info,
+            Addr::unchecked(token_address),
+            user_address,
+            account_id,
+            Addr::unchecked(collateral_token),
+        ),
+        ExecuteMsg::TopUpNative {
+            token_address,
+            account_id,
+        } => repay_leverage::top_up_native(
+            _deps,
+            _env,
+            sub_accounts::sender_account(_info, account_id)?,
+            Addr::unchecked(token_address),
+        ),
+        ExecuteMsg::GrantRole { role, address } => {
+            access_control::grant_role(_deps, _env, _info, role, address)
+        }
+        ExecuteMsg::RevokeRole { role, address } => {
+            access_control::revoke_role(_deps, _env, _info, role, address)
         }
-        ExecuteMsg::DepositNative { token_address } => {
-            deposit_collateral::native_token(_deps, _env, _info, Addr::unchecked(token_address))
+        ExecuteMsg::ProposeOwnership { new_owner } => {
+            access_control::propose_ownership(_deps, _env, _info, new_owner)
+        }
+        ExecuteMsg::AcceptOwnership {} => access_control::accept_ownership(_deps, _env, _info),
+        ExecuteMsg::ProposeAction { action } => {
+            timelock_module::propose_action(_deps, _env, _info, action)
+        }
+        ExecuteMsg::CancelAction { id } => timelock_module::cancel_action(_deps, _env, _info, id),
+        ExecuteMsg::ExecuteAction { id } => timelock_module::execute_action(_deps, _env, _info, id),
+        ExecuteMsg::Pause {} => access_control::pause(_deps, _env, _info),
+        ExecuteMsg::Unpause {} => access_control::unpause(_deps, _env, _info),
+        ExecuteMsg::UpdateTokenStatus {
+            token_address,
+            status,
+        } => token_listing::update_token_status(
+            _deps,
+            _env,
+            _info,
+            Addr::unchecked(token_address),
+            status,
+        ),
+        ExecuteMsg::DelistToken { token_address } => {
+            token_listing::delist_token(_deps, _env, _info, Addr::unchecked(token_address))
+        }
+        ExecuteMsg::CollectRevenue(withdraw_data) => {
+            execute_module::collect_revenue(_deps, _env, _info, withdraw_data)
+        }
+        ExecuteMsg::Borrow(token_data) => {
+            borrow_leverage::borrow_for_account(_deps, _env, _info, token_data)
+        }
+        ExecuteMsg::Repay(token_data) => {
+            repay_leverage::repay_for_account(_deps, _env, _info, token_data)
+        }
+        ExecuteMsg::Burn(token_data) => {
+            let info = operator_approvals::own_account(_info, &token_data, "burn")?;
+            burn_tokens::burn(_deps, _env, info, token_data)
         }
-        ExecuteMsg::Borrow(token_data) => borrow_leverage::borrow(_deps, _env, _info, token_data),
-        ExecuteMsg::Repay(token_data) => repay_leverage::repay(_deps, _env, _info, token_data),
-        ExecuteMsg::Burn(token_data) => burn_tokens::burn(_deps, _env, _info, token_data),
         ExecuteMsg::ExecuteOrder(order_execute) => {
             exchange_tokens::execute_order(_deps, _env, _info, order_execute)
         }
         ExecuteMsg::WithdrawToken(withdraw_data) => {
-            withdraw_collateral::withdraw(_deps, _env, _info, withdraw_data)
+            let info = sub_accounts::sender_account(_info, withdraw_data.account_id)?;
+            withdraw_collateral::withdraw(_deps, _env, info, withdraw_data)
+        }
+        ExecuteMsg::ClaimRewards { account_id } => incentives_module::claim_rewards(
+            _deps,
+            _env,
+            sub_accounts::sender_account(_info, account_id)?,
+        ),
+        ExecuteMsg::TransferCollateral {
+            token_address,
+            amount,
+            from_account_id,
+            to_account_id,
+        } => sub_accounts::transfer_collateral(
+            _deps,
+            _env,
+            _info,
+            Addr::unchecked(token_address),
+            amount,
+            from_account_id,
+            to_account_id,
+        ),
+        ExecuteMsg::RegisterReferralCode { code } => {
+            referral_program::register_code(_deps, _env, _info, code)
+        }
+        ExecuteMsg::ClaimReferralRebates {} => referral_program::claim_rebates(_deps, _env, _info),
+        ExecuteMsg::ApproveOperator {
+            operator,
+            permissions,
+            expires,
+        } => {
+            operator_approvals::approve_operator(_deps, _env, _info, operator, permissions, expires)
+        }
+        ExecuteMsg::RevokeOperator { operator } => {
+            operator_approvals::revoke_operator(_deps, _env, _info, operator)
         }
     }
 }
@@ -78,21 +296,189 @@ pub fn execute(
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn query(_deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
-        QueryMsg::UserCollateralTokenBalance(query_token_data) => to_json_binary(
-            &query_module::fetch_user_collateral_token_balance(_deps, _env, query_token_data)?,
+        QueryMsg::UserCollateralTokenBalance(query_token_data) => {
+            to_json_binary(&query_module::fetch_user_collateral_token_balance(
+                _deps,
+                _env,
+                sub_accounts::query_account(query_token_data)?,
+            )?)
+        }
+        QueryMsg::UserWrappedTokenBalance(query_token_data) => {
+            to_json_binary(&query_module::fetch_user_wrapped_token_balance(
+                _deps,
+                _env,
+                sub_accounts::query_account(query_token_data)?,
+            )?)
+        }
+        QueryMsg::UserBorrowTokenBalance(query_token_data) => {
+            to_json_binary(&query_module::fetch_user_borrow_token_balance(
+                _deps,
+                _env,
+                sub_accounts::query_account(query_token_data)?,
+            )?)
+        }
+        QueryMsg::UserVTokenBalance(query_token_data) => {
+            to_json_binary(&query_module::fetch_user_v_token_balance(
+                _deps,
+                _env,
+                sub_accounts::query_account(query_token_data)?,
+            )?)
+        }
+        QueryMsg::UserBorrowInterest(query_token_data) => {
+            to_json_binary(&query_module::fetch_user_borrow_interest(
+                _deps,
+                _env,
+                sub_accounts::query_account(query_token_data)?,
+            )?)
+        }
+        QueryMsg::UserOrders {
+            user_address,
+            account_id,
+        } => to_json_binary(&query_module::fetch_user_orders(
+            _deps,
+            _env,
+            sub_accounts::account_address(&user_address, account_id)?,
+        )?),
+        QueryMsg::Config {} => to_json_binary(&query_module::fetch_config(_deps, _env)?),
+        QueryMsg::ContractVersion {} => to_json_binary(&cw2::get_contract_version(_deps.storage)?),
+        QueryMsg::RoleMembers {
+            role,
+            start_after,
+            limit,
+        } => to_json_binary(&access_control::fetch_role_members(
+            _deps,
+            _env,
+            role,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::ListedTokens { start_after, limit } => to_json_binary(
+            &token_listing::fetch_listed_tokens(_deps, _env, start_after, limit)?,
+        ),
+        QueryMsg::ListedToken { token_address } => to_json_binary(
+            &token_listing::fetch_listed_token(_deps, _env, token_address)?,
+        ),
+        QueryMsg::Price { token_address } => {
+            to_json_binary(&price_oracle::fetch_price(_deps, _env, token_address)?)
+        }
+        QueryMsg::AccountHealth {
+            user_address,
+            account_id,
+        } => to_json_binary(&valuation_module::account_health(
+            _deps,
+            &_env,
+            &sub_accounts::account_address(&user_address, account_id)?,
+        )?),
+        QueryMsg::MarketState { token_address } => to_json_binary(
+            &market_module::fetch_market_state(_deps, _env, token_address)?,
+        ),
+        QueryMsg::AllMarkets { start_after, limit } => to_json_binary(
+            &market_module::fetch_all_markets(_deps, _env, start_after, limit)?,
+        ),
+        QueryMsg::LiquidatableAccounts { start_after, limit } => to_json_binary(
+            &liquidate_leverage::fetch_liquidatable_accounts(_deps, _env, start_after, limit)?,
         ),
-        QueryMsg::UserWrappedTokenBalance(query_token_data) => to_json_binary(
-            &query_module::fetch_user_wrapped_token_balance(_deps, _env, query_token_data)?,
+        QueryMsg::AdlRanking {
+            token_address,
+            start_after,
+            limit,
+        } => to_json_binary(&auto_deleverage::fetch_adl_ranking(
+            _deps,
+            _env,
+            token_address,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::SimulateBorrow {
+            user_address,
+            token_data,
+        } => to_json_binary(&simulation_module::simulate_borrow(
+            _deps,
+            _env,
+            user_address,
+            token_data,
+        )?),
+        QueryMsg::SimulateWithdraw {
+            user_address,
+            withdraw_data,
+        } => to_json_binary(&simulation_module::simulate_withdraw(
+            _deps,
+            _env,
+            user_address,
+            withdraw_data,
+        )?),
+        QueryMsg::SimulateOrder(order) => {
+            to_json_binary(&simulation_module::simulate_order(_deps, _env, order)?)
+        }
+        QueryMsg::SimulateBurn {
+            user_address,
+            token_data,
+        } => to_json_binary(&simulation_module::simulate_burn(
+            _deps,
+            _env,
+            user_address,
+            token_data,
+        )?),
+        QueryMsg::SimulateLiquidation {
+            liquidator,
+            user_address,
+            token_data,
+            collateral_token,
+            account_id,
+        } => to_json_binary(&simulation_module::simulate_liquidation(
+            _deps,
+            _env,
+            liquidator,
+            user_address,
+            account_id,
+            token_data,
+            collateral_token,
+        )?),
+        QueryMsg::UserAccount {
+            user_address,
+            account_id,
+        } => to_json_binary(&query_module::fetch_user_account(
+            _deps,
+            _env,
+            sub_accounts::account_address(&user_address, account_id)?,
+        )?),
+        QueryMsg::PendingActions { start_after, limit } => to_json_binary(
+            &timelock_module::fetch_pending_actions(_deps, _env, start_after, limit)?,
         ),
-        QueryMsg::UserBorrowTokenBalance(query_token_data) => to_json_binary(
-            &query_module::fetch_user_borrow_token_balance(_deps, _env, query_token_data)?,
+        QueryMsg::PendingAction { id } => {
+            to_json_binary(&timelock_module::fetch_pending_action(_deps, _env, id)?)
+        }
+        QueryMsg::PendingRewards {
+            user_address,
+            account_id,
+        } => to_json_binary(&incentives_module::fetch_pending_rewards(
+            _deps,
+            _env,
+            sub_accounts::account_address(&user_address, account_id)?,
+        )?),
+        QueryMsg::RewardState { token_address } => to_json_binary(
+            &incentives_module::fetch_reward_state(_deps, _env, token_address)?,
         ),
-        QueryMsg::UserVTokenBalance(query_token_data) => to_json_binary(
-            &query_module::fetch_user_v_token_balance(_deps, _env, query_token_data)?,
+        QueryMsg::ReferralStats { referrer_address } => to_json_binary(
+            &referral_program::fetch_referral_stats(_deps, referrer_address)?,
         ),
-        QueryMsg::UserOrders { user_address } => {
-            to_json_binary(&query_module::fetch_user_orders(_deps, _env, user_address)?)
+        QueryMsg::Referrer { user_address } => {
+            to_json_binary(&referral_program::fetch_referrer(_deps, user_address)?)
+        }
+        QueryMsg::SubAccounts { owner } => {
+            to_json_binary(&sub_accounts::fetch_sub_accounts(_deps, owner)?)
         }
+        QueryMsg::OperatorApprovals {
+            owner,
+            start_after,
+            limit,
+        } => to_json_binary(&operator_approvals::fetch_operator_approvals(
+            _deps,
+            _env,
+            owner,
+            start_after,
+            limit,
+        )?),
     }
 }
 
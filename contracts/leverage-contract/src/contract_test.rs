@@ -1,210 +1,4962 @@
 #[cfg(test)]
 mod test {
-    use cosmwasm_std::{coins, to_json_binary, Addr, Coin, Uint128};
-    use cw_multi_test::{AppBuilder, ContractWrapper, Executor};
+    use std::collections::HashMap;
+    use std::marker::PhantomData;
 
-    use crate::contract::{execute, instantiate, query};
-    use crate::execute;
+    use cosmwasm_std::testing::{
+        mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage,
+    };
+    use cosmwasm_std::{
+        coins, from_json, to_json_binary, Addr, BankMsg, Binary, ContractResult, Decimal,
+        Decimal256, Empty, Env, Order, OwnedDeps, Querier, QuerierResult, QueryRequest, StdResult,
+        Storage, SystemError, SystemResult, Timestamp, Uint128,
+    };
+    use cw20::Expiration;
+    use cw_multi_test::{App, AppBuilder, AppResponse, ContractWrapper, Executor};
+    use cw_storage_plus::Item;
+
+    use crate::contract::{execute, instantiate, migrate, query};
+    use crate::ibc_hooks::ibc_hook_deposit::derive_intermediate_sender;
+    use crate::interest::interest_module::SECONDS_PER_YEAR;
+    use crate::mock_pyth::mock_pyth_contract as mock_pyth;
     use crate::msg::{
-        Cw20ReceiveMsg, ExecuteMsg, InstantiateMsg, OrderExecute, QueryMsg, QueryTokenData,
-        TokenData,
+        AccountHealthResponse, AdlRankingResponse, ConfigResponse, Cw20HookMsg, Cw20ReceiveMsg,
+        ExecuteMsg, InitialToken, InstantiateMsg, LiquidatableAccount,
+        LiquidatableAccountsResponse, ListedTokensResponse, MarketStateResponse, MarketsResponse,
+        MigrateMsg, OperatorApprovalsResponse, OrderExecute, PendingActionsResponse,
+        PendingRewardsResponse, PriceResponse, PriceSourceUpdate, QueryMsg, QueryTokenData,
+        ReferralStatsResponse, ReferrerResponse, RewardStateResponse, RoleMembersResponse,
+        SimulationResponse, SubAccountsResponse, SudoMsg, TokenData, UserAccountResponse,
+        WithdrawData,
+    };
+    use crate::oracle::price_oracle::set_price_source;
+    use crate::scenario::scenario_harness::{Scenario, ATOM, ATOM_DENOM, OSMO, OWNER, USDC};
+    use crate::state::{
+        AssetInfo, FeeConfig, MarketCaps, OperatorPermission, PendingAction, PriceSource,
+        RewardSchedule, Role, TimelockAction, TokenInfo, TokenStatus, BORROWERS,
+        LEVERAGE_CONTRACT_OWNER, TOKEN_BALANCE, TOTAL_VTOKENS, USER_VTOKEN_BALANCE,
+        WRAPPED_TOKEN_BALANCE, WRAPPED_TOKEN_BORROW_BALANCE,
     };
+    use crate::sub_account::sub_accounts;
+    use crate::timelock::timelock_module::{DEFAULT_TIMELOCK_DELAY, MIN_TIMELOCK_DELAY};
+    use crate::twap::osmosis_twap::{
+        encode_request, ArithmeticTwapToNowResponse, ARITHMETIC_TWAP_TO_NOW_PATH,
+    };
+    use crate::ContractError;
 
-    #[test]
-    fn cw_multi_instantiate() {
-        let init_funds = coins(10000000000, "uosmo");
+    fn setup_leverage_contract() -> (App, Addr) {
         let mut app = AppBuilder::new().build(|router, _, storage| {
             router
                 .bank
-                .init_balance(storage, &Addr::unchecked("creator"), init_funds)
+                .init_balance(
+                    storage,
+                    &Addr::unchecked("user_one"),
+                    coins(10000000000, "uosmo"),
+                )
                 .unwrap();
         });
 
-        let group_code_id =
-            app.store_code(Box::new(ContractWrapper::new(execute, instantiate, query)));
+        let code_id = app.store_code(Box::new(ContractWrapper::new(execute, instantiate, query)));
 
-        let msg = InstantiateMsg {
-            token_contract_address: String::from("usdc_contract"),
-        };
+        let contract = app
+            .instantiate_contract(
+                code_id,
+                Addr::unchecked("creator"),
+                &InstantiateMsg {
+                    owner: None,
+                    tokens: vec![
+                        initial_token(
+                            "USDC",
+                            AssetInfo::Cw20 {
+                                contract_addr: Addr::unchecked("usdc_contract"),
+                            },
+                            Decimal::one(),
+                        ),
+                        initial_token(
+                            "OSMO",
+                            AssetInfo::Native {
+                                denom: String::from("uosmo"),
+                            },
+                            Decimal::one(),
+                        ),
+                        initial_token(
+                            "ATOM",
+                            AssetInfo::Native {
+                                denom: String::from("uatom"),
+                            },
+                            Decimal::percent(1000),
+                        ),
+                    ],
+                    fees: None,
+                    timelock_delay: None,
+                },
+                &[],
+                "leverage_contract",
+                None,
+            )
+            .unwrap();
 
-        let contract_addr = app.instantiate_contract(
-            group_code_id,
-            Addr::unchecked("creator"),
-            &msg,
+        (app, contract)
+    }
+
+    fn initial_token(token: &str, asset: AssetInfo, price: Decimal) -> InitialToken {
+        InitialToken {
+            token_address: String::from(token),
+            asset,
+            decimals: Some(6),
+            symbol: String::from(token),
+            price_source: Some(PriceSource::Fixed { price }),
+        }
+    }
+
+    fn set_price(app: &mut App, contract: &Addr, token: &str, price: Decimal) {
+        run_timelocked(
+            app,
+            contract,
+            "creator",
+            TimelockAction::SetPriceSource {
+                token_address: String::from(token),
+                source: PriceSource::Fixed { price },
+            },
+        );
+    }
+
+    fn proposed_action_id(res: &AppResponse) -> u64 {
+        res.custom_attrs(1)
+            .iter()
+            .find(|attr| attr.key == "action_id")
+            .unwrap()
+            .value
+            .parse()
+            .unwrap()
+    }
+
+    fn run_timelocked(app: &mut App, contract: &Addr, sender: &str, action: TimelockAction) {
+        let res = app
+            .execute_contract(
+                Addr::unchecked(sender),
+                contract.clone(),
+                &ExecuteMsg::ProposeAction { action },
+                &[],
+            )
+            .unwrap();
+        let id = proposed_action_id(&res);
+
+        app.update_block(|block| block.time = block.time.plus_seconds(DEFAULT_TIMELOCK_DELAY));
+
+        app.execute_contract(
+            Addr::unchecked(sender),
+            contract.clone(),
+            &ExecuteMsg::ExecuteAction { id },
+            &[],
+        )
+        .unwrap();
+    }
+
+    fn query_balance(app: &App, contract: &Addr, msg: QueryMsg) -> Uint128 {
+        app.wrap().query_wasm_smart(contract.clone(), &msg).unwrap()
+    }
+
+    fn osmo_of(user: &str) -> QueryTokenData {
+        QueryTokenData {
+            token_address: Addr::unchecked("OSMO"),
+            user_address: Addr::unchecked(user),
+            account_id: None,
+        }
+    }
+
+    #[test]
+    fn cw_multi_instantiate() {
+        let mut scenario = Scenario::new();
+        let user = scenario.funded_user("user_one", OSMO, 100000000);
+
+        scenario.deposit(&user, OSMO, 100000000).unwrap();
+
+        assert_eq!(
+            scenario.collateral(&user, OSMO),
+            Uint128::from(100000000u128)
+        );
+        assert_eq!(
+            scenario.query::<Uint128>(&QueryMsg::UserWrappedTokenBalance(QueryTokenData {
+                token_address: Addr::unchecked(OSMO),
+                user_address: user.clone(),
+                account_id: None,
+            })),
+            Uint128::from(1000000000u128)
+        );
+        assert_eq!(scenario.holdings(OSMO), Uint128::from(100000000u128));
+        assert_eq!(scenario.wallet_balance(&user, OSMO), Uint128::zero());
+    }
+
+    #[test]
+    fn repay_native_settles_interest_after_losing_vtokens() {
+        let (mut app, contract) = setup_leverage_contract();
+        let user = Addr::unchecked("user_one");
+
+        run_timelocked(
+            &mut app,
+            &contract,
+            "creator",
+            TimelockAction::UpdateBorrowRate {
+                token_address: String::from("OSMO"),
+                rate: Decimal::percent(10),
+            },
+        );
+
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::DepositNative {
+                token_address: String::from("OSMO"),
+                referral_code: None,
+                account_id: None,
+            },
+            &coins(1000, "uosmo"),
+        )
+        .unwrap();
+
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::Borrow(TokenData {
+                token_address: Addr::unchecked("OSMO"),
+                token_amount: Uint128::from(10000u128),
+                account_id: None,
+                on_behalf_of: None,
+            }),
+            &[],
+        )
+        .unwrap();
+
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::ExecuteOrder(OrderExecute {
+                order_id: String::from("1"),
+                user_address: user.clone(),
+                token_in: Addr::unchecked("OSMO"),
+                token_out: Addr::unchecked("ATOM"),
+                amount_in: Uint128::from(10000u128),
+                amount_out: Uint128::from(1000u128),
+                account_id: None,
+                min_out: None,
+            }),
+            &[],
+        )
+        .unwrap();
+
+        // The ATOM position loses half of its value
+        set_price(&mut app, &contract, "ATOM", Decimal::percent(500));
+
+        app.update_block(|block| {
+            block.time = block
+                .time
+                .plus_seconds(SECONDS_PER_YEAR - DEFAULT_TIMELOCK_DELAY)
+        });
+
+        let interest = query_balance(
+            &app,
+            &contract,
+            QueryMsg::UserBorrowInterest(osmo_of("user_one")),
+        );
+        assert_eq!(interest, Uint128::from(1000u128));
+
+        // Without vTokens the position can't be repaid from bookkeeping
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::Repay(TokenData {
+                token_address: Addr::unchecked("OSMO"),
+                token_amount: Uint128::from(11000u128),
+                account_id: None,
+                on_behalf_of: None,
+            }),
+            &[],
+        )
+        .unwrap_err();
+
+        // Paying more than the debt is rejected
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::RepayNative {
+                token_address: String::from("OSMO"),
+                account_id: None,
+            },
+            &coins(11001, "uosmo"),
+        )
+        .unwrap_err();
+
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::RepayNative {
+                token_address: String::from("OSMO"),
+                account_id: None,
+            },
+            &coins(11000, "uosmo"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            query_balance(
+                &app,
+                &contract,
+                QueryMsg::UserBorrowTokenBalance(osmo_of("user_one"))
+            ),
+            Uint128::zero()
+        );
+        assert_eq!(
+            query_balance(
+                &app,
+                &contract,
+                QueryMsg::UserBorrowInterest(osmo_of("user_one"))
+            ),
+            Uint128::zero()
+        );
+        assert_eq!(
+            query_balance(
+                &app,
+                &contract,
+                QueryMsg::UserWrappedTokenBalance(osmo_of("user_one"))
+            ),
+            Uint128::from(10000u128)
+        );
+
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::WithdrawToken(WithdrawData {
+                token_address: Addr::unchecked("OSMO"),
+                token_amount: Uint128::from(1000u128),
+                account_id: None,
+            }),
+            &[],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn top_up_restores_vtokens_for_repay() {
+        let (mut app, contract) = setup_leverage_contract();
+        let user = Addr::unchecked("user_one");
+
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::DepositNative {
+                token_address: String::from("OSMO"),
+                referral_code: None,
+                account_id: None,
+            },
+            &coins(100, "uosmo"),
+        )
+        .unwrap();
+
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::Borrow(TokenData {
+                token_address: Addr::unchecked("OSMO"),
+                token_amount: Uint128::from(1000u128),
+                account_id: None,
+                on_behalf_of: None,
+            }),
+            &[],
+        )
+        .unwrap();
+
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::ExecuteOrder(OrderExecute {
+                order_id: String::from("1"),
+                user_address: user.clone(),
+                token_in: Addr::unchecked("OSMO"),
+                token_out: Addr::unchecked("ATOM"),
+                amount_in: Uint128::from(400u128),
+                amount_out: Uint128::from(40u128),
+                account_id: None,
+                min_out: None,
+            }),
+            &[],
+        )
+        .unwrap();
+
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::TopUpNative {
+                token_address: String::from("OSMO"),
+                account_id: None,
+            },
+            &coins(400, "uosmo"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            query_balance(
+                &app,
+                &contract,
+                QueryMsg::UserVTokenBalance(osmo_of("user_one"))
+            ),
+            Uint128::from(1000u128)
+        );
+
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::Repay(TokenData {
+                token_address: Addr::unchecked("OSMO"),
+                token_amount: Uint128::from(1000u128),
+                account_id: None,
+                on_behalf_of: None,
+            }),
             &[],
-            "leverage_contract",
-            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            query_balance(
+                &app,
+                &contract,
+                QueryMsg::UserBorrowTokenBalance(osmo_of("user_one"))
+            ),
+            Uint128::zero()
         );
+    }
 
-        let contract = contract_addr.unwrap();
+    #[test]
+    fn repay_and_top_up_reject_other_denoms() {
+        let mut scenario = Scenario::new();
+        let user = scenario.funded_user("user_one", OSMO, 110);
+        scenario.fund(&user, ATOM, 100);
+        scenario.deposit(&user, OSMO, 100).unwrap();
+        scenario.borrow(&user, OSMO, 1000).unwrap();
+
+        // ATOM is listed too, but it can't pay for OSMO debt or OSMO vTokens
+        let contract = scenario.contract.clone();
+        for msg in [
+            ExecuteMsg::RepayNative {
+                token_address: String::from(OSMO),
+                account_id: None,
+            },
+            ExecuteMsg::TopUpNative {
+                token_address: String::from(OSMO),
+                account_id: None,
+            },
+        ] {
+            let err = scenario
+                .app
+                .execute_contract(user.clone(), contract.clone(), &msg, &coins(10, ATOM_DENOM))
+                .unwrap_err();
+            assert_eq!(
+                err.root_cause().to_string(),
+                ContractError::InsufficientNativeToken {}.to_string()
+            );
+        }
+        assert_eq!(scenario.borrowed(&user, OSMO), Uint128::from(1000u128));
+        assert_eq!(scenario.vtokens(&user, OSMO), Uint128::from(1000u128));
+        assert_eq!(scenario.wallet_balance(&user, ATOM), Uint128::from(100u128));
+
+        scenario.repay_with_funds(&user, OSMO, 10).unwrap();
+        assert_eq!(scenario.borrowed(&user, OSMO), Uint128::from(990u128));
+    }
 
-        let execute_osmo_deposit = ExecuteMsg::DepositNative { token_address: String::from("OSMO") };
+    #[test]
+    fn fee_collector_only_collects_accrued_revenue() {
+        let mut scenario = Scenario::new();
+        scenario.set_borrow_rate(OSMO, Decimal::percent(10));
+        let user = scenario.funded_user("user_one", OSMO, 200);
+        scenario.deposit(&user, OSMO, 100).unwrap();
+        scenario.borrow(&user, OSMO, 1000).unwrap();
+        scenario.advance_time(SECONDS_PER_YEAR);
+        scenario.repay_with_funds(&user, OSMO, 100).unwrap();
+        assert_eq!(
+            scenario
+                .query::<MarketStateResponse>(&QueryMsg::MarketState {
+                    token_address: Addr::unchecked(OSMO),
+                })
+                .revenue,
+            Uint128::from(100u128)
+        );
 
-        let user_one_execute_send_usdc = app
+        let contract = scenario.contract.clone();
+        let collector = scenario.user("collector");
+        scenario
+            .app
             .execute_contract(
                 Addr::unchecked("creator"),
                 contract.clone(),
-                &execute_osmo_deposit,
-                &[Coin{
-                    denom: String::from("uosmo"),
-                    amount: Uint128::from(100000000u128)
-                }],
+                &ExecuteMsg::GrantRole {
+                    role: Role::FeeCollector,
+                    address: collector.to_string(),
+                },
+                &[],
+            )
+            .unwrap();
+        let collect = |token: &str, amount: u128| {
+            ExecuteMsg::CollectRevenue(WithdrawData {
+                token_address: Addr::unchecked(token),
+                token_amount: Uint128::from(amount),
+                account_id: None,
+            })
+        };
+
+        // Collateral and unlisted tokens are out of reach
+        let err = scenario
+            .app
+            .execute_contract(
+                collector.clone(),
+                contract.clone(),
+                &collect(OSMO, 101),
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::InsufficientBalance {}.to_string()
+        );
+        let err = scenario
+            .app
+            .execute_contract(collector.clone(), contract.clone(), &collect(ATOM, 1), &[])
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::InsufficientBalance {}.to_string()
+        );
+        let err = scenario
+            .app
+            .execute_contract(
+                collector.clone(),
+                contract.clone(),
+                &collect("uosmo", 1),
+                &[],
             )
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::TokenNotListed {
+                token: String::from("uosmo")
+            }
+            .to_string()
+        );
+        scenario
+            .app
+            .execute_contract(user.clone(), contract.clone(), &collect(OSMO, 100), &[])
+            .unwrap_err();
+
+        scenario
+            .app
+            .execute_contract(collector.clone(), contract, &collect(OSMO, 100), &[])
             .unwrap();
+        assert_eq!(
+            scenario.wallet_balance(&collector, OSMO),
+            Uint128::from(100u128)
+        );
+        assert_eq!(
+            scenario
+                .query::<MarketStateResponse>(&QueryMsg::MarketState {
+                    token_address: Addr::unchecked(OSMO),
+                })
+                .revenue,
+            Uint128::zero()
+        );
+        assert_eq!(scenario.collateral(&user, OSMO), Uint128::from(100u128));
+        scenario.assert_invariants();
+    }
 
-        // let execute_usdc_deposit_msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
-        //     sender: String::from("user_one"),
-        //     amount: Uint128::from(1000u128),
-        //     msg: to_json_binary(&{}).unwrap(),
-        // });
+    #[test]
+    fn cw20_send_repays_borrow() {
+        let (mut app, contract) = setup_leverage_contract();
+        let usdc_of_user = QueryTokenData {
+            token_address: Addr::unchecked("USDC"),
+            user_address: Addr::unchecked("user_one"),
+            account_id: None,
+        };
+
+        app.execute_contract(
+            Addr::unchecked("usdc_contract"),
+            contract.clone(),
+            &ExecuteMsg::Receive(Cw20ReceiveMsg {
+                sender: String::from("user_one"),
+                amount: Uint128::from(100u128),
+                msg: to_json_binary(&Cw20HookMsg::Deposit {
+                    referral_code: None,
+                    account_id: None,
+                })
+                .unwrap(),
+            }),
+            &[],
+        )
+        .unwrap();
 
-        // let user_one_execute_send_usdc = app
-        //     .execute_contract(
-        //         Addr::unchecked("usdc_contract"),
-        //         contract.clone(),
-        //         &execute_usdc_deposit_msg,
-        //         &[],
-        //     )
-        //     .unwrap();
+        app.execute_contract(
+            Addr::unchecked("user_one"),
+            contract.clone(),
+            &ExecuteMsg::Borrow(TokenData {
+                token_address: Addr::unchecked("USDC"),
+                token_amount: Uint128::from(600u128),
+                account_id: None,
+                on_behalf_of: None,
+            }),
+            &[],
+        )
+        .unwrap();
 
-        // println!("{:?}", user_one_execute_send_usdc);
+        // Only listed CW20 contracts can repay
+        app.execute_contract(
+            Addr::unchecked("other_contract"),
+            contract.clone(),
+            &ExecuteMsg::Receive(Cw20ReceiveMsg {
+                sender: String::from("user_one"),
+                amount: Uint128::from(600u128),
+                msg: to_json_binary(&Cw20HookMsg::Repay { account_id: None }).unwrap(),
+            }),
+            &[],
+        )
+        .unwrap_err();
 
-        // // --------------------------------------------------------------------------------------
-        let user_query_data = QueryTokenData {
-            token_address: Addr::unchecked("OSMO"),
-            user_address: Addr::unchecked("creator"),
+        app.execute_contract(
+            Addr::unchecked("usdc_contract"),
+            contract.clone(),
+            &ExecuteMsg::Receive(Cw20ReceiveMsg {
+                sender: String::from("user_one"),
+                amount: Uint128::from(600u128),
+                msg: to_json_binary(&Cw20HookMsg::Repay { account_id: None }).unwrap(),
+            }),
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(
+            query_balance(
+                &app,
+                &contract,
+                QueryMsg::UserBorrowTokenBalance(usdc_of_user.clone())
+            ),
+            Uint128::zero()
+        );
+        assert_eq!(
+            query_balance(
+                &app,
+                &contract,
+                QueryMsg::UserWrappedTokenBalance(usdc_of_user.clone())
+            ),
+            Uint128::from(1000u128)
+        );
+        // The borrowed vTokens stay with the user as they were paid for
+        assert_eq!(
+            query_balance(&app, &contract, QueryMsg::UserVTokenBalance(usdc_of_user)),
+            Uint128::from(600u128)
+        );
+    }
+
+    #[test]
+    fn roles_are_granted_by_admins_and_listed() {
+        let (mut app, contract) = setup_leverage_contract();
+
+        // Only the owner hands out the admin role
+        app.execute_contract(
+            Addr::unchecked("user_one"),
+            contract.clone(),
+            &ExecuteMsg::GrantRole {
+                role: Role::Admin,
+                address: String::from("user_one"),
+            },
+            &[],
+        )
+        .unwrap_err();
+
+        app.execute_contract(
+            Addr::unchecked("creator"),
+            contract.clone(),
+            &ExecuteMsg::GrantRole {
+                role: Role::Admin,
+                address: String::from("admin"),
+            },
+            &[],
+        )
+        .unwrap();
+
+        for keeper in ["keeper_two", "keeper_one"] {
+            app.execute_contract(
+                Addr::unchecked("admin"),
+                contract.clone(),
+                &ExecuteMsg::GrantRole {
+                    role: Role::Keeper,
+                    address: String::from(keeper),
+                },
+                &[],
+            )
+            .unwrap();
+        }
+
+        // Admins can't manage other admins
+        app.execute_contract(
+            Addr::unchecked("admin"),
+            contract.clone(),
+            &ExecuteMsg::RevokeRole {
+                role: Role::Admin,
+                address: String::from("admin"),
+            },
+            &[],
+        )
+        .unwrap_err();
+
+        let keepers: RoleMembersResponse = app
+            .wrap()
+            .query_wasm_smart(
+                contract.clone(),
+                &QueryMsg::RoleMembers {
+                    role: Role::Keeper,
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            keepers.members,
+            vec![Addr::unchecked("keeper_one"), Addr::unchecked("keeper_two")]
+        );
+
+        let keepers: RoleMembersResponse = app
+            .wrap()
+            .query_wasm_smart(
+                contract.clone(),
+                &QueryMsg::RoleMembers {
+                    role: Role::Keeper,
+                    start_after: Some(String::from("keeper_one")),
+                    limit: Some(1),
+                },
+            )
+            .unwrap();
+        assert_eq!(keepers.members, vec![Addr::unchecked("keeper_two")]);
+
+        app.execute_contract(
+            Addr::unchecked("admin"),
+            contract.clone(),
+            &ExecuteMsg::RevokeRole {
+                role: Role::Keeper,
+                address: String::from("keeper_one"),
+            },
+            &[],
+        )
+        .unwrap();
+
+        // Listing a token is proposed by an admin
+        app.execute_contract(
+            Addr::unchecked("keeper_two"),
+            contract.clone(),
+            &ExecuteMsg::ProposeAction {
+                action: TimelockAction::ListToken {
+                    token_address: String::from("JUNO"),
+                    asset: AssetInfo::Native {
+                        denom: String::from("ujuno"),
+                    },
+                    decimals: Some(6),
+                    symbol: String::from("JUNO"),
+                },
+            },
+            &[],
+        )
+        .unwrap_err();
+        run_timelocked(
+            &mut app,
+            &contract,
+            "admin",
+            TimelockAction::ListToken {
+                token_address: String::from("JUNO"),
+                asset: AssetInfo::Native {
+                    denom: String::from("ujuno"),
+                },
+                decimals: Some(6),
+                symbol: String::from("JUNO"),
+            },
+        );
+    }
+
+    #[test]
+    fn ownership_transfer_needs_acceptance() {
+        let (mut app, contract) = setup_leverage_contract();
+
+        app.execute_contract(
+            Addr::unchecked("user_one"),
+            contract.clone(),
+            &ExecuteMsg::ProposeOwnership {
+                new_owner: String::from("user_one"),
+            },
+            &[],
+        )
+        .unwrap_err();
+
+        app.execute_contract(
+            Addr::unchecked("creator"),
+            contract.clone(),
+            &ExecuteMsg::ProposeOwnership {
+                new_owner: String::from("new_owner"),
+            },
+            &[],
+        )
+        .unwrap();
+
+        // Proposing alone doesn't move any permission
+        app.execute_contract(
+            Addr::unchecked("new_owner"),
+            contract.clone(),
+            &ExecuteMsg::GrantRole {
+                role: Role::Admin,
+                address: String::from("new_owner"),
+            },
+            &[],
+        )
+        .unwrap_err();
+
+        app.execute_contract(
+            Addr::unchecked("user_one"),
+            contract.clone(),
+            &ExecuteMsg::AcceptOwnership {},
+            &[],
+        )
+        .unwrap_err();
+
+        app.execute_contract(
+            Addr::unchecked("new_owner"),
+            contract.clone(),
+            &ExecuteMsg::AcceptOwnership {},
+            &[],
+        )
+        .unwrap();
+
+        app.execute_contract(
+            Addr::unchecked("creator"),
+            contract.clone(),
+            &ExecuteMsg::Pause {},
+            &[],
+        )
+        .unwrap_err();
+        app.execute_contract(
+            Addr::unchecked("new_owner"),
+            contract.clone(),
+            &ExecuteMsg::Pause {},
+            &[],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn guardian_pause_blocks_everything_but_repay() {
+        let (mut app, contract) = setup_leverage_contract();
+        let user = Addr::unchecked("user_one");
+
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::DepositNative {
+                token_address: String::from("OSMO"),
+                referral_code: None,
+                account_id: None,
+            },
+            &coins(100, "uosmo"),
+        )
+        .unwrap();
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::Borrow(TokenData {
+                token_address: Addr::unchecked("OSMO"),
+                token_amount: Uint128::from(500u128),
+                account_id: None,
+                on_behalf_of: None,
+            }),
+            &[],
+        )
+        .unwrap();
+
+        app.execute_contract(
+            Addr::unchecked("creator"),
+            contract.clone(),
+            &ExecuteMsg::GrantRole {
+                role: Role::Guardian,
+                address: String::from("guardian"),
+            },
+            &[],
+        )
+        .unwrap();
+        app.execute_contract(
+            Addr::unchecked("guardian"),
+            contract.clone(),
+            &ExecuteMsg::Pause {},
+            &[],
+        )
+        .unwrap();
+
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::Borrow(TokenData {
+                token_address: Addr::unchecked("OSMO"),
+                token_amount: Uint128::from(100u128),
+                account_id: None,
+                on_behalf_of: None,
+            }),
+            &[],
+        )
+        .unwrap_err();
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::Repay(TokenData {
+                token_address: Addr::unchecked("OSMO"),
+                token_amount: Uint128::from(500u128),
+                account_id: None,
+                on_behalf_of: None,
+            }),
+            &[],
+        )
+        .unwrap();
+
+        // Guardians can't resume the protocol
+        app.execute_contract(
+            Addr::unchecked("guardian"),
+            contract.clone(),
+            &ExecuteMsg::Unpause {},
+            &[],
+        )
+        .unwrap_err();
+        app.execute_contract(
+            Addr::unchecked("creator"),
+            contract.clone(),
+            &ExecuteMsg::Unpause {},
+            &[],
+        )
+        .unwrap();
+
+        app.execute_contract(
+            user,
+            contract,
+            &ExecuteMsg::Borrow(TokenData {
+                token_address: Addr::unchecked("OSMO"),
+                token_amount: Uint128::from(100u128),
+                account_id: None,
+                on_behalf_of: None,
+            }),
+            &[],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn orders_of_other_users_need_keeper_role() {
+        let (mut app, contract) = setup_leverage_contract();
+        let user = Addr::unchecked("user_one");
+
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::DepositNative {
+                token_address: String::from("OSMO"),
+                referral_code: None,
+                account_id: None,
+            },
+            &coins(100, "uosmo"),
+        )
+        .unwrap();
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::Borrow(TokenData {
+                token_address: Addr::unchecked("OSMO"),
+                token_amount: Uint128::from(500u128),
+                account_id: None,
+                on_behalf_of: None,
+            }),
+            &[],
+        )
+        .unwrap();
+
+        let order = ExecuteMsg::ExecuteOrder(OrderExecute {
+            order_id: String::from("1"),
+            user_address: user,
+            token_in: Addr::unchecked("OSMO"),
+            token_out: Addr::unchecked("ATOM"),
+            amount_in: Uint128::from(100u128),
+            amount_out: Uint128::from(10u128),
+            account_id: None,
+            min_out: None,
+        });
+
+        app.execute_contract(Addr::unchecked("keeper"), contract.clone(), &order, &[])
+            .unwrap_err();
+
+        app.execute_contract(
+            Addr::unchecked("creator"),
+            contract.clone(),
+            &ExecuteMsg::GrantRole {
+                role: Role::Keeper,
+                address: String::from("keeper"),
+            },
+            &[],
+        )
+        .unwrap();
+
+        app.execute_contract(Addr::unchecked("keeper"), contract, &order, &[])
+            .unwrap();
+    }
+
+    #[test]
+    fn timelocked_actions_wait_for_the_delay() {
+        let (mut app, contract) = setup_leverage_contract();
+        let rate_change = TimelockAction::UpdateBorrowRate {
+            token_address: String::from("OSMO"),
+            rate: Decimal::percent(50),
+        };
+
+        app.execute_contract(
+            Addr::unchecked("creator"),
+            contract.clone(),
+            &ExecuteMsg::GrantRole {
+                role: Role::Guardian,
+                address: String::from("guardian"),
+            },
+            &[],
+        )
+        .unwrap();
+
+        let ids: Vec<u64> = (0..2)
+            .map(|_| {
+                let res = app
+                    .execute_contract(
+                        Addr::unchecked("creator"),
+                        contract.clone(),
+                        &ExecuteMsg::ProposeAction {
+                            action: rate_change.clone(),
+                        },
+                        &[],
+                    )
+                    .unwrap();
+                proposed_action_id(&res)
+            })
+            .collect();
+
+        let pending: PendingActionsResponse = app
+            .wrap()
+            .query_wasm_smart(
+                contract.clone(),
+                &QueryMsg::PendingActions {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(pending.delay, DEFAULT_TIMELOCK_DELAY);
+        assert_eq!(pending.actions.len(), 2);
+        assert_eq!(
+            pending.actions[1].eta,
+            app.block_info().time.plus_seconds(DEFAULT_TIMELOCK_DELAY)
+        );
+
+        app.execute_contract(
+            Addr::unchecked("creator"),
+            contract.clone(),
+            &ExecuteMsg::ExecuteAction { id: ids[0] },
+            &[],
+        )
+        .unwrap_err();
+
+        // Users can't veto, guardians can
+        app.execute_contract(
+            Addr::unchecked("user_one"),
+            contract.clone(),
+            &ExecuteMsg::CancelAction { id: ids[1] },
+            &[],
+        )
+        .unwrap_err();
+        app.execute_contract(
+            Addr::unchecked("guardian"),
+            contract.clone(),
+            &ExecuteMsg::CancelAction { id: ids[1] },
+            &[],
+        )
+        .unwrap();
+
+        app.update_block(|block| block.time = block.time.plus_seconds(DEFAULT_TIMELOCK_DELAY));
+
+        let pending_action: PendingAction = app
+            .wrap()
+            .query_wasm_smart(contract.clone(), &QueryMsg::PendingAction { id: ids[0] })
+            .unwrap();
+        assert_eq!(pending_action.action, rate_change);
+
+        app.execute_contract(
+            Addr::unchecked("user_one"),
+            contract.clone(),
+            &ExecuteMsg::ExecuteAction { id: ids[1] },
+            &[],
+        )
+        .unwrap_err();
+        app.execute_contract(
+            Addr::unchecked("user_one"),
+            contract.clone(),
+            &ExecuteMsg::ExecuteAction { id: ids[0] },
+            &[],
+        )
+        .unwrap();
+
+        let pending: PendingActionsResponse = app
+            .wrap()
+            .query_wasm_smart(
+                contract,
+                &QueryMsg::PendingActions {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        assert!(pending.actions.is_empty());
+    }
+
+    #[test]
+    fn timelock_delay_has_a_lower_bound() {
+        let (mut app, contract) = setup_leverage_contract();
+        let propose_delay = |delay: u64| ExecuteMsg::ProposeAction {
+            action: TimelockAction::UpdateTimelockDelay { delay },
         };
 
-        let res_query_user_collateral_token_balance: Uint128 = app
+        let err = app
+            .execute_contract(
+                Addr::unchecked("creator"),
+                contract.clone(),
+                &propose_delay(MIN_TIMELOCK_DELAY - 1),
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::TimelockDelayTooShort {
+                min: MIN_TIMELOCK_DELAY
+            }
+            .to_string()
+        );
+
+        run_timelocked(
+            &mut app,
+            &contract,
+            "creator",
+            TimelockAction::UpdateTimelockDelay {
+                delay: MIN_TIMELOCK_DELAY,
+            },
+        );
+        let res = app
+            .execute_contract(
+                Addr::unchecked("creator"),
+                contract.clone(),
+                &propose_delay(MIN_TIMELOCK_DELAY),
+                &[],
+            )
+            .unwrap();
+        let id = proposed_action_id(&res);
+        let pending_action: PendingAction = app
+            .wrap()
+            .query_wasm_smart(contract.clone(), &QueryMsg::PendingAction { id })
+            .unwrap();
+        assert_eq!(
+            pending_action.eta,
+            app.block_info().time.plus_seconds(MIN_TIMELOCK_DELAY)
+        );
+
+        // Nor can the timelock be skipped from the start
+        let code_id = app.store_code(Box::new(ContractWrapper::new(execute, instantiate, query)));
+        let err = app
+            .instantiate_contract(
+                code_id,
+                Addr::unchecked("creator"),
+                &InstantiateMsg {
+                    owner: None,
+                    tokens: vec![],
+                    fees: None,
+                    timelock_delay: Some(0),
+                },
+                &[],
+                "leverage_contract",
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::TimelockDelayTooShort {
+                min: MIN_TIMELOCK_DELAY
+            }
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn listed_tokens_carry_metadata_and_status() {
+        let (mut app, contract) = setup_leverage_contract();
+        let user = Addr::unchecked("user_one");
+
+        let listed: ListedTokensResponse = app
             .wrap()
             .query_wasm_smart(
                 contract.clone(),
-                &QueryMsg::UserCollateralTokenBalance(user_query_data.clone()),
-            )
-            .unwrap();
-
-        println!("collateral: {}", res_query_user_collateral_token_balance)
-
-        // let res_query_user_wrapped_token_balance: Uint128 = app
-        //     .wrap()
-        //     .query_wasm_smart(
-        //         contract.clone(),
-        //         &QueryMsg::UserWrappedTokenBalance(user_query_data.clone()),
-        //     )
-        //     .unwrap();
-
-        // println!(
-        //     "res_query_user_collateral_token_balance: {:?}",
-        //     res_query_user_collateral_token_balance
-        // );
-        // println!(
-        //     "res_query_user_wrapped_token_balance: {:?}",
-        //     res_query_user_wrapped_token_balance
-        // );
-
-        // // --------------------------------------------------------------------------------------
-
-        // let user_borrow_token_data = TokenData {
-        //     token_address: Addr::unchecked("usdc_contract"),
-        //     token_amount: Uint128::from(5000u128),
-        // };
-        // let borrow_usdc_exe_msg = ExecuteMsg::Borrow(user_borrow_token_data.clone());
-        // let borrow_usdc_exe = app
-        //     .execute_contract(
-        //         Addr::unchecked("user_one"),
-        //         contract.clone(),
-        //         &borrow_usdc_exe_msg,
-        //         &[],
-        //     )
-        //     .unwrap();
-
-        // println!("{:?}", borrow_usdc_exe);
-
-        // // --------------------------------------------------------------------------------------
-
-        // let res_query_user_wrapped_token_balance: Uint128 = app
-        //     .wrap()
-        //     .query_wasm_smart(
-        //         contract.clone(),
-        //         &QueryMsg::UserWrappedTokenBalance(user_query_data.clone()),
-        //     )
-        //     .unwrap();
-
-        // let res_query_user_borrow_token_balance: Uint128 = app
-        //     .wrap()
-        //     .query_wasm_smart(
-        //         contract.clone(),
-        //         &QueryMsg::UserBorrowTokenBalance(user_query_data.clone()),
-        //     )
-        //     .unwrap();
-
-        // let res_query_user_v_token_balance: Uint128 = app
-        //     .wrap()
-        //     .query_wasm_smart(
-        //         contract.clone(),
-        //         &QueryMsg::UserVTokenBalance(user_query_data.clone()),
-        //     )
-        //     .unwrap();
-
-        // println!(
-        //     "res_query_user_wrapped_token_balance: {:?}",
-        //     res_query_user_wrapped_token_balance
-        // );
-        // println!(
-        //     "res_query_user_borrow_token_balance: {:?}",
-        //     res_query_user_borrow_token_balance
-        // );
-        // println!(
-        //     "res_query_user_v_token_balance: {:?}",
-        //     res_query_user_v_token_balance
-        // );
-
-        // --------------------------------------------------------------------------------------
-
-        // let order = OrderExecute {
-        //     order_id: String::from("id"),
-        //     user_address: Addr::unchecked("user_one"),
-        //     token_in: Addr::unchecked("usdc_contract"),
-        //     amount_in: Uint128::from(1000u128),
-        //     token_out: Addr::unchecked("osmo"),
-        //     amount_out: Uint128::from(100u128),
-        // };
-
-        // let exchange_execute_msg = ExecuteMsg::ExecuteOrder(order);
-        // let exchange_order_exe = app.execute_contract(
-        //     Addr::unchecked("user_one"),
-        //     contract.clone(),
-        //     &exchange_execute_msg,
-        //     &[],
-        // );
-
-        // println!("{:?}", exchange_order_exe);
-
-        // -------------------------------------------------------------------------------------
-
-        // let res_query_user_v_token_balance: Uint128 = app
-        // .wrap()
-        // .query_wasm_smart(
-        //     contract.clone(),
-        //     &QueryMsg::UserVTokenBalance(user_query_data.clone()),
-        // )
-        // .unwrap();
-
-        // let user_query_for_native = QueryTokenData {
-        //     token_address: Addr::unchecked("osmo"),
-        //     user_address: Addr::unchecked("user_one")
-        // };
-
-        // let res_query_user_native_token_balance: Uint128 = app
-        // .wrap()
-        // .query_wasm_smart(
-        //     contract.clone(),
-        //     &QueryMsg::UserVTokenBalance(user_query_for_native.clone()),
-        // )
-        // .unwrap();
-
-
-        // println!("res_query_user_v_token_balance: {}", res_query_user_v_token_balance);
-        // println!("res_query_user_native_token_balance: {}", res_query_user_native_token_balance);
+                &QueryMsg::ListedTokens {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        let symbols: Vec<String> = listed.tokens.iter().map(|t| t.symbol.clone()).collect();
+        assert_eq!(symbols, vec!["ATOM", "OSMO", "USDC"]);
+        assert_eq!(
+            listed.tokens[2].asset,
+            AssetInfo::Cw20 {
+                contract_addr: Addr::unchecked("usdc_contract")
+            }
+        );
+
+        let listed: ListedTokensResponse = app
+            .wrap()
+            .query_wasm_smart(
+                contract.clone(),
+                &QueryMsg::ListedTokens {
+                    start_after: Some(String::from("ATOM")),
+                    limit: Some(1),
+                },
+            )
+            .unwrap();
+        assert_eq!(listed.tokens.len(), 1);
+        assert_eq!(listed.tokens[0].token, "OSMO");
+
+        // Listing the same denom twice fails when it's proposed
+        let list_juno = |token: &str| ExecuteMsg::ProposeAction {
+            action: TimelockAction::ListToken {
+                token_address: String::from(token),
+                asset: AssetInfo::Native {
+                    denom: String::from("ujuno"),
+                },
+                decimals: Some(6),
+                symbol: String::from("JUNO"),
+            },
+        };
+        let err = app
+            .execute_contract(
+                Addr::unchecked("creator"),
+                contract.clone(),
+                &ExecuteMsg::ProposeAction {
+                    action: TimelockAction::ListToken {
+                        token_address: String::from("OSMO2"),
+                        asset: AssetInfo::Native {
+                            denom: String::from("uosmo"),
+                        },
+                        decimals: Some(6),
+                        symbol: String::from("OSMO"),
+                    },
+                },
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::TokenAlreadyListed {
+                token: String::from("uosmo")
+            }
+            .to_string()
+        );
+
+        // And again when it executes, if another listing got there first
+        let ids = ["JUNO", "JUNO2"].map(|token| {
+            let res = app
+                .execute_contract(
+                    Addr::unchecked("creator"),
+                    contract.clone(),
+                    &list_juno(token),
+                    &[],
+                )
+                .unwrap();
+            proposed_action_id(&res)
+        });
+        app.update_block(|block| block.time = block.time.plus_seconds(DEFAULT_TIMELOCK_DELAY));
+        app.execute_contract(
+            Addr::unchecked("creator"),
+            contract.clone(),
+            &ExecuteMsg::ExecuteAction { id: ids[0] },
+            &[],
+        )
+        .unwrap();
+        let err = app
+            .execute_contract(
+                Addr::unchecked("creator"),
+                contract.clone(),
+                &ExecuteMsg::ExecuteAction { id: ids[1] },
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::TokenAlreadyListed {
+                token: String::from("ujuno")
+            }
+            .to_string()
+        );
+
+        // Deposits must send the listed denom
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::DepositNative {
+                token_address: String::from("ATOM"),
+                referral_code: None,
+                account_id: None,
+            },
+            &coins(100, "uosmo"),
+        )
+        .unwrap_err();
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::DepositNative {
+                token_address: String::from("OSMO"),
+                referral_code: None,
+                account_id: None,
+            },
+            &coins(100, "uosmo"),
+        )
+        .unwrap();
+
+        app.execute_contract(
+            Addr::unchecked("creator"),
+            contract.clone(),
+            &ExecuteMsg::UpdateTokenStatus {
+                token_address: String::from("OSMO"),
+                status: TokenStatus::ReduceOnly,
+            },
+            &[],
+        )
+        .unwrap();
+
+        let osmo: TokenInfo = app
+            .wrap()
+            .query_wasm_smart(
+                contract.clone(),
+                &QueryMsg::ListedToken {
+                    token_address: Addr::unchecked("OSMO"),
+                },
+            )
+            .unwrap();
+        assert_eq!(osmo.status, TokenStatus::ReduceOnly);
+
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::Borrow(TokenData {
+                token_address: Addr::unchecked("OSMO"),
+                token_amount: Uint128::from(100u128),
+                account_id: None,
+                on_behalf_of: None,
+            }),
+            &[],
+        )
+        .unwrap_err();
+
+        app.execute_contract(
+            Addr::unchecked("user_one"),
+            contract.clone(),
+            &ExecuteMsg::DelistToken {
+                token_address: String::from("OSMO"),
+            },
+            &[],
+        )
+        .unwrap_err();
+        app.execute_contract(
+            Addr::unchecked("creator"),
+            contract.clone(),
+            &ExecuteMsg::DelistToken {
+                token_address: String::from("OSMO"),
+            },
+            &[],
+        )
+        .unwrap();
+
+        // A delisted token can't be reactivated
+        app.execute_contract(
+            Addr::unchecked("creator"),
+            contract.clone(),
+            &ExecuteMsg::UpdateTokenStatus {
+                token_address: String::from("OSMO"),
+                status: TokenStatus::Active,
+            },
+            &[],
+        )
+        .unwrap_err();
+
+        // Users can still leave a delisted token
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::WithdrawToken(WithdrawData {
+                token_address: Addr::unchecked("OSMO"),
+                token_amount: Uint128::from(100u128),
+                account_id: None,
+            }),
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            app.wrap().query_balance(user, "uosmo").unwrap().amount,
+            Uint128::from(10000000000u128)
+        );
+    }
+
+    #[test]
+    fn mixed_decimal_tokens_are_valued_on_one_scale() {
+        let (mut app, contract) = setup_leverage_contract();
+        let user = Addr::unchecked("user_one");
+        let one_weth = 1_000_000_000_000_000_000u128;
+
+        app.init_modules(|router, _, storage| {
+            router
+                .bank
+                .init_balance(storage, &user, coins(one_weth, "wei"))
+                .unwrap();
+        });
+
+        run_timelocked(
+            &mut app,
+            &contract,
+            "creator",
+            TimelockAction::ListToken {
+                token_address: String::from("WETH"),
+                asset: AssetInfo::Native {
+                    denom: String::from("wei"),
+                },
+                decimals: Some(18),
+                symbol: String::from("WETH"),
+            },
+        );
+        set_price(
+            &mut app,
+            &contract,
+            "WETH",
+            Decimal::from_ratio(2000u128, 1u128),
+        );
+
+        // Native tokens can't be queried for their decimals
+        let err = app
+            .execute_contract(
+                Addr::unchecked("creator"),
+                contract.clone(),
+                &ExecuteMsg::ProposeAction {
+                    action: TimelockAction::ListToken {
+                        token_address: String::from("STARS"),
+                        asset: AssetInfo::Native {
+                            denom: String::from("ustars"),
+                        },
+                        decimals: None,
+                        symbol: String::from("STARS"),
+                    },
+                },
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::MissingDecimals {
+                token: String::from("STARS")
+            }
+            .to_string()
+        );
+
+        // Nor can they have more decimals than valuations can scale
+        let err = app
+            .execute_contract(
+                Addr::unchecked("creator"),
+                contract.clone(),
+                &ExecuteMsg::ProposeAction {
+                    action: TimelockAction::ListToken {
+                        token_address: String::from("STARS"),
+                        asset: AssetInfo::Native {
+                            denom: String::from("ustars"),
+                        },
+                        decimals: Some(19),
+                        symbol: String::from("STARS"),
+                    },
+                },
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::DecimalsTooLarge { max: 18 }.to_string()
+        );
+
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::DepositNative {
+                token_address: String::from("WETH"),
+                referral_code: None,
+                account_id: None,
+            },
+            &coins(one_weth, "wei"),
+        )
+        .unwrap();
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::Borrow(TokenData {
+                token_address: Addr::unchecked("WETH"),
+                token_amount: Uint128::from(one_weth),
+                account_id: None,
+                on_behalf_of: None,
+            }),
+            &[],
+        )
+        .unwrap();
+
+        // 0.1 WETH is worth 200 USDC, not 10^17 raw USDC units
+        let order = |amount_out: u128| {
+            ExecuteMsg::ExecuteOrder(OrderExecute {
+                order_id: String::from("1"),
+                user_address: user.clone(),
+                token_in: Addr::unchecked("WETH"),
+                token_out: Addr::unchecked("USDC"),
+                amount_in: Uint128::from(one_weth / 10),
+                amount_out: Uint128::from(amount_out),
+                account_id: None,
+                min_out: None,
+            })
+        };
+        app.execute_contract(user.clone(), contract.clone(), &order(one_weth / 10), &[])
+            .unwrap_err();
+        app.execute_contract(user.clone(), contract.clone(), &order(200_000_000), &[])
+            .unwrap();
+
+        let health: AccountHealthResponse = app
+            .wrap()
+            .query_wasm_smart(
+                contract.clone(),
+                &QueryMsg::AccountHealth {
+                    user_address: user.clone(),
+                    account_id: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            health.collateral_value,
+            Decimal256::from_ratio(2000u128, 1u128)
+        );
+        assert_eq!(
+            health.position_value,
+            Decimal256::from_ratio(2000u128, 1u128)
+        );
+        assert_eq!(health.debt_value, Decimal256::from_ratio(2000u128, 1u128));
+        assert_eq!(
+            health.health_factor,
+            Some(Decimal256::from_ratio(4000u128, 2100u128))
+        );
+        assert_eq!(health.leverage, Some(Decimal256::one()));
+
+        let price: PriceResponse = app
+            .wrap()
+            .query_wasm_smart(
+                contract,
+                &QueryMsg::Price {
+                    token_address: Addr::unchecked("WETH"),
+                },
+            )
+            .unwrap();
+        assert_eq!(price.price, Decimal::from_ratio(2000u128, 1u128));
+    }
+
+    #[test]
+    fn user_account_sums_up_all_listed_tokens() {
+        let (mut app, contract) = setup_leverage_contract();
+        let user = Addr::unchecked("user_one");
+
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::DepositNative {
+                token_address: String::from("OSMO"),
+                referral_code: None,
+                account_id: None,
+            },
+            &coins(1000, "uosmo"),
+        )
+        .unwrap();
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::Borrow(TokenData {
+                token_address: Addr::unchecked("OSMO"),
+                token_amount: Uint128::from(2000u128),
+                account_id: None,
+                on_behalf_of: None,
+            }),
+            &[],
+        )
+        .unwrap();
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::ExecuteOrder(OrderExecute {
+                order_id: String::from("1"),
+                user_address: user.clone(),
+                token_in: Addr::unchecked("OSMO"),
+                token_out: Addr::unchecked("ATOM"),
+                amount_in: Uint128::from(1000u128),
+                amount_out: Uint128::from(100u128),
+                account_id: None,
+                min_out: None,
+            }),
+            &[],
+        )
+        .unwrap();
+
+        let account: UserAccountResponse = app
+            .wrap()
+            .query_wasm_smart(
+                contract,
+                &QueryMsg::UserAccount {
+                    user_address: user.clone(),
+                    account_id: None,
+                },
+            )
+            .unwrap();
+
+        assert_eq!(account.user_address, user);
+        let tokens: Vec<&str> = account
+            .balances
+            .iter()
+            .map(|balance| balance.token.as_str())
+            .collect();
+        assert_eq!(tokens, vec!["ATOM", "OSMO", "USDC"]);
+
+        let atom = &account.balances[0];
+        assert_eq!(atom.vtokens, Uint128::from(100u128));
+        assert_eq!(atom.position_value, Decimal256::from_ratio(1u128, 1000u128));
+
+        let osmo = &account.balances[1];
+        assert_eq!(osmo.collateral, Uint128::from(1000u128));
+        assert_eq!(osmo.wrapped, Uint128::from(8000u128));
+        assert_eq!(osmo.borrowed, Uint128::from(2000u128));
+        assert_eq!(osmo.vtokens, Uint128::from(1000u128));
+
+        let usdc = &account.balances[2];
+        assert_eq!(usdc.collateral, Uint128::zero());
+        assert_eq!(usdc.debt_value, Decimal256::zero());
+
+        // 1000 collateral and 2000 in positions against 2000 of debt, all in uosmo
+        assert_eq!(
+            account.health.collateral_value,
+            Decimal256::from_ratio(1u128, 1000u128)
+        );
+        assert_eq!(
+            account.health.position_value,
+            Decimal256::from_ratio(2u128, 1000u128)
+        );
+        assert_eq!(
+            account.health.debt_value,
+            Decimal256::from_ratio(2u128, 1000u128)
+        );
+        assert_eq!(
+            account.health.health_factor,
+            Some(Decimal256::from_ratio(3000u128, 2100u128))
+        );
+        assert_eq!(account.health.leverage, Some(Decimal256::percent(200)));
+        assert_eq!(account.open_orders, 0);
+    }
+
+    #[test]
+    fn market_state_tracks_totals_and_caps() {
+        let (mut app, contract) = setup_leverage_contract();
+        let user = Addr::unchecked("user_one");
+
+        run_timelocked(
+            &mut app,
+            &contract,
+            "creator",
+            TimelockAction::UpdateMarketCaps {
+                token_address: String::from("OSMO"),
+                caps: MarketCaps {
+                    deposit_cap: Some(Uint128::from(1500u128)),
+                    borrow_cap: Some(Uint128::from(5000u128)),
+                },
+            },
+        );
+
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::DepositNative {
+                token_address: String::from("OSMO"),
+                referral_code: None,
+                account_id: None,
+            },
+            &coins(1000, "uosmo"),
+        )
+        .unwrap();
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::DepositNative {
+                token_address: String::from("OSMO"),
+                referral_code: None,
+                account_id: None,
+            },
+            &coins(1000, "uosmo"),
+        )
+        .unwrap_err();
+
+        let borrow = |amount: u128| {
+            ExecuteMsg::Borrow(TokenData {
+                token_address: Addr::unchecked("OSMO"),
+                token_amount: Uint128::from(amount),
+                account_id: None,
+                on_behalf_of: None,
+            })
+        };
+        app.execute_contract(user.clone(), contract.clone(), &borrow(6000), &[])
+            .unwrap_err();
+        app.execute_contract(user.clone(), contract.clone(), &borrow(4000), &[])
+            .unwrap();
+
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::ExecuteOrder(OrderExecute {
+                order_id: String::from("1"),
+                user_address: user.clone(),
+                token_in: Addr::unchecked("OSMO"),
+                token_out: Addr::unchecked("ATOM"),
+                amount_in: Uint128::from(1000u128),
+                amount_out: Uint128::from(100u128),
+                account_id: None,
+                min_out: None,
+            }),
+            &[],
+        )
+        .unwrap();
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::Repay(TokenData {
+                token_address: Addr::unchecked("OSMO"),
+                token_amount: Uint128::from(1000u128),
+                account_id: None,
+                on_behalf_of: None,
+            }),
+            &[],
+        )
+        .unwrap();
+
+        let osmo: MarketStateResponse = app
+            .wrap()
+            .query_wasm_smart(
+                contract.clone(),
+                &QueryMsg::MarketState {
+                    token_address: Addr::unchecked("OSMO"),
+                },
+            )
+            .unwrap();
+        assert_eq!(osmo.total_collateral, Uint128::from(1000u128));
+        assert_eq!(osmo.total_wrapped, Uint128::from(7000u128));
+        assert_eq!(osmo.total_borrowed, Uint128::from(3000u128));
+        assert_eq!(osmo.total_vtokens, Uint128::from(2000u128));
+        assert_eq!(osmo.utilization, Decimal::percent(30));
+        assert_eq!(osmo.deposit_cap, Some(Uint128::from(1500u128)));
+        assert_eq!(osmo.borrow_cap, Some(Uint128::from(5000u128)));
+
+        let markets: MarketsResponse = app
+            .wrap()
+            .query_wasm_smart(
+                contract,
+                &QueryMsg::AllMarkets {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(markets.markets.len(), 3);
+        assert_eq!(markets.markets[0].token, "ATOM");
+        assert_eq!(markets.markets[0].total_vtokens, Uint128::from(100u128));
+        assert_eq!(markets.markets[0].utilization, Decimal::zero());
+        assert_eq!(markets.markets[1], osmo);
+    }
+
+    #[test]
+    fn keepers_find_and_liquidate_unhealthy_accounts() {
+        let (mut app, contract) = setup_leverage_contract();
+        let user = Addr::unchecked("user_one");
+        let liquidator = Addr::unchecked("liquidator");
+
+        app.init_modules(|router, _, storage| {
+            router
+                .bank
+                .init_balance(storage, &liquidator, coins(10000, "uosmo"))
+                .unwrap();
+        });
+
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::DepositNative {
+                token_address: String::from("OSMO"),
+                referral_code: None,
+                account_id: None,
+            },
+            &coins(1000, "uosmo"),
+        )
+        .unwrap();
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::Borrow(TokenData {
+                token_address: Addr::unchecked("OSMO"),
+                token_amount: Uint128::from(10000u128),
+                account_id: None,
+                on_behalf_of: None,
+            }),
+            &[],
+        )
+        .unwrap();
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::ExecuteOrder(OrderExecute {
+                order_id: String::from("1"),
+                user_address: user.clone(),
+                token_in: Addr::unchecked("OSMO"),
+                token_out: Addr::unchecked("ATOM"),
+                amount_in: Uint128::from(10000u128),
+                amount_out: Uint128::from(1000u128),
+                account_id: None,
+                min_out: None,
+            }),
+            &[],
+        )
+        .unwrap();
+
+        let liquidate = |amount: u128| {
+            (
+                ExecuteMsg::LiquidateNative {
+                    token_address: String::from("OSMO"),
+                    user_address: String::from("user_one"),
+                    collateral_token: String::from("OSMO"),
+                    account_id: None,
+                },
+                coins(amount, "uosmo"),
+            )
+        };
+        let query_accounts = |app: &App| -> LiquidatableAccountsResponse {
+            app.wrap()
+                .query_wasm_smart(
+                    contract.clone(),
+                    &QueryMsg::LiquidatableAccounts {
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap()
+        };
+
+        assert_eq!(query_accounts(&app).accounts, vec![]);
+        let (msg, funds) = liquidate(100);
+        app.execute_contract(liquidator.clone(), contract.clone(), &msg, &funds)
+            .unwrap_err();
+
+        set_price(&mut app, &contract, "ATOM", Decimal::percent(500));
+
+        // 1000 collateral pays the liquidator for at most 952 of debt, the
+        // rounding dust goes along
+        let accounts = query_accounts(&app);
+        assert_eq!(accounts.next_start_after, None);
+        assert_eq!(
+            accounts.accounts,
+            vec![LiquidatableAccount {
+                user_address: user.clone(),
+                health_factor: Decimal256::from_ratio(6000u128, 10500u128),
+                debt_token: String::from("OSMO"),
+                max_repayable: Uint128::from(952u128),
+                collateral_token: String::from("OSMO"),
+                expected_seized: Uint128::from(1000u128),
+                account_id: 0,
+            }]
+        );
+
+        let (msg, funds) = liquidate(5001);
+        app.execute_contract(liquidator.clone(), contract.clone(), &msg, &funds)
+            .unwrap_err();
+
+        let (msg, funds) = liquidate(952);
+        app.execute_contract(liquidator.clone(), contract.clone(), &msg, &funds)
+            .unwrap();
+
+        assert_eq!(
+            app.wrap()
+                .query_balance(&liquidator, "uosmo")
+                .unwrap()
+                .amount,
+            Uint128::from(10000u128 - 952 + 1000)
+        );
+        assert_eq!(
+            query_balance(
+                &app,
+                &contract,
+                QueryMsg::UserCollateralTokenBalance(osmo_of("user_one"))
+            ),
+            Uint128::zero()
+        );
+        // Without collateral left the account is settled with its ATOM
+        // vTokens, what they don't cover is the deficit
+        assert_eq!(
+            query_balance(
+                &app,
+                &contract,
+                QueryMsg::UserBorrowTokenBalance(osmo_of("user_one"))
+            ),
+            Uint128::zero()
+        );
+    }
+
+    #[test]
+    fn simulations_preview_actions_without_changing_state() {
+        let (mut app, contract) = setup_leverage_contract();
+        let user = Addr::unchecked("user_one");
+
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::DepositNative {
+                token_address: String::from("OSMO"),
+                referral_code: None,
+                account_id: None,
+            },
+            &coins(1000, "uosmo"),
+        )
+        .unwrap();
+
+        let simulated: SimulationResponse = app
+            .wrap()
+            .query_wasm_smart(
+                contract.clone(),
+                &QueryMsg::SimulateBorrow {
+                    user_address: user.clone(),
+                    token_data: TokenData {
+                        token_address: Addr::unchecked("OSMO"),
+                        token_amount: Uint128::from(4000u128),
+                        account_id: None,
+                        on_behalf_of: None,
+                    },
+                },
+            )
+            .unwrap();
+        let osmo = simulated
+            .balances
+            .iter()
+            .find(|balance| balance.token == "OSMO")
+            .unwrap();
+        assert_eq!(osmo.borrowed, Uint128::from(4000u128));
+        assert_eq!(osmo.vtokens, Uint128::from(4000u128));
+        assert_eq!(osmo.wrapped, Uint128::from(6000u128));
+        assert_eq!(
+            simulated.health.health_factor,
+            Some(Decimal256::from_ratio(5000u128, 4200u128))
+        );
+        assert_eq!(simulated.fee, Uint128::zero());
+
+        // Nothing was borrowed for real
+        assert_eq!(
+            query_balance(
+                &app,
+                &contract,
+                QueryMsg::UserBorrowTokenBalance(osmo_of("user_one"))
+            ),
+            Uint128::zero()
+        );
+
+        // Simulations fail with the error of the real call
+        let err = app
+            .wrap()
+            .query_wasm_smart::<SimulationResponse>(
+                contract.clone(),
+                &QueryMsg::SimulateBorrow {
+                    user_address: user.clone(),
+                    token_data: TokenData {
+                        token_address: Addr::unchecked("OSMO"),
+                        token_amount: Uint128::from(20000u128),
+                        account_id: None,
+                        on_behalf_of: None,
+                    },
+                },
+            )
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains(&ContractError::InsufficientUnmintedToken {}.to_string()));
+
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::Borrow(TokenData {
+                token_address: Addr::unchecked("OSMO"),
+                token_amount: Uint128::from(4000u128),
+                account_id: None,
+                on_behalf_of: None,
+            }),
+            &[],
+        )
+        .unwrap();
+
+        let err = app
+            .wrap()
+            .query_wasm_smart::<SimulationResponse>(
+                contract.clone(),
+                &QueryMsg::SimulateOrder(OrderExecute {
+                    order_id: String::from("1"),
+                    user_address: user.clone(),
+                    token_in: Addr::unchecked("OSMO"),
+                    token_out: Addr::unchecked("ATOM"),
+                    amount_in: Uint128::from(1000u128),
+                    amount_out: Uint128::from(200u128),
+                    account_id: None,
+                    min_out: None,
+                }),
+            )
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains(&ContractError::OrderAbovePrice {}.to_string()));
+
+        let err = app
+            .wrap()
+            .query_wasm_smart::<SimulationResponse>(
+                contract.clone(),
+                &QueryMsg::SimulateWithdraw {
+                    user_address: user.clone(),
+                    withdraw_data: WithdrawData {
+                        token_address: Addr::unchecked("OSMO"),
+                        token_amount: Uint128::from(1000u128),
+                        account_id: None,
+                    },
+                },
+            )
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains(&ContractError::BorrowAmountIsNotZero {}.to_string()));
+
+        let simulated: SimulationResponse = app
+            .wrap()
+            .query_wasm_smart(
+                contract.clone(),
+                &QueryMsg::SimulateOrder(OrderExecute {
+                    order_id: String::from("1"),
+                    user_address: user.clone(),
+                    token_in: Addr::unchecked("OSMO"),
+                    token_out: Addr::unchecked("ATOM"),
+                    amount_in: Uint128::from(1000u128),
+                    amount_out: Uint128::from(100u128),
+                    account_id: None,
+                    min_out: None,
+                }),
+            )
+            .unwrap();
+        let atom = simulated
+            .balances
+            .iter()
+            .find(|balance| balance.token == "ATOM")
+            .unwrap();
+        assert_eq!(atom.vtokens, Uint128::from(100u128));
+
+        let err = app
+            .wrap()
+            .query_wasm_smart::<SimulationResponse>(
+                contract.clone(),
+                &QueryMsg::SimulateLiquidation {
+                    liquidator: Addr::unchecked("liquidator"),
+                    user_address: user.clone(),
+                    token_data: TokenData {
+                        token_address: Addr::unchecked("OSMO"),
+                        token_amount: Uint128::from(100u128),
+                        account_id: None,
+                        on_behalf_of: None,
+                    },
+                    collateral_token: Addr::unchecked("OSMO"),
+                    account_id: None,
+                },
+            )
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains(&ContractError::AccountHealthy {}.to_string()));
+
+        let err = app
+            .wrap()
+            .query_wasm_smart::<SimulationResponse>(
+                contract,
+                &QueryMsg::SimulateBurn {
+                    user_address: user,
+                    token_data: TokenData {
+                        token_address: Addr::unchecked("OSMO"),
+                        token_amount: Uint128::from(100u128),
+                        account_id: None,
+                        on_behalf_of: None,
+                    },
+                },
+            )
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains(&ContractError::PayBorrowAmount {}.to_string()));
+    }
+
+    #[test]
+    fn config_reflects_instantiation_and_fee_updates() {
+        let (mut app, contract) = setup_leverage_contract();
+        let user = Addr::unchecked("user_one");
+
+        let config: ConfigResponse = app
+            .wrap()
+            .query_wasm_smart(contract.clone(), &QueryMsg::Config {})
+            .unwrap();
+        assert_eq!(
+            config,
+            ConfigResponse {
+                owner: Addr::unchecked("creator"),
+                pending_owner: None,
+                paused: false,
+                timelock_delay: DEFAULT_TIMELOCK_DELAY,
+                fees: FeeConfig::default(),
+            }
+        );
+
+        let version: cw2::ContractVersion = app
+            .wrap()
+            .query_wasm_smart(contract.clone(), &QueryMsg::ContractVersion {})
+            .unwrap();
+        assert_eq!(version.contract, "crates.io:leverage-contract");
+        assert_eq!(version.version, env!("CARGO_PKG_VERSION"));
+
+        // Fees are capped, even for admins
+        let err = app
+            .execute_contract(
+                Addr::unchecked("creator"),
+                contract.clone(),
+                &ExecuteMsg::ProposeAction {
+                    action: TimelockAction::UpdateFees {
+                        fees: FeeConfig {
+                            borrow_fee: Decimal::percent(11),
+                            trading_fee: Decimal::zero(),
+                            referral_share: Decimal::zero(),
+                        },
+                    },
+                },
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::InvalidFee {}.to_string()
+        );
+
+        let fees = FeeConfig {
+            borrow_fee: Decimal::percent(1),
+            trading_fee: Decimal::percent(2),
+            referral_share: Decimal::zero(),
+        };
+        run_timelocked(
+            &mut app,
+            &contract,
+            "creator",
+            TimelockAction::UpdateFees { fees: fees.clone() },
+        );
+        let config: ConfigResponse = app
+            .wrap()
+            .query_wasm_smart(contract.clone(), &QueryMsg::Config {})
+            .unwrap();
+        assert_eq!(config.fees, fees);
+
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::DepositNative {
+                token_address: String::from("OSMO"),
+                referral_code: None,
+                account_id: None,
+            },
+            &coins(1000, "uosmo"),
+        )
+        .unwrap();
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::Borrow(TokenData {
+                token_address: Addr::unchecked("OSMO"),
+                token_amount: Uint128::from(5000u128),
+                account_id: None,
+                on_behalf_of: None,
+            }),
+            &[],
+        )
+        .unwrap();
+        app.execute_contract(
+            user.clone(),
+            contract.clone(),
+            &ExecuteMsg::ExecuteOrder(OrderExecute {
+                order_id: String::from("1"),
+                user_address: user.clone(),
+                token_in: Addr::unchecked("OSMO"),
+                token_out: Addr::unchecked("ATOM"),
+                amount_in: Uint128::from(1000u128),
+                amount_out: Uint128::from(100u128),
+                account_id: None,
+                min_out: None,
+            }),
+            &[],
+        )
+        .unwrap();
+
+        // The borrow fee is owed like interest, the trading fee is kept from the order
+        assert_eq!(
+            query_balance(
+                &app,
+                &contract,
+                QueryMsg::UserBorrowInterest(osmo_of("user_one"))
+            ),
+            Uint128::from(50u128)
+        );
+        assert_eq!(
+            query_balance(
+                &app,
+                &contract,
+                QueryMsg::UserVTokenBalance(QueryTokenData {
+                    token_address: Addr::unchecked("ATOM"),
+                    user_address: user,
+                    account_id: None,
+                })
+            ),
+            Uint128::from(98u128)
+        );
+
+        let atom: MarketStateResponse = app
+            .wrap()
+            .query_wasm_smart(
+                contract,
+                &QueryMsg::MarketState {
+                    token_address: Addr::unchecked("ATOM"),
+                },
+            )
+            .unwrap();
+        assert_eq!(atom.trading_fees, Uint128::from(2u128));
+        assert_eq!(atom.total_vtokens, Uint128::from(98u128));
+    }
+
+    #[test]
+    fn migration_moves_the_first_release_listing() {
+        let mut deps = mock_dependencies();
+        let storage = deps.as_mut().storage;
+        let legacy_listing: Item<Vec<String>> = Item::new("listed_chain");
+        legacy_listing
+            .save(storage, &vec![String::from(OSMO), String::from(ATOM)])
+            .unwrap();
+        LEVERAGE_CONTRACT_OWNER
+            .save(storage, &Addr::unchecked(OWNER))
+            .unwrap();
+        let (osmo, atom) = (Addr::unchecked(OSMO), Addr::unchecked(ATOM));
+        let (alice, bob) = (Addr::unchecked("alice"), Addr::unchecked("bob"));
+        for (user, collateral) in [(&alice, 1000u128), (&bob, 500)] {
+            TOKEN_BALANCE
+                .save(storage, (&osmo, user), &Uint128::from(collateral))
+                .unwrap();
+            WRAPPED_TOKEN_BALANCE
+                .save(storage, (&osmo, user), &Uint128::from(collateral * 10))
+                .unwrap();
+        }
+        WRAPPED_TOKEN_BORROW_BALANCE
+            .save(storage, (&osmo, &alice), &Uint128::from(4000u128))
+            .unwrap();
+        USER_VTOKEN_BALANCE
+            .save(storage, (&atom, &alice), &Uint128::from(400u128))
+            .unwrap();
+
+        let tokens = vec![
+            initial_token(
+                OSMO,
+                AssetInfo::Native {
+                    denom: String::from("uosmo"),
+                },
+                Decimal::one(),
+            ),
+            initial_token(
+                ATOM,
+                AssetInfo::Native {
+                    denom: String::from("uatom"),
+                },
+                Decimal::percent(1000),
+            ),
+        ];
+        let migrate_msg = |tokens: Vec<InitialToken>| MigrateMsg::FromFirstRelease {
+            tokens,
+            fees: None,
+            timelock_delay: None,
+        };
+        let err =
+            migrate(deps.as_mut(), mock_env(), migrate_msg(tokens[..1].to_vec())).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            ContractError::MissingTokenMetadata {
+                token: String::from(ATOM)
+            }
+            .to_string()
+        );
+        migrate(deps.as_mut(), mock_env(), migrate_msg(tokens.clone())).unwrap();
+
+        let listed: ListedTokensResponse = from_json(
+            query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::ListedTokens {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(listed.tokens.len(), 2);
+        let market_state = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>, token: &str| {
+            from_json::<MarketStateResponse>(
+                query(
+                    deps.as_ref(),
+                    mock_env(),
+                    QueryMsg::MarketState {
+                        token_address: Addr::unchecked(token),
+                    },
+                )
+                .unwrap(),
+            )
+            .unwrap()
+        };
+        let osmo_market = market_state(&deps, OSMO);
+        assert_eq!(osmo_market.total_collateral, Uint128::from(1500u128));
+        assert_eq!(osmo_market.total_wrapped, Uint128::from(15000u128));
+        assert_eq!(osmo_market.total_borrowed, Uint128::from(4000u128));
+        assert_eq!(
+            market_state(&deps, ATOM).total_vtokens,
+            Uint128::from(400u128)
+        );
+        assert!(BORROWERS.has(deps.as_ref().storage, &alice));
+        assert!(!BORROWERS.has(deps.as_ref().storage, &bob));
+
+        let err = migrate(deps.as_mut(), mock_env(), migrate_msg(tokens)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            ContractError::NothingToMigrate {}.to_string()
+        );
+    }
+
+    fn event_attr(res: &AppResponse, kind: &str, key: &str) -> String {
+        res.events
+            .iter()
+            .find(|event| event.ty == format!("wasm-leverage-{kind}"))
+            .unwrap_or_else(|| panic!("missing leverage-{kind} event"))
+            .attributes
+            .iter()
+            .find(|attr| attr.key == key)
+            .unwrap_or_else(|| panic!("missing {key} in leverage-{kind} event"))
+            .value
+            .clone()
+    }
+
+    #[test]
+    fn state_changes_emit_typed_events() {
+        let (mut app, contract) = setup_leverage_contract();
+        let user = Addr::unchecked("user_one");
+
+        let res = app
+            .execute_contract(
+                user.clone(),
+                contract.clone(),
+                &ExecuteMsg::DepositNative {
+                    token_address: String::from("OSMO"),
+                    referral_code: None,
+                    account_id: None,
+                },
+                &coins(1000, "uosmo"),
+            )
+            .unwrap();
+        assert_eq!(event_attr(&res, "deposit", "user"), "user_one");
+        assert_eq!(event_attr(&res, "deposit", "token"), "OSMO");
+        assert_eq!(event_attr(&res, "deposit", "amount"), "1000");
+        assert_eq!(event_attr(&res, "deposit", "collateral_balance"), "1000");
+        assert_eq!(event_attr(&res, "deposit", "wrapped_balance"), "10000");
+
+        let res = app
+            .execute_contract(
+                user.clone(),
+                contract.clone(),
+                &ExecuteMsg::Borrow(TokenData {
+                    token_address: Addr::unchecked("OSMO"),
+                    token_amount: Uint128::from(1000u128),
+                    account_id: None,
+                    on_behalf_of: None,
+                }),
+                &[],
+            )
+            .unwrap();
+        assert_eq!(event_attr(&res, "borrow", "amount"), "1000");
+        assert_eq!(event_attr(&res, "borrow", "fee"), "0");
+        assert_eq!(event_attr(&res, "borrow", "borrow_balance"), "1000");
+        assert_eq!(event_attr(&res, "borrow", "wrapped_balance"), "9000");
+        assert_eq!(event_attr(&res, "borrow", "vtoken_balance"), "1000");
+
+        let res = app
+            .execute_contract(
+                user.clone(),
+                contract.clone(),
+                &ExecuteMsg::ExecuteOrder(OrderExecute {
+                    order_id: String::from("order-1"),
+                    user_address: user.clone(),
+                    token_in: Addr::unchecked("OSMO"),
+                    token_out: Addr::unchecked("ATOM"),
+                    amount_in: Uint128::from(400u128),
+                    amount_out: Uint128::from(40u128),
+                    account_id: None,
+                    min_out: None,
+                }),
+                &[],
+            )
+            .unwrap();
+        assert_eq!(event_attr(&res, "order", "order_id"), "order-1");
+        assert_eq!(event_attr(&res, "order", "token_in"), "OSMO");
+        assert_eq!(event_attr(&res, "order", "token_out"), "ATOM");
+        assert_eq!(event_attr(&res, "order", "vtoken_in_balance"), "600");
+        assert_eq!(event_attr(&res, "order", "vtoken_out_balance"), "40");
+
+        let res = app
+            .execute_contract(
+                user.clone(),
+                contract.clone(),
+                &ExecuteMsg::Repay(TokenData {
+                    token_address: Addr::unchecked("OSMO"),
+                    token_amount: Uint128::from(600u128),
+                    account_id: None,
+                    on_behalf_of: None,
+                }),
+                &[],
+            )
+            .unwrap();
+        assert_eq!(event_attr(&res, "repay", "interest_paid"), "0");
+        assert_eq!(event_attr(&res, "repay", "principal_paid"), "600");
+        assert_eq!(event_attr(&res, "repay", "borrow_balance"), "400");
+        assert_eq!(event_attr(&res, "repay", "vtoken_balance"), "0");
+
+        let res = app
+            .execute_contract(
+                user.clone(),
+                contract.clone(),
+                &ExecuteMsg::RepayNative {
+                    token_address: String::from("OSMO"),
+                    account_id: None,
+                },
+                &coins(400, "uosmo"),
+            )
+            .unwrap();
+        assert_eq!(event_attr(&res, "repay", "user"), "user_one");
+        assert_eq!(event_attr(&res, "repay", "borrow_balance"), "0");
+
+        let res = app
+            .execute_contract(
+                user.clone(),
+                contract.clone(),
+                &ExecuteMsg::WithdrawToken(WithdrawData {
+                    token_address: Addr::unchecked("OSMO"),
+                    token_amount: Uint128::from(500u128),
+                    account_id: None,
+                }),
+                &[],
+            )
+            .unwrap();
+        assert_eq!(event_attr(&res, "withdraw", "amount"), "500");
+        assert_eq!(event_attr(&res, "withdraw", "collateral_balance"), "500");
+        assert_eq!(event_attr(&res, "withdraw", "wrapped_balance"), "5000");
+
+        // Admin actions are covered as well
+        let res = app
+            .execute_contract(
+                Addr::unchecked("creator"),
+                contract.clone(),
+                &ExecuteMsg::Pause {},
+                &[],
+            )
+            .unwrap();
+        assert_eq!(event_attr(&res, "paused", "sender"), "creator");
+
+        let res = app
+            .execute_contract(
+                Addr::unchecked("creator"),
+                contract.clone(),
+                &ExecuteMsg::ProposeAction {
+                    action: TimelockAction::UpdateMarketCaps {
+                        token_address: String::from("OSMO"),
+                        caps: MarketCaps {
+                            deposit_cap: Some(Uint128::from(5000u128)),
+                            borrow_cap: None,
+                        },
+                    },
+                },
+                &[],
+            )
+            .unwrap();
+        let id = proposed_action_id(&res);
+        assert_eq!(
+            event_attr(&res, "action_proposed", "action_id"),
+            id.to_string()
+        );
+
+        app.update_block(|block| block.time = block.time.plus_seconds(DEFAULT_TIMELOCK_DELAY));
+        let res = app
+            .execute_contract(
+                Addr::unchecked("creator"),
+                contract,
+                &ExecuteMsg::ExecuteAction { id },
+                &[],
+            )
+            .unwrap();
+        assert_eq!(
+            event_attr(&res, "action_executed", "action_id"),
+            id.to_string()
+        );
+        assert_eq!(
+            event_attr(&res, "market_caps_updated", "deposit_cap"),
+            "5000"
+        );
+        assert!(!res
+            .events
+            .iter()
+            .any(|event| event.attributes.iter().any(|attr| attr.key == "borrow_cap")));
+    }
+    #[test]
+    fn scenario_leveraged_round_trip_keeps_invariants() {
+        let mut scenario = Scenario::new();
+        let user = scenario.funded_user("alice", OSMO, 1000);
+
+        scenario.deposit(&user, OSMO, 1000).unwrap();
+        scenario.borrow(&user, OSMO, 5000).unwrap();
+        scenario.order(&user, OSMO, 1000, ATOM, 100).unwrap();
+        scenario.order(&user, ATOM, 100, OSMO, 1000).unwrap();
+
+        // Failed actions leave the invariants intact as well
+        scenario.withdraw(&user, OSMO, 1000).unwrap_err();
+        scenario.repay(&user, OSMO, 5001).unwrap_err();
+
+        scenario.repay(&user, OSMO, 5000).unwrap();
+        scenario.withdraw(&user, OSMO, 1000).unwrap();
+
+        assert_eq!(scenario.holdings(OSMO), Uint128::zero());
+        assert_eq!(
+            scenario.wallet_balance(&user, OSMO),
+            Uint128::from(1000u128)
+        );
+        assert_eq!(scenario.borrowed(&user, OSMO), Uint128::zero());
+        assert!(scenario.check_invariants().is_empty());
+    }
+
+    #[test]
+    fn scenario_cw20_debt_is_repaid_with_interest() {
+        let mut scenario = Scenario::new();
+        let user = scenario.funded_user("bob", USDC, 10000);
+        scenario.set_borrow_rate(USDC, Decimal::percent(10));
+
+        scenario.deposit(&user, USDC, 1000).unwrap();
+        scenario.borrow(&user, USDC, 2000).unwrap();
+        scenario.order(&user, USDC, 2000, ATOM, 200).unwrap();
+        assert_eq!(scenario.holdings(USDC), Uint128::from(1000u128));
+
+        scenario.advance_time(SECONDS_PER_YEAR);
+        let interest: Uint128 = scenario.query(&QueryMsg::UserBorrowInterest(QueryTokenData {
+            token_address: Addr::unchecked(USDC),
+            user_address: user.clone(),
+            account_id: None,
+        }));
+        assert!(!interest.is_zero());
+
+        let debt = 2000 + interest.u128();
+        scenario
+            .repay_with_funds(&user, USDC, debt + 1)
+            .unwrap_err();
+        scenario.repay_with_funds(&user, USDC, debt).unwrap();
+
+        assert_eq!(scenario.borrowed(&user, USDC), Uint128::zero());
+        assert_eq!(scenario.holdings(USDC), Uint128::from(1000u128 + debt));
+        assert_eq!(scenario.vtokens(&user, ATOM), Uint128::from(200u128));
+    }
+
+    #[test]
+    fn scenario_liquidation_after_price_move() {
+        let mut scenario = Scenario::new();
+        let user = scenario.funded_user("carol", OSMO, 1000);
+        let liquidator = scenario.funded_user("liquidator", OSMO, 10000);
+
+        scenario.deposit(&user, OSMO, 1000).unwrap();
+        scenario.borrow(&user, OSMO, 10000).unwrap();
+        scenario.order(&user, OSMO, 10000, ATOM, 1000).unwrap();
+        scenario
+            .liquidate(&liquidator, &user, OSMO, 100, OSMO)
+            .unwrap_err();
+
+        scenario.set_price(ATOM, Decimal::percent(500));
+        scenario
+            .liquidate(&liquidator, &user, OSMO, 952, OSMO)
+            .unwrap();
+
+        assert_eq!(scenario.collateral(&user, OSMO), Uint128::zero());
+        assert_eq!(
+            scenario.holdings(OSMO),
+            Uint128::from(1000u128 + 952 - 1000)
+        );
+        assert_eq!(scenario.borrowed(&user, OSMO), Uint128::zero());
+        assert_eq!(scenario.vtokens(&user, ATOM), Uint128::zero());
+        assert_eq!(
+            adl_ranking(&scenario, OSMO).deficit,
+            Uint128::from(4048u128)
+        );
+        assert_eq!(
+            scenario.wallet_balance(&liquidator, OSMO),
+            Uint128::from(10000u128 - 952 + 1000)
+        );
+    }
+
+    #[test]
+    fn self_filled_round_trips_do_not_add_value() {
+        let mut scenario = Scenario::new();
+        let user = scenario.funded_user("frank", OSMO, 1000);
+        let keeper = Addr::unchecked(OWNER);
+        scenario.deposit(&user, OSMO, 1000).unwrap();
+        scenario.borrow(&user, OSMO, 1000).unwrap();
+        let position_value = |scenario: &Scenario| {
+            let health: AccountHealthResponse = scenario.query(&QueryMsg::AccountHealth {
+                user_address: user.clone(),
+                account_id: None,
+            });
+            health.position_value
+        };
+
+        // The price tolerance is left to keepers, the account fills at most at
+        // the oracle price
+        let err = scenario.order(&user, OSMO, 1000, ATOM, 101).unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::OrderAbovePrice {}.to_string()
+        );
+
+        let before = position_value(&scenario);
+        for _ in 0..5 {
+            scenario.order(&user, OSMO, 1000, ATOM, 100).unwrap();
+            scenario.order(&user, ATOM, 100, OSMO, 1000).unwrap();
+        }
+        assert_eq!(position_value(&scenario), before);
+        assert_eq!(scenario.vtokens(&user, OSMO), Uint128::from(1000u128));
+
+        // A keeper fill can't go below the minimum the user accepts
+        let err = scenario
+            .fill_order(&keeper, &user, OSMO, 1000, ATOM, 50, Some(90))
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::OrderBelowMinimum {}.to_string()
+        );
+        scenario
+            .fill_order(&keeper, &user, OSMO, 1000, ATOM, 50, Some(50))
+            .unwrap();
+        scenario
+            .order_as(&keeper, &user, ATOM, 50, OSMO, 505)
+            .unwrap();
+        assert_eq!(scenario.vtokens(&user, OSMO), Uint128::from(505u128));
+    }
+
+    #[test]
+    fn scenario_top_up_and_burn_stay_backed() {
+        let mut scenario = Scenario::new();
+        let user = scenario.funded_user("dave", ATOM, 1000);
+
+        scenario.deposit(&user, ATOM, 500).unwrap();
+        scenario.borrow(&user, ATOM, 1000).unwrap();
+        scenario.order(&user, ATOM, 100, OSMO, 1000).unwrap();
+
+        // The sold vTokens are topped up from the wallet to close the debt
+        scenario.top_up(&user, ATOM, 100).unwrap();
+        scenario.repay(&user, ATOM, 1000).unwrap();
+
+        scenario.top_up(&user, ATOM, 400).unwrap();
+        scenario.burn(&user, ATOM, 400).unwrap();
+        assert_eq!(scenario.collateral(&user, ATOM), Uint128::from(540u128));
+        assert_eq!(scenario.holdings(ATOM), Uint128::from(1000u128));
+
+        scenario.withdraw(&user, ATOM, 541).unwrap_err();
+        scenario.withdraw(&user, ATOM, 540).unwrap();
+        assert_eq!(scenario.wallet_balance(&user, ATOM), Uint128::from(540u128));
+    }
+
+    #[test]
+    fn invariant_checker_reports_unbacked_collateral() {
+        let mut scenario = Scenario::new();
+        let user = scenario.funded_user("erin", OSMO, 1000);
+        scenario.deposit(&user, OSMO, 1000).unwrap();
+
+        // Funds leaving the contract behind its back break the collateral coverage
+        let contract = scenario.contract.clone();
+        scenario
+            .app
+            .execute(
+                contract,
+                BankMsg::Send {
+                    to_address: user.to_string(),
+                    amount: coins(1, "uosmo"),
+                }
+                .into(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            scenario.check_invariants(),
+            vec![String::from(
+                "contract holds 999 OSMO but owes 1000 of collateral and revenue"
+            )]
+        );
+    }
+
+    #[test]
+    fn invariant_checker_reports_phantom_vtokens() {
+        let mut scenario = Scenario::new();
+        let user = scenario.funded_user("erin", OSMO, 1000);
+        scenario.deposit(&user, OSMO, 1000).unwrap();
+        scenario.borrow(&user, OSMO, 500).unwrap();
+        scenario.order(&user, OSMO, 500, ATOM, 50).unwrap();
+
+        // vTokens credited behind the contract's back, with matching totals
+        let mut patch = MockStorage::new();
+        let (osmo, atom) = (Addr::unchecked(OSMO), Addr::unchecked(ATOM));
+        USER_VTOKEN_BALANCE
+            .save(&mut patch, (&osmo, &user), &Uint128::from(100u128))
+            .unwrap();
+        TOTAL_VTOKENS
+            .save(&mut patch, &osmo, &Uint128::from(100u128))
+            .unwrap();
+        USER_VTOKEN_BALANCE
+            .save(&mut patch, (&atom, &user), &Uint128::from(51u128))
+            .unwrap();
+        TOTAL_VTOKENS
+            .save(&mut patch, &atom, &Uint128::from(51u128))
+            .unwrap();
+        write_contract_storage(&mut scenario, patch);
+
+        // Only what the sold debt and the filled order back stays unflagged
+        assert_eq!(
+            scenario.check_invariants(),
+            vec![String::from(
+                "51 ATOM vTokens are issued but only 50 are backed"
+            )]
+        );
+    }
+
+    // Writes raw entries into the contract's namespace of the multi-test storage
+    fn write_contract_storage(scenario: &mut Scenario, patch: MockStorage) {
+        let mut namespace = vec![0, 4];
+        namespace.extend_from_slice(b"wasm");
+        let contract_data = format!("contract_data/{}", scenario.contract);
+        namespace.extend_from_slice(&(contract_data.len() as u16).to_be_bytes());
+        namespace.extend_from_slice(contract_data.as_bytes());
+        let entries: Vec<(Vec<u8>, Vec<u8>)> = patch.range(None, None, Order::Ascending).collect();
+        let storage = scenario.app.storage_mut();
+        for (key, value) in entries {
+            storage.set(&[namespace.as_slice(), &key].concat(), &value);
+        }
+    }
+
+    #[test]
+    fn zero_amounts_are_rejected() {
+        let mut scenario = Scenario::new();
+        let user = scenario.funded_user("alice", OSMO, 1000);
+        scenario.deposit(&user, OSMO, 1000).unwrap();
+
+        let zero_amount = ContractError::ZeroAmount {}.to_string();
+        let err = scenario.borrow(&user, OSMO, 0).unwrap_err();
+        assert_eq!(err.root_cause().to_string(), zero_amount);
+        let err = scenario.order(&user, OSMO, 0, ATOM, 0).unwrap_err();
+        assert_eq!(err.root_cause().to_string(), zero_amount);
+        let err = scenario.burn(&user, OSMO, 0).unwrap_err();
+        assert_eq!(err.root_cause().to_string(), zero_amount);
+        let err = scenario.withdraw(&user, OSMO, 0).unwrap_err();
+        assert_eq!(err.root_cause().to_string(), zero_amount);
+
+        // A zero borrow used to index the account as a borrower
+        assert_eq!(
+            scenario
+                .query::<LiquidatableAccountsResponse>(&QueryMsg::LiquidatableAccounts {
+                    start_after: None,
+                    limit: None,
+                })
+                .accounts,
+            vec![]
+        );
+    }
+
+    #[test]
+    fn burning_traded_vtokens_needs_reserve() {
+        let mut scenario = Scenario::new();
+        let user = scenario.funded_user("bob", ATOM, 1000);
+        let funder = scenario.funded_user("carol", OSMO, 1000);
+
+        scenario.deposit(&user, ATOM, 7).unwrap();
+        scenario.borrow(&user, ATOM, 30).unwrap();
+        scenario.order(&user, ATOM, 12, OSMO, 120).unwrap();
+
+        // Nothing was ever paid into the OSMO reserve
+        let err = scenario.burn(&user, OSMO, 100).unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::InsufficientReserve {}.to_string()
+        );
+
+        scenario.top_up(&funder, OSMO, 10).unwrap();
+        scenario.burn(&user, OSMO, 100).unwrap();
+        assert_eq!(scenario.collateral(&user, OSMO), Uint128::from(10u128));
+        assert_eq!(scenario.holdings(OSMO), Uint128::from(10u128));
+    }
+    #[test]
+    fn governance_recovers_a_lost_owner_through_sudo() {
+        let mut scenario = Scenario::new();
+        let user = scenario.funded_user("alice", OSMO, 1000);
+        scenario
+            .app
+            .execute_contract(
+                Addr::unchecked("creator"),
+                scenario.contract.clone(),
+                &ExecuteMsg::ProposeOwnership {
+                    new_owner: String::from("lost_key"),
+                },
+                &[],
+            )
+            .unwrap();
+
+        let res = scenario.sudo(&SudoMsg::ForcePause {}).unwrap();
+        assert_eq!(
+            event_attr(&res, "paused", "sender"),
+            scenario.contract.to_string()
+        );
+        let err = scenario.deposit(&user, OSMO, 1000).unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::Paused {}.to_string()
+        );
+
+        let res = scenario
+            .sudo(&SudoMsg::ReplaceOwner {
+                new_owner: String::from("governance_owner"),
+            })
+            .unwrap();
+        assert_eq!(
+            event_attr(&res, "owner_replaced", "previous_owner"),
+            "creator"
+        );
+        let config: ConfigResponse = scenario.query(&QueryMsg::Config {});
+        assert_eq!(config.owner, Addr::unchecked("governance_owner"));
+        assert_eq!(config.pending_owner, None);
+        assert!(config.paused);
+
+        // The new owner runs the protocol again
+        scenario
+            .app
+            .execute_contract(
+                Addr::unchecked("governance_owner"),
+                scenario.contract.clone(),
+                &ExecuteMsg::Unpause {},
+                &[],
+            )
+            .unwrap();
+        scenario.deposit(&user, OSMO, 1000).unwrap();
+    }
+
+    #[test]
+    fn governance_updates_price_sources_without_timelock() {
+        let mut scenario = Scenario::new();
+
+        let res = scenario
+            .sudo(&SudoMsg::UpdatePriceSources {
+                sources: vec![
+                    PriceSourceUpdate {
+                        token_address: String::from(ATOM),
+                        source: PriceSource::Fixed {
+                            price: Decimal::percent(500),
+                        },
+                    },
+                    PriceSourceUpdate {
+                        token_address: String::from(OSMO),
+                        source: PriceSource::Fixed {
+                            price: Decimal::percent(50),
+                        },
+                    },
+                ],
+            })
+            .unwrap();
+        assert_eq!(
+            res.events
+                .iter()
+                .filter(|event| event.ty == "wasm-leverage-price_source_updated")
+                .count(),
+            2
+        );
+
+        let price: PriceResponse = scenario.query(&QueryMsg::Price {
+            token_address: Addr::unchecked(ATOM),
+        });
+        assert_eq!(price.price, Decimal::percent(500));
+
+        // One unknown token fails the whole update
+        scenario
+            .sudo(&SudoMsg::UpdatePriceSources {
+                sources: vec![
+                    PriceSourceUpdate {
+                        token_address: String::from(ATOM),
+                        source: PriceSource::Fixed {
+                            price: Decimal::one(),
+                        },
+                    },
+                    PriceSourceUpdate {
+                        token_address: String::from("DOGE"),
+                        source: PriceSource::Fixed {
+                            price: Decimal::one(),
+                        },
+                    },
+                ],
+            })
+            .unwrap_err();
+        let price: PriceResponse = scenario.query(&QueryMsg::Price {
+            token_address: Addr::unchecked(ATOM),
+        });
+        assert_eq!(price.price, Decimal::percent(500));
+    }
+
+    #[test]
+    fn governance_force_settles_a_user() {
+        let mut scenario = Scenario::new();
+        let user = scenario.funded_user("alice", OSMO, 1000);
+        let other = scenario.funded_user("bob", ATOM, 100);
+
+        // Bob's debt is fully covered by his vTokens
+        scenario.deposit(&other, ATOM, 100).unwrap();
+        scenario.borrow(&other, ATOM, 500).unwrap();
+
+        // Alice traded all her OSMO vTokens away
+        scenario.deposit(&user, OSMO, 1000).unwrap();
+        scenario.borrow(&user, OSMO, 9995).unwrap();
+        scenario.order(&user, OSMO, 9995, ATOM, 999).unwrap();
+
+        scenario.sudo(&SudoMsg::ForcePause {}).unwrap();
+
+        let res = scenario
+            .sudo(&SudoMsg::ForceSettle {
+                user_address: other.to_string(),
+                account_id: None,
+            })
+            .unwrap();
+        assert_eq!(event_attr(&res, "force_settled", "repaid"), "500");
+        assert_eq!(event_attr(&res, "force_settled", "seized"), "0");
+        assert_eq!(scenario.collateral(&other, ATOM), Uint128::from(100u128));
+
+        // 9995 of debt takes the 1000 collateral and 900 of the ATOM vTokens
+        let res = scenario
+            .sudo(&SudoMsg::ForceSettle {
+                user_address: user.to_string(),
+                account_id: None,
+            })
+            .unwrap();
+        assert_eq!(event_attr(&res, "force_settled", "token"), OSMO);
+        assert_eq!(event_attr(&res, "force_settled", "repaid"), "0");
+        assert_eq!(event_attr(&res, "force_settled", "seized"), "1000");
+        assert_eq!(event_attr(&res, "force_settled", "bad_debt"), "0");
+        assert_eq!(event_attr(&res, "settlement_seized", "vtokens"), "900");
+
+        assert_eq!(scenario.borrowed(&user, OSMO), Uint128::zero());
+        assert_eq!(scenario.collateral(&user, OSMO), Uint128::zero());
+        assert_eq!(scenario.vtokens(&user, ATOM), Uint128::from(99u128));
+        let market: MarketStateResponse = scenario.query(&QueryMsg::MarketState {
+            token_address: Addr::unchecked(OSMO),
+        });
+        assert_eq!(market.reserve, Uint128::from(1000u128));
+        assert_eq!(market.total_borrowed, Uint128::zero());
+        assert_eq!(scenario.holdings(OSMO), Uint128::from(1000u128));
+
+        // Settling again finds nothing to do
+        let res = scenario
+            .sudo(&SudoMsg::ForceSettle {
+                user_address: user.to_string(),
+                account_id: None,
+            })
+            .unwrap();
+        assert!(res
+            .events
+            .iter()
+            .all(|event| event.ty != "wasm-leverage-force_settled"));
+    }
+
+    #[test]
+    fn force_settle_seizes_other_tokens_before_writing_off_debt() {
+        let mut scenario = Scenario::new();
+        let user = scenario.funded_user("alice", OSMO, 1000);
+        scenario.set_borrow_rate(OSMO, Decimal::percent(10));
+
+        scenario.deposit(&user, OSMO, 1000).unwrap();
+        scenario.borrow(&user, OSMO, 10000).unwrap();
+        scenario.order(&user, OSMO, 10000, ATOM, 1000).unwrap();
+        scenario.advance_time(SECONDS_PER_YEAR);
+
+        // The collateral covers 1000 of the 11000 owed, 1000 ATOM vTokens the rest
+        let res = scenario
+            .sudo(&SudoMsg::ForceSettle {
+                user_address: user.to_string(),
+                account_id: None,
+            })
+            .unwrap();
+        assert_eq!(event_attr(&res, "force_settled", "seized"), "1000");
+        assert_eq!(event_attr(&res, "force_settled", "bad_debt"), "0");
+        assert_eq!(event_attr(&res, "settlement_seized", "token"), ATOM);
+        assert_eq!(event_attr(&res, "settlement_seized", "vtokens"), "1000");
+        assert_eq!(event_attr(&res, "settlement_seized", "covered"), "10000");
+        assert_eq!(scenario.vtokens(&user, ATOM), Uint128::zero());
+        assert_eq!(
+            scenario.query::<Uint128>(&QueryMsg::UserBorrowInterest(QueryTokenData {
+                token_address: Addr::unchecked(OSMO),
+                user_address: user.clone(),
+                account_id: None,
+            })),
+            Uint128::zero()
+        );
+    }
+
+    #[test]
+    fn force_settle_writes_off_debt_beyond_the_account() {
+        let mut scenario = Scenario::new();
+        let user = scenario.funded_user("alice", OSMO, 1000);
+        scenario.set_borrow_rate(OSMO, Decimal::percent(10));
+
+        scenario.deposit(&user, OSMO, 1000).unwrap();
+        scenario.borrow(&user, OSMO, 10000).unwrap();
+        scenario.order(&user, OSMO, 10000, ATOM, 1000).unwrap();
+        scenario.advance_time(SECONDS_PER_YEAR);
+        scenario.set_price(ATOM, Decimal::permille(500));
+
+        // All 1000 ATOM vTokens are worth 500 of the 10000 left after the collateral
+        let res = scenario
+            .sudo(&SudoMsg::ForceSettle {
+                user_address: user.to_string(),
+                account_id: None,
+            })
+            .unwrap();
+        assert_eq!(event_attr(&res, "force_settled", "seized"), "1000");
+        assert_eq!(event_attr(&res, "settlement_seized", "vtokens"), "1000");
+        assert_eq!(event_attr(&res, "force_settled", "bad_debt"), "9500");
+        assert_eq!(scenario.vtokens(&user, ATOM), Uint128::zero());
+        assert_eq!(
+            scenario.query::<Uint128>(&QueryMsg::UserBorrowInterest(QueryTokenData {
+                token_address: Addr::unchecked(OSMO),
+                user_address: user.clone(),
+                account_id: None,
+            })),
+            Uint128::zero()
+        );
+    }
+
+    const HUB_CHANNEL: &str = "channel-0";
+    const HUB_SENDER: &str = "cosmos1alice";
+
+    // The transfer arrives on the ibc-hooks address of Alice's hub account
+    fn ibc_hook_sender(scenario: &mut Scenario, amount: u128) -> Addr {
+        let sender =
+            Addr::unchecked(derive_intermediate_sender(HUB_CHANNEL, HUB_SENDER, "osmo").unwrap());
+        scenario.fund(&sender, ATOM, amount);
+        sender
+    }
+
+    #[test]
+    fn ibc_hook_sender_is_derived_from_channel_and_original_sender() {
+        assert_eq!(
+            derive_intermediate_sender(HUB_CHANNEL, HUB_SENDER, "osmo").unwrap(),
+            "osmo10xm6h6fmpnv8j2y325tq44fp8nkajhu2e56actqxca83cdlhvjvq3kt58v"
+        );
+        assert_ne!(
+            derive_intermediate_sender("channel-1", HUB_SENDER, "osmo").unwrap(),
+            derive_intermediate_sender(HUB_CHANNEL, HUB_SENDER, "osmo").unwrap()
+        );
+        assert!(
+            derive_intermediate_sender(HUB_CHANNEL, HUB_SENDER, "neutron")
+                .unwrap()
+                .starts_with("neutron1")
+        );
+    }
+
+    #[test]
+    fn ibc_hook_deposits_and_borrows_for_the_original_sender() {
+        let mut scenario = Scenario::new();
+        let sender = ibc_hook_sender(&mut scenario, 150);
+
+        let res = scenario
+            .ibc_hook_deposit(
+                &sender,
+                &coins(100, ATOM_DENOM),
+                HUB_CHANNEL,
+                HUB_SENDER,
+                None,
+            )
+            .unwrap();
+        assert_eq!(event_attr(&res, "ibc_hook_deposit", "token"), ATOM);
+        assert_eq!(
+            event_attr(&res, "ibc_hook_deposit", "original_sender"),
+            HUB_SENDER
+        );
+        assert_eq!(scenario.collateral(&sender, ATOM), Uint128::from(100u128));
+        assert_eq!(scenario.wrapped(&sender, ATOM), Uint128::from(1000u128));
+
+        // A second transfer deposits and borrows in one go
+        scenario
+            .ibc_hook_deposit(
+                &sender,
+                &coins(50, ATOM_DENOM),
+                HUB_CHANNEL,
+                HUB_SENDER,
+                Some(400),
+            )
+            .unwrap();
+        assert_eq!(scenario.collateral(&sender, ATOM), Uint128::from(150u128));
+        assert_eq!(scenario.wrapped(&sender, ATOM), Uint128::from(1100u128));
+        assert_eq!(scenario.borrowed(&sender, ATOM), Uint128::from(400u128));
+        assert_eq!(scenario.vtokens(&sender, ATOM), Uint128::from(400u128));
+        assert_eq!(scenario.wallet_balance(&sender, ATOM), Uint128::zero());
+    }
+
+    #[test]
+    fn ibc_hook_deposit_checks_sender_and_denom() {
+        let mut scenario = Scenario::new();
+        let sender = ibc_hook_sender(&mut scenario, 100);
+
+        // Only the derived address of the original sender may credit its position
+        let err = scenario
+            .ibc_hook_deposit(
+                &sender,
+                &coins(100, ATOM_DENOM),
+                "channel-1",
+                HUB_SENDER,
+                None,
+            )
+            .unwrap_err();
+        assert!(err.root_cause().to_string().contains(
+            &ContractError::InvalidIbcHookSender {
+                channel: String::from("channel-1"),
+                original_sender: String::from(HUB_SENDER),
+            }
+            .to_string()
+        ));
+        let user = scenario.funded_user("bob", ATOM, 100);
+        scenario
+            .ibc_hook_deposit(
+                &user,
+                &coins(100, ATOM_DENOM),
+                HUB_CHANNEL,
+                HUB_SENDER,
+                None,
+            )
+            .unwrap_err();
+
+        // Denoms without a listed token are refused
+        scenario
+            .app
+            .sudo(cw_multi_test::SudoMsg::Bank(
+                cw_multi_test::BankSudo::Mint {
+                    to_address: sender.to_string(),
+                    amount: coins(100, "ibc/UNLISTED"),
+                },
+            ))
+            .unwrap();
+        let err = scenario
+            .ibc_hook_deposit(
+                &sender,
+                &coins(100, "ibc/UNLISTED"),
+                HUB_CHANNEL,
+                HUB_SENDER,
+                None,
+            )
+            .unwrap_err();
+        assert!(err
+            .root_cause()
+            .to_string()
+            .contains(&ContractError::UnauthorizedToken {}.to_string()));
+
+        // A borrow beyond the deposit reverts the whole transfer
+        scenario
+            .ibc_hook_deposit(
+                &sender,
+                &coins(100, ATOM_DENOM),
+                HUB_CHANNEL,
+                HUB_SENDER,
+                Some(1001),
+            )
+            .unwrap_err();
+        assert_eq!(scenario.collateral(&sender, ATOM), Uint128::zero());
+        assert_eq!(
+            scenario.wallet_balance(&sender, ATOM),
+            Uint128::from(100u128)
+        );
+    }
+
+    /**
+     * Answers the Osmosis TWAP Stargate query with the TWAP recorded for the
+     * exact request, other queries go to the default mock querier.
+     */
+    struct TwapQuerier {
+        base: MockQuerier,
+        twaps: HashMap<Binary, Decimal>,
+    }
+
+    impl Querier for TwapQuerier {
+        fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+            let request: QueryRequest<Empty> = match from_json(bin_request) {
+                Ok(request) => request,
+                Err(err) => {
+                    return SystemResult::Err(SystemError::InvalidRequest {
+                        error: err.to_string(),
+                        request: Binary::from(bin_request),
+                    })
+                }
+            };
+            match request {
+                QueryRequest::Stargate { path, data } if path == ARITHMETIC_TWAP_TO_NOW_PATH => {
+                    match self.twaps.get(&data) {
+                        Some(twap) => SystemResult::Ok(ContractResult::Ok(
+                            to_json_binary(&ArithmeticTwapToNowResponse {
+                                arithmetic_twap: *twap,
+                            })
+                            .unwrap(),
+                        )),
+                        None => SystemResult::Ok(ContractResult::Err(String::from(
+                            "no TWAP records in the window",
+                        ))),
+                    }
+                }
+                _ => self.base.raw_query(bin_request),
+            }
+        }
+    }
+
+    const TWAP_WINDOW: u64 = 3600;
+
+    fn twap_source(pool_id: u64) -> PriceSource {
+        PriceSource::OsmosisTwap {
+            pool_id,
+            quote_denom: String::from("uusdc"),
+            quote_decimals: 6,
+            window_seconds: TWAP_WINDOW,
+        }
+    }
+
+    // OSMO priced by pool 1 and EVMOS, with 18 decimals, by pool 2
+    fn setup_twap_contract() -> OwnedDeps<MockStorage, MockApi, TwapQuerier> {
+        let start_time = mock_env().block.time.minus_seconds(TWAP_WINDOW);
+        let mut deps = OwnedDeps {
+            storage: MockStorage::default(),
+            api: MockApi::default(),
+            querier: TwapQuerier {
+                base: MockQuerier::default(),
+                twaps: HashMap::from([
+                    (
+                        encode_request(1, "uosmo", "uusdc", start_time),
+                        Decimal::percent(200),
+                    ),
+                    (
+                        encode_request(2, "aevmos", "uusdc", start_time),
+                        Decimal::from_ratio(5u128, 10u128.pow(13)),
+                    ),
+                ]),
+            },
+            custom_query_type: PhantomData,
+        };
+
+        let token = |token: &str, denom: &str, decimals: u8, pool_id: u64| InitialToken {
+            token_address: String::from(token),
+            asset: AssetInfo::Native {
+                denom: String::from(denom),
+            },
+            decimals: Some(decimals),
+            symbol: String::from(token),
+            price_source: Some(twap_source(pool_id)),
+        };
+        instantiate(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            InstantiateMsg {
+                owner: None,
+                tokens: vec![token(OSMO, "uosmo", 6, 1), token("EVMOS", "aevmos", 18, 2)],
+                fees: None,
+                timelock_delay: None,
+            },
+        )
+        .unwrap();
+        deps
+    }
+
+    fn twap_price(
+        deps: &OwnedDeps<MockStorage, MockApi, TwapQuerier>,
+        env: Env,
+        token: &str,
+    ) -> StdResult<Decimal> {
+        let res = query(
+            deps.as_ref(),
+            env,
+            QueryMsg::Price {
+                token_address: Addr::unchecked(token),
+            },
+        )?;
+        Ok(from_json::<PriceResponse>(&res)?.price)
+    }
+
+    #[test]
+    fn osmosis_twap_request_is_protobuf_encoded() {
+        let mut expected = vec![0x08, 0x01, 0x12, 0x05];
+        expected.extend_from_slice(b"uatom");
+        expected.extend_from_slice(&[0x1a, 0x05]);
+        expected.extend_from_slice(b"uusdc");
+        expected.extend_from_slice(&[0x22, 0x06, 0x08, 0x80, 0xe2, 0xcf, 0xaa, 0x06]);
+        assert_eq!(
+            encode_request(1, "uatom", "uusdc", Timestamp::from_seconds(1_700_000_000)),
+            Binary::from(expected)
+        );
+    }
+
+    #[test]
+    fn osmosis_twap_prices_collateral_per_whole_token() {
+        let mut deps = setup_twap_contract();
+
+        assert_eq!(
+            twap_price(&deps, mock_env(), OSMO).unwrap(),
+            Decimal::percent(200)
+        );
+        // 5e-13 uusdc per aevmos is 0.5 USDC per EVMOS
+        assert_eq!(
+            twap_price(&deps, mock_env(), "EVMOS").unwrap(),
+            Decimal::percent(50)
+        );
+
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &coins(1_000_000, "uosmo")),
+            ExecuteMsg::DepositNative {
+                token_address: String::from(OSMO),
+                referral_code: None,
+                account_id: None,
+            },
+        )
+        .unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            ExecuteMsg::Borrow(TokenData {
+                token_address: Addr::unchecked(OSMO),
+                token_amount: Uint128::from(5_000_000u128),
+                account_id: None,
+                on_behalf_of: None,
+            }),
+        )
+        .unwrap();
+        let health: AccountHealthResponse = from_json(
+            query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::AccountHealth {
+                    user_address: Addr::unchecked("alice"),
+                    account_id: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(health.collateral_value, Decimal256::percent(200));
+
+        // Without TWAP records in the window the token can't be priced
+        let mut later = mock_env();
+        later.block.time = later.block.time.plus_seconds(60);
+        twap_price(&deps, later.clone(), OSMO).unwrap_err();
+        query(
+            deps.as_ref(),
+            later,
+            QueryMsg::AccountHealth {
+                user_address: Addr::unchecked("alice"),
+                account_id: None,
+            },
+        )
+        .unwrap_err();
+    }
+
+    #[test]
+    fn osmosis_twap_sources_are_validated() {
+        let mut deps = setup_twap_contract();
+        let env = mock_env();
+
+        for window_seconds in [0, 48 * 60 * 60 + 1] {
+            let err = set_price_source(
+                deps.as_mut(),
+                env.clone(),
+                Addr::unchecked(OSMO),
+                PriceSource::OsmosisTwap {
+                    pool_id: 1,
+                    quote_denom: String::from("uusdc"),
+                    quote_decimals: 6,
+                    window_seconds,
+                },
+            )
+            .unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                ContractError::InvalidTwapWindow {}.to_string()
+            );
+        }
+        let err = set_price_source(
+            deps.as_mut(),
+            env.clone(),
+            Addr::unchecked(OSMO),
+            PriceSource::OsmosisTwap {
+                pool_id: 1,
+                quote_denom: String::from("uusdc"),
+                quote_decimals: 19,
+                window_seconds: 60,
+            },
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            ContractError::DecimalsTooLarge { max: 18 }.to_string()
+        );
+
+        let err = instantiate(
+            deps.as_mut(),
+            env,
+            mock_info("creator", &[]),
+            InstantiateMsg {
+                owner: None,
+                tokens: vec![InitialToken {
+                    token_address: String::from(USDC),
+                    asset: AssetInfo::Cw20 {
+                        contract_addr: Addr::unchecked("usdc_contract"),
+                    },
+                    decimals: Some(6),
+                    symbol: String::from(USDC),
+                    price_source: Some(twap_source(3)),
+                }],
+                fees: None,
+                timelock_delay: None,
+            },
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            ContractError::TwapNeedsNativeToken {
+                token: String::from(USDC)
+            }
+            .to_string()
+        );
+    }
+
+    const ATOM_FEED: &str = "b00b60f88b03a6a625a8d1c048c3f66653edf217439983d037e7222c4e612819";
+
+    fn pyth_source(pyth: &Addr, max_confidence_ratio: Decimal) -> PriceSource {
+        PriceSource::Pyth {
+            contract_addr: pyth.clone(),
+            price_feed_id: String::from(ATOM_FEED),
+            max_age_seconds: 60,
+            max_confidence_ratio,
+        }
+    }
+
+    // ATOM switches to a Pyth feed publishing 10.00 ± 0.01
+    fn setup_pyth_scenario() -> (Scenario, Addr) {
+        let mut scenario = Scenario::new();
+        let pyth = mock_pyth::deploy(&mut scenario.app);
+        let now = scenario.app.block_info().time;
+        mock_pyth::publish(
+            &mut scenario.app,
+            &pyth,
+            ATOM_FEED,
+            1_000_000_000,
+            1_000_000,
+            -8,
+            now,
+        )
+        .unwrap();
+        scenario
+            .sudo(&SudoMsg::UpdatePriceSources {
+                sources: vec![PriceSourceUpdate {
+                    token_address: String::from(ATOM),
+                    source: pyth_source(&pyth, Decimal::percent(1)),
+                }],
+            })
+            .unwrap();
+        (scenario, pyth)
+    }
+
+    fn pyth_price(scenario: &Scenario) -> StdResult<Decimal> {
+        let res: PriceResponse = scenario.app.wrap().query_wasm_smart(
+            scenario.contract.clone(),
+            &QueryMsg::Price {
+                token_address: Addr::unchecked(ATOM),
+            },
+        )?;
+        Ok(res.price)
+    }
+
+    #[test]
+    fn pyth_feed_prices_the_token() {
+        let (mut scenario, _) = setup_pyth_scenario();
+        assert_eq!(pyth_price(&scenario).unwrap(), Decimal::percent(1000));
+
+        let user = scenario.funded_user("alice", OSMO, 1000);
+        scenario.deposit(&user, OSMO, 1000).unwrap();
+        scenario.borrow(&user, OSMO, 10000).unwrap();
+        scenario.order(&user, OSMO, 1000, ATOM, 102).unwrap_err();
+        scenario.order(&user, OSMO, 1000, ATOM, 100).unwrap();
+        assert_eq!(scenario.vtokens(&user, ATOM), Uint128::from(100u128));
+    }
+
+    #[test]
+    fn pyth_guards_refuse_stale_and_uncertain_prices() {
+        let (mut scenario, pyth) = setup_pyth_scenario();
+        let user = scenario.funded_user("alice", OSMO, 1000);
+        scenario.deposit(&user, OSMO, 1000).unwrap();
+        scenario.borrow(&user, OSMO, 10000).unwrap();
+
+        // A minute old is still fine, a second more is not
+        scenario.advance_time(60);
+        pyth_price(&scenario).unwrap();
+        scenario.advance_time(1);
+        let stale = ContractError::StalePrice {
+            token: String::from(ATOM),
+        };
+        assert!(pyth_price(&scenario)
+            .unwrap_err()
+            .to_string()
+            .contains(&stale.to_string()));
+        let err = scenario.order(&user, OSMO, 1000, ATOM, 100).unwrap_err();
+        assert_eq!(err.root_cause().to_string(), stale.to_string());
+
+        // 10.00 ± 0.11 is more than 1% uncertain
+        let now = scenario.app.block_info().time;
+        mock_pyth::publish(
+            &mut scenario.app,
+            &pyth,
+            ATOM_FEED,
+            1_000_000_000,
+            11_000_000,
+            -8,
+            now,
+        )
+        .unwrap();
+        let err = scenario.order(&user, OSMO, 1000, ATOM, 100).unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::PriceUncertain {
+                token: String::from(ATOM)
+            }
+            .to_string()
+        );
+
+        mock_pyth::publish(&mut scenario.app, &pyth, ATOM_FEED, -1, 0, -8, now).unwrap();
+        pyth_price(&scenario).unwrap_err();
+
+        mock_pyth::publish(&mut scenario.app, &pyth, ATOM_FEED, 999, 0, -2, now).unwrap();
+        assert_eq!(pyth_price(&scenario).unwrap(), Decimal::permille(9990));
+        scenario.order(&user, OSMO, 999, ATOM, 100).unwrap();
+    }
+
+    #[test]
+    fn pyth_sources_are_validated() {
+        let (mut scenario, pyth) = setup_pyth_scenario();
+
+        for (source, error) in [
+            (
+                pyth_source(&pyth, Decimal::zero()),
+                ContractError::InvalidConfidenceRatio {},
+            ),
+            (
+                pyth_source(&pyth, Decimal::percent(101)),
+                ContractError::InvalidConfidenceRatio {},
+            ),
+            (
+                PriceSource::Pyth {
+                    contract_addr: pyth.clone(),
+                    price_feed_id: String::from(ATOM_FEED),
+                    max_age_seconds: 0,
+                    max_confidence_ratio: Decimal::percent(1),
+                },
+                ContractError::InvalidPriceAge {},
+            ),
+        ] {
+            let err = scenario
+                .sudo(&SudoMsg::UpdatePriceSources {
+                    sources: vec![PriceSourceUpdate {
+                        token_address: String::from(ATOM),
+                        source,
+                    }],
+                })
+                .unwrap_err();
+            assert_eq!(err.root_cause().to_string(), error.to_string());
+        }
+    }
+
+    // ATOM priced by the Pyth feed first, backed by two manual feeds
+    fn aggregate_atom_price(scenario: &mut Scenario, pyth: &Addr, min_sources: u32) {
+        scenario
+            .sudo(&SudoMsg::UpdatePriceSources {
+                sources: vec![PriceSourceUpdate {
+                    token_address: String::from(ATOM),
+                    source: PriceSource::Aggregate {
+                        sources: vec![
+                            pyth_source(pyth, Decimal::percent(1)),
+                            PriceSource::Fixed {
+                                price: Decimal::permille(10200),
+                            },
+                            PriceSource::Fixed {
+                                price: Decimal::permille(9900),
+                            },
+                        ],
+                        max_deviation: Decimal::percent(5),
+                        min_sources,
+                    },
+                }],
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn aggregated_price_is_the_median_of_the_fresh_sources() {
+        let (mut scenario, pyth) = setup_pyth_scenario();
+        aggregate_atom_price(&mut scenario, &pyth, 2);
+        assert_eq!(pyth_price(&scenario).unwrap(), Decimal::percent(1000));
+
+        // The stale Pyth feed drops out, the manual feeds meet in the middle
+        scenario.advance_time(61);
+        assert_eq!(pyth_price(&scenario).unwrap(), Decimal::permille(10050));
+
+        let now = scenario.app.block_info().time;
+        mock_pyth::publish(
+            &mut scenario.app,
+            &pyth,
+            ATOM_FEED,
+            1_005_000_000,
+            0,
+            -8,
+            now,
+        )
+        .unwrap();
+        assert_eq!(pyth_price(&scenario).unwrap(), Decimal::permille(10050));
+        mock_pyth::publish(
+            &mut scenario.app,
+            &pyth,
+            ATOM_FEED,
+            1_010_000_000,
+            0,
+            -8,
+            now,
+        )
+        .unwrap();
+        assert_eq!(pyth_price(&scenario).unwrap(), Decimal::permille(10100));
+
+        // Above both manual feeds the primary doesn't set the price by itself
+        mock_pyth::publish(
+            &mut scenario.app,
+            &pyth,
+            ATOM_FEED,
+            1_030_000_000,
+            0,
+            -8,
+            now,
+        )
+        .unwrap();
+        assert_eq!(pyth_price(&scenario).unwrap(), Decimal::permille(10200));
+    }
+
+    #[test]
+    fn unpriceable_borrowers_do_not_hide_liquidatable_ones() {
+        let (mut scenario, _) = setup_pyth_scenario();
+        // Alice and Carol sell OSMO for USDC, Bob for ATOM priced by Pyth
+        let mut users = vec![];
+        for (name, token_out, amount_out) in [
+            ("alice", USDC, 10000),
+            ("bob", ATOM, 1000),
+            ("carol", USDC, 10000),
+        ] {
+            let user = scenario.funded_user(name, OSMO, 1000);
+            scenario.deposit(&user, OSMO, 1000).unwrap();
+            scenario.borrow(&user, OSMO, 10000).unwrap();
+            scenario
+                .order(&user, OSMO, 10000, token_out, amount_out)
+                .unwrap();
+            users.push(user);
+        }
+        scenario.set_price(USDC, Decimal::percent(50));
+        scenario.advance_time(61);
+
+        let res: LiquidatableAccountsResponse = scenario.query(&QueryMsg::LiquidatableAccounts {
+            start_after: None,
+            limit: None,
+        });
+        let liquidatable: Vec<Addr> = res
+            .accounts
+            .into_iter()
+            .map(|account| account.user_address)
+            .collect();
+        assert_eq!(liquidatable, vec![users[0].clone(), users[2].clone()]);
+        assert_eq!(res.next_start_after, None);
+    }
+
+    #[test]
+    fn aggregated_price_needs_enough_sources() {
+        let (mut scenario, pyth) = setup_pyth_scenario();
+        aggregate_atom_price(&mut scenario, &pyth, 3);
+        assert_eq!(pyth_price(&scenario).unwrap(), Decimal::percent(1000));
+
+        scenario.advance_time(61);
+        let not_available = ContractError::PriceNotAvailable {
+            token: String::from(ATOM),
+        };
+        assert!(pyth_price(&scenario)
+            .unwrap_err()
+            .to_string()
+            .contains(&not_available.to_string()));
+    }
+
+    #[test]
+    fn aggregated_price_fails_on_an_uncertain_primary() {
+        let (mut scenario, pyth) = setup_pyth_scenario();
+        aggregate_atom_price(&mut scenario, &pyth, 2);
+
+        // A 2% confidence interval isn't a stale price, the manual feeds don't step in
+        let now = scenario.app.block_info().time;
+        mock_pyth::publish(
+            &mut scenario.app,
+            &pyth,
+            ATOM_FEED,
+            1_000_000_000,
+            20_000_000,
+            -8,
+            now,
+        )
+        .unwrap();
+        let uncertain = ContractError::PriceUncertain {
+            token: String::from(ATOM),
+        };
+        assert!(pyth_price(&scenario)
+            .unwrap_err()
+            .to_string()
+            .contains(&uncertain.to_string()));
+    }
+
+    #[test]
+    fn diverging_sources_block_borrows_but_not_repays() {
+        let (mut scenario, pyth) = setup_pyth_scenario();
+        aggregate_atom_price(&mut scenario, &pyth, 2);
+        let user = scenario.funded_user("alice", ATOM, 100);
+        scenario.deposit(&user, ATOM, 100).unwrap();
+        scenario.borrow(&user, ATOM, 500).unwrap();
+
+        // 12.00 is 17% above the 10.20 median
+        let now = scenario.app.block_info().time;
+        mock_pyth::publish(
+            &mut scenario.app,
+            &pyth,
+            ATOM_FEED,
+            1_200_000_000,
+            0,
+            -8,
+            now,
+        )
+        .unwrap();
+        let deviation = ContractError::PriceDeviation {
+            token: String::from(ATOM),
+        };
+        assert!(pyth_price(&scenario)
+            .unwrap_err()
+            .to_string()
+            .contains(&deviation.to_string()));
+        let err = scenario.borrow(&user, ATOM, 1).unwrap_err();
+        assert_eq!(err.root_cause().to_string(), deviation.to_string());
+
+        scenario.repay(&user, ATOM, 500).unwrap();
+        assert_eq!(scenario.borrowed(&user, ATOM), Uint128::zero());
+    }
+
+    #[test]
+    fn aggregate_sources_are_validated() {
+        let (mut scenario, pyth) = setup_pyth_scenario();
+        let fixed = PriceSource::Fixed {
+            price: Decimal::one(),
+        };
+        let aggregate =
+            |sources: Vec<PriceSource>, max_deviation: Decimal| PriceSource::Aggregate {
+                sources,
+                max_deviation,
+                min_sources: 2,
+            };
+
+        for (source, error) in [
+            (
+                aggregate(vec![fixed.clone()], Decimal::percent(5)),
+                ContractError::InvalidAggregateSources {},
+            ),
+            (
+                aggregate(vec![fixed.clone(); 6], Decimal::percent(5)),
+                ContractError::InvalidAggregateSources {},
+            ),
+            (
+                aggregate(
+                    vec![
+                        fixed.clone(),
+                        aggregate(vec![fixed.clone(); 2], Decimal::percent(5)),
+                    ],
+                    Decimal::percent(5),
+                ),
+                ContractError::InvalidAggregateSources {},
+            ),
+            (
+                aggregate(vec![fixed.clone(); 2], Decimal::zero()),
+                ContractError::InvalidDeviation {},
+            ),
+            (
+                aggregate(
+                    vec![fixed.clone(), pyth_source(&pyth, Decimal::zero())],
+                    Decimal::percent(5),
+                ),
+                ContractError::InvalidConfidenceRatio {},
+            ),
+            (
+                PriceSource::Aggregate {
+                    sources: vec![fixed.clone(); 2],
+                    max_deviation: Decimal::percent(5),
+                    min_sources: 1,
+                },
+                ContractError::InvalidMinSources {},
+            ),
+            (
+                PriceSource::Aggregate {
+                    sources: vec![fixed.clone(); 2],
+                    max_deviation: Decimal::percent(5),
+                    min_sources: 3,
+                },
+                ContractError::InvalidMinSources {},
+            ),
+        ] {
+            let err = scenario
+                .sudo(&SudoMsg::UpdatePriceSources {
+                    sources: vec![PriceSourceUpdate {
+                        token_address: String::from(ATOM),
+                        source,
+                    }],
+                })
+                .unwrap_err();
+            assert_eq!(err.root_cause().to_string(), error.to_string());
+        }
+    }
+
+    const REWARD_DENOM: &str = "uinc";
+
+    // OSMO emits 10 uinc per second to collateral and 4 to debt for 1000 seconds
+    fn setup_osmo_rewards(scenario: &mut Scenario) -> RewardSchedule {
+        // Starting once the timelocked schedule applies
+        let now = scenario
+            .app
+            .block_info()
+            .time
+            .plus_seconds(MIN_TIMELOCK_DELAY);
+        let schedule = RewardSchedule {
+            reward_denom: String::from(REWARD_DENOM),
+            start_time: now,
+            end_time: now.plus_seconds(1000),
+            collateral_emission: Uint128::from(10u128),
+            borrow_emission: Uint128::from(4u128),
+        };
+        scenario.set_reward_schedule(OSMO, schedule.clone());
+        schedule
+    }
+
+    fn pending_rewards(scenario: &Scenario, user: &Addr) -> Uint128 {
+        let res: PendingRewardsResponse = scenario.query(&QueryMsg::PendingRewards {
+            user_address: user.clone(),
+            account_id: None,
+        });
+        res.rewards
+            .iter()
+            .find(|coin| coin.denom == REWARD_DENOM)
+            .map(|coin| coin.amount)
+            .unwrap_or_default()
+    }
+
+    fn reward_balance(scenario: &Scenario, user: &Addr) -> Uint128 {
+        scenario
+            .app
+            .wrap()
+            .query_balance(user, REWARD_DENOM)
+            .unwrap()
+            .amount
+    }
+
+    #[test]
+    fn rewards_are_shared_by_collateral_and_debt() {
+        let mut scenario = Scenario::new();
+        setup_osmo_rewards(&mut scenario);
+        let alice = scenario.funded_user("alice", OSMO, 300);
+        let bob = scenario.funded_user("bob", OSMO, 100);
+
+        scenario.deposit(&alice, OSMO, 300).unwrap();
+        scenario.deposit(&bob, OSMO, 100).unwrap();
+        scenario.advance_time(100);
+        assert_eq!(pending_rewards(&scenario, &alice), Uint128::from(750u128));
+        assert_eq!(pending_rewards(&scenario, &bob), Uint128::from(250u128));
+
+        // Bob is the only borrower from here on
+        scenario.borrow(&bob, OSMO, 500).unwrap();
+        scenario.advance_time(100);
+        assert_eq!(pending_rewards(&scenario, &alice), Uint128::from(1500u128));
+        assert_eq!(
+            pending_rewards(&scenario, &bob),
+            Uint128::from(500u128 + 400)
+        );
+
+        let res = scenario.claim_rewards(&alice).unwrap();
+        assert_eq!(event_attr(&res, "rewards_claimed", "rewards"), "1500uinc");
+        assert_eq!(reward_balance(&scenario, &alice), Uint128::from(1500u128));
+        assert_eq!(pending_rewards(&scenario, &alice), Uint128::zero());
+        let err = scenario.claim_rewards(&alice).unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::NoRewards {}.to_string()
+        );
+
+        // Repaying ends the borrow rewards, withdrawing halves Alice's share
+        scenario.repay(&bob, OSMO, 500).unwrap();
+        scenario.withdraw(&alice, OSMO, 200).unwrap();
+        scenario.advance_time(100);
+        assert_eq!(pending_rewards(&scenario, &alice), Uint128::from(500u128));
+        assert_eq!(
+            pending_rewards(&scenario, &bob),
+            Uint128::from(900u128 + 500)
+        );
+
+        scenario.claim_rewards(&bob).unwrap();
+        assert_eq!(reward_balance(&scenario, &bob), Uint128::from(1400u128));
+
+        let state: RewardStateResponse = scenario.query(&QueryMsg::RewardState {
+            token_address: Addr::unchecked(OSMO),
+        });
+        assert_eq!(state.collateral_index, Decimal256::percent(1000));
+        assert_eq!(state.borrow_index, Decimal256::percent(80));
+        assert_eq!(state.funds, Uint128::from(14_000u128 - 1500 - 1400));
+    }
+
+    #[test]
+    fn reward_schedules_need_their_emission_funded() {
+        let mut scenario = Scenario::new();
+        // Starting once the timelocked schedule applies, for 10000 seconds
+        let schedule_from = |scenario: &Scenario| {
+            let start = scenario
+                .app
+                .block_info()
+                .time
+                .plus_seconds(MIN_TIMELOCK_DELAY);
+            RewardSchedule {
+                reward_denom: String::from(REWARD_DENOM),
+                start_time: start,
+                end_time: start.plus_seconds(10_000),
+                collateral_emission: Uint128::from(10u128),
+                borrow_emission: Uint128::from(4u128),
+            }
+        };
+        scenario
+            .app
+            .sudo(cw_multi_test::SudoMsg::Bank(
+                cw_multi_test::BankSudo::Mint {
+                    to_address: String::from(OWNER),
+                    amount: coins(140_001, REWARD_DENOM),
+                },
+            ))
+            .unwrap();
+
+        let err = scenario
+            .try_run_timelocked_with_funds(
+                TimelockAction::SetRewardSchedule {
+                    token_address: String::from(OSMO),
+                    schedule: schedule_from(&scenario),
+                },
+                &coins(139_999, REWARD_DENOM),
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::RewardsNotFunded {
+                required: Uint128::from(140_000u128)
+            }
+            .to_string()
+        );
+
+        // Funds are only taken with the proposal and in the reward denom
+        let owner = Addr::unchecked(OWNER);
+        let contract = scenario.contract.clone();
+        let propose = |schedule: RewardSchedule| ExecuteMsg::ProposeAction {
+            action: TimelockAction::SetRewardSchedule {
+                token_address: String::from(OSMO),
+                schedule,
+            },
+        };
+        let err = scenario
+            .app
+            .execute_contract(
+                owner.clone(),
+                contract.clone(),
+                &propose(RewardSchedule {
+                    reward_denom: String::from("uosmo"),
+                    ..schedule_from(&scenario)
+                }),
+                &coins(140_000, REWARD_DENOM),
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::UnexpectedFunds {}.to_string()
+        );
+        let res = scenario
+            .app
+            .execute_contract(
+                owner.clone(),
+                contract.clone(),
+                &propose(schedule_from(&scenario)),
+                &coins(140_000, REWARD_DENOM),
+            )
+            .unwrap();
+        let id = proposed_action_id(&res);
+        let pending_action: PendingAction = scenario.query(&QueryMsg::PendingAction { id });
+        assert_eq!(pending_action.funds, coins(140_000, REWARD_DENOM));
+        scenario.advance_time(MIN_TIMELOCK_DELAY);
+        let err = scenario
+            .app
+            .execute_contract(
+                owner.clone(),
+                contract.clone(),
+                &ExecuteMsg::ExecuteAction { id },
+                &coins(1, REWARD_DENOM),
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::UnexpectedFunds {}.to_string()
+        );
+
+        // The escrow goes back to the proposer on cancelling
+        scenario
+            .app
+            .execute_contract(
+                owner.clone(),
+                contract,
+                &ExecuteMsg::CancelAction { id },
+                &[],
+            )
+            .unwrap();
+        assert_eq!(
+            reward_balance(&scenario, &owner),
+            Uint128::from(140_001u128)
+        );
+
+        let schedule = schedule_from(&scenario);
+        let res = scenario
+            .try_run_timelocked_with_funds(
+                TimelockAction::SetRewardSchedule {
+                    token_address: String::from(OSMO),
+                    schedule: schedule.clone(),
+                },
+                &coins(140_000, REWARD_DENOM),
+            )
+            .unwrap();
+        assert_eq!(
+            event_attr(&res, "reward_schedule_updated", "funded"),
+            "140000"
+        );
+
+        // A shorter schedule is covered by what the current one would still emit
+        let alice = scenario.funded_user("alice", OSMO, 100);
+        scenario.deposit(&alice, OSMO, 100).unwrap();
+        let res = scenario
+            .try_run_timelocked(TimelockAction::SetRewardSchedule {
+                token_address: String::from(OSMO),
+                schedule: RewardSchedule {
+                    end_time: schedule.start_time.plus_seconds(5000),
+                    ..schedule
+                },
+            })
+            .unwrap();
+        assert_eq!(event_attr(&res, "reward_schedule_updated", "funded"), "0");
+
+        scenario.advance_time(10_000);
+        assert_eq!(
+            pending_rewards(&scenario, &alice),
+            Uint128::from(50_000u128)
+        );
+        scenario.claim_rewards(&alice).unwrap();
+        let state: RewardStateResponse = scenario.query(&QueryMsg::RewardState {
+            token_address: Addr::unchecked(OSMO),
+        });
+        assert_eq!(state.funds, Uint128::from(90_000u128));
+    }
+
+    #[test]
+    fn reward_schedules_end_and_can_be_extended() {
+        let mut scenario = Scenario::new();
+        let schedule = setup_osmo_rewards(&mut scenario);
+        let alice = scenario.funded_user("alice", OSMO, 100);
+        scenario.deposit(&alice, OSMO, 100).unwrap();
+
+        // Nothing is emitted after the end of the schedule
+        scenario.advance_time(2000);
+        assert_eq!(
+            pending_rewards(&scenario, &alice),
+            Uint128::from(10_000u128)
+        );
+
+        let now = scenario
+            .app
+            .block_info()
+            .time
+            .plus_seconds(MIN_TIMELOCK_DELAY);
+        scenario.set_reward_schedule(
+            OSMO,
+            RewardSchedule {
+                start_time: now,
+                end_time: now.plus_seconds(100),
+                collateral_emission: Uint128::from(1u128),
+                ..schedule.clone()
+            },
+        );
+        scenario.advance_time(50);
+        assert_eq!(
+            pending_rewards(&scenario, &alice),
+            Uint128::from(10_050u128)
+        );
+
+        let err = scenario
+            .try_run_timelocked(TimelockAction::SetRewardSchedule {
+                token_address: String::from(OSMO),
+                schedule: RewardSchedule {
+                    reward_denom: String::from("uother"),
+                    ..schedule.clone()
+                },
+            })
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::RewardDenomChanged {
+                denom: String::from(REWARD_DENOM)
+            }
+            .to_string()
+        );
+        let err = scenario
+            .try_run_timelocked(TimelockAction::SetRewardSchedule {
+                token_address: String::from(ATOM),
+                schedule: RewardSchedule {
+                    end_time: schedule.start_time,
+                    ..schedule
+                },
+            })
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::InvalidRewardSchedule {}.to_string()
+        );
+    }
+
+    fn setup_referral_fees(scenario: &mut Scenario) {
+        scenario.run_timelocked(TimelockAction::UpdateFees {
+            fees: FeeConfig {
+                borrow_fee: Decimal::percent(1),
+                trading_fee: Decimal::percent(2),
+                referral_share: Decimal::percent(50),
+            },
+        });
+    }
+
+    fn referrer_of(scenario: &Scenario, user: &Addr) -> Option<Addr> {
+        let res: ReferrerResponse = scenario.query(&QueryMsg::Referrer {
+            user_address: user.clone(),
+        });
+        res.referrer
+    }
+
+    #[test]
+    fn referrers_earn_a_share_of_borrow_and_trading_fees() {
+        let mut scenario = Scenario::new();
+        setup_referral_fees(&mut scenario);
+        let alice = scenario.user("alice");
+        let bob = scenario.funded_user("bob", OSMO, 1050);
+        let carol = scenario.user("carol");
+
+        let res = scenario.register_referral_code(&alice, "alice-1").unwrap();
+        assert_eq!(
+            event_attr(&res, "referral_code_registered", "code"),
+            "alice-1"
+        );
+        scenario.register_referral_code(&carol, "carol").unwrap();
+
+        let res = scenario
+            .deposit_with_code(&bob, OSMO, 500, Some("alice-1"))
+            .unwrap();
+        assert_eq!(
+            event_attr(&res, "referrer_bound", "referrer"),
+            alice.to_string()
+        );
+        // Only the first deposit binds a referrer
+        scenario
+            .deposit_with_code(&bob, OSMO, 500, Some("carol"))
+            .unwrap();
+        assert_eq!(referrer_of(&scenario, &bob), Some(alice.clone()));
+
+        // Half of the 50 OSMO borrow fee and of the 2 ATOM vToken trading fee
+        scenario.borrow(&bob, OSMO, 5000).unwrap();
+        scenario.order(&bob, OSMO, 1000, ATOM, 100).unwrap();
+
+        // The borrow fee isn't revenue before Bob paid it
+        let res = scenario.claim_referral_rebates(&alice).unwrap();
+        assert_eq!(
+            event_attr(&res, "referral_rebates_claimed", "trading_fees"),
+            "1"
+        );
+        assert_eq!(scenario.vtokens(&alice, ATOM), Uint128::from(1u128));
+        assert_eq!(scenario.wallet_balance(&alice, OSMO), Uint128::zero());
+
+        scenario.repay_with_funds(&bob, OSMO, 50).unwrap();
+        let res = scenario.claim_referral_rebates(&alice).unwrap();
+        assert_eq!(
+            event_attr(&res, "referral_rebates_claimed", "borrow_fees"),
+            "25"
+        );
+        assert_eq!(scenario.wallet_balance(&alice, OSMO), Uint128::from(25u128));
+        let err = scenario.claim_referral_rebates(&alice).unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::NoRebates {}.to_string()
+        );
+
+        let stats: ReferralStatsResponse = scenario.query(&QueryMsg::ReferralStats {
+            referrer_address: alice,
+        });
+        assert_eq!(stats.code, Some(String::from("alice-1")));
+        assert_eq!(stats.referred_users, 1);
+        let tokens: Vec<(&str, Uint128, Uint128, Uint128)> = stats
+            .rebates
+            .iter()
+            .map(|rebates| {
+                (
+                    rebates.token.as_str(),
+                    rebates.pending.borrow_fees + rebates.pending.trading_fees,
+                    rebates.claimed.borrow_fees,
+                    rebates.claimed.trading_fees,
+                )
+            })
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (ATOM, Uint128::zero(), Uint128::zero(), Uint128::from(1u128)),
+                (
+                    OSMO,
+                    Uint128::zero(),
+                    Uint128::from(25u128),
+                    Uint128::zero()
+                ),
+            ]
+        );
+        let stats: ReferralStatsResponse = scenario.query(&QueryMsg::ReferralStats {
+            referrer_address: carol,
+        });
+        assert_eq!(stats.referred_users, 0);
+        assert!(stats.rebates.is_empty());
+    }
+
+    #[test]
+    fn referral_codes_are_validated() {
+        let mut scenario = Scenario::new();
+        setup_referral_fees(&mut scenario);
+        let alice = scenario.funded_user("alice", OSMO, 100);
+        let bob = scenario.funded_user("bob", OSMO, 200);
+
+        for code in ["ab", "Alice", "alice!", &"a".repeat(33)] {
+            let err = scenario.register_referral_code(&alice, code).unwrap_err();
+            assert_eq!(
+                err.root_cause().to_string(),
+                ContractError::InvalidReferralCode {}.to_string()
+            );
+        }
+        scenario.register_referral_code(&alice, "alice").unwrap();
+        let err = scenario
+            .register_referral_code(&alice, "alice_2")
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::ReferralCodeRegistered {
+                code: String::from("alice")
+            }
+            .to_string()
+        );
+        let err = scenario.register_referral_code(&bob, "alice").unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::ReferralCodeTaken {
+                code: String::from("alice")
+            }
+            .to_string()
+        );
+
+        let err = scenario
+            .deposit_with_code(&alice, OSMO, 100, Some("alice"))
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::SelfReferral {}.to_string()
+        );
+        let err = scenario
+            .deposit_with_code(&bob, OSMO, 100, Some("nobody"))
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::UnknownReferralCode {
+                code: String::from("nobody")
+            }
+            .to_string()
+        );
+
+        // A first deposit without a code leaves the user unreferred
+        scenario.deposit(&bob, OSMO, 100).unwrap();
+        scenario
+            .deposit_with_code(&bob, OSMO, 100, Some("alice"))
+            .unwrap();
+        assert_eq!(referrer_of(&scenario, &bob), None);
+
+        let err = scenario
+            .try_run_timelocked(TimelockAction::UpdateFees {
+                fees: FeeConfig {
+                    referral_share: Decimal::percent(101),
+                    ..FeeConfig::default()
+                },
+            })
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::InvalidReferralShare {}.to_string()
+        );
+    }
+
+    #[test]
+    fn sub_accounts_keep_separate_balances_and_risk() {
+        let mut scenario = Scenario::new();
+        let bob = scenario.funded_user("bob", OSMO, 1500);
+        let bob_2 = sub_accounts::account_address(&bob, Some(2)).unwrap();
+
+        scenario.deposit(&bob, OSMO, 1000).unwrap();
+        scenario.deposit_to_account(&bob, OSMO, 500, 2).unwrap();
+        assert_eq!(scenario.collateral(&bob, OSMO), Uint128::from(1000u128));
+        assert_eq!(scenario.collateral(&bob_2, OSMO), Uint128::from(500u128));
+
+        // Sub-account 2 only borrows against its own wrapped balance
+        let err = scenario.borrow_on_account(&bob, OSMO, 6000, 2).unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::InsufficientUnmintedToken {}.to_string()
+        );
+        scenario.borrow_on_account(&bob, OSMO, 4000, 2).unwrap();
+        assert_eq!(scenario.borrowed(&bob_2, OSMO), Uint128::from(4000u128));
+        assert_eq!(scenario.borrowed(&bob, OSMO), Uint128::zero());
+
+        let health: AccountHealthResponse = scenario.query(&QueryMsg::AccountHealth {
+            user_address: bob.clone(),
+            account_id: Some(2),
+        });
+        assert!(health.health_factor.is_some());
+        let health: AccountHealthResponse = scenario.query(&QueryMsg::AccountHealth {
+            user_address: bob.clone(),
+            account_id: None,
+        });
+        assert_eq!(health.health_factor, None);
+
+        let user_account: UserAccountResponse = scenario.query(&QueryMsg::UserAccount {
+            user_address: bob,
+            account_id: Some(2),
+        });
+        assert_eq!(user_account.user_address, bob_2);
+    }
+
+    #[test]
+    fn collateral_moves_between_sub_accounts() {
+        let mut scenario = Scenario::new();
+        let bob = scenario.funded_user("bob", OSMO, 1500);
+        let bob_2 = sub_accounts::account_address(&bob, Some(2)).unwrap();
+        let bob_3 = sub_accounts::account_address(&bob, Some(3)).unwrap();
+        scenario.deposit(&bob, OSMO, 1000).unwrap();
+        scenario.deposit_to_account(&bob, OSMO, 500, 2).unwrap();
+
+        let res = scenario.transfer_collateral(&bob, OSMO, 200, 0, 3).unwrap();
+        assert_eq!(
+            event_attr(&res, "collateral_transferred", "to_collateral_balance"),
+            "200"
+        );
+        assert_eq!(scenario.collateral(&bob, OSMO), Uint128::from(800u128));
+        assert_eq!(scenario.collateral(&bob_3, OSMO), Uint128::from(200u128));
+        assert_eq!(scenario.wrapped(&bob_3, OSMO), Uint128::from(2000u128));
+
+        // Like a withdrawal, an account can't move out a token it owes
+        scenario.borrow_on_account(&bob, OSMO, 1000, 2).unwrap();
+        let err = scenario
+            .transfer_collateral(&bob, OSMO, 100, 2, 0)
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::BorrowAmountIsNotZero {}.to_string()
+        );
+        let err = scenario
+            .transfer_collateral(&bob, OSMO, 100, 3, 3)
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::SameAccount {}.to_string()
+        );
+        let err = scenario
+            .transfer_collateral(&bob, OSMO, 100, 0, sub_accounts::MAX_ACCOUNT_ID + 1)
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::InvalidAccountId { account_id: 65 }.to_string()
+        );
+
+        let res: SubAccountsResponse =
+            scenario.query(&QueryMsg::SubAccounts { owner: bob.clone() });
+        assert_eq!(res.account_ids, vec![2, 3]);
+
+        // Withdrawals from a sub-account are paid to the owner's wallet
+        scenario.withdraw_from_account(&bob, OSMO, 200, 3).unwrap();
+        assert_eq!(scenario.collateral(&bob_3, OSMO), Uint128::zero());
+        assert_eq!(scenario.wallet_balance(&bob, OSMO), Uint128::from(200u128));
+        assert_eq!(scenario.wallet_balance(&bob_3, OSMO), Uint128::zero());
+        assert_eq!(scenario.collateral(&bob_2, OSMO), Uint128::from(500u128));
+    }
+
+    #[test]
+    fn operators_act_within_their_permissions() {
+        let mut scenario = Scenario::new();
+        let bob = scenario.funded_user("bob", OSMO, 1000);
+        let bot = scenario.user("bot");
+        scenario.deposit(&bob, OSMO, 1000).unwrap();
+
+        let err = scenario.borrow_for(&bot, &bob, OSMO, 5000).unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::OperatorNotApproved {
+                permission: String::from("borrow")
+            }
+            .to_string()
+        );
+
+        let res = scenario
+            .approve_operator(
+                &bob,
+                &bot,
+                vec![
+                    OperatorPermission::Borrow,
+                    OperatorPermission::Trade,
+                    OperatorPermission::Borrow,
+                ],
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            event_attr(&res, "operator_approved", "permissions"),
+            "borrow,trade"
+        );
+        scenario.borrow_for(&bot, &bob, OSMO, 5000).unwrap();
+        scenario
+            .order_as(&bot, &bob, OSMO, 1000, ATOM, 100)
+            .unwrap();
+        assert_eq!(scenario.borrowed(&bob, OSMO), Uint128::from(5000u128));
+        assert_eq!(scenario.vtokens(&bob, ATOM), Uint128::from(100u128));
+        assert_eq!(scenario.borrowed(&bot, OSMO), Uint128::zero());
+
+        let err = scenario.repay_for(&bot, &bob, OSMO, 1000).unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::OperatorNotApproved {
+                permission: String::from("repay")
+            }
+            .to_string()
+        );
+
+        // Approving again replaces the permissions
+        scenario
+            .approve_operator(&bob, &bot, vec![OperatorPermission::Repay], None)
+            .unwrap();
+        scenario.repay_for(&bot, &bob, OSMO, 1000).unwrap();
+        assert_eq!(scenario.vtokens(&bob, OSMO), Uint128::from(3000u128));
+        let err = scenario.borrow_for(&bot, &bob, OSMO, 1000).unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::OperatorNotApproved {
+                permission: String::from("borrow")
+            }
+            .to_string()
+        );
+        // Neither keeper nor trading operator anymore
+        let err = scenario
+            .order_as(&bot, &bob, ATOM, 100, OSMO, 1000)
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::Unauthorized {}.to_string()
+        );
+
+        let res = scenario.revoke_operator(&bob, &bot).unwrap();
+        assert_eq!(
+            event_attr(&res, "operator_revoked", "operator"),
+            bot.to_string()
+        );
+        assert!(scenario.repay_for(&bot, &bob, OSMO, 1000).is_err());
+        let err = scenario.revoke_operator(&bob, &bot).unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::NoOperatorApproval {}.to_string()
+        );
+    }
+
+    #[test]
+    fn operator_approvals_expire_and_are_validated() {
+        let mut scenario = Scenario::new();
+        let bob = scenario.funded_user("bob", OSMO, 1000);
+        let bot = scenario.user("bot");
+        scenario.deposit(&bob, OSMO, 1000).unwrap();
+        let now = scenario.app.block_info().time;
+
+        let err = scenario
+            .approve_operator(&bob, &bob, vec![OperatorPermission::Trade], None)
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::SelfOperator {}.to_string()
+        );
+        let err = scenario
+            .approve_operator(&bob, &bot, vec![], None)
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::NoOperatorPermissions {}.to_string()
+        );
+        let err = scenario
+            .approve_operator(
+                &bob,
+                &bot,
+                vec![OperatorPermission::Trade],
+                Some(Expiration::AtTime(now)),
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::ApprovalExpired {}.to_string()
+        );
+
+        scenario
+            .approve_operator(
+                &bob,
+                &bot,
+                vec![OperatorPermission::Borrow],
+                Some(Expiration::AtTime(now.plus_seconds(3600))),
+            )
+            .unwrap();
+        scenario.borrow_for(&bot, &bob, OSMO, 1000).unwrap();
+
+        scenario.advance_time(3600);
+        let res: OperatorApprovalsResponse = scenario.query(&QueryMsg::OperatorApprovals {
+            owner: bob.clone(),
+            start_after: None,
+            limit: None,
+        });
+        assert_eq!(res.approvals.len(), 1);
+        assert_eq!(res.approvals[0].operator, bot);
+        assert!(res.approvals[0].expired);
+        let err = scenario.borrow_for(&bot, &bob, OSMO, 1000).unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::OperatorNotApproved {
+                permission: String::from("borrow")
+            }
+            .to_string()
+        );
+        assert_eq!(scenario.borrowed(&bob, OSMO), Uint128::from(1000u128));
+    }
+
+    #[test]
+    fn operators_simulate_borrows_for_the_owner() {
+        let mut scenario = Scenario::new();
+        let bob = scenario.funded_user("bob", OSMO, 1000);
+        let bot = scenario.user("bot");
+        scenario.deposit(&bob, OSMO, 1000).unwrap();
+        let simulate_borrow = |scenario: &Scenario| {
+            scenario.app.wrap().query_wasm_smart::<SimulationResponse>(
+                scenario.contract.clone(),
+                &QueryMsg::SimulateBorrow {
+                    user_address: bot.clone(),
+                    token_data: TokenData {
+                        token_address: Addr::unchecked(OSMO),
+                        token_amount: Uint128::from(1000u128),
+                        account_id: None,
+                        on_behalf_of: Some(bob.clone()),
+                    },
+                },
+            )
+        };
+
+        let err = simulate_borrow(&scenario).unwrap_err();
+        assert!(err.to_string().contains(
+            &ContractError::OperatorNotApproved {
+                permission: String::from("borrow")
+            }
+            .to_string()
+        ));
+
+        scenario
+            .approve_operator(&bob, &bot, vec![OperatorPermission::Borrow], None)
+            .unwrap();
+        let simulated = simulate_borrow(&scenario).unwrap();
+        let osmo = simulated
+            .balances
+            .iter()
+            .find(|balance| balance.token == OSMO)
+            .unwrap();
+        assert_eq!(osmo.borrowed, Uint128::from(1000u128));
+        assert_eq!(osmo.collateral, Uint128::from(1000u128));
+        assert!(simulated
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "operator" && attr.value == bot.as_str()));
+        assert_eq!(scenario.borrowed(&bob, OSMO), Uint128::zero());
+    }
+
+    // Carol and Dave sell ATOM for OSMO vTokens and profit once ATOM halves
+    fn setup_profitable_osmo_holders(scenario: &mut Scenario) -> (Addr, Addr) {
+        let carol = scenario.funded_user("carol", ATOM, 100);
+        let dave = scenario.funded_user("dave", ATOM, 100);
+        scenario.deposit(&carol, ATOM, 100).unwrap();
+        scenario.borrow(&carol, ATOM, 1000).unwrap();
+        scenario.order(&carol, ATOM, 1000, OSMO, 10000).unwrap();
+        scenario.deposit(&dave, ATOM, 100).unwrap();
+        scenario.borrow(&dave, ATOM, 500).unwrap();
+        scenario.order(&dave, ATOM, 500, OSMO, 5000).unwrap();
+        scenario.set_price(ATOM, Decimal::percent(500));
+        (carol, dave)
+    }
+
+    // Alice's OSMO debt outgrows her collateral by 1000 vTokens of interest
+    fn setup_bankrupt_osmo_borrower(scenario: &mut Scenario, atom_out: u128) -> Addr {
+        let alice = scenario.funded_user("alice", OSMO, 10000);
+        scenario.deposit(&alice, OSMO, 10000).unwrap();
+        scenario.borrow(&alice, OSMO, 10000).unwrap();
+        scenario.order(&alice, OSMO, 10000, ATOM, atom_out).unwrap();
+        alice
+    }
+
+    // Runs auto-deleveraging page by page, returns the completing call and the
+    // last holder of every page before it
+    fn run_auto_deleverage(
+        scenario: &mut Scenario,
+        token: &str,
+        limit: Option<u32>,
+    ) -> (AppResponse, Vec<String>) {
+        let mut pages = vec![];
+        loop {
+            let res = scenario
+                .sudo(&SudoMsg::AutoDeleverage {
+                    token_address: String::from(token),
+                    limit,
+                })
+                .unwrap();
+            let next_start_after = res
+                .events
+                .iter()
+                .flat_map(|event| &event.attributes)
+                .find(|attr| attr.key == "next_start_after");
+            match next_start_after {
+                Some(attr) => {
+                    // Nothing is closed before all holders are ranked
+                    assert!(!res
+                        .events
+                        .iter()
+                        .any(|event| event.ty.starts_with("wasm-leverage-")));
+                    pages.push(attr.value.clone());
+                }
+                None => return (res, pages),
+            }
+        }
+    }
+
+    fn adl_ranking(scenario: &Scenario, token: &str) -> AdlRankingResponse {
+        scenario.query(&QueryMsg::AdlRanking {
+            token_address: Addr::unchecked(token),
+            start_after: None,
+            limit: None,
+        })
+    }
+
+    #[test]
+    fn deficits_are_insured_then_auto_deleveraged_by_rank() {
+        let mut scenario = Scenario::new();
+        scenario.set_borrow_rate(OSMO, Decimal::percent(10));
+        let (carol, dave) = setup_profitable_osmo_holders(&mut scenario);
+        let alice = setup_bankrupt_osmo_borrower(&mut scenario, 2000);
+        let eve = scenario.funded_user("eve", OSMO, 150);
+        scenario.deposit(&eve, OSMO, 100).unwrap();
+        scenario.borrow(&eve, OSMO, 1000).unwrap();
+        scenario.advance_time(SECONDS_PER_YEAR);
+        // 50 OSMO of interest become revenue, the insurance of the OSMO market
+        scenario.repay_with_funds(&eve, OSMO, 50).unwrap();
+
+        // Carol's profit is larger relative to her collateral and more leveraged
+        let ranking = adl_ranking(&scenario, OSMO);
+        assert_eq!(ranking.deficit, Uint128::zero());
+        let ranked: Vec<(Addr, Uint128, Uint128)> = ranking
+            .ranking
+            .iter()
+            .map(|rank| (rank.user_address.clone(), rank.position, rank.closable))
+            .collect();
+        assert_eq!(
+            ranked,
+            vec![
+                (
+                    carol.clone(),
+                    Uint128::from(10000u128),
+                    Uint128::from(5000u128)
+                ),
+                (
+                    dave.clone(),
+                    Uint128::from(5000u128),
+                    Uint128::from(2500u128)
+                ),
+            ]
+        );
+        assert!(ranking.ranking[0].score > ranking.ranking[1].score);
+
+        // Alice's 2000 ATOM vTokens are left worth 2 of the 1000 OSMO she owes
+        scenario.set_price(ATOM, Decimal::permille(1));
+        let res = scenario
+            .sudo(&SudoMsg::ForceSettle {
+                user_address: alice.to_string(),
+                account_id: None,
+            })
+            .unwrap();
+        assert_eq!(event_attr(&res, "force_settled", "bad_debt"), "998");
+        assert_eq!(
+            event_attr(&res, "deficit_covered", "insurance_tokens"),
+            "50"
+        );
+        assert_eq!(event_attr(&res, "deficit_covered", "deficit"), "498");
+        assert!(!res
+            .events
+            .iter()
+            .any(|event| event.ty == "wasm-leverage-auto_deleveraged"));
+
+        let (res, _) = run_auto_deleverage(&mut scenario, OSMO, None);
+        assert_eq!(event_attr(&res, "deficit_covered", "deleveraged"), "498");
+        assert_eq!(event_attr(&res, "deficit_covered", "deficit"), "0");
+        assert_eq!(
+            event_attr(&res, "auto_deleveraged", "user"),
+            carol.to_string()
+        );
+        assert_eq!(event_attr(&res, "auto_deleveraged", "rank"), "1");
+        assert_eq!(event_attr(&res, "auto_deleveraged", "absorbed"), "498");
+
+        // With ATOM crashed Carol's whole position is profit, the rest of it is
+        // credited as collateral in whole units
+        assert_eq!(event_attr(&res, "auto_deleveraged", "closed"), "9998");
+        assert_eq!(event_attr(&res, "auto_deleveraged", "credited"), "950");
+        assert_eq!(scenario.vtokens(&carol, OSMO), Uint128::from(2u128));
+        assert_eq!(scenario.collateral(&carol, OSMO), Uint128::from(950u128));
+        assert_eq!(scenario.wrapped(&carol, OSMO), Uint128::from(9500u128));
+        assert_eq!(scenario.vtokens(&dave, OSMO), Uint128::from(5000u128));
+        assert_eq!(scenario.collateral(&dave, OSMO), Uint128::zero());
+        let market: MarketStateResponse = scenario.query(&QueryMsg::MarketState {
+            token_address: Addr::unchecked(OSMO),
+        });
+        assert_eq!(market.revenue, Uint128::zero());
+        assert_eq!(adl_ranking(&scenario, OSMO).deficit, Uint128::zero());
+    }
+
+    #[test]
+    fn liquidating_underwater_accounts_records_the_deficit() {
+        let mut scenario = Scenario::new();
+        scenario.set_borrow_rate(OSMO, Decimal::percent(10));
+        let alice = scenario.funded_user("alice", OSMO, 1000);
+        scenario.deposit(&alice, OSMO, 1000).unwrap();
+        scenario.borrow(&alice, OSMO, 10000).unwrap();
+        scenario.order(&alice, OSMO, 10000, ATOM, 1000).unwrap();
+        scenario.advance_time(SECONDS_PER_YEAR);
+        // Alice's 1000 OSMO of collateral and 1000 ATOM vTokens worth 1 OSMO
+        // are left for the 11000 OSMO she owes
+        scenario.set_price(ATOM, Decimal::permille(1));
+        let liquidator = scenario.funded_user("liquidator", OSMO, 5000);
+
+        // Her collateral only pays for 952 of the liquidator's 5000 OSMO, the
+        // rest goes back
+        let res = scenario
+            .liquidate(&liquidator, &alice, OSMO, 5000, OSMO)
+            .unwrap();
+        assert_eq!(event_attr(&res, "liquidate", "amount"), "952");
+        assert_eq!(event_attr(&res, "liquidate", "seized"), "1000");
+        assert_eq!(
+            scenario.wallet_balance(&liquidator, OSMO),
+            Uint128::from(5048u128)
+        );
+
+        // Without collateral left her account is settled, the insurance covers
+        // 9520 of the 10047 bad debt and the rest is the deficit
+        assert_eq!(event_attr(&res, "force_settled", "bad_debt"), "10047");
+        assert_eq!(event_attr(&res, "settlement_seized", "covered"), "1");
+        assert_eq!(
+            event_attr(&res, "deficit_covered", "insurance_tokens"),
+            "952"
+        );
+        assert_eq!(event_attr(&res, "deficit_covered", "deficit"), "527");
+        assert_eq!(adl_ranking(&scenario, OSMO).deficit, Uint128::from(527u128));
+        assert_eq!(scenario.borrowed(&alice, OSMO), Uint128::zero());
+        assert_eq!(scenario.collateral(&alice, OSMO), Uint128::zero());
+        assert_eq!(scenario.vtokens(&alice, ATOM), Uint128::zero());
+    }
+
+    #[test]
+    fn uncovered_deficits_wait_for_profitable_positions() {
+        let mut scenario = Scenario::new();
+        scenario.set_borrow_rate(OSMO, Decimal::percent(10));
+        let alice = setup_bankrupt_osmo_borrower(&mut scenario, 1000);
+        scenario.advance_time(SECONDS_PER_YEAR);
+        // Alice's 1000 ATOM vTokens are left worth 1 of the 1000 OSMO she owes
+        scenario.set_price(ATOM, Decimal::permille(1));
+
+        let res = scenario
+            .sudo(&SudoMsg::ForceSettle {
+                user_address: alice.to_string(),
+                account_id: None,
+            })
+            .unwrap();
+        assert_eq!(event_attr(&res, "deficit_covered", "deficit"), "999");
+        let ranking = adl_ranking(&scenario, OSMO);
+        assert_eq!(ranking.deficit, Uint128::from(999u128));
+        assert!(ranking.ranking.is_empty());
+
+        let err = scenario
+            .sudo(&SudoMsg::AutoDeleverage {
+                token_address: String::from(ATOM),
+                limit: None,
+            })
+            .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            ContractError::NoDeficit {
+                token: String::from(ATOM)
+            }
+            .to_string()
+        );
+
+        scenario.set_price(ATOM, Decimal::percent(1000));
+        let (carol, dave) = setup_profitable_osmo_holders(&mut scenario);
+
+        // Pages of one holder, Alice's settled account comes first
+        let page: AdlRankingResponse = scenario.query(&QueryMsg::AdlRanking {
+            token_address: Addr::unchecked(OSMO),
+            start_after: Some(alice.to_string()),
+            limit: Some(1),
+        });
+        assert_eq!(page.ranking[0].user_address, carol);
+        assert_eq!(page.next_start_after, Some(carol.clone()));
+
+        let (res, pages) = run_auto_deleverage(&mut scenario, OSMO, Some(1));
+        assert_eq!(
+            pages,
+            vec![alice.to_string(), carol.to_string(), dave.to_string()]
+        );
+        assert_eq!(
+            event_attr(&res, "auto_deleveraged", "user"),
+            carol.to_string()
+        );
+        assert_eq!(event_attr(&res, "auto_deleveraged", "absorbed"), "999");
+
+        // Carol's profit of 5000 is closed, 4000 of it credited back as 400 OSMO
+        assert_eq!(event_attr(&res, "auto_deleveraged", "closed"), "4999");
+        assert_eq!(event_attr(&res, "auto_deleveraged", "credited"), "400");
+        assert_eq!(scenario.vtokens(&carol, OSMO), Uint128::from(5001u128));
+        assert_eq!(scenario.collateral(&carol, OSMO), Uint128::from(400u128));
+        assert_eq!(adl_ranking(&scenario, OSMO).deficit, Uint128::zero());
+    }
+
+    #[test]
+    fn auto_deleveraging_ranks_all_holders_before_closing() {
+        let mut scenario = Scenario::new();
+        scenario.set_borrow_rate(OSMO, Decimal::percent(10));
+        let alice = setup_bankrupt_osmo_borrower(&mut scenario, 1000);
+        scenario.advance_time(SECONDS_PER_YEAR);
+        scenario.set_price(ATOM, Decimal::permille(1));
+        scenario
+            .sudo(&SudoMsg::ForceSettle {
+                user_address: alice.to_string(),
+                account_id: None,
+            })
+            .unwrap();
+        assert_eq!(adl_ranking(&scenario, OSMO).deficit, Uint128::from(999u128));
+
+        // More holders than a page holds, the most leveraged one sorts last
+        scenario.set_price(ATOM, Decimal::percent(1000));
+        for holder in 0..32 {
+            let holder = scenario.funded_user(&format!("holder_{holder:02}"), ATOM, 100);
+            scenario.deposit(&holder, ATOM, 100).unwrap();
+            scenario.borrow(&holder, ATOM, 500).unwrap();
+            scenario.order(&holder, ATOM, 500, OSMO, 5000).unwrap();
+        }
+        let zed = scenario.funded_user("zed", ATOM, 100);
+        scenario.deposit(&zed, ATOM, 100).unwrap();
+        scenario.borrow(&zed, ATOM, 1000).unwrap();
+        scenario.order(&zed, ATOM, 1000, OSMO, 10000).unwrap();
+        scenario.set_price(ATOM, Decimal::percent(500));
+
+        let first_page: AdlRankingResponse = scenario.query(&QueryMsg::AdlRanking {
+            token_address: Addr::unchecked(OSMO),
+            start_after: None,
+            limit: Some(30),
+        });
+        assert!(first_page
+            .ranking
+            .iter()
+            .all(|rank| rank.user_address != zed));
+
+        let (res, pages) = run_auto_deleverage(&mut scenario, OSMO, Some(30));
+        assert_eq!(pages.len(), 1);
+        assert_eq!(
+            event_attr(&res, "auto_deleveraged", "user"),
+            zed.to_string()
+        );
+        assert_eq!(event_attr(&res, "auto_deleveraged", "rank"), "1");
+        assert_eq!(event_attr(&res, "auto_deleveraged", "absorbed"), "999");
+        assert_eq!(adl_ranking(&scenario, OSMO).deficit, Uint128::zero());
+        assert_eq!(
+            scenario.vtokens(&Addr::unchecked("holder_00"), OSMO),
+            Uint128::from(5000u128)
+        );
     }
 }
@@ -0,0 +1,398 @@
+pub mod auto_deleverage {
+    use cosmwasm_std::{Addr, Decimal256, Deps, DepsMut, Env, Order, StdResult, Storage, Uint128};
+    use cw_storage_plus::Bound;
+
+    use crate::deposit::deposit_collateral;
+    use crate::error::ContractError;
+    use crate::events::leverage_events::LeverageEvent;
+    use crate::interest::interest_module;
+    use crate::listing::token_listing;
+    use crate::market::market_module;
+    use crate::msg::{AdlRank, AdlRankingResponse};
+    use crate::oracle::price_oracle;
+    use crate::state::{
+        AdlScan, TokenInfo, ADL_SCANS, DEFICITS, PROTOCOL_REVENUE, RESERVE_BALANCE,
+        TOTAL_COLLATERAL, TOTAL_VTOKENS, TOTAL_WRAPPED, TRADING_FEES, USER_VTOKEN_BALANCE,
+        WRAPPED_TOKEN_BORROW_BALANCE,
+    };
+    use crate::sub_account::sub_accounts;
+    use crate::valuation::valuation_module;
+
+    const DEFAULT_LIMIT: u32 = 10;
+    const MAX_LIMIT: u32 = 30;
+    // Accounts a scan carries between its pages, at most this many are deleveraged per round
+    const MAX_CANDIDATES: usize = 30;
+
+    /**
+     * Adds debt written off in the token to its deficit.
+     */
+    pub fn record_deficit(
+        storage: &mut dyn Storage,
+        token_address: &Addr,
+        bad_debt: Uint128,
+    ) -> Result<(), ContractError> {
+        if !bad_debt.is_zero() {
+            market_module::increase_total(storage, &DEFICITS, token_address, bad_debt)?;
+        }
+        Ok(())
+    }
+
+    /**
+     * Profitable positions among up to `limit` holders of the token, best
+     * ranked first.
+     *
+     * A position is the vTokens of the token an account holds beyond its own
+     * debt in it, the opposite side of the debt written off. Accounts rank by
+     * their profit relative to their collateral times their leverage, and only
+     * as much of a position as the account's profit can be closed.
+     *
+     * @return The ranking and the last scanned holder, `None` once all holders were scanned.
+     */
+    pub fn rank_positions(
+        deps: Deps,
+        env: &Env,
+        token_address: &Addr,
+        start_after: Option<Addr>,
+        limit: Option<u32>,
+    ) -> Result<(Vec<AdlRank>, Option<Addr>), ContractError> {
+        let token_info = token_listing::load_token(deps.storage, token_address)?;
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+        let start = start_after.as_ref().map(Bound::exclusive);
+        let holders = USER_VTOKEN_BALANCE
+            .prefix(token_address)
+            .range(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .collect::<StdResult<Vec<(Addr, Uint128)>>>()?;
+        let next_start_after = if holders.len() == limit {
+            holders.last().map(|(account, _)| account.clone())
+        } else {
+            None
+        };
+
+        let mut ranking = vec![];
+        for (account, vtokens) in holders {
+            if let Some(rank) = rank_account(deps, env, &token_info, &account, vtokens)? {
+                ranking.push(rank);
+            }
+        }
+
+        sort_ranking(&mut ranking);
+        Ok((ranking, next_start_after))
+    }
+
+    /**
+     * Scans the next page of up to `limit` holders of the token.
+     *
+     * Positions are only closed once every holder was scanned, so where a
+     * holder's address sorts doesn't decide whether they're deleveraged.
+     * Between pages the scan keeps the best ranked accounts so far, which are
+     * ranked again with every page at the current prices and balances.
+     *
+     * @return The best ranked positions of all holders and `None` once the scan
+     * is complete, otherwise the ranking so far and the last scanned holder.
+     */
+    pub fn scan_positions(
+        deps: DepsMut,
+        env: &Env,
+        token_address: &Addr,
+        limit: Option<u32>,
+    ) -> Result<(Vec<AdlRank>, Option<Addr>), ContractError> {
+        let token_info = token_listing::load_token(deps.storage, token_address)?;
+        let (start_after, candidates) = match ADL_SCANS.may_load(deps.storage, token_address)? {
+            Some(scan) => (Some(scan.last_holder), scan.candidates),
+            None => (None, vec![]),
+        };
+
+        let (mut ranking, next_start_after) =
+            rank_positions(deps.as_ref(), env, token_address, start_after, limit)?;
+        for account in candidates {
+            let vtokens = market_module::user_balance(
+                deps.storage,
+                &USER_VTOKEN_BALANCE,
+                token_address,
+                &account,
+            )?;
+            if let Some(rank) = rank_account(deps.as_ref(), env, &token_info, &account, vtokens)? {
+                ranking.push(rank);
+            }
+        }
+        sort_ranking(&mut ranking);
+        ranking.truncate(MAX_CANDIDATES);
+
+        match &next_start_after {
+            Some(last_holder) => {
+                let candidates = ranking
+                    .iter()
+                    .map(|rank| {
+                        sub_accounts::account_address(&rank.user_address, Some(rank.account_id))
+                    })
+                    .collect::<Result<Vec<Addr>, ContractError>>()?;
+                ADL_SCANS.save(
+                    deps.storage,
+                    token_address,
+                    &AdlScan {
+                        last_holder: last_holder.clone(),
+                        candidates,
+                    },
+                )?;
+            }
+            None => ADL_SCANS.remove(deps.storage, token_address),
+        }
+        Ok((ranking, next_start_after))
+    }
+
+    // Profitable position of the account holding `vtokens` of the token, if any
+    fn rank_account(
+        deps: Deps,
+        env: &Env,
+        token_info: &TokenInfo,
+        account: &Addr,
+        vtokens: Uint128,
+    ) -> Result<Option<AdlRank>, ContractError> {
+        let token_address = Addr::unchecked(&token_info.token);
+        let debt = market_module::user_balance(
+            deps.storage,
+            &WRAPPED_TOKEN_BORROW_BALANCE,
+            &token_address,
+            account,
+        )?
+        .checked_add(interest_module::pending_interest(
+            deps.storage,
+            &token_address,
+            account,
+            env.block.time,
+        )?)
+        .map_err(|_| ContractError::Overflow {})?;
+        let position = vtokens.saturating_sub(debt);
+        if position.is_zero() {
+            return Ok(None);
+        }
+
+        let health = valuation_module::account_health(deps, env, account)?;
+        let pnl = match health.position_value.checked_sub(health.debt_value) {
+            Ok(pnl) if !pnl.is_zero() => pnl,
+            _ => return Ok(None),
+        };
+        let price = price_oracle::query_price(deps, env, token_info)?;
+        let closable = position.min(valuation_module::token_amount(pnl, token_info, price)?);
+        if closable.is_zero() {
+            return Ok(None);
+        }
+
+        // Without collateral at stake the profit is unbounded relative to it
+        let pnl_ratio = if health.collateral_value.is_zero() {
+            Decimal256::MAX
+        } else {
+            pnl.checked_div(health.collateral_value)
+                .map_err(|_| ContractError::Overflow {})?
+        };
+        let leverage = health.leverage.unwrap_or_default();
+        let (user_address, account_id) = sub_accounts::parse(account);
+        Ok(Some(AdlRank {
+            user_address,
+            account_id,
+            position,
+            closable,
+            pnl,
+            leverage,
+            score: pnl_ratio.saturating_mul(leverage),
+        }))
+    }
+
+    // Equal scores go to the larger profit, then to the lower address
+    fn sort_ranking(ranking: &mut [AdlRank]) {
+        ranking.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then(b.pnl.cmp(&a.pnl))
+                .then(a.user_address.cmp(&b.user_address))
+                .then(a.account_id.cmp(&b.account_id))
+        });
+    }
+
+    /**
+     * Cancels the token's deficit with the insurance and the ranked positions.
+     *
+     * The protocol's own funds in the token are its insurance: trading fees,
+     * kept as vTokens, are burned one for one, then protocol revenue moves into
+     * the reserve at the 10:1 wrapping ratio. Once they're exhausted, the
+     * closable part of positions is closed in ranking order. Each closed
+     * position absorbs its share of the deficit, the bankruptcy price of the
+     * settled accounts, and the holder is credited the rest of it at the mark
+     * price as collateral from the reserve, like a burn would. What the profits
+     * can't cover stays recorded for a later round.
+     */
+    pub fn cover_deficit(
+        deps: DepsMut,
+        env: &Env,
+        token_address: &Addr,
+        ranking: Vec<AdlRank>,
+    ) -> Result<Vec<LeverageEvent>, ContractError> {
+        let mut deficit = DEFICITS
+            .may_load(deps.storage, token_address)?
+            .unwrap_or_default();
+        if deficit.is_zero() {
+            return Ok(vec![]);
+        }
+
+        let trading_fees = TRADING_FEES
+            .may_load(deps.storage, token_address)?
+            .unwrap_or_default();
+        let insurance_vtokens = trading_fees.min(deficit);
+        market_module::decrease_total(
+            deps.storage,
+            &TRADING_FEES,
+            token_address,
+            insurance_vtokens,
+        )?;
+        deficit -= insurance_vtokens;
+
+        // Rounded up like the collateral seized when settling
+        let revenue = PROTOCOL_REVENUE
+            .may_load(deps.storage, token_address)?
+            .unwrap_or_default();
+        let insurance_tokens = deficit
+            .checked_add(Uint128::from(9u128))
+            .map_err(|_| ContractError::Overflow {})?
+            .checked_div(Uint128::from(10u128))
+            .map_err(|_| ContractError::Overflow {})?
+            .min(revenue);
+        market_module::decrease_total(
+            deps.storage,
+            &PROTOCOL_REVENUE,
+            token_address,
+            insurance_tokens,
+        )?;
+        market_module::increase_total(
+            deps.storage,
+            &RESERVE_BALANCE,
+            token_address,
+            insurance_tokens,
+        )?;
+        deficit = deficit.saturating_sub(
+            insurance_tokens
+                .checked_mul(Uint128::from(10u128))
+                .map_err(|_| ContractError::Overflow {})?,
+        );
+
+        let mut events = vec![];
+        let mut deleveraged = Uint128::zero();
+        for (rank, position) in ranking.into_iter().enumerate() {
+            if deficit.is_zero() {
+                break;
+            }
+            let account =
+                sub_accounts::account_address(&position.user_address, Some(position.account_id))?;
+            let absorbed = position.closable.min(deficit);
+
+            // Only whole collateral units are credited, as far as the reserve holds them
+            let reserve = RESERVE_BALANCE
+                .may_load(deps.storage, token_address)?
+                .unwrap_or_default();
+            let credited = (position.closable - absorbed)
+                .checked_div(Uint128::from(10u128))
+                .map_err(|_| ContractError::Overflow {})?
+                .min(reserve);
+            let credited_wrapped = credited
+                .checked_mul(Uint128::from(10u128))
+                .map_err(|_| ContractError::Overflow {})?;
+            let closed = absorbed + credited_wrapped;
+
+            let vtoken_balance = USER_VTOKEN_BALANCE.update(
+                deps.storage,
+                (token_address, &account),
+                |opt_balance| -> Result<Uint128, ContractError> {
+                    opt_balance
+                        .unwrap_or_default()
+                        .checked_sub(closed)
+                        .map_err(|_| ContractError::Overflow {})
+                },
+            )?;
+            market_module::decrease_total(deps.storage, &TOTAL_VTOKENS, token_address, closed)?;
+
+            let (collateral_balance, _) = deposit_collateral::credit_collateral(
+                deps.storage,
+                env.block.time,
+                token_address,
+                &account,
+                credited,
+            )?;
+            market_module::decrease_total(deps.storage, &RESERVE_BALANCE, token_address, credited)?;
+            market_module::increase_total(
+                deps.storage,
+                &TOTAL_COLLATERAL,
+                token_address,
+                credited,
+            )?;
+            market_module::increase_total(
+                deps.storage,
+                &TOTAL_WRAPPED,
+                token_address,
+                credited_wrapped,
+            )?;
+            deficit -= absorbed;
+            deleveraged += absorbed;
+
+            events.push(LeverageEvent::AutoDeleveraged {
+                user: account,
+                token: token_address.to_string(),
+                rank: rank as u64 + 1,
+                closed,
+                absorbed,
+                credited,
+                collateral_balance,
+                vtoken_balance,
+            });
+        }
+
+        // A running scan is dropped with the deficit, the next deficit starts over
+        if deficit.is_zero() {
+            DEFICITS.remove(deps.storage, token_address);
+            ADL_SCANS.remove(deps.storage, token_address);
+        } else {
+            DEFICITS.save(deps.storage, token_address, &deficit)?;
+        }
+
+        events.insert(
+            0,
+            LeverageEvent::DeficitCovered {
+                token: token_address.to_string(),
+                insurance_vtokens,
+                insurance_tokens,
+                deleveraged,
+                deficit,
+            },
+        );
+        Ok(events)
+    }
+
+    /**
+     * Ranks up to `limit` holders of the token.
+     *
+     * Keepers continue the scan from `next_start_after` until it is `None`.
+     */
+    pub fn fetch_adl_ranking(
+        deps: Deps,
+        env: Env,
+        token_address: Addr,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    ) -> Result<AdlRankingResponse, ContractError> {
+        let (ranking, next_start_after) = rank_positions(
+            deps,
+            &env,
+            &token_address,
+            start_after.map(Addr::unchecked),
+            limit,
+        )?;
+
+        Ok(AdlRankingResponse {
+            token: token_address.to_string(),
+            deficit: DEFICITS
+                .may_load(deps.storage, &token_address)?
+                .unwrap_or_default(),
+            ranking,
+            next_start_after,
+        })
+    }
+}
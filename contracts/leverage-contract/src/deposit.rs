@@ -1,56 +1,54 @@
 pub mod deposit_collateral {
 
+    use crate::access::access_control;
     use crate::error::ContractError;
+    use crate::events::leverage_events::LeverageEvent;
+    use crate::incentives::incentives_module;
+    use crate::listing::token_listing;
+    use crate::market::market_module;
     use crate::msg::Cw20ReceiveMsg;
-    use crate::state::{LISTED_TOKEN, TOKEN_BALANCE, WRAPPED_TOKEN_BALANCE};
+    use crate::referral::referral_program;
+    use crate::state::{TOKEN_BALANCE, TOTAL_COLLATERAL, TOTAL_WRAPPED, WRAPPED_TOKEN_BALANCE};
+    use crate::sub_account::sub_accounts;
 
-    use cosmwasm_std::{Addr, DepsMut, Env, MessageInfo, Response, Uint128};
+    use cosmwasm_std::{Addr, DepsMut, Env, MessageInfo, Response, Storage, Timestamp, Uint128};
 
     pub fn native_token(
         deps: DepsMut,
         _env: Env,
         info: MessageInfo,
         token_address: Addr,
+        referral_code: Option<String>,
     ) -> Result<Response, ContractError> {
-        if info.funds[0].amount.le(&Uint128::zero()) {
-            return Err(ContractError::InsufficientNativeToken {});
-        }
+        access_control::assert_not_paused(deps.storage)?;
 
-        TOKEN_BALANCE.update(
+        let token_info = token_listing::load_active_token(deps.storage, &token_address)?;
+        let amount = token_listing::native_payment(&info, &token_info)?;
+        let referrer_bound =
+            referral_program::bind_referrer(deps.storage, &info.sender, referral_code)?;
+        let (collateral_balance, wrapped_balance) = credit_collateral(
             deps.storage,
-            (&token_address, &info.sender),
-            |opt_balance| -> Result<Uint128, ContractError> {
-                match opt_balance {
-                    Some(balance) => match balance.checked_add(info.funds[0].amount) {
-                        Ok(data) => Ok(data),
-                        Err(_) => Err(ContractError::Overflow {}),
-                    },
-                    None => Ok(info.funds[0].amount),
-                }
-            },
+            _env.block.time,
+            &token_address,
+            &info.sender,
+            amount,
         )?;
 
-        // Calculate the wrapped token amount and update the user's wrapped token balance
-        let wrapped_token = match info.funds[0].amount.checked_mul(Uint128::from(10u128)) {
-            Ok(data) => data,
-            Err(_) => return Err(ContractError::Overflow {}),
-        };
+        add_to_totals(deps, &token_address, amount)?;
 
-        WRAPPED_TOKEN_BALANCE.update(
-            deps.storage,
-            (&token_address, &info.sender),
-            |opt_balance| -> Result<Uint128, ContractError> {
-                match opt_balance {
-                    Some(balance) => match balance.checked_add(wrapped_token) {
-                        Ok(data) => Ok(data),
-                        Err(_) => Err(ContractError::OverflowBalance {}),
-                    },
-                    None => Ok(wrapped_token),
+        Ok(Response::new()
+            .add_attribute("method", "deposit_collateral_native")
+            .add_event(
+                LeverageEvent::Deposit {
+                    user: info.sender,
+                    token: token_address.to_string(),
+                    amount,
+                    collateral_balance,
+                    wrapped_balance,
                 }
-            },
-        )?;
-
-        Ok(Response::new().add_attribute("method", "deposit_collateral_native"))
+                .into(),
+            )
+            .add_events(referrer_bound.map(Into::into)))
     }
 
     /**
@@ -58,8 +56,8 @@ pub mod deposit_collateral {
      *
      * This function allows users to deposit tokens into the contract.
      * It performs the following steps:
-     * 2. Loads the listed tokens from storage.
-     * 3. Checks if the sender's token is listed in the contract.
+     * 2. Loads the listed token of the sending CW20 contract.
+     * 3. Checks if the token still accepts deposits.
      * 4. If the token is listed, updates the user's token balance.
      * 5. Calculates the amount of unminted tokens based on the received amount.
      * 6. Updates the user's unminted token balance.
@@ -75,56 +73,110 @@ pub mod deposit_collateral {
         _env: Env,
         info: MessageInfo,
         msg: Cw20ReceiveMsg,
+        referral_code: Option<String>,
     ) -> Result<Response, ContractError> {
-        let token = match LISTED_TOKEN.load(deps.storage) {
-            Ok(tokens) => tokens,
-            Err(_) => vec![],
-        };
+        access_control::assert_not_paused(deps.storage)?;
 
-        // Check if the sender's token is listed
-        if token.contains(&info.sender.to_string()) {
-            println!("{:?}", msg.clone());
-            // Update the user's token balance
-            TOKEN_BALANCE.update(
-                deps.storage,
-                (&Addr::unchecked("USDC"), &Addr::unchecked(&msg.sender)),
-                |opt_balance| -> Result<Uint128, ContractError> {
-                    match opt_balance {
-                        Some(balance) => match balance.checked_add(msg.amount) {
-                            Ok(data) => Ok(data),
-                            Err(_) => Err(ContractError::Overflow {}),
-                        },
-                        None => Ok(msg.amount),
-                    }
-                },
-            )?;
-
-            // Calculate the wrapped token amount and update the user's wrapped token balance
-            let wrapped_token = match msg.amount.checked_mul(Uint128::from(10u128)) {
-                Ok(data) => data,
-                Err(_) => return Err(ContractError::Overflow {}),
-            };
-
-            WRAPPED_TOKEN_BALANCE.update(
-                deps.storage,
-                (&Addr::unchecked("USDC"), &Addr::unchecked(&msg.sender)),
-                |opt_balance| -> Result<Uint128, ContractError> {
-                    match opt_balance {
-                        Some(balance) => match balance.checked_add(wrapped_token) {
-                            Ok(data) => Ok(data),
-                            Err(_) => Err(ContractError::OverflowBalance {}),
-                        },
-                        None => Ok(wrapped_token),
-                    }
-                },
-            )?;
-        } else {
-            return Err(ContractError::UnauthorizedToken {});
-        }
+        let token_info = token_listing::load_cw20_token(deps.storage, &info.sender)?;
+        token_listing::assert_active(&token_info)?;
+        let token_key = Addr::unchecked(token_info.token);
+        let referrer_bound = referral_program::bind_referrer(
+            deps.storage,
+            &Addr::unchecked(&msg.sender),
+            referral_code,
+        )?;
+        let (collateral_balance, wrapped_balance) = credit_collateral(
+            deps.storage,
+            _env.block.time,
+            &token_key,
+            &Addr::unchecked(&msg.sender),
+            msg.amount,
+        )?;
+
+        add_to_totals(deps, &token_key, msg.amount)?;
 
         Ok(Response::new()
             .add_attribute("method", "token_deposit")
-            .add_attribute("token_owner", msg.sender)
-            .add_attribute("token_address", info.sender))
+            .add_attribute("token_owner", msg.sender.clone())
+            .add_attribute("token_address", info.sender)
+            .add_event(
+                LeverageEvent::Deposit {
+                    user: Addr::unchecked(msg.sender),
+                    token: token_key.to_string(),
+                    amount: msg.amount,
+                    collateral_balance,
+                    wrapped_balance,
+                }
+                .into(),
+            )
+            .add_events(referrer_bound.map(Into::into)))
+    }
+
+    /**
+     * Adds collateral and its wrapped tokens to the user's balances, without
+     * touching the market totals.
+     *
+     * @return The new collateral and wrapped token balances.
+     */
+    pub fn credit_collateral(
+        storage: &mut dyn Storage,
+        now: Timestamp,
+        token_address: &Addr,
+        user_address: &Addr,
+        amount: Uint128,
+    ) -> Result<(Uint128, Uint128), ContractError> {
+        incentives_module::update_user_rewards(storage, token_address, user_address, now)?;
+
+        let collateral_balance = TOKEN_BALANCE.update(
+            storage,
+            (token_address, user_address),
+            |opt_balance| -> Result<Uint128, ContractError> {
+                match opt_balance {
+                    Some(balance) => match balance.checked_add(amount) {
+                        Ok(data) => Ok(data),
+                        Err(_) => Err(ContractError::Overflow {}),
+                    },
+                    None => Ok(amount),
+                }
+            },
+        )?;
+
+        // Calculate the wrapped token amount and update the user's wrapped token balance
+        let wrapped_token = match amount.checked_mul(Uint128::from(10u128)) {
+            Ok(data) => data,
+            Err(_) => return Err(ContractError::Overflow {}),
+        };
+
+        let wrapped_balance = WRAPPED_TOKEN_BALANCE.update(
+            storage,
+            (token_address, user_address),
+            |opt_balance| -> Result<Uint128, ContractError> {
+                match opt_balance {
+                    Some(balance) => match balance.checked_add(wrapped_token) {
+                        Ok(data) => Ok(data),
+                        Err(_) => Err(ContractError::OverflowBalance {}),
+                    },
+                    None => Ok(wrapped_token),
+                }
+            },
+        )?;
+
+        sub_accounts::record(storage, user_address)?;
+        Ok((collateral_balance, wrapped_balance))
+    }
+
+    fn add_to_totals(
+        deps: DepsMut,
+        token_address: &Addr,
+        amount: Uint128,
+    ) -> Result<(), ContractError> {
+        let wrapped_token = match amount.checked_mul(Uint128::from(10u128)) {
+            Ok(data) => data,
+            Err(_) => return Err(ContractError::Overflow {}),
+        };
+        market_module::increase_total(deps.storage, &TOTAL_COLLATERAL, token_address, amount)?;
+        market_module::increase_total(deps.storage, &TOTAL_WRAPPED, token_address, wrapped_token)?;
+
+        market_module::assert_deposit_cap(deps.storage, token_address)
     }
 }
@@ -1,6 +1,6 @@
-use cosmwasm_std::StdError;
-use thiserror::Error;
+use cosmwasm_std::{StdError, Uint128};
 use serde::{Serialize, Serializer};
+use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum ContractError {
@@ -16,6 +16,18 @@ pub enum ContractError {
     #[error("Insufficient Balance")]
     InsufficientBalance {},
 
+    #[error("Amount must be greater than zero")]
+    ZeroAmount {},
+
+    #[error("Insufficient reserve to back the burned vTokens")]
+    InsufficientReserve {},
+
+    #[error("Sender is not the ibc-hooks address of {original_sender} on {channel}")]
+    InvalidIbcHookSender {
+        channel: String,
+        original_sender: String,
+    },
+
     #[error("Overflow Balance")]
     OverflowBalance {},
 
@@ -24,7 +36,7 @@ pub enum ContractError {
 
     #[error("Contract Instantiate Error")]
     ContractInstantiateError {},
-    
+
     #[error("Custom Error val: {val:?}")]
     CustomError { val: String },
 
@@ -45,7 +57,7 @@ pub enum ContractError {
 
     #[error("User VToken Balance Load Error")]
     UserVTokenBalanceLoadError {},
-    
+
     #[error("User repay more than borrowed")]
     RepayOverflow {},
 
@@ -62,7 +74,7 @@ pub enum ContractError {
     UpdateTokenListFailed {},
 
     #[error("Unable to fetch listed token")]
-    UnableToFetchListedToken {}, 
+    UnableToFetchListedToken {},
 
     #[error("Wrapped token query failed")]
     WrappedTokenQueryFailed {},
@@ -76,7 +88,6 @@ pub enum ContractError {
     #[error("User profit token balance query failed")]
     UserProfitTokenBalanceQueryFailed {},
 
-
     #[error("unable to update wrapped token balance")]
     UpdateWrapTokenErr {},
 
@@ -96,14 +107,180 @@ pub enum ContractError {
     UserVTokenBalanceQueryFailed {},
 
     #[error("User orders query failed")]
-    UserOrderQueryFailed {}
+    UserOrderQueryFailed {},
+
+    #[error("Protocol is paused")]
+    Paused {},
+
+    #[error("No ownership transfer is pending")]
+    NoPendingOwner {},
+
+    #[error("Role is not granted to the address")]
+    RoleNotGranted {},
+
+    #[error("Token {token} is not listed")]
+    TokenNotListed { token: String },
+
+    #[error("Token {token} is already listed")]
+    TokenAlreadyListed { token: String },
+
+    #[error("Token {token} only allows reducing positions")]
+    TokenNotActive { token: String },
+
+    #[error("Token {token} is delisted")]
+    TokenDelisted { token: String },
+
+    #[error("Decimals of token {token} are missing")]
+    MissingDecimals { token: String },
+
+    #[error("Metadata of listed token {token} is missing from the migration")]
+    MissingTokenMetadata { token: String },
+
+    #[error("Contract has nothing to migrate")]
+    NothingToMigrate {},
+
+    #[error("Decimals can't be above {max}")]
+    DecimalsTooLarge { max: u8 },
+
+    #[error("No price available for token {token}")]
+    PriceNotAvailable { token: String },
+
+    #[error("TWAP window must be between 1 second and 48 hours")]
+    InvalidTwapWindow {},
+
+    #[error("Token {token} isn't a native denom and has no Osmosis pool")]
+    TwapNeedsNativeToken { token: String },
+
+    #[error("Price of token {token} is older than its maximum age")]
+    StalePrice { token: String },
+
+    #[error("Confidence interval of the price of token {token} is too wide")]
+    PriceUncertain { token: String },
+
+    #[error("Maximum price age must be above zero")]
+    InvalidPriceAge {},
+
+    #[error("Confidence ratio must be above zero and at most one")]
+    InvalidConfidenceRatio {},
+
+    #[error("Price sources of token {token} disagree beyond the allowed deviation")]
+    PriceDeviation { token: String },
+
+    #[error("Aggregated prices need two to five sources that aren't aggregates themselves")]
+    InvalidAggregateSources {},
+
+    #[error("Maximum price deviation must be above zero and at most one")]
+    InvalidDeviation {},
+
+    #[error("Aggregated prices need at least two and at most all of their sources")]
+    InvalidMinSources {},
+
+    #[error("Reward schedule needs a reward denom and has to end after it starts")]
+    InvalidRewardSchedule {},
+
+    #[error("Rewards of the market are paid in {denom} and can't change denom")]
+    RewardDenomChanged { denom: String },
+
+    #[error("No rewards to claim")]
+    NoRewards {},
+
+    #[error("Reward schedule has to be sent {required} of its reward denom")]
+    RewardsNotFunded { required: Uint128 },
+
+    #[error("Action doesn't take the funds sent along")]
+    UnexpectedFunds {},
+
+    #[error("Order pays out more than the price of the sold tokens")]
+    OrderAbovePrice {},
+
+    #[error("Order pays out less than the minimum the user accepts")]
+    OrderBelowMinimum {},
+
+    #[error("Account would fall below the maintenance margin")]
+    UnhealthyAccount {},
+
+    #[error("Deposit cap of token {token} exceeded")]
+    DepositCapExceeded { token: String },
+
+    #[error("Borrow cap of token {token} exceeded")]
+    BorrowCapExceeded { token: String },
+
+    #[error("Fees can't be above 10%")]
+    InvalidFee {},
+
+    #[error("Referral share can't be above 100%")]
+    InvalidReferralShare {},
+
+    #[error("Referral codes have 3 to 32 lowercase letters, digits, '-' or '_'")]
+    InvalidReferralCode {},
+
+    #[error("Referral code {code} is already taken")]
+    ReferralCodeTaken { code: String },
+
+    #[error("Referrer already registered code {code}")]
+    ReferralCodeRegistered { code: String },
+
+    #[error("Referral code {code} not found")]
+    UnknownReferralCode { code: String },
+
+    #[error("Users can't refer themselves")]
+    SelfReferral {},
+
+    #[error("No referral rebates to claim")]
+    NoRebates {},
+
+    #[error("Sub-account {account_id} doesn't exist, account ids go up to 64")]
+    InvalidAccountId { account_id: u64 },
+
+    #[error("Collateral can only move between different accounts")]
+    SameAccount {},
+
+    #[error("Operator isn't approved to {permission} for the account")]
+    OperatorNotApproved { permission: String },
+
+    #[error("Operator approvals need at least one permission")]
+    NoOperatorPermissions {},
+
+    #[error("Can't approve yourself as operator")]
+    SelfOperator {},
+
+    #[error("Operator approval already expired")]
+    ApprovalExpired {},
+
+    #[error("Operator has no approval to revoke")]
+    NoOperatorApproval {},
+
+    #[error("Token {token} has no deficit to cover")]
+    NoDeficit { token: String },
+
+    #[error("Only the wallet itself can {action}")]
+    OwnerOnlyAction { action: String },
+
+    #[error("Account is healthy and can't be liquidated")]
+    AccountHealthy {},
+
+    #[error("Liquidation repays more than the close factor allows")]
+    RepayAboveCloseFactor {},
+
+    #[error("Timelock action {id} not found")]
+    ActionNotFound { id: u64 },
+
+    #[error("Timelock action can't be executed before {eta}")]
+    TimelockNotExpired { eta: u64 },
+
+    #[error("Timelock action {id} expired")]
+    ActionExpired { id: u64 },
+
+    #[error("Timelock delay must be at least {min} seconds")]
+    TimelockDelayTooShort { min: u64 },
 }
 
 impl Serialize for ContractError {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-        where
-            S: Serializer {
-        serializer.serialize_str("ContractError")   
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str("ContractError")
     }
 }
 
@@ -111,4 +288,4 @@ impl From<ContractError> for StdError {
     fn from(err: ContractError) -> StdError {
         StdError::generic_err(err.to_string())
     }
-}
\ No newline at end of file
+}
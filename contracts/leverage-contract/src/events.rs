@@ -0,0 +1,658 @@
+pub mod leverage_events {
+    use cosmwasm_std::{Addr, Coin, Decimal, Event, Timestamp, Uint128};
+    use cw20::Expiration;
+
+    use crate::state::{OperatorPermission, Role, TokenStatus};
+
+    /**
+     * Structured events of every state change of the protocol.
+     *
+     * Each variant becomes an event of type `leverage-<kind>`, which the chain
+     * reports as `wasm-leverage-<kind>`. Balances carry the value after the
+     * change, so indexers don't have to replay the arithmetic.
+     */
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum LeverageEvent {
+        Deposit {
+            user: Addr,
+            token: String,
+            amount: Uint128,
+            collateral_balance: Uint128,
+            wrapped_balance: Uint128,
+        },
+        Borrow {
+            user: Addr,
+            token: String,
+            amount: Uint128,
+            fee: Uint128,
+            borrow_balance: Uint128,
+            wrapped_balance: Uint128,
+            vtoken_balance: Uint128,
+        },
+        Order {
+            user: Addr,
+            order_id: String,
+            token_in: String,
+            amount_in: Uint128,
+            token_out: String,
+            amount_out: Uint128,
+            fee: Uint128,
+            vtoken_in_balance: Uint128,
+            vtoken_out_balance: Uint128,
+        },
+        Repay {
+            user: Addr,
+            token: String,
+            interest_paid: Uint128,
+            principal_paid: Uint128,
+            borrow_balance: Uint128,
+            interest_balance: Uint128,
+            vtoken_balance: Uint128,
+        },
+        TopUp {
+            user: Addr,
+            token: String,
+            amount: Uint128,
+            vtoken_balance: Uint128,
+        },
+        Burn {
+            user: Addr,
+            token: String,
+            amount: Uint128,
+            collateral_balance: Uint128,
+            wrapped_balance: Uint128,
+            vtoken_balance: Uint128,
+        },
+        IbcHookDeposit {
+            user: Addr,
+            channel: String,
+            original_sender: String,
+            token: String,
+            amount: Uint128,
+        },
+        Withdraw {
+            user: Addr,
+            token: String,
+            amount: Uint128,
+            collateral_balance: Uint128,
+            wrapped_balance: Uint128,
+        },
+        Liquidate {
+            user: Addr,
+            liquidator: Addr,
+            token: String,
+            amount: Uint128,
+            collateral_token: String,
+            seized: Uint128,
+            borrow_balance: Uint128,
+            collateral_balance: Uint128,
+        },
+        CollectRevenue {
+            collector: Addr,
+            token: String,
+            amount: Uint128,
+            revenue_balance: Uint128,
+        },
+        RoleGranted {
+            role: Role,
+            address: Addr,
+        },
+        RoleRevoked {
+            role: Role,
+            address: Addr,
+        },
+        OwnershipProposed {
+            pending_owner: Addr,
+        },
+        OwnershipAccepted {
+            owner: Addr,
+        },
+        OwnerReplaced {
+            previous_owner: Addr,
+            owner: Addr,
+        },
+        Paused {
+            sender: Addr,
+        },
+        Unpaused {
+            sender: Addr,
+        },
+        TokenListed {
+            token: String,
+            asset: String,
+            decimals: u8,
+            symbol: String,
+        },
+        TokenStatusUpdated {
+            token: String,
+            status: TokenStatus,
+        },
+        ActionProposed {
+            id: u64,
+            proposer: Addr,
+            eta: u64,
+        },
+        ActionCancelled {
+            id: u64,
+        },
+        ActionExecuted {
+            id: u64,
+        },
+        BorrowRateUpdated {
+            token: String,
+            rate: Decimal,
+        },
+        PriceSourceUpdated {
+            token: String,
+        },
+        MarketCapsUpdated {
+            token: String,
+            deposit_cap: Option<Uint128>,
+            borrow_cap: Option<Uint128>,
+        },
+        FeesUpdated {
+            borrow_fee: Decimal,
+            trading_fee: Decimal,
+            referral_share: Decimal,
+        },
+        TimelockDelayUpdated {
+            delay: u64,
+        },
+        RewardScheduleUpdated {
+            token: String,
+            reward_denom: String,
+            start_time: Timestamp,
+            end_time: Timestamp,
+            collateral_emission: Uint128,
+            borrow_emission: Uint128,
+            funded: Uint128,
+        },
+        RewardsClaimed {
+            user: Addr,
+            rewards: Vec<Coin>,
+        },
+        ReferralCodeRegistered {
+            referrer: Addr,
+            code: String,
+        },
+        ReferrerBound {
+            user: Addr,
+            referrer: Addr,
+            code: String,
+        },
+        ReferralRebatesClaimed {
+            referrer: Addr,
+            token: String,
+            borrow_fees: Uint128,
+            trading_fees: Uint128,
+            vtoken_balance: Uint128,
+        },
+        CollateralTransferred {
+            owner: Addr,
+            token: String,
+            amount: Uint128,
+            from_account: u64,
+            to_account: u64,
+            from_collateral_balance: Uint128,
+            to_collateral_balance: Uint128,
+        },
+        OperatorApproved {
+            owner: Addr,
+            operator: Addr,
+            permissions: Vec<OperatorPermission>,
+            expires: Expiration,
+        },
+        OperatorRevoked {
+            owner: Addr,
+            operator: Addr,
+        },
+        DeficitCovered {
+            token: String,
+            insurance_vtokens: Uint128,
+            insurance_tokens: Uint128,
+            deleveraged: Uint128,
+            deficit: Uint128,
+        },
+        AutoDeleveraged {
+            user: Addr,
+            token: String,
+            rank: u64,
+            closed: Uint128,
+            absorbed: Uint128,
+            credited: Uint128,
+            collateral_balance: Uint128,
+            vtoken_balance: Uint128,
+        },
+        ForceSettled {
+            user: Addr,
+            token: String,
+            repaid: Uint128,
+            seized: Uint128,
+            bad_debt: Uint128,
+            collateral_balance: Uint128,
+            vtoken_balance: Uint128,
+        },
+        SettlementSeized {
+            user: Addr,
+            token: String,
+            debt_token: String,
+            vtokens: Uint128,
+            collateral: Uint128,
+            covered: Uint128,
+            collateral_balance: Uint128,
+            vtoken_balance: Uint128,
+        },
+    }
+
+    impl LeverageEvent {
+        pub fn kind(&self) -> &'static str {
+            match self {
+                LeverageEvent::Deposit { .. } => "deposit",
+                LeverageEvent::Borrow { .. } => "borrow",
+                LeverageEvent::Order { .. } => "order",
+                LeverageEvent::Repay { .. } => "repay",
+                LeverageEvent::TopUp { .. } => "top_up",
+                LeverageEvent::Burn { .. } => "burn",
+                LeverageEvent::IbcHookDeposit { .. } => "ibc_hook_deposit",
+                LeverageEvent::Withdraw { .. } => "withdraw",
+                LeverageEvent::Liquidate { .. } => "liquidate",
+                LeverageEvent::CollectRevenue { .. } => "collect_revenue",
+                LeverageEvent::RoleGranted { .. } => "role_granted",
+                LeverageEvent::RoleRevoked { .. } => "role_revoked",
+                LeverageEvent::OwnershipProposed { .. } => "ownership_proposed",
+                LeverageEvent::OwnershipAccepted { .. } => "ownership_accepted",
+                LeverageEvent::OwnerReplaced { .. } => "owner_replaced",
+                LeverageEvent::Paused { .. } => "paused",
+                LeverageEvent::Unpaused { .. } => "unpaused",
+                LeverageEvent::TokenListed { .. } => "token_listed",
+                LeverageEvent::TokenStatusUpdated { .. } => "token_status_updated",
+                LeverageEvent::ActionProposed { .. } => "action_proposed",
+                LeverageEvent::ActionCancelled { .. } => "action_cancelled",
+                LeverageEvent::ActionExecuted { .. } => "action_executed",
+                LeverageEvent::BorrowRateUpdated { .. } => "borrow_rate_updated",
+                LeverageEvent::PriceSourceUpdated { .. } => "price_source_updated",
+                LeverageEvent::MarketCapsUpdated { .. } => "market_caps_updated",
+                LeverageEvent::FeesUpdated { .. } => "fees_updated",
+                LeverageEvent::TimelockDelayUpdated { .. } => "timelock_delay_updated",
+                LeverageEvent::RewardScheduleUpdated { .. } => "reward_schedule_updated",
+                LeverageEvent::RewardsClaimed { .. } => "rewards_claimed",
+                LeverageEvent::ReferralCodeRegistered { .. } => "referral_code_registered",
+                LeverageEvent::ReferrerBound { .. } => "referrer_bound",
+                LeverageEvent::ReferralRebatesClaimed { .. } => "referral_rebates_claimed",
+                LeverageEvent::CollateralTransferred { .. } => "collateral_transferred",
+                LeverageEvent::OperatorApproved { .. } => "operator_approved",
+                LeverageEvent::OperatorRevoked { .. } => "operator_revoked",
+                LeverageEvent::DeficitCovered { .. } => "deficit_covered",
+                LeverageEvent::AutoDeleveraged { .. } => "auto_deleveraged",
+                LeverageEvent::ForceSettled { .. } => "force_settled",
+                LeverageEvent::SettlementSeized { .. } => "settlement_seized",
+            }
+        }
+    }
+
+    impl From<LeverageEvent> for Event {
+        fn from(leverage_event: LeverageEvent) -> Event {
+            let event = Event::new(format!("leverage-{}", leverage_event.kind()));
+
+            match leverage_event {
+                LeverageEvent::Deposit {
+                    user,
+                    token,
+                    amount,
+                    collateral_balance,
+                    wrapped_balance,
+                } => event
+                    .add_attribute("user", user)
+                    .add_attribute("token", token)
+                    .add_attribute("amount", amount)
+                    .add_attribute("collateral_balance", collateral_balance)
+                    .add_attribute("wrapped_balance", wrapped_balance),
+                LeverageEvent::Borrow {
+                    user,
+                    token,
+                    amount,
+                    fee,
+                    borrow_balance,
+                    wrapped_balance,
+                    vtoken_balance,
+                } => event
+                    .add_attribute("user", user)
+                    .add_attribute("token", token)
+                    .add_attribute("amount", amount)
+                    .add_attribute("fee", fee)
+                    .add_attribute("borrow_balance", borrow_balance)
+                    .add_attribute("wrapped_balance", wrapped_balance)
+                    .add_attribute("vtoken_balance", vtoken_balance),
+                LeverageEvent::Order {
+                    user,
+                    order_id,
+                    token_in,
+                    amount_in,
+                    token_out,
+                    amount_out,
+                    fee,
+                    vtoken_in_balance,
+                    vtoken_out_balance,
+                } => event
+                    .add_attribute("user", user)
+                    .add_attribute("order_id", order_id)
+                    .add_attribute("token_in", token_in)
+                    .add_attribute("amount_in", amount_in)
+                    .add_attribute("token_out", token_out)
+                    .add_attribute("amount_out", amount_out)
+                    .add_attribute("fee", fee)
+                    .add_attribute("vtoken_in_balance", vtoken_in_balance)
+                    .add_attribute("vtoken_out_balance", vtoken_out_balance),
+                LeverageEvent::Repay {
+                    user,
+                    token,
+                    interest_paid,
+                    principal_paid,
+                    borrow_balance,
+                    interest_balance,
+                    vtoken_balance,
+                } => event
+                    .add_attribute("user", user)
+                    .add_attribute("token", token)
+                    .add_attribute("interest_paid", interest_paid)
+                    .add_attribute("principal_paid", principal_paid)
+                    .add_attribute("borrow_balance", borrow_balance)
+                    .add_attribute("interest_balance", interest_balance)
+                    .add_attribute("vtoken_balance", vtoken_balance),
+                LeverageEvent::TopUp {
+                    user,
+                    token,
+                    amount,
+                    vtoken_balance,
+                } => event
+                    .add_attribute("user", user)
+                    .add_attribute("token", token)
+                    .add_attribute("amount", amount)
+                    .add_attribute("vtoken_balance", vtoken_balance),
+                LeverageEvent::Burn {
+                    user,
+                    token,
+                    amount,
+                    collateral_balance,
+                    wrapped_balance,
+                    vtoken_balance,
+                } => event
+                    .add_attribute("user", user)
+                    .add_attribute("token", token)
+                    .add_attribute("amount", amount)
+                    .add_attribute("collateral_balance", collateral_balance)
+                    .add_attribute("wrapped_balance", wrapped_balance)
+                    .add_attribute("vtoken_balance", vtoken_balance),
+                LeverageEvent::IbcHookDeposit {
+                    user,
+                    channel,
+                    original_sender,
+                    token,
+                    amount,
+                } => event
+                    .add_attribute("user", user)
+                    .add_attribute("channel", channel)
+                    .add_attribute("original_sender", original_sender)
+                    .add_attribute("token", token)
+                    .add_attribute("amount", amount),
+                LeverageEvent::Withdraw {
+                    user,
+                    token,
+                    amount,
+                    collateral_balance,
+                    wrapped_balance,
+                } => event
+                    .add_attribute("user", user)
+                    .add_attribute("token", token)
+                    .add_attribute("amount", amount)
+                    .add_attribute("collateral_balance", collateral_balance)
+                    .add_attribute("wrapped_balance", wrapped_balance),
+                LeverageEvent::Liquidate {
+                    user,
+                    liquidator,
+                    token,
+                    amount,
+                    collateral_token,
+                    seized,
+                    borrow_balance,
+                    collateral_balance,
+                } => event
+                    .add_attribute("user", user)
+                    .add_attribute("liquidator", liquidator)
+                    .add_attribute("token", token)
+                    .add_attribute("amount", amount)
+                    .add_attribute("collateral_token", collateral_token)
+                    .add_attribute("seized", seized)
+                    .add_attribute("borrow_balance", borrow_balance)
+                    .add_attribute("collateral_balance", collateral_balance),
+                LeverageEvent::CollectRevenue {
+                    collector,
+                    token,
+                    amount,
+                    revenue_balance,
+                } => event
+                    .add_attribute("collector", collector)
+                    .add_attribute("token", token)
+                    .add_attribute("amount", amount)
+                    .add_attribute("revenue_balance", revenue_balance),
+                LeverageEvent::RoleGranted { role, address }
+                | LeverageEvent::RoleRevoked { role, address } => event
+                    .add_attribute("role", role.as_str())
+                    .add_attribute("address", address),
+                LeverageEvent::OwnershipProposed { pending_owner } => {
+                    event.add_attribute("pending_owner", pending_owner)
+                }
+                LeverageEvent::OwnershipAccepted { owner } => event.add_attribute("owner", owner),
+                LeverageEvent::OwnerReplaced {
+                    previous_owner,
+                    owner,
+                } => event
+                    .add_attribute("previous_owner", previous_owner)
+                    .add_attribute("owner", owner),
+                LeverageEvent::Paused { sender } | LeverageEvent::Unpaused { sender } => {
+                    event.add_attribute("sender", sender)
+                }
+                LeverageEvent::TokenListed {
+                    token,
+                    asset,
+                    decimals,
+                    symbol,
+                } => event
+                    .add_attribute("token", token)
+                    .add_attribute("asset", asset)
+                    .add_attribute("decimals", decimals.to_string())
+                    .add_attribute("symbol", symbol),
+                LeverageEvent::TokenStatusUpdated { token, status } => event
+                    .add_attribute("token", token)
+                    .add_attribute("status", status.as_str()),
+                LeverageEvent::ActionProposed { id, proposer, eta } => event
+                    .add_attribute("action_id", id.to_string())
+                    .add_attribute("proposer", proposer)
+                    .add_attribute("eta", eta.to_string()),
+                LeverageEvent::ActionCancelled { id } | LeverageEvent::ActionExecuted { id } => {
+                    event.add_attribute("action_id", id.to_string())
+                }
+                LeverageEvent::BorrowRateUpdated { token, rate } => event
+                    .add_attribute("token", token)
+                    .add_attribute("rate", rate.to_string()),
+                LeverageEvent::PriceSourceUpdated { token } => event.add_attribute("token", token),
+                LeverageEvent::MarketCapsUpdated {
+                    token,
+                    deposit_cap,
+                    borrow_cap,
+                } => {
+                    let event = event.add_attribute("token", token);
+                    let event = match deposit_cap {
+                        Some(cap) => event.add_attribute("deposit_cap", cap),
+                        None => event,
+                    };
+                    match borrow_cap {
+                        Some(cap) => event.add_attribute("borrow_cap", cap),
+                        None => event,
+                    }
+                }
+                LeverageEvent::FeesUpdated {
+                    borrow_fee,
+                    trading_fee,
+                    referral_share,
+                } => event
+                    .add_attribute("borrow_fee", borrow_fee.to_string())
+                    .add_attribute("trading_fee", trading_fee.to_string())
+                    .add_attribute("referral_share", referral_share.to_string()),
+                LeverageEvent::TimelockDelayUpdated { delay } => {
+                    event.add_attribute("delay", delay.to_string())
+                }
+                LeverageEvent::RewardScheduleUpdated {
+                    token,
+                    reward_denom,
+                    start_time,
+                    end_time,
+                    collateral_emission,
+                    borrow_emission,
+                    funded,
+                } => event
+                    .add_attribute("token", token)
+                    .add_attribute("reward_denom", reward_denom)
+                    .add_attribute("start_time", start_time.seconds().to_string())
+                    .add_attribute("end_time", end_time.seconds().to_string())
+                    .add_attribute("collateral_emission", collateral_emission)
+                    .add_attribute("borrow_emission", borrow_emission)
+                    .add_attribute("funded", funded),
+                LeverageEvent::RewardsClaimed { user, rewards } => {
+                    event.add_attribute("user", user).add_attribute(
+                        "rewards",
+                        rewards
+                            .iter()
+                            .map(Coin::to_string)
+                            .collect::<Vec<String>>()
+                            .join(","),
+                    )
+                }
+                LeverageEvent::ReferralCodeRegistered { referrer, code } => event
+                    .add_attribute("referrer", referrer)
+                    .add_attribute("code", code),
+                LeverageEvent::ReferrerBound {
+                    user,
+                    referrer,
+                    code,
+                } => event
+                    .add_attribute("user", user)
+                    .add_attribute("referrer", referrer)
+                    .add_attribute("code", code),
+                LeverageEvent::ReferralRebatesClaimed {
+                    referrer,
+                    token,
+                    borrow_fees,
+                    trading_fees,
+                    vtoken_balance,
+                } => event
+                    .add_attribute("referrer", referrer)
+                    .add_attribute("token", token)
+                    .add_attribute("borrow_fees", borrow_fees)
+                    .add_attribute("trading_fees", trading_fees)
+                    .add_attribute("vtoken_balance", vtoken_balance),
+                LeverageEvent::CollateralTransferred {
+                    owner,
+                    token,
+                    amount,
+                    from_account,
+                    to_account,
+                    from_collateral_balance,
+                    to_collateral_balance,
+                } => event
+                    .add_attribute("owner", owner)
+                    .add_attribute("token", token)
+                    .add_attribute("amount", amount)
+                    .add_attribute("from_account", from_account.to_string())
+                    .add_attribute("to_account", to_account.to_string())
+                    .add_attribute("from_collateral_balance", from_collateral_balance)
+                    .add_attribute("to_collateral_balance", to_collateral_balance),
+                LeverageEvent::OperatorApproved {
+                    owner,
+                    operator,
+                    permissions,
+                    expires,
+                } => event
+                    .add_attribute("owner", owner)
+                    .add_attribute("operator", operator)
+                    .add_attribute(
+                        "permissions",
+                        permissions
+                            .iter()
+                            .map(OperatorPermission::as_str)
+                            .collect::<Vec<&str>>()
+                            .join(","),
+                    )
+                    .add_attribute("expires", expires.to_string()),
+                LeverageEvent::OperatorRevoked { owner, operator } => event
+                    .add_attribute("owner", owner)
+                    .add_attribute("operator", operator),
+                LeverageEvent::DeficitCovered {
+                    token,
+                    insurance_vtokens,
+                    insurance_tokens,
+                    deleveraged,
+                    deficit,
+                } => event
+                    .add_attribute("token", token)
+                    .add_attribute("insurance_vtokens", insurance_vtokens)
+                    .add_attribute("insurance_tokens", insurance_tokens)
+                    .add_attribute("deleveraged", deleveraged)
+                    .add_attribute("deficit", deficit),
+                LeverageEvent::AutoDeleveraged {
+                    user,
+                    token,
+                    rank,
+                    closed,
+                    absorbed,
+                    credited,
+                    collateral_balance,
+                    vtoken_balance,
+                } => event
+                    .add_attribute("user", user)
+                    .add_attribute("token", token)
+                    .add_attribute("rank", rank.to_string())
+                    .add_attribute("closed", closed)
+                    .add_attribute("absorbed", absorbed)
+                    .add_attribute("credited", credited)
+                    .add_attribute("collateral_balance", collateral_balance)
+                    .add_attribute("vtoken_balance", vtoken_balance),
+                LeverageEvent::ForceSettled {
+                    user,
+                    token,
+                    repaid,
+                    seized,
+                    bad_debt,
+                    collateral_balance,
+                    vtoken_balance,
+                } => event
+                    .add_attribute("user", user)
+                    .add_attribute("token", token)
+                    .add_attribute("repaid", repaid)
+                    .add_attribute("seized", seized)
+                    .add_attribute("bad_debt", bad_debt)
+                    .add_attribute("borrow_balance", Uint128::zero())
+                    .add_attribute("collateral_balance", collateral_balance)
+                    .add_attribute("vtoken_balance", vtoken_balance),
+                LeverageEvent::SettlementSeized {
+                    user,
+                    token,
+                    debt_token,
+                    vtokens,
+                    collateral,
+                    covered,
+                    collateral_balance,
+                    vtoken_balance,
+                } => event
+                    .add_attribute("user", user)
+                    .add_attribute("token", token)
+                    .add_attribute("debt_token", debt_token)
+                    .add_attribute("vtokens", vtokens)
+                    .add_attribute("collateral", collateral)
+                    .add_attribute("covered", covered)
+                    .add_attribute("collateral_balance", collateral_balance)
+                    .add_attribute("vtoken_balance", vtoken_balance),
+            }
+        }
+    }
+}
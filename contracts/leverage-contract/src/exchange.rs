@@ -1,15 +1,70 @@
 pub mod exchange_tokens {
+    use crate::access::access_control;
     use crate::error::ContractError;
+    use crate::events::leverage_events::LeverageEvent;
+    use crate::listing::token_listing;
+    use crate::market::market_module;
     use crate::msg::OrderExecute;
-    use crate::state::{OrderState, Status, ORDER_STATE, USER_VTOKEN_BALANCE};
-    use cosmwasm_std::{DepsMut, Env, MessageInfo, Response, Uint128};
+    use crate::operator::operator_approvals;
+    use crate::referral::referral_program;
+    use crate::state::{
+        OperatorPermission, OrderState, Role, Status, FEE_CONFIG, ORDER_STATE, TOTAL_VTOKENS,
+        TRADING_FEES, USER_VTOKEN_BALANCE,
+    };
+    use crate::sub_account::sub_accounts;
+    use crate::valuation::valuation_module;
+    use cosmwasm_std::{Decimal, DepsMut, Env, MessageInfo, Response, Uint128};
 
     pub fn execute_order(
         deps: DepsMut,
         _env: Env,
-        _info: MessageInfo,
+        info: MessageInfo,
         order: OrderExecute,
     ) -> Result<Response, ContractError> {
+        access_control::assert_not_paused(deps.storage)?;
+
+        // Orders are placed by the user, an operator trading for them or a keeper.
+        // Only keeper fills get room for price lag, an account trading on its
+        // own could otherwise round-trip its way to more vTokens
+        let tolerance = if info.sender == order.user_address
+            || operator_approvals::is_approved(
+                deps.as_ref(),
+                &_env,
+                &order.user_address,
+                &info.sender,
+                OperatorPermission::Trade,
+            )? {
+            Decimal::zero()
+        } else {
+            access_control::assert_role(deps.storage, Role::Keeper, &info.sender)?;
+            valuation_module::ORDER_PRICE_TOLERANCE
+        };
+        // The order trades on the account's balances from here on
+        let order = OrderExecute {
+            user_address: sub_accounts::account_address(&order.user_address, order.account_id)?,
+            account_id: None,
+            ..order
+        };
+
+        if order.amount_in.is_zero() || order.amount_out.is_zero() {
+            return Err(ContractError::ZeroAmount {});
+        }
+
+        // Closing out is always possible, only buying needs an active token
+        let token_in = token_listing::load_token(deps.storage, &order.token_in)?;
+        let token_out = token_listing::load_active_token(deps.storage, &order.token_out)?;
+
+        // Amounts are compared by value, so tokens with different decimals trade fairly
+        valuation_module::assert_order_price(
+            deps.as_ref(),
+            &_env,
+            &token_in,
+            order.amount_in,
+            &token_out,
+            order.amount_out,
+            tolerance,
+        )?;
+
         let user_vtoken_balance = match USER_VTOKEN_BALANCE
             .may_load(deps.storage, (&order.token_in, &order.user_address))
         {
@@ -24,34 +79,69 @@ pub mod exchange_tokens {
             return Err(ContractError::InsufficientBalance {});
         }
 
-        USER_VTOKEN_BALANCE.update(
+        // The trading fee is kept back from the bought vTokens
+        let fees = FEE_CONFIG.may_load(deps.storage)?.unwrap_or_default();
+        let fee = match order.amount_out.checked_mul_floor(fees.trading_fee) {
+            Ok(data) => data,
+            Err(_) => return Err(ContractError::Overflow {}),
+        };
+        let amount_received = order.amount_out - fee;
+        if order
+            .min_out
+            .is_some_and(|min_out| amount_received < min_out)
+        {
+            return Err(ContractError::OrderBelowMinimum {});
+        }
+        market_module::increase_total(deps.storage, &TRADING_FEES, &order.token_out, fee)?;
+        referral_program::share_fees(
+            deps.storage,
+            &order.user_address,
+            &order.token_out,
+            Uint128::zero(),
+            fee,
+        )?;
+
+        let vtoken_out_balance = USER_VTOKEN_BALANCE.update(
             deps.storage,
             (&order.token_out, &order.user_address),
             |opt_data| -> Result<Uint128, ContractError> {
                 match opt_data {
-                    Some(data) => match data.checked_add(order.amount_out) {
+                    Some(data) => match data.checked_add(amount_received) {
                         Ok(used_wrapped_token_balance) => Ok(used_wrapped_token_balance),
-                        Err(_) => return Err(ContractError::Overflow {}),
+                        Err(_) => Err(ContractError::Overflow {}),
                     },
-                    None => Ok(order.amount_out),
+                    None => Ok(amount_received),
                 }
             },
         )?;
 
-        USER_VTOKEN_BALANCE.update(
+        let vtoken_in_balance = USER_VTOKEN_BALANCE.update(
             deps.storage,
             (&order.token_in, &order.user_address),
             |opt_data| -> Result<Uint128, ContractError> {
                 match opt_data {
                     Some(data) => match data.checked_sub(order.amount_in) {
                         Ok(used_wrapped_token_balance) => Ok(used_wrapped_token_balance),
-                        Err(_) => return Err(ContractError::Overflow {}),
+                        Err(_) => Err(ContractError::Overflow {}),
                     },
                     None => Ok(order.amount_in),
                 }
             },
         )?;
 
+        market_module::increase_total(
+            deps.storage,
+            &TOTAL_VTOKENS,
+            &order.token_out,
+            amount_received,
+        )?;
+        market_module::decrease_total(
+            deps.storage,
+            &TOTAL_VTOKENS,
+            &order.token_in,
+            order.amount_in,
+        )?;
+
         ORDER_STATE.update(
             deps.storage,
             &order.user_address,
@@ -59,11 +149,11 @@ pub mod exchange_tokens {
                 match opt_data {
                     Some(mut data) => {
                         let order_state = OrderState {
-                            order_id: order.order_id,
+                            order_id: order.order_id.clone(),
                             sell_token: order.token_in.to_string(),
                             buy_token: order.token_out.to_string(),
                             sell_token_amount: order.amount_in,
-                            buy_token_amount: order.amount_out,
+                            buy_token_amount: amount_received,
                             time: _env.block.time,
                             status: Status::Fullfiled,
                         };
@@ -72,11 +162,11 @@ pub mod exchange_tokens {
                     }
                     None => {
                         let order_state = OrderState {
-                            order_id: order.order_id,
+                            order_id: order.order_id.clone(),
                             sell_token: order.token_in.to_string(),
                             buy_token: order.token_out.to_string(),
                             sell_token_amount: order.amount_in,
-                            buy_token_amount: order.amount_out,
+                            buy_token_amount: amount_received,
                             time: _env.block.time,
                             status: Status::Fullfiled,
                         };
@@ -87,6 +177,24 @@ pub mod exchange_tokens {
             },
         )?;
 
-        Ok(Response::new().add_attribute("method", "execute_order"))
+        valuation_module::assert_healthy(deps.as_ref(), &_env, &order.user_address)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "execute_order")
+            .add_attribute("fee", fee)
+            .add_event(
+                LeverageEvent::Order {
+                    user: order.user_address,
+                    order_id: order.order_id,
+                    token_in: order.token_in.to_string(),
+                    amount_in: order.amount_in,
+                    token_out: order.token_out.to_string(),
+                    amount_out: amount_received,
+                    fee,
+                    vtoken_in_balance,
+                    vtoken_out_balance,
+                }
+                .into(),
+            ))
     }
 }
@@ -1,37 +1,126 @@
 pub mod execute_module {
-    use cosmwasm_std::{DepsMut, Env, MessageInfo, Response};
+    use cosmwasm_std::{Addr, Decimal, DepsMut, Env, MessageInfo, Response, Uint128};
 
+    use crate::access::access_control;
     use crate::error::ContractError;
-    use crate::state::{LEVERAGE_CONTRACT_OWNER, LISTED_TOKEN};
+    use crate::events::leverage_events::LeverageEvent;
+    use crate::listing::token_listing;
+    use crate::msg::WithdrawData;
+    use crate::state::{FeeConfig, Role, BORROW_RATE, FEE_CONFIG, PROTOCOL_REVENUE};
+    use crate::withdraw::withdraw_collateral;
 
-    pub fn list_token_on_leverage(
+    pub const MAX_FEE: Decimal = Decimal::percent(10);
+
+    /**
+     * Sets the annual borrow rate, only reachable through an executed timelock action.
+     */
+    pub fn update_borrow_rate(
         deps: DepsMut,
         _env: Env,
-        info: MessageInfo,
-        token_address: String,
+        token_address: Addr,
+        rate: Decimal,
+    ) -> Result<Response, ContractError> {
+        token_listing::load_token(deps.storage, &token_address)?;
+
+        BORROW_RATE.save(deps.storage, &token_address, &rate)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "update_borrow_rate")
+            .add_attribute("token_address", token_address.clone())
+            .add_attribute("rate", rate.to_string())
+            .add_event(
+                LeverageEvent::BorrowRateUpdated {
+                    token: token_address.to_string(),
+                    rate,
+                }
+                .into(),
+            ))
+    }
+
+    pub fn assert_fees(fees: &FeeConfig) -> Result<(), ContractError> {
+        if fees.borrow_fee > MAX_FEE || fees.trading_fee > MAX_FEE {
+            return Err(ContractError::InvalidFee {});
+        }
+        if fees.referral_share > Decimal::one() {
+            return Err(ContractError::InvalidReferralShare {});
+        }
+        Ok(())
+    }
+
+    /**
+     * Replaces the fee settings, only reachable through an executed timelock action.
+     */
+    pub fn update_fees(
+        deps: DepsMut,
+        _env: Env,
+        fees: FeeConfig,
     ) -> Result<Response, ContractError> {
-        match LEVERAGE_CONTRACT_OWNER.load(deps.storage) {
-            Ok(owner) => {
-                if owner != info.sender {
-                    return Err(ContractError::Unauthorized {});
+        assert_fees(&fees)?;
+
+        FEE_CONFIG.save(deps.storage, &fees)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "update_fees")
+            .add_attribute("borrow_fee", fees.borrow_fee.to_string())
+            .add_attribute("trading_fee", fees.trading_fee.to_string())
+            .add_attribute("referral_share", fees.referral_share.to_string())
+            .add_event(
+                LeverageEvent::FeesUpdated {
+                    borrow_fee: fees.borrow_fee,
+                    trading_fee: fees.trading_fee,
+                    referral_share: fees.referral_share,
                 }
-            }
-            Err(err) => {
-                return Err(ContractError::GenericError {
-                    error: err.to_string(),
-                })
-            }
-        };
-
-        match LISTED_TOKEN.update(
+                .into(),
+            ))
+    }
+
+    /**
+     * Sends the interest paid in real tokens to the fee collector.
+     */
+    pub fn collect_revenue(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        withdraw_data: WithdrawData,
+    ) -> Result<Response, ContractError> {
+        access_control::assert_role(deps.storage, Role::FeeCollector, &info.sender)?;
+
+        // Only accrued revenue of a listed token leaves, in that token's own asset
+        let token_info = token_listing::load_token(deps.storage, &withdraw_data.token_address)?;
+        let revenue_balance = PROTOCOL_REVENUE.update(
             deps.storage,
-            |mut listed_token| -> Result<Vec<String>, ContractError> {
-                listed_token.push(token_address);
-                Ok(listed_token)
+            &withdraw_data.token_address,
+            |opt_revenue| -> Result<Uint128, ContractError> {
+                match opt_revenue {
+                    Some(revenue) => match revenue.checked_sub(withdraw_data.token_amount) {
+                        Ok(data) => Ok(data),
+                        Err(_) => Err(ContractError::InsufficientBalance {}),
+                    },
+                    None => Err(ContractError::InsufficientBalance {}),
+                }
             },
-        ) {
-            Ok(_) => Ok(Response::new().add_attribute("method", "list_token_on_leverage")),
-            Err(_) => Err(ContractError::UpdateTokenListFailed {}),
-        }
+        )?;
+
+        let cosmos_msg = withdraw_collateral::transfer_msg(
+            &token_info.asset,
+            withdraw_data.token_amount,
+            &info.sender,
+        )?;
+
+        Ok(Response::new()
+            .add_attribute("method", "collect_revenue")
+            .add_attribute("token_address", withdraw_data.token_address.clone())
+            .add_attribute("amount", withdraw_data.token_amount)
+            .add_attribute("collector", info.sender.clone())
+            .add_event(
+                LeverageEvent::CollectRevenue {
+                    collector: info.sender,
+                    token: withdraw_data.token_address.to_string(),
+                    amount: withdraw_data.token_amount,
+                    revenue_balance,
+                }
+                .into(),
+            )
+            .add_message(cosmos_msg))
     }
 }
@@ -0,0 +1,238 @@
+#[cfg(test)]
+mod test {
+    use cosmwasm_std::{Addr, Uint128};
+    use proptest::prelude::*;
+
+    use crate::scenario::scenario_harness::{Scenario, ATOM, OSMO, USDC};
+
+    const USERS: [&str; 3] = ["alice", "bob", "carol"];
+    // USDC goes through the CW20 `Receive` hook, the others through `DepositNative`
+    const TOKENS: [&str; 3] = [USDC, OSMO, ATOM];
+    const WALLET_FUNDS: u128 = 1_000_000;
+
+    /**
+     * One message of a fuzzed sequence.
+     *
+     * Amounts are mostly given as a percentage of the balance the message
+     * spends, so most generated messages pass the contract's own checks.
+     */
+    #[derive(Clone, Debug)]
+    enum Action {
+        Deposit {
+            user: usize,
+            token: usize,
+            amount: u128,
+        },
+        Borrow {
+            user: usize,
+            token: usize,
+            percent: u128,
+        },
+        Order {
+            user: usize,
+            token_in: usize,
+            token_out: usize,
+            percent: u128,
+            // Asked on top of the oracle price, in basis points
+            premium: u128,
+        },
+        Repay {
+            user: usize,
+            token: usize,
+            percent: u128,
+        },
+        Burn {
+            user: usize,
+            token: usize,
+            percent: u128,
+        },
+        // Credits vTokens against the reserve, the other source of vTokens besides debt and orders
+        TopUp {
+            user: usize,
+            token: usize,
+            amount: u128,
+        },
+        Withdraw {
+            user: usize,
+            token: usize,
+            percent: u128,
+        },
+    }
+
+    fn action() -> impl Strategy<Value = Action> {
+        let user = 0..USERS.len();
+        let token = 0..TOKENS.len();
+        let percent = 1..=100u128;
+        prop_oneof![
+            (user.clone(), token.clone(), 1..100_000u128).prop_map(|(user, token, amount)| {
+                Action::Deposit {
+                    user,
+                    token,
+                    amount,
+                }
+            }),
+            (user.clone(), token.clone(), percent.clone()).prop_map(|(user, token, percent)| {
+                Action::Borrow {
+                    user,
+                    token,
+                    percent,
+                }
+            }),
+            (
+                user.clone(),
+                token.clone(),
+                token.clone(),
+                percent.clone(),
+                0..=150u128
+            )
+                .prop_map(|(user, token_in, token_out, percent, premium)| {
+                    Action::Order {
+                        user,
+                        token_in,
+                        token_out,
+                        percent,
+                        premium,
+                    }
+                }),
+            (user.clone(), token.clone(), percent.clone()).prop_map(|(user, token, percent)| {
+                Action::Repay {
+                    user,
+                    token,
+                    percent,
+                }
+            }),
+            (user.clone(), token.clone(), percent.clone()).prop_map(|(user, token, percent)| {
+                Action::Burn {
+                    user,
+                    token,
+                    percent,
+                }
+            }),
+            (user.clone(), token.clone(), 1..10_000u128).prop_map(|(user, token, amount)| {
+                Action::TopUp {
+                    user,
+                    token,
+                    amount,
+                }
+            }),
+            (user, token, percent).prop_map(|(user, token, percent)| Action::Withdraw {
+                user,
+                token,
+                percent
+            }),
+        ]
+    }
+
+    fn share(balance: Uint128, percent: u128) -> u128 {
+        balance.multiply_ratio(percent, 100u128).u128()
+    }
+
+    // Every listed token is valued at 1 except ATOM at 10, all with 6 decimals
+    fn price(token: &str) -> u128 {
+        match token {
+            ATOM => 10,
+            _ => 1,
+        }
+    }
+
+    /**
+     * Runs the action, the scenario checks the invariants after every message.
+     * Rejected messages are part of the fuzzing and don't fail the run.
+     */
+    fn apply(scenario: &mut Scenario, users: &[Addr], action: &Action) {
+        let _ = match *action {
+            Action::Deposit {
+                user,
+                token,
+                amount,
+            } => scenario.deposit(&users[user], TOKENS[token], amount),
+            Action::Borrow {
+                user,
+                token,
+                percent,
+            } => {
+                let wrapped = scenario.wrapped(&users[user], TOKENS[token]);
+                scenario.borrow(&users[user], TOKENS[token], share(wrapped, percent))
+            }
+            Action::Order {
+                user,
+                token_in,
+                token_out,
+                percent,
+                premium,
+            } => {
+                let (token_in, token_out) = (TOKENS[token_in], TOKENS[token_out]);
+                let amount_in = share(scenario.vtokens(&users[user], token_in), percent);
+                let amount_out = Uint128::from(amount_in)
+                    .multiply_ratio(
+                        price(token_in) * (10_000 + premium),
+                        price(token_out) * 10_000,
+                    )
+                    .u128();
+                scenario.order(&users[user], token_in, amount_in, token_out, amount_out)
+            }
+            Action::Repay {
+                user,
+                token,
+                percent,
+            } => {
+                let vtokens = scenario.vtokens(&users[user], TOKENS[token]);
+                let borrowed = scenario.borrowed(&users[user], TOKENS[token]);
+                scenario.repay(
+                    &users[user],
+                    TOKENS[token],
+                    share(vtokens.min(borrowed), percent),
+                )
+            }
+            Action::Burn {
+                user,
+                token,
+                percent,
+            } => {
+                let vtokens = scenario.vtokens(&users[user], TOKENS[token]);
+                scenario.burn(&users[user], TOKENS[token], share(vtokens, percent))
+            }
+            Action::TopUp {
+                user,
+                token,
+                amount,
+            } => scenario.top_up(&users[user], TOKENS[token], amount),
+            Action::Withdraw {
+                user,
+                token,
+                percent,
+            } => {
+                let collateral = scenario.collateral(&users[user], TOKENS[token]);
+                scenario.withdraw(&users[user], TOKENS[token], share(collateral, percent))
+            }
+        };
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn random_message_sequences_keep_invariants(
+            actions in prop::collection::vec(action(), 1..40)
+        ) {
+            let mut scenario = Scenario::new();
+            let users: Vec<Addr> = USERS
+                .iter()
+                .map(|name| {
+                    let user = scenario.user(name);
+                    for token in TOKENS {
+                        scenario.fund(&user, token, WALLET_FUNDS);
+                    }
+                    user
+                })
+                .collect();
+
+            for action in &actions {
+                apply(&mut scenario, &users, action);
+            }
+            // Also no vTokens beyond what debt, reserve, orders and rebates back
+            let violations = scenario.check_invariants();
+            prop_assert!(violations.is_empty(), "{}", violations.join("\n"));
+        }
+    }
+}
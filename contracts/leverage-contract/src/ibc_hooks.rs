@@ -0,0 +1,128 @@
+pub mod ibc_hook_deposit {
+    use bech32::{ToBase32, Variant};
+    use cosmwasm_std::{Addr, DepsMut, Env, MessageInfo, Response, Uint128};
+    use sha2::{Digest, Sha256};
+
+    use crate::borrow::borrow_leverage;
+    use crate::deposit::deposit_collateral;
+    use crate::events::leverage_events::LeverageEvent;
+    use crate::listing::token_listing;
+    use crate::msg::TokenData;
+    use crate::sub_account::sub_accounts;
+    use crate::ContractError;
+
+    /// Address type ibc-hooks derives the sender of a hook call with
+    pub const SENDER_PREFIX: &str = "ibc-wasm-hook-intermediary";
+
+    /**
+     * The address ibc-hooks calls the contract from for a transfer of
+     * `original_sender` arriving through `channel`.
+     *
+     * It's the Cosmos SDK `address.Hash` of `<channel>/<original_sender>`,
+     * i.e. `sha256(sha256(SENDER_PREFIX) ++ key)`, encoded with the chain's
+     * bech32 prefix.
+     */
+    pub fn derive_intermediate_sender(
+        channel: &str,
+        original_sender: &str,
+        bech32_prefix: &str,
+    ) -> Result<String, ContractError> {
+        let type_hash = Sha256::digest(SENDER_PREFIX.as_bytes());
+        let mut hasher = Sha256::new();
+        hasher.update(type_hash);
+        hasher.update(format!("{channel}/{original_sender}").as_bytes());
+        let address_bytes = hasher.finalize();
+
+        bech32::encode(bech32_prefix, address_bytes.to_base32(), Variant::Bech32).map_err(|err| {
+            ContractError::GenericError {
+                error: err.to_string(),
+            }
+        })
+    }
+
+    /**
+     * Deposits the transferred coins as collateral of the token listed for their
+     * IBC denom and optionally borrows against them right away.
+     *
+     * The position belongs to the intermediate sender, so the sender has to be
+     * the address ibc-hooks derives for `original_sender` on `channel`.
+     */
+    pub fn deposit(
+        mut deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        channel: String,
+        original_sender: String,
+        borrow_amount: Option<Uint128>,
+        account_id: Option<u64>,
+    ) -> Result<Response, ContractError> {
+        assert_hook_sender(&info.sender, &channel, &original_sender)?;
+        let info = sub_accounts::sender_account(info, account_id)?;
+
+        let coin = match info.funds.as_slice() {
+            [coin] => coin.clone(),
+            _ => return Err(ContractError::InsufficientNativeToken {}),
+        };
+        let token_info = token_listing::load_native_token(deps.storage, &coin.denom)?;
+        let token_address = Addr::unchecked(&token_info.token);
+
+        let deposited = deposit_collateral::native_token(
+            deps.branch(),
+            env.clone(),
+            info.clone(),
+            token_address.clone(),
+            None,
+        )?;
+
+        let mut response = Response::new()
+            .add_attribute("method", "ibc_hook_deposit")
+            .add_attribute("channel", channel.clone())
+            .add_attribute("original_sender", original_sender.clone())
+            .add_event(
+                LeverageEvent::IbcHookDeposit {
+                    user: info.sender.clone(),
+                    channel,
+                    original_sender,
+                    token: token_info.token,
+                    amount: coin.amount,
+                }
+                .into(),
+            )
+            .add_events(deposited.events);
+
+        if let Some(borrow_amount) = borrow_amount {
+            let borrowed = borrow_leverage::borrow(
+                deps,
+                env,
+                info,
+                TokenData {
+                    token_address,
+                    token_amount: borrow_amount,
+                    account_id,
+                    on_behalf_of: None,
+                },
+            )?;
+            response = response.add_events(borrowed.events);
+        }
+
+        Ok(response)
+    }
+
+    // The bech32 prefix comes from the sender itself, so the check works on every chain
+    fn assert_hook_sender(
+        sender: &Addr,
+        channel: &str,
+        original_sender: &str,
+    ) -> Result<(), ContractError> {
+        let invalid_sender = || ContractError::InvalidIbcHookSender {
+            channel: channel.to_string(),
+            original_sender: original_sender.to_string(),
+        };
+
+        let (prefix, _, _) = bech32::decode(sender.as_str()).map_err(|_| invalid_sender())?;
+        if derive_intermediate_sender(channel, original_sender, &prefix)? != sender.as_str() {
+            return Err(invalid_sender());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,434 @@
+pub mod incentives_module {
+    use std::collections::BTreeMap;
+
+    use cosmwasm_std::{
+        Addr, BankMsg, Coin, Decimal256, Deps, DepsMut, Env, MessageInfo, Order, Response,
+        StdResult, Storage, Timestamp, Uint128, Uint256,
+    };
+    use cw_storage_plus::Map;
+
+    use crate::error::ContractError;
+    use crate::events::leverage_events::LeverageEvent;
+    use crate::listing::token_listing;
+    use crate::market::market_module;
+    use crate::msg::{PendingRewardsResponse, RewardStateResponse};
+    use crate::state::{
+        RewardIndex, RewardSchedule, RewardSide, LISTED_TOKEN, PENDING_REWARDS, REWARD_FUNDS,
+        REWARD_INDEX, REWARD_SCHEDULE, TOKEN_BALANCE, TOTAL_BORROWED, TOTAL_COLLATERAL,
+        USER_REWARD_INDEX, WRAPPED_TOKEN_BORROW_BALANCE,
+    };
+    use crate::sub_account::sub_accounts;
+
+    const REWARD_SIDES: [RewardSide; 2] = [RewardSide::Collateral, RewardSide::Borrow];
+
+    type BalanceMaps = (
+        &'static Map<'static, (&'static Addr, &'static Addr), Uint128>,
+        &'static Map<'static, &'static Addr, Uint128>,
+    );
+
+    fn balance_maps(side: RewardSide) -> BalanceMaps {
+        match side {
+            RewardSide::Collateral => (&TOKEN_BALANCE, &TOTAL_COLLATERAL),
+            RewardSide::Borrow => (&WRAPPED_TOKEN_BORROW_BALANCE, &TOTAL_BORROWED),
+        }
+    }
+
+    fn emission(schedule: &RewardSchedule, side: RewardSide) -> Uint128 {
+        match side {
+            RewardSide::Collateral => schedule.collateral_emission,
+            RewardSide::Borrow => schedule.borrow_emission,
+        }
+    }
+
+    // Rewards both sides of the schedule emit from `from` until its end
+    fn remaining_emission(
+        schedule: &RewardSchedule,
+        from: Timestamp,
+    ) -> Result<Uint128, ContractError> {
+        let from = from.max(schedule.start_time);
+        if schedule.end_time <= from {
+            return Ok(Uint128::zero());
+        }
+        schedule
+            .collateral_emission
+            .checked_add(schedule.borrow_emission)
+            .and_then(|emission| {
+                emission.checked_mul(Uint128::from(schedule.end_time.seconds() - from.seconds()))
+            })
+            .map_err(|_| ContractError::Overflow {})
+    }
+
+    /**
+     * Index of a market side at `now`, with the rewards emitted since its last
+     * update shared by the current total balance.
+     *
+     * Rewards emitted while nothing is deposited or borrowed aren't paid to anyone.
+     */
+    fn current_index(
+        storage: &dyn Storage,
+        schedule: &RewardSchedule,
+        token_address: &Addr,
+        side: RewardSide,
+        now: Timestamp,
+    ) -> Result<RewardIndex, ContractError> {
+        let reward_index = REWARD_INDEX
+            .may_load(storage, (token_address, side.as_str()))?
+            .unwrap_or(RewardIndex {
+                index: Decimal256::zero(),
+                updated_at: now,
+            });
+
+        let from = reward_index.updated_at.max(schedule.start_time);
+        let to = now.min(schedule.end_time);
+        let (_, total_map) = balance_maps(side);
+        let total = total_map
+            .may_load(storage, token_address)?
+            .unwrap_or_default();
+        if to <= from || total.is_zero() {
+            return Ok(RewardIndex {
+                index: reward_index.index,
+                updated_at: now,
+            });
+        }
+
+        let emitted = Uint256::from(emission(schedule, side))
+            .checked_mul(Uint256::from(to.seconds() - from.seconds()))
+            .map_err(|_| ContractError::Overflow {})?;
+        let index = Decimal256::checked_from_ratio(emitted, total)
+            .ok()
+            .and_then(|increase| reward_index.index.checked_add(increase).ok())
+            .ok_or(ContractError::Overflow {})?;
+        Ok(RewardIndex {
+            index,
+            updated_at: now,
+        })
+    }
+
+    // Rewards the user's current balance earned between their index and `index`
+    fn accrued_rewards(
+        storage: &dyn Storage,
+        token_address: &Addr,
+        side: RewardSide,
+        user_address: &Addr,
+        index: Decimal256,
+    ) -> Result<Uint128, ContractError> {
+        let user_index = USER_REWARD_INDEX
+            .may_load(storage, (token_address, side.as_str(), user_address))?
+            .unwrap_or_default();
+        let (balance_map, _) = balance_maps(side);
+        let balance =
+            market_module::user_balance(storage, balance_map, token_address, user_address)?;
+
+        let rewards = (index - user_index)
+            .checked_mul(
+                Decimal256::from_atomics(balance, 0).map_err(|_| ContractError::Overflow {})?,
+            )
+            .map_err(|_| ContractError::Overflow {})?
+            .to_uint_floor();
+        Uint128::try_from(rewards).map_err(|_| ContractError::Overflow {})
+    }
+
+    fn add_pending_rewards(
+        storage: &mut dyn Storage,
+        user_address: &Addr,
+        denom: &str,
+        amount: Uint128,
+    ) -> Result<(), ContractError> {
+        if amount.is_zero() {
+            return Ok(());
+        }
+        PENDING_REWARDS.update(
+            storage,
+            (user_address, denom),
+            |opt_pending| -> Result<Uint128, ContractError> {
+                opt_pending
+                    .unwrap_or_default()
+                    .checked_add(amount)
+                    .map_err(|_| ContractError::Overflow {})
+            },
+        )?;
+        Ok(())
+    }
+
+    /**
+     * Brings the market's reward indexes up to `now` and moves what the user
+     * earned with their balances so far into their pending rewards.
+     *
+     * Has to run before any change of the user's collateral or debt in the
+     * token, otherwise the new balance would earn the past rewards.
+     */
+    pub fn update_user_rewards(
+        storage: &mut dyn Storage,
+        token_address: &Addr,
+        user_address: &Addr,
+        now: Timestamp,
+    ) -> Result<(), ContractError> {
+        let schedule = match REWARD_SCHEDULE.may_load(storage, token_address)? {
+            Some(schedule) => schedule,
+            None => return Ok(()),
+        };
+
+        for side in REWARD_SIDES {
+            let reward_index = current_index(storage, &schedule, token_address, side, now)?;
+            REWARD_INDEX.save(storage, (token_address, side.as_str()), &reward_index)?;
+
+            let rewards = accrued_rewards(
+                storage,
+                token_address,
+                side,
+                user_address,
+                reward_index.index,
+            )?;
+            add_pending_rewards(storage, user_address, &schedule.reward_denom, rewards)?;
+            USER_REWARD_INDEX.save(
+                storage,
+                (token_address, side.as_str(), user_address),
+                &reward_index.index,
+            )?;
+        }
+        Ok(())
+    }
+
+    /**
+     * Checks a schedule can replace the market's current one at `now`, with
+     * `funded` of its reward denom sent along.
+     */
+    pub fn validate_reward_schedule(
+        storage: &dyn Storage,
+        now: Timestamp,
+        token_address: &Addr,
+        schedule: &RewardSchedule,
+        funded: Uint128,
+    ) -> Result<(), ContractError> {
+        token_listing::load_token(storage, token_address)?;
+
+        if schedule.reward_denom.is_empty() || schedule.end_time <= schedule.start_time {
+            return Err(ContractError::InvalidRewardSchedule {});
+        }
+        let previous = REWARD_SCHEDULE.may_load(storage, token_address)?;
+        let still_funded = match &previous {
+            Some(previous) if previous.reward_denom != schedule.reward_denom => {
+                return Err(ContractError::RewardDenomChanged {
+                    denom: previous.reward_denom.clone(),
+                })
+            }
+            Some(previous) => remaining_emission(previous, now)?,
+            None => Uint128::zero(),
+        };
+        let required = remaining_emission(schedule, now)?.saturating_sub(still_funded);
+        if funded < required {
+            return Err(ContractError::RewardsNotFunded { required });
+        }
+        Ok(())
+    }
+
+    /**
+     * Sets the reward schedule of a market, only reachable through an executed timelock action.
+     *
+     * Rewards of the previous schedule are accounted up to now first. The
+     * reward denom of a market can't change, since users may still have
+     * rewards to accrue in it.
+     *
+     * `funded` is what the proposer escrowed in the reward denom. It has to cover
+     * what the new schedule emits from now on beyond what the previous one still would.
+     */
+    pub fn set_reward_schedule(
+        deps: DepsMut,
+        env: Env,
+        token_address: Addr,
+        schedule: RewardSchedule,
+        funded: Uint128,
+    ) -> Result<Response, ContractError> {
+        validate_reward_schedule(
+            deps.storage,
+            env.block.time,
+            &token_address,
+            &schedule,
+            funded,
+        )?;
+
+        let previous = REWARD_SCHEDULE.may_load(deps.storage, &token_address)?;
+        for side in REWARD_SIDES {
+            // A new market starts from a zero index, balances held already earn from the start
+            let reward_index = match &previous {
+                Some(previous) => {
+                    current_index(deps.storage, previous, &token_address, side, env.block.time)?
+                }
+                None => RewardIndex {
+                    index: Decimal256::zero(),
+                    updated_at: env.block.time,
+                },
+            };
+            REWARD_INDEX.save(deps.storage, (&token_address, side.as_str()), &reward_index)?;
+        }
+
+        REWARD_FUNDS.update(
+            deps.storage,
+            &schedule.reward_denom,
+            |opt_funds| -> Result<Uint128, ContractError> {
+                opt_funds
+                    .unwrap_or_default()
+                    .checked_add(funded)
+                    .map_err(|_| ContractError::Overflow {})
+            },
+        )?;
+
+        REWARD_SCHEDULE.save(deps.storage, &token_address, &schedule)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "set_reward_schedule")
+            .add_attribute("token_address", token_address.clone())
+            .add_event(
+                LeverageEvent::RewardScheduleUpdated {
+                    token: token_address.to_string(),
+                    reward_denom: schedule.reward_denom,
+                    start_time: schedule.start_time,
+                    end_time: schedule.end_time,
+                    collateral_emission: schedule.collateral_emission,
+                    borrow_emission: schedule.borrow_emission,
+                    funded,
+                }
+                .into(),
+            ))
+    }
+
+    /**
+     * Pays out the rewards the sender earned in every market.
+     *
+     * Payouts are capped by the funds of each reward denom, what they don't
+     * cover stays pending.
+     */
+    pub fn claim_rewards(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+    ) -> Result<Response, ContractError> {
+        let tokens = REWARD_SCHEDULE
+            .keys(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<Addr>>>()?;
+        for token_address in tokens {
+            update_user_rewards(deps.storage, &token_address, &info.sender, env.block.time)?;
+        }
+
+        let pending = PENDING_REWARDS
+            .prefix(&info.sender)
+            .range(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<(String, Uint128)>>>()?;
+        let mut rewards = vec![];
+        for (denom, amount) in pending {
+            let funds = REWARD_FUNDS
+                .may_load(deps.storage, &denom)?
+                .unwrap_or_default();
+            let paid = amount.min(funds);
+            if paid.is_zero() {
+                continue;
+            }
+            REWARD_FUNDS.save(deps.storage, &denom, &(funds - paid))?;
+            if paid == amount {
+                PENDING_REWARDS.remove(deps.storage, (&info.sender, &denom));
+            } else {
+                PENDING_REWARDS.save(deps.storage, (&info.sender, &denom), &(amount - paid))?;
+            }
+            rewards.push(Coin {
+                denom,
+                amount: paid,
+            });
+        }
+        if rewards.is_empty() {
+            return Err(ContractError::NoRewards {});
+        }
+
+        Ok(Response::new()
+            .add_attribute("method", "claim_rewards")
+            .add_attribute("user", info.sender.clone())
+            .add_event(
+                LeverageEvent::RewardsClaimed {
+                    user: info.sender.clone(),
+                    rewards: rewards.clone(),
+                }
+                .into(),
+            )
+            .add_message(BankMsg::Send {
+                to_address: sub_accounts::owner_of(&info.sender).to_string(),
+                amount: rewards,
+            }))
+    }
+
+    pub fn fetch_pending_rewards(
+        deps: Deps,
+        env: Env,
+        user_address: Addr,
+    ) -> StdResult<PendingRewardsResponse> {
+        let mut rewards: BTreeMap<String, Uint128> = PENDING_REWARDS
+            .prefix(&user_address)
+            .range(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()?;
+
+        for item in REWARD_SCHEDULE.range(deps.storage, None, None, Order::Ascending) {
+            let (token_address, schedule) = item?;
+            for side in REWARD_SIDES {
+                let reward_index = current_index(
+                    deps.storage,
+                    &schedule,
+                    &token_address,
+                    side,
+                    env.block.time,
+                )?;
+                let accrued = accrued_rewards(
+                    deps.storage,
+                    &token_address,
+                    side,
+                    &user_address,
+                    reward_index.index,
+                )?;
+                let pending = rewards.entry(schedule.reward_denom.clone()).or_default();
+                *pending = pending
+                    .checked_add(accrued)
+                    .map_err(|_| ContractError::Overflow {})?;
+            }
+        }
+
+        Ok(PendingRewardsResponse {
+            user: user_address,
+            rewards: rewards
+                .into_iter()
+                .filter(|(_, amount)| !amount.is_zero())
+                .map(|(denom, amount)| Coin { denom, amount })
+                .collect(),
+        })
+    }
+
+    pub fn fetch_reward_state(
+        deps: Deps,
+        env: Env,
+        token_address: Addr,
+    ) -> StdResult<RewardStateResponse> {
+        let token_info = LISTED_TOKEN
+            .may_load(deps.storage, token_address.as_str())?
+            .ok_or(ContractError::UnauthorizedToken {})?;
+        let schedule = REWARD_SCHEDULE.may_load(deps.storage, &token_address)?;
+
+        let funds = match &schedule {
+            Some(schedule) => REWARD_FUNDS
+                .may_load(deps.storage, &schedule.reward_denom)?
+                .unwrap_or_default(),
+            None => Uint128::zero(),
+        };
+        let mut indexes = [Decimal256::zero(); 2];
+        if let Some(schedule) = &schedule {
+            for (index, side) in indexes.iter_mut().zip(REWARD_SIDES) {
+                *index =
+                    current_index(deps.storage, schedule, &token_address, side, env.block.time)?
+                        .index;
+            }
+        }
+
+        Ok(RewardStateResponse {
+            token: token_info.token,
+            schedule,
+            collateral_index: indexes[0],
+            borrow_index: indexes[1],
+            funds,
+        })
+    }
+}
@@ -0,0 +1,81 @@
+pub mod interest_module {
+    use cosmwasm_std::{Addr, Decimal, Storage, Timestamp, Uint128};
+
+    use crate::error::ContractError;
+    use crate::state::{
+        BORROW_INTEREST, BORROW_INTEREST_TIMESTAMP, BORROW_RATE, WRAPPED_TOKEN_BORROW_BALANCE,
+    };
+
+    pub const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
+
+    /**
+     * Interest the principal earned since the last accrual, without touching storage.
+     *
+     * Interest is simple interest on the borrowed principal at the token's annual
+     * borrow rate, so queries can show the up to date debt.
+     */
+    pub fn pending_interest(
+        storage: &dyn Storage,
+        token_address: &Addr,
+        user_address: &Addr,
+        now: Timestamp,
+    ) -> Result<Uint128, ContractError> {
+        let accrued = BORROW_INTEREST
+            .may_load(storage, (token_address, user_address))?
+            .unwrap_or_default();
+
+        let principal = WRAPPED_TOKEN_BORROW_BALANCE
+            .may_load(storage, (token_address, user_address))?
+            .unwrap_or_default();
+
+        let last_accrual =
+            match BORROW_INTEREST_TIMESTAMP.may_load(storage, (token_address, user_address))? {
+                Some(time) => time,
+                None => return Ok(accrued),
+            };
+
+        let rate = BORROW_RATE
+            .may_load(storage, token_address)?
+            .unwrap_or_else(Decimal::zero);
+
+        let elapsed = now.seconds().saturating_sub(last_accrual.seconds());
+        if principal.is_zero() || rate.is_zero() || elapsed == 0 {
+            return Ok(accrued);
+        }
+
+        let yearly_interest = match principal.checked_mul_floor(rate) {
+            Ok(data) => data,
+            Err(_) => return Err(ContractError::Overflow {}),
+        };
+
+        let interest = match yearly_interest.checked_multiply_ratio(elapsed, SECONDS_PER_YEAR) {
+            Ok(data) => data,
+            Err(_) => return Err(ContractError::Overflow {}),
+        };
+
+        match accrued.checked_add(interest) {
+            Ok(data) => Ok(data),
+            Err(_) => Err(ContractError::Overflow {}),
+        }
+    }
+
+    /**
+     * Moves the pending interest into storage and restarts the accrual clock.
+     * Must run before every change of the borrowed principal.
+     *
+     * @return The total outstanding interest of the user for the token.
+     */
+    pub fn accrue_interest(
+        storage: &mut dyn Storage,
+        token_address: &Addr,
+        user_address: &Addr,
+        now: Timestamp,
+    ) -> Result<Uint128, ContractError> {
+        let interest = pending_interest(storage, token_address, user_address, now)?;
+
+        BORROW_INTEREST.save(storage, (token_address, user_address), &interest)?;
+        BORROW_INTEREST_TIMESTAMP.save(storage, (token_address, user_address), &now)?;
+
+        Ok(interest)
+    }
+}
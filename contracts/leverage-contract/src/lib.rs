@@ -1,16 +1,41 @@
+pub mod access;
+pub mod aggregator;
+pub mod borrow;
+pub mod burn;
 pub mod contract;
-mod error;
-pub mod msg;
-pub mod state;
+pub mod deleverage;
 pub mod deposit;
-pub mod borrow;
+mod error;
+pub mod events;
 pub mod exchange;
+pub mod execute;
+pub mod ibc_hooks;
+pub mod incentives;
+pub mod interest;
+pub mod liquidate;
+pub mod listing;
+pub mod market;
+pub mod migrate;
+pub mod msg;
+pub mod operator;
+pub mod oracle;
+pub mod pyth;
+pub mod query;
+pub mod receive;
+pub mod referral;
 pub mod repay;
-pub mod burn;
+pub mod simulate;
+pub mod state;
+pub mod sub_account;
+pub mod sudo;
+pub mod timelock;
+pub mod twap;
+pub mod valuation;
 pub mod withdraw;
-pub mod query;
-pub mod execute;
 
 pub mod contract_test;
+pub mod fuzz_test;
+pub mod mock_pyth;
+pub mod scenario;
 
-pub use crate::error::ContractError;
\ No newline at end of file
+pub use crate::error::ContractError;
@@ -1,3 +1,481 @@
 pub mod liquidate_leverage {
+    use cosmwasm_std::{
+        Addr, Decimal, Decimal256, Deps, DepsMut, Empty, Env, Event, MessageInfo, Order, Response,
+        StdResult, Storage, Timestamp, Uint128,
+    };
+    use cw_storage_plus::Bound;
 
-}
\ No newline at end of file
+    use crate::deleverage::auto_deleverage;
+    use crate::error::ContractError;
+    use crate::events::leverage_events::LeverageEvent;
+    use crate::incentives::incentives_module;
+    use crate::interest::interest_module;
+    use crate::listing::token_listing;
+    use crate::market::market_module;
+    use crate::msg::{AccountTokenBalance, LiquidatableAccount, LiquidatableAccountsResponse};
+    use crate::oracle::price_oracle;
+    use crate::repay::repay_leverage;
+    use crate::state::{
+        TokenInfo, BORROWERS, BORROW_INTEREST, LISTED_TOKEN, TOKEN_BALANCE, TOTAL_COLLATERAL,
+        TOTAL_WRAPPED, WRAPPED_TOKEN_BALANCE, WRAPPED_TOKEN_BORROW_BALANCE,
+    };
+    use crate::sub_account::sub_accounts;
+    use crate::sudo::sudo_module;
+    use crate::valuation::valuation_module;
+    use crate::withdraw::withdraw_collateral;
+
+    // Share of a debt a single liquidation may repay
+    pub const CLOSE_FACTOR: Decimal = Decimal::percent(50);
+    // Collateral the liquidator receives on top of the repaid value
+    pub const LIQUIDATION_BONUS: Decimal = Decimal::percent(5);
+
+    const DEFAULT_LIMIT: u32 = 10;
+    const MAX_LIMIT: u32 = 30;
+
+    /**
+     * Liquidates with native tokens sent along with the message.
+     */
+    pub fn liquidate_native(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        token_address: Addr,
+        user_address: String,
+        account_id: Option<u64>,
+        collateral_token: Addr,
+    ) -> Result<Response, ContractError> {
+        let token_info = token_listing::load_token(deps.storage, &token_address)?;
+        let amount = token_listing::native_payment(&info, &token_info)?;
+        let user_address =
+            sub_accounts::account_address(&deps.api.addr_validate(&user_address)?, account_id)?;
+
+        liquidate(
+            deps,
+            env,
+            info.sender,
+            token_address,
+            amount,
+            user_address,
+            collateral_token,
+        )
+    }
+
+    /**
+     * Repays part of the debt of an unhealthy account with real tokens.
+     *
+     * The liquidator receives collateral of the account worth the repaid amount
+     * plus the liquidation bonus. At most the close factor of the debt in the
+     * repaid token can be settled at once. `user_address` is the liquidated
+     * account, sub-accounts included.
+     *
+     * When the collateral doesn't cover the payment, it is all seized and the
+     * rest of the payment goes back to the liquidator. An account left without
+     * any collateral can't be liquidated further, so its debts are settled like
+     * a forced settlement and what the account can't cover becomes the deficit
+     * of the token, which the insurance covers as far as it goes.
+     */
+    pub fn liquidate(
+        mut deps: DepsMut,
+        env: Env,
+        liquidator: Addr,
+        token_address: Addr,
+        amount: Uint128,
+        user_address: Addr,
+        collateral_token: Addr,
+    ) -> Result<Response, ContractError> {
+        let health = valuation_module::account_health(deps.as_ref(), &env, &user_address)?;
+        match health.health_factor {
+            Some(health_factor) if health_factor < Decimal256::one() => {}
+            _ => return Err(ContractError::AccountHealthy {}),
+        }
+
+        let debt_info = token_listing::load_token(deps.storage, &token_address)?;
+        let collateral_info = token_listing::load_token(deps.storage, &collateral_token)?;
+
+        let debt = outstanding_debt(deps.storage, &token_address, &user_address, env.block.time)?;
+        let max_repayable = match debt.checked_mul_floor(CLOSE_FACTOR) {
+            Ok(data) => data,
+            Err(_) => return Err(ContractError::Overflow {}),
+        };
+        if amount > max_repayable {
+            return Err(ContractError::RepayAboveCloseFactor {});
+        }
+
+        let collateral = TOKEN_BALANCE
+            .may_load(deps.storage, (&collateral_token, &user_address))?
+            .unwrap_or_default();
+        let (repayable, seizable) = liquidation_amounts(
+            deps.as_ref(),
+            &env,
+            &debt_info,
+            debt,
+            &collateral_info,
+            collateral,
+        )?;
+        let repaid = amount.min(repayable);
+        let seized = if repaid == repayable {
+            seizable
+        } else {
+            seized_collateral(deps.as_ref(), &env, &debt_info, repaid, &collateral_info)?
+        };
+        if repaid.is_zero() || seized.is_zero() {
+            return Err(ContractError::InsufficientBalance {});
+        }
+        let refunded = amount - repaid;
+
+        let (interest_paid, principal_paid) = repay_leverage::settle_debt_with_funds(
+            deps.storage,
+            &token_address,
+            &user_address,
+            repaid,
+            env.block.time,
+        )?;
+
+        incentives_module::update_user_rewards(
+            deps.storage,
+            &collateral_token,
+            &user_address,
+            env.block.time,
+        )?;
+        seize_collateral(deps.storage, &collateral_token, &user_address, seized)?;
+
+        let mut settled = vec![];
+        if !has_collateral(deps.storage, &user_address)? {
+            let (events, deficit_tokens) =
+                sudo_module::settle_account(deps.branch(), &env, &user_address)?;
+            settled = events;
+            for deficit_token in deficit_tokens {
+                settled.extend(auto_deleverage::cover_deficit(
+                    deps.branch(),
+                    &env,
+                    &deficit_token,
+                    vec![],
+                )?);
+            }
+        }
+
+        let cosmos_msg =
+            withdraw_collateral::transfer_msg(&collateral_info.asset, seized, &liquidator)?;
+        let refund_msg = if refunded.is_zero() {
+            None
+        } else {
+            Some(withdraw_collateral::transfer_msg(
+                &debt_info.asset,
+                refunded,
+                &liquidator,
+            )?)
+        };
+
+        let liquidate_event = LeverageEvent::Liquidate {
+            user: user_address.clone(),
+            liquidator: liquidator.clone(),
+            token: token_address.to_string(),
+            amount: repaid,
+            collateral_token: collateral_token.to_string(),
+            seized,
+            borrow_balance: market_module::user_balance(
+                deps.storage,
+                &WRAPPED_TOKEN_BORROW_BALANCE,
+                &token_address,
+                &user_address,
+            )?,
+            collateral_balance: market_module::user_balance(
+                deps.storage,
+                &TOKEN_BALANCE,
+                &collateral_token,
+                &user_address,
+            )?,
+        };
+
+        Ok(Response::new()
+            .add_attribute("method", "liquidate")
+            .add_attribute("user", user_address)
+            .add_attribute("liquidator", liquidator)
+            .add_attribute("token_address", token_address)
+            .add_attribute("interest_paid", interest_paid)
+            .add_attribute("principal_paid", principal_paid)
+            .add_attribute("collateral_token", collateral_token)
+            .add_attribute("seized", seized)
+            .add_attribute("refunded", refunded)
+            .add_event(liquidate_event.into())
+            .add_events(settled.into_iter().map(Event::from))
+            .add_message(cosmos_msg)
+            .add_messages(refund_msg))
+    }
+
+    // Whether the account holds collateral in any listed token
+    fn has_collateral(storage: &dyn Storage, user_address: &Addr) -> Result<bool, ContractError> {
+        for token in LISTED_TOKEN.keys(storage, None, None, Order::Ascending) {
+            let token_address = Addr::unchecked(token?);
+            if !market_module::user_balance(storage, &TOKEN_BALANCE, &token_address, user_address)?
+                .is_zero()
+            {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /**
+     * Collateral worth the repaid amount plus the liquidation bonus.
+     */
+    pub fn seized_collateral(
+        deps: Deps,
+        env: &Env,
+        debt_info: &TokenInfo,
+        repay_amount: Uint128,
+        collateral_info: &TokenInfo,
+    ) -> Result<Uint128, ContractError> {
+        let repaid_value = valuation_module::token_value(
+            repay_amount,
+            debt_info,
+            price_oracle::query_price(deps, env, debt_info)?,
+        )?;
+        let seized_value = repaid_value
+            .checked_mul(Decimal256::one() + Decimal256::from(LIQUIDATION_BONUS))
+            .map_err(|_| ContractError::Overflow {})?;
+
+        valuation_module::token_amount(
+            seized_value,
+            collateral_info,
+            price_oracle::query_price(deps, env, collateral_info)?,
+        )
+    }
+
+    /**
+     * Largest repayment allowed against a debt and the collateral it seizes.
+     *
+     * The repayment is limited by the close factor and by the collateral left
+     * to pay the liquidator with.
+     */
+    pub fn liquidation_amounts(
+        deps: Deps,
+        env: &Env,
+        debt_info: &TokenInfo,
+        debt: Uint128,
+        collateral_info: &TokenInfo,
+        collateral: Uint128,
+    ) -> Result<(Uint128, Uint128), ContractError> {
+        let mut repay_amount = match debt.checked_mul_floor(CLOSE_FACTOR) {
+            Ok(data) => data,
+            Err(_) => return Err(ContractError::Overflow {}),
+        };
+        let mut seized = seized_collateral(deps, env, debt_info, repay_amount, collateral_info)?;
+
+        if seized > collateral {
+            let collateral_value = valuation_module::token_value(
+                collateral,
+                collateral_info,
+                price_oracle::query_price(deps, env, collateral_info)?,
+            )?;
+            let repaid_value = collateral_value
+                .checked_div(Decimal256::one() + Decimal256::from(LIQUIDATION_BONUS))
+                .map_err(|_| ContractError::Overflow {})?;
+
+            repay_amount = valuation_module::token_amount(
+                repaid_value,
+                debt_info,
+                price_oracle::query_price(deps, env, debt_info)?,
+            )?
+            .min(repay_amount);
+            // The rounding dust goes along so the account isn't left with
+            // collateral too small to liquidate
+            seized = collateral;
+        }
+
+        Ok((repay_amount, seized))
+    }
+
+    /**
+     * Keeps the borrower index in line with the user's debt after a repayment.
+     */
+    pub fn update_borrower_index(
+        storage: &mut dyn Storage,
+        user_address: &Addr,
+    ) -> Result<(), ContractError> {
+        let tokens = LISTED_TOKEN
+            .keys(storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<String>>>()?;
+
+        for token in tokens {
+            let token_address = Addr::unchecked(token);
+            let principal = WRAPPED_TOKEN_BORROW_BALANCE
+                .may_load(storage, (&token_address, user_address))?
+                .unwrap_or_default();
+            let interest = BORROW_INTEREST
+                .may_load(storage, (&token_address, user_address))?
+                .unwrap_or_default();
+
+            if !principal.is_zero() || !interest.is_zero() {
+                BORROWERS.save(storage, user_address, &Empty {})?;
+                return Ok(());
+            }
+        }
+
+        BORROWERS.remove(storage, user_address);
+        Ok(())
+    }
+
+    fn outstanding_debt(
+        storage: &dyn Storage,
+        token_address: &Addr,
+        user_address: &Addr,
+        now: Timestamp,
+    ) -> Result<Uint128, ContractError> {
+        let principal = WRAPPED_TOKEN_BORROW_BALANCE
+            .may_load(storage, (token_address, user_address))?
+            .unwrap_or_default();
+        let interest =
+            interest_module::pending_interest(storage, token_address, user_address, now)?;
+
+        match principal.checked_add(interest) {
+            Ok(data) => Ok(data),
+            Err(_) => Err(ContractError::Overflow {}),
+        }
+    }
+
+    /**
+     * Takes collateral off the user, the wrapped tokens it backed go with it.
+     */
+    pub fn seize_collateral(
+        storage: &mut dyn Storage,
+        token_address: &Addr,
+        user_address: &Addr,
+        amount: Uint128,
+    ) -> Result<(), ContractError> {
+        TOKEN_BALANCE.update(
+            storage,
+            (token_address, user_address),
+            |opt_balance| -> Result<Uint128, ContractError> {
+                match opt_balance.unwrap_or_default().checked_sub(amount) {
+                    Ok(data) => Ok(data),
+                    Err(_) => Err(ContractError::InsufficientBalance {}),
+                }
+            },
+        )?;
+
+        // Wrapped tokens which were borrowed against are already gone
+        let wrapped_balance = WRAPPED_TOKEN_BALANCE
+            .may_load(storage, (token_address, user_address))?
+            .unwrap_or_default();
+        let removed_wrapped = match amount.checked_mul(Uint128::from(10u128)) {
+            Ok(data) => data.min(wrapped_balance),
+            Err(_) => wrapped_balance,
+        };
+        WRAPPED_TOKEN_BALANCE.save(
+            storage,
+            (token_address, user_address),
+            &(wrapped_balance - removed_wrapped),
+        )?;
+
+        market_module::decrease_total(storage, &TOTAL_COLLATERAL, token_address, amount)?;
+        market_module::decrease_total(storage, &TOTAL_WRAPPED, token_address, removed_wrapped)?;
+
+        Ok(())
+    }
+
+    /**
+     * The liquidation of an account which repays its largest debt with its largest collateral.
+     */
+    fn liquidatable_account(
+        deps: Deps,
+        env: &Env,
+        user_address: Addr,
+        balances: &[AccountTokenBalance],
+        health_factor: Decimal256,
+    ) -> Result<LiquidatableAccount, ContractError> {
+        let largest_debt = balances.iter().max_by_key(|balance| balance.debt_value);
+        let largest_collateral = balances
+            .iter()
+            .max_by_key(|balance| balance.collateral_value);
+
+        let (debt_balance, collateral_balance) = match (largest_debt, largest_collateral) {
+            (Some(debt_balance), Some(collateral_balance)) => (debt_balance, collateral_balance),
+            _ => return Err(ContractError::AccountHealthy {}),
+        };
+
+        let debt_info =
+            token_listing::load_token(deps.storage, &Addr::unchecked(&debt_balance.token))?;
+        let collateral_info =
+            token_listing::load_token(deps.storage, &Addr::unchecked(&collateral_balance.token))?;
+
+        let debt = match debt_balance.borrowed.checked_add(debt_balance.interest) {
+            Ok(data) => data,
+            Err(_) => return Err(ContractError::Overflow {}),
+        };
+        let (max_repayable, expected_seized) = liquidation_amounts(
+            deps,
+            env,
+            &debt_info,
+            debt,
+            &collateral_info,
+            collateral_balance.collateral,
+        )?;
+
+        // Keepers liquidate sub-accounts through their owner and account id
+        let (user_address, account_id) = sub_accounts::parse(&user_address);
+        Ok(LiquidatableAccount {
+            user_address,
+            account_id,
+            health_factor,
+            debt_token: debt_info.token,
+            max_repayable,
+            collateral_token: collateral_info.token,
+            expected_seized,
+        })
+    }
+
+    /**
+     * Scans up to `limit` borrowers and returns the ones which can be liquidated.
+     *
+     * Borrowers holding a token without a usable price can't be valued and are
+     * left out, so they don't hide the other borrowers of the page. Keepers
+     * continue the scan from `next_start_after` until it is `None`.
+     */
+    pub fn fetch_liquidatable_accounts(
+        deps: Deps,
+        env: Env,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    ) -> StdResult<LiquidatableAccountsResponse> {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+        let start_after = start_after.map(Addr::unchecked);
+        let start = start_after.as_ref().map(Bound::exclusive);
+
+        let borrowers = BORROWERS
+            .keys(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .collect::<StdResult<Vec<Addr>>>()?;
+
+        let next_start_after = if borrowers.len() == limit {
+            borrowers.last().cloned()
+        } else {
+            None
+        };
+
+        let mut accounts = vec![];
+        for user_address in borrowers {
+            let balances = match valuation_module::account_balances(deps, &env, &user_address) {
+                Ok(balances) => balances,
+                Err(err) if price_oracle::is_price_unavailable(&err) => continue,
+                Err(err) => return Err(err.into()),
+            };
+            let health = valuation_module::health_of(&balances)?;
+
+            if let Some(health_factor) = health.health_factor {
+                if health_factor < Decimal256::one() {
+                    match liquidatable_account(deps, &env, user_address, &balances, health_factor) {
+                        Ok(account) => accounts.push(account),
+                        Err(err) if price_oracle::is_price_unavailable(&err) => continue,
+                        Err(err) => return Err(err.into()),
+                    }
+                }
+            }
+        }
+
+        Ok(LiquidatableAccountsResponse {
+            accounts,
+            next_start_after,
+        })
+    }
+}
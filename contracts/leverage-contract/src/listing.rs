@@ -0,0 +1,287 @@
+pub mod token_listing {
+    use cosmwasm_std::{
+        Addr, Deps, DepsMut, Env, MessageInfo, Order, Response, StdResult, Storage, Uint128,
+    };
+    use cw_storage_plus::Bound;
+
+    use crate::access::access_control;
+    use crate::error::ContractError;
+    use crate::events::leverage_events::LeverageEvent;
+    use crate::msg::ListedTokensResponse;
+    use crate::state::{AssetInfo, Role, TokenInfo, TokenStatus, ASSET_TOKEN, LISTED_TOKEN};
+
+    // Amounts are normalized to 18 decimals, more precise tokens would lose value
+    pub const MAX_DECIMALS: u8 = 18;
+
+    const DEFAULT_LIMIT: u32 = 10;
+    const MAX_LIMIT: u32 = 30;
+
+    pub fn load_token(
+        storage: &dyn Storage,
+        token_address: &Addr,
+    ) -> Result<TokenInfo, ContractError> {
+        match LISTED_TOKEN.may_load(storage, token_address.as_str())? {
+            Some(token_info) => Ok(token_info),
+            None => Err(ContractError::TokenNotListed {
+                token: token_address.to_string(),
+            }),
+        }
+    }
+
+    /**
+     * Loads a token which new deposits, borrows and buy orders may go into.
+     */
+    pub fn load_active_token(
+        storage: &dyn Storage,
+        token_address: &Addr,
+    ) -> Result<TokenInfo, ContractError> {
+        let token_info = load_token(storage, token_address)?;
+        assert_active(&token_info)?;
+        Ok(token_info)
+    }
+
+    pub fn assert_active(token_info: &TokenInfo) -> Result<(), ContractError> {
+        if token_info.status != TokenStatus::Active {
+            return Err(ContractError::TokenNotActive {
+                token: token_info.token.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /**
+     * Loads the token a native denom is listed as, e.g. an `ibc/...` denom.
+     */
+    pub fn load_native_token(
+        storage: &dyn Storage,
+        denom: &str,
+    ) -> Result<TokenInfo, ContractError> {
+        let token = match ASSET_TOKEN.may_load(storage, denom)? {
+            Some(token) => token,
+            None => return Err(ContractError::UnauthorizedToken {}),
+        };
+
+        let token_info = load_token(storage, &Addr::unchecked(token))?;
+        match &token_info.asset {
+            AssetInfo::Native { .. } => Ok(token_info),
+            AssetInfo::Cw20 { .. } => Err(ContractError::UnauthorizedToken {}),
+        }
+    }
+
+    /**
+     * Loads the token a CW20 contract is listed as, the contract being the `Send` caller.
+     */
+    pub fn load_cw20_token(
+        storage: &dyn Storage,
+        contract_addr: &Addr,
+    ) -> Result<TokenInfo, ContractError> {
+        let token = match ASSET_TOKEN.may_load(storage, contract_addr.as_str())? {
+            Some(token) => token,
+            None => return Err(ContractError::UnauthorizedToken {}),
+        };
+
+        let token_info = load_token(storage, &Addr::unchecked(token))?;
+        match &token_info.asset {
+            AssetInfo::Cw20 { .. } => Ok(token_info),
+            AssetInfo::Native { .. } => Err(ContractError::UnauthorizedToken {}),
+        }
+    }
+
+    /**
+     * Amount of the token's native coin sent along with the message, nothing else may be sent.
+     */
+    pub fn native_payment(
+        info: &MessageInfo,
+        token_info: &TokenInfo,
+    ) -> Result<Uint128, ContractError> {
+        let denom = match &token_info.asset {
+            AssetInfo::Native { denom } => denom,
+            AssetInfo::Cw20 { .. } => return Err(ContractError::UnauthorizedToken {}),
+        };
+
+        match info.funds.first() {
+            Some(coin)
+                if info.funds.len() == 1 && coin.denom == *denom && !coin.amount.is_zero() =>
+            {
+                Ok(coin.amount)
+            }
+            _ => Err(ContractError::InsufficientNativeToken {}),
+        }
+    }
+
+    /**
+     * Checks a token can be listed, returns its validated asset and decimals.
+     *
+     * CW20 tokens without explicit decimals have them queried from the token contract.
+     */
+    pub fn validate_listing(
+        deps: Deps,
+        token_address: &str,
+        asset: &AssetInfo,
+        decimals: Option<u8>,
+    ) -> Result<(AssetInfo, u8), ContractError> {
+        if LISTED_TOKEN.has(deps.storage, token_address) {
+            return Err(ContractError::TokenAlreadyListed {
+                token: token_address.to_string(),
+            });
+        }
+        if ASSET_TOKEN.has(deps.storage, asset.reference()) {
+            return Err(ContractError::TokenAlreadyListed {
+                token: asset.reference().to_string(),
+            });
+        }
+
+        let asset = match asset {
+            AssetInfo::Cw20 { contract_addr } => AssetInfo::Cw20 {
+                contract_addr: deps.api.addr_validate(contract_addr.as_str())?,
+            },
+            native => native.clone(),
+        };
+
+        let decimals = match (decimals, &asset) {
+            (Some(decimals), _) => decimals,
+            (None, AssetInfo::Cw20 { contract_addr }) => {
+                let token_info: cw20::TokenInfoResponse = deps
+                    .querier
+                    .query_wasm_smart(contract_addr, &cw20::Cw20QueryMsg::TokenInfo {})?;
+                token_info.decimals
+            }
+            (None, AssetInfo::Native { .. }) => {
+                return Err(ContractError::MissingDecimals {
+                    token: token_address.to_string(),
+                })
+            }
+        };
+        if decimals > MAX_DECIMALS {
+            return Err(ContractError::DecimalsTooLarge { max: MAX_DECIMALS });
+        }
+        Ok((asset, decimals))
+    }
+
+    /**
+     * Lists a token, only reachable through an executed timelock action.
+     */
+    pub fn list_token(
+        deps: DepsMut,
+        env: Env,
+        token_address: String,
+        asset: AssetInfo,
+        decimals: Option<u8>,
+        symbol: String,
+    ) -> Result<Response, ContractError> {
+        let (asset, decimals) = validate_listing(deps.as_ref(), &token_address, &asset, decimals)?;
+
+        ASSET_TOKEN.save(deps.storage, asset.reference(), &token_address)?;
+        let token_info = TokenInfo {
+            token: token_address.clone(),
+            asset,
+            decimals,
+            symbol,
+            listed_at: env.block.time,
+            status: TokenStatus::Active,
+        };
+        LISTED_TOKEN.save(deps.storage, &token_address, &token_info)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "list_token_on_leverage")
+            .add_attribute("token_address", token_address)
+            .add_event(
+                LeverageEvent::TokenListed {
+                    token: token_info.token,
+                    asset: token_info.asset.reference().to_string(),
+                    decimals: token_info.decimals,
+                    symbol: token_info.symbol,
+                }
+                .into(),
+            ))
+    }
+
+    /**
+     * Moves a token between active and reduce-only, delisting has its own message.
+     */
+    pub fn update_token_status(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        token_address: Addr,
+        status: TokenStatus,
+    ) -> Result<Response, ContractError> {
+        access_control::assert_role(deps.storage, Role::RiskManager, &info.sender)?;
+
+        let mut token_info = load_token(deps.storage, &token_address)?;
+        if token_info.status == TokenStatus::Delisted || status == TokenStatus::Delisted {
+            return Err(ContractError::TokenDelisted {
+                token: token_info.token,
+            });
+        }
+
+        token_info.status = status;
+        LISTED_TOKEN.save(deps.storage, token_address.as_str(), &token_info)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "update_token_status")
+            .add_attribute("token_address", token_address)
+            .add_event(
+                LeverageEvent::TokenStatusUpdated {
+                    token: token_info.token,
+                    status: token_info.status,
+                }
+                .into(),
+            ))
+    }
+
+    /**
+     * Closes a token for good. Open positions can still be repaid and withdrawn.
+     */
+    pub fn delist_token(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        token_address: Addr,
+    ) -> Result<Response, ContractError> {
+        access_control::assert_role(deps.storage, Role::Admin, &info.sender)?;
+
+        let mut token_info = load_token(deps.storage, &token_address)?;
+        if token_info.status == TokenStatus::Delisted {
+            return Err(ContractError::TokenDelisted {
+                token: token_info.token,
+            });
+        }
+
+        token_info.status = TokenStatus::Delisted;
+        LISTED_TOKEN.save(deps.storage, token_address.as_str(), &token_info)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "delist_token")
+            .add_attribute("token_address", token_address)
+            .add_event(
+                LeverageEvent::TokenStatusUpdated {
+                    token: token_info.token,
+                    status: TokenStatus::Delisted,
+                }
+                .into(),
+            ))
+    }
+
+    pub fn fetch_listed_tokens(
+        deps: Deps,
+        _env: Env,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    ) -> StdResult<ListedTokensResponse> {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+        let start = start_after.as_deref().map(Bound::exclusive);
+
+        let tokens = LISTED_TOKEN
+            .range(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .map(|item| item.map(|(_, token_info)| token_info))
+            .collect::<StdResult<Vec<TokenInfo>>>()?;
+
+        Ok(ListedTokensResponse { tokens })
+    }
+
+    pub fn fetch_listed_token(deps: Deps, _env: Env, token_address: Addr) -> StdResult<TokenInfo> {
+        Ok(load_token(deps.storage, &token_address)?)
+    }
+}
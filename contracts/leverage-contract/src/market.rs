@@ -0,0 +1,212 @@
+pub mod market_module {
+    use cosmwasm_std::{
+        Addr, Decimal, Deps, DepsMut, Env, Order, Response, StdResult, Storage, Uint128,
+    };
+    use cw_storage_plus::{Bound, Map};
+
+    use crate::error::ContractError;
+    use crate::events::leverage_events::LeverageEvent;
+    use crate::listing::token_listing;
+    use crate::msg::{MarketStateResponse, MarketsResponse};
+    use crate::state::{
+        MarketCaps, BORROW_RATE, LISTED_TOKEN, MARKET_CAPS, PROTOCOL_REVENUE, RESERVE_BALANCE,
+        TOTAL_BORROWED, TOTAL_COLLATERAL, TOTAL_VTOKENS, TOTAL_WRAPPED, TRADING_FEES,
+    };
+
+    const DEFAULT_LIMIT: u32 = 10;
+    const MAX_LIMIT: u32 = 30;
+
+    pub fn increase_total(
+        storage: &mut dyn Storage,
+        total_map: &Map<&Addr, Uint128>,
+        token_address: &Addr,
+        amount: Uint128,
+    ) -> Result<Uint128, ContractError> {
+        total_map.update(
+            storage,
+            token_address,
+            |opt_total| -> Result<Uint128, ContractError> {
+                match opt_total {
+                    Some(total) => match total.checked_add(amount) {
+                        Ok(data) => Ok(data),
+                        Err(_) => Err(ContractError::Overflow {}),
+                    },
+                    None => Ok(amount),
+                }
+            },
+        )
+    }
+
+    pub fn decrease_total(
+        storage: &mut dyn Storage,
+        total_map: &Map<&Addr, Uint128>,
+        token_address: &Addr,
+        amount: Uint128,
+    ) -> Result<Uint128, ContractError> {
+        total_map.update(
+            storage,
+            token_address,
+            |opt_total| -> Result<Uint128, ContractError> {
+                match opt_total.unwrap_or_default().checked_sub(amount) {
+                    Ok(data) => Ok(data),
+                    Err(_) => Err(ContractError::Overflow {}),
+                }
+            },
+        )
+    }
+
+    /**
+     * Balance of a user in one of the per-user maps, zero when never set.
+     */
+    pub fn user_balance(
+        storage: &dyn Storage,
+        balance_map: &Map<(&Addr, &Addr), Uint128>,
+        token_address: &Addr,
+        user_address: &Addr,
+    ) -> StdResult<Uint128> {
+        Ok(balance_map
+            .may_load(storage, (token_address, user_address))?
+            .unwrap_or_default())
+    }
+
+    /**
+     * Fails once the total collateral of the token grew above its deposit cap.
+     */
+    pub fn assert_deposit_cap(
+        storage: &dyn Storage,
+        token_address: &Addr,
+    ) -> Result<(), ContractError> {
+        let caps = MARKET_CAPS
+            .may_load(storage, token_address)?
+            .unwrap_or_default();
+        let total = TOTAL_COLLATERAL
+            .may_load(storage, token_address)?
+            .unwrap_or_default();
+
+        match caps.deposit_cap {
+            Some(cap) if total > cap => Err(ContractError::DepositCapExceeded {
+                token: token_address.to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /**
+     * Fails once the total borrows of the token grew above its borrow cap.
+     */
+    pub fn assert_borrow_cap(
+        storage: &dyn Storage,
+        token_address: &Addr,
+    ) -> Result<(), ContractError> {
+        let caps = MARKET_CAPS
+            .may_load(storage, token_address)?
+            .unwrap_or_default();
+        let total = TOTAL_BORROWED
+            .may_load(storage, token_address)?
+            .unwrap_or_default();
+
+        match caps.borrow_cap {
+            Some(cap) if total > cap => Err(ContractError::BorrowCapExceeded {
+                token: token_address.to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /**
+     * Sets the deposit and borrow caps, only reachable through an executed timelock action.
+     */
+    pub fn update_market_caps(
+        deps: DepsMut,
+        _env: Env,
+        token_address: Addr,
+        caps: MarketCaps,
+    ) -> Result<Response, ContractError> {
+        token_listing::load_token(deps.storage, &token_address)?;
+
+        MARKET_CAPS.save(deps.storage, &token_address, &caps)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "update_market_caps")
+            .add_attribute("token_address", token_address.clone())
+            .add_event(
+                LeverageEvent::MarketCapsUpdated {
+                    token: token_address.to_string(),
+                    deposit_cap: caps.deposit_cap,
+                    borrow_cap: caps.borrow_cap,
+                }
+                .into(),
+            ))
+    }
+
+    fn market_state(storage: &dyn Storage, token: String) -> StdResult<MarketStateResponse> {
+        let token_address = Addr::unchecked(&token);
+        let total_of = |total_map: &Map<&Addr, Uint128>| -> StdResult<Uint128> {
+            Ok(total_map
+                .may_load(storage, &token_address)?
+                .unwrap_or_default())
+        };
+
+        let total_collateral = total_of(&TOTAL_COLLATERAL)?;
+        let total_wrapped = total_of(&TOTAL_WRAPPED)?;
+        let total_borrowed = total_of(&TOTAL_BORROWED)?;
+        let total_vtokens = total_of(&TOTAL_VTOKENS)?;
+
+        // Share of the wrapped supply which is borrowed out
+        let supply = total_wrapped + total_borrowed;
+        let utilization = if supply.is_zero() {
+            Decimal::zero()
+        } else {
+            Decimal::from_ratio(total_borrowed, supply)
+        };
+
+        let caps = MARKET_CAPS
+            .may_load(storage, &token_address)?
+            .unwrap_or_default();
+
+        Ok(MarketStateResponse {
+            token,
+            total_collateral,
+            total_wrapped,
+            total_borrowed,
+            total_vtokens,
+            utilization,
+            deposit_cap: caps.deposit_cap,
+            borrow_cap: caps.borrow_cap,
+            borrow_rate: BORROW_RATE
+                .may_load(storage, &token_address)?
+                .unwrap_or_else(Decimal::zero),
+            reserve: total_of(&RESERVE_BALANCE)?,
+            revenue: total_of(&PROTOCOL_REVENUE)?,
+            trading_fees: total_of(&TRADING_FEES)?,
+        })
+    }
+
+    pub fn fetch_market_state(
+        deps: Deps,
+        _env: Env,
+        token_address: Addr,
+    ) -> StdResult<MarketStateResponse> {
+        let token_info = token_listing::load_token(deps.storage, &token_address)?;
+
+        market_state(deps.storage, token_info.token)
+    }
+
+    pub fn fetch_all_markets(
+        deps: Deps,
+        _env: Env,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    ) -> StdResult<MarketsResponse> {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+        let start = start_after.as_deref().map(Bound::exclusive);
+
+        let markets = LISTED_TOKEN
+            .keys(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .map(|item| item.and_then(|token| market_state(deps.storage, token)))
+            .collect::<StdResult<Vec<MarketStateResponse>>>()?;
+
+        Ok(MarketsResponse { markets })
+    }
+}
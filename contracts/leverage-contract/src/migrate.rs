@@ -0,0 +1,85 @@
+pub mod contract_migration {
+    use cosmwasm_std::{Addr, Empty, Order, StdResult, Storage, Timestamp, Uint128};
+    use cw_storage_plus::{Item, Map};
+
+    use crate::error::ContractError;
+    use crate::market::market_module;
+    use crate::msg::InitialToken;
+    use crate::state::{
+        BORROWERS, BORROW_INTEREST_TIMESTAMP, TOKEN_BALANCE, TOTAL_BORROWED, TOTAL_COLLATERAL,
+        TOTAL_VTOKENS, TOTAL_WRAPPED, USER_VTOKEN_BALANCE, WRAPPED_TOKEN_BALANCE,
+        WRAPPED_TOKEN_BORROW_BALANCE,
+    };
+
+    // Listing of the first release, token keys without any metadata
+    const LEGACY_LISTED_TOKEN: Item<Vec<String>> = Item::new("listed_chain");
+
+    /**
+     * Drops the listing of the first release, once `tokens` has the metadata
+     * of every token in it.
+     */
+    pub fn take_legacy_listing(
+        storage: &mut dyn Storage,
+        tokens: &[InitialToken],
+    ) -> Result<(), ContractError> {
+        let legacy_tokens = LEGACY_LISTED_TOKEN
+            .may_load(storage)?
+            .ok_or(ContractError::NothingToMigrate {})?;
+
+        for token in legacy_tokens {
+            if !tokens
+                .iter()
+                .any(|initial_token| initial_token.token_address == token)
+            {
+                return Err(ContractError::MissingTokenMetadata { token });
+            }
+        }
+
+        LEGACY_LISTED_TOKEN.remove(storage);
+        Ok(())
+    }
+
+    /**
+     * Rebuilds what the first release didn't keep track of from the balances.
+     *
+     * The market totals are summed up over all users and every account with
+     * debt joins the borrower index. Interest wasn't charged before, it starts
+     * accruing with the migration.
+     */
+    pub fn rebuild_market_state(
+        storage: &mut dyn Storage,
+        now: Timestamp,
+    ) -> Result<(), ContractError> {
+        sum_up(storage, &TOKEN_BALANCE, &TOTAL_COLLATERAL)?;
+        sum_up(storage, &WRAPPED_TOKEN_BALANCE, &TOTAL_WRAPPED)?;
+        sum_up(storage, &USER_VTOKEN_BALANCE, &TOTAL_VTOKENS)?;
+
+        let borrows = sum_up(storage, &WRAPPED_TOKEN_BORROW_BALANCE, &TOTAL_BORROWED)?;
+        for (token_address, user_address) in borrows {
+            BORROWERS.save(storage, &user_address, &Empty {})?;
+            BORROW_INTEREST_TIMESTAMP.save(storage, (&token_address, &user_address), &now)?;
+        }
+        Ok(())
+    }
+
+    // Adds every user balance to the token's total, returns the non-zero balances
+    fn sum_up(
+        storage: &mut dyn Storage,
+        balance_map: &Map<(&Addr, &Addr), Uint128>,
+        total_map: &Map<&Addr, Uint128>,
+    ) -> Result<Vec<(Addr, Addr)>, ContractError> {
+        let balances = balance_map
+            .range(storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<((Addr, Addr), Uint128)>>>()?;
+
+        let mut holders = vec![];
+        for ((token_address, user_address), amount) in balances {
+            if amount.is_zero() {
+                continue;
+            }
+            market_module::increase_total(storage, total_map, &token_address, amount)?;
+            holders.push((token_address, user_address));
+        }
+        Ok(holders)
+    }
+}
@@ -0,0 +1,100 @@
+#[cfg(test)]
+pub mod mock_pyth_contract {
+    use cosmwasm_schema::cw_serde;
+    use cosmwasm_std::{
+        to_json_binary, Addr, Binary, Deps, DepsMut, Empty, Env, Int64, MessageInfo, Response,
+        StdError, StdResult, Timestamp, Uint64,
+    };
+    use cw_multi_test::error::AnyResult;
+    use cw_multi_test::{App, AppResponse, ContractWrapper, Executor};
+    use cw_storage_plus::Map;
+
+    use crate::pyth::pyth_oracle::{PriceFeed, PriceFeedResponse, PythPrice, PythQueryMsg};
+
+    const PRICE_FEEDS: Map<&str, PriceFeed> = Map::new("price_feeds");
+
+    /**
+     * Stands in for the Pyth contract, anyone can publish a price.
+     */
+    #[cw_serde]
+    pub enum MockPythExecuteMsg {
+        UpdatePriceFeed { price_feed: PriceFeed },
+    }
+
+    pub fn instantiate(
+        _deps: DepsMut,
+        _env: Env,
+        _info: MessageInfo,
+        _msg: Empty,
+    ) -> StdResult<Response> {
+        Ok(Response::new())
+    }
+
+    pub fn execute(
+        deps: DepsMut,
+        _env: Env,
+        _info: MessageInfo,
+        msg: MockPythExecuteMsg,
+    ) -> StdResult<Response> {
+        match msg {
+            MockPythExecuteMsg::UpdatePriceFeed { price_feed } => {
+                PRICE_FEEDS.save(deps.storage, &price_feed.id.clone(), &price_feed)?;
+                Ok(Response::new())
+            }
+        }
+    }
+
+    pub fn query(deps: Deps, _env: Env, msg: PythQueryMsg) -> StdResult<Binary> {
+        match msg {
+            PythQueryMsg::PriceFeed { id } => match PRICE_FEEDS.may_load(deps.storage, &id)? {
+                Some(price_feed) => to_json_binary(&PriceFeedResponse { price_feed }),
+                None => Err(StdError::not_found(format!("price feed {id}"))),
+            },
+        }
+    }
+
+    pub fn deploy(app: &mut App) -> Addr {
+        let code_id = app.store_code(Box::new(ContractWrapper::new(execute, instantiate, query)));
+        app.instantiate_contract(
+            code_id,
+            Addr::unchecked("pyth_admin"),
+            &Empty {},
+            &[],
+            "mock_pyth",
+            None,
+        )
+        .unwrap()
+    }
+
+    /**
+     * Publishes `price ± conf` times `10^expo` for the feed at `publish_time`.
+     */
+    pub fn publish(
+        app: &mut App,
+        pyth: &Addr,
+        id: &str,
+        price: i64,
+        conf: u64,
+        expo: i32,
+        publish_time: Timestamp,
+    ) -> AnyResult<AppResponse> {
+        let price = PythPrice {
+            price: Int64::new(price),
+            conf: Uint64::new(conf),
+            expo,
+            publish_time: publish_time.seconds() as i64,
+        };
+        app.execute_contract(
+            Addr::unchecked("pyth_publisher"),
+            pyth.clone(),
+            &MockPythExecuteMsg::UpdatePriceFeed {
+                price_feed: PriceFeed {
+                    id: String::from(id),
+                    price: price.clone(),
+                    ema_price: price,
+                },
+            },
+            &[],
+        )
+    }
+}
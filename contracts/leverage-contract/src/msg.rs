@@ -1,14 +1,35 @@
+use crate::state::{
+    AssetInfo, FeeConfig, OperatorPermission, OrderState, PendingAction, PriceSource,
+    ReferralRebate, RewardSchedule, Role, TimelockAction, TokenInfo, TokenStatus,
+};
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::{Addr, Binary, Uint128};
-use cw20::{Cw20Coin, Logo, MinterResponse};
+use cosmwasm_std::{Addr, Attribute, Binary, Coin, Decimal, Decimal256, Uint128};
+use cw20::{Cw20Coin, Expiration, Logo, MinterResponse};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::fmt;
-use crate::state::OrderState;
 
 #[cw_serde]
 pub struct InstantiateMsg {
-    pub token_contract_address: String,
+    /// Defaults to the instantiating address
+    pub owner: Option<String>,
+    /// Listed right away, without going through the timelock
+    pub tokens: Vec<InitialToken>,
+    /// No fees are charged when not set
+    pub fees: Option<FeeConfig>,
+    /// Seconds admin actions wait in the timelock, two days when not set and
+    /// at least `MIN_TIMELOCK_DELAY`
+    pub timelock_delay: Option<u64>,
+}
+
+#[cw_serde]
+pub struct InitialToken {
+    pub token_address: String,
+    pub asset: AssetInfo,
+    /// Queried from the CW20 token info when not set
+    pub decimals: Option<u8>,
+    pub symbol: String,
+    pub price_source: Option<PriceSource>,
 }
 
 #[cw_serde]
@@ -29,20 +50,117 @@ pub struct Cw20Instantiate {
     pub marketing: Option<InstantiateMarketingInfo>,
 }
 
+/// User actions take an optional `account_id`, the sub-account of the sender
+/// they act on, which is the wallet's main account when not set
 #[cw_serde]
 pub enum ExecuteMsg {
-    ListTokenOnLeverage {
-        token_address: String,
-    },
     Receive(Cw20ReceiveMsg),
     DepositNative {
         token_address: String,
+        /// Binds the referrer behind the code on the user's first deposit
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        referral_code: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        account_id: Option<u64>,
+    },
+    /// Deposits an ICS-20 transfer on arrival, called by ibc-hooks from the transfer memo
+    IbcHookDeposit {
+        /// Channel on this chain the transfer arrived through
+        channel: String,
+        /// Sender on the source chain
+        original_sender: String,
+        /// Borrowed right after the deposit when set
+        borrow_amount: Option<Uint128>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        account_id: Option<u64>,
+    },
+    RepayNative {
+        token_address: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        account_id: Option<u64>,
+    },
+    TopUpNative {
+        token_address: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        account_id: Option<u64>,
+    },
+    /// Repays debt of an unhealthy account in `token_address` with the sent funds
+    LiquidateNative {
+        token_address: String,
+        user_address: String,
+        collateral_token: String,
+        /// Sub-account of the liquidated user
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        account_id: Option<u64>,
+    },
+    GrantRole {
+        role: Role,
+        address: String,
+    },
+    RevokeRole {
+        role: Role,
+        address: String,
+    },
+    ProposeOwnership {
+        new_owner: String,
+    },
+    AcceptOwnership {},
+    ProposeAction {
+        action: TimelockAction,
+    },
+    CancelAction {
+        id: u64,
+    },
+    ExecuteAction {
+        id: u64,
+    },
+    Pause {},
+    Unpause {},
+    UpdateTokenStatus {
+        token_address: String,
+        status: TokenStatus,
     },
+    DelistToken {
+        token_address: String,
+    },
+    CollectRevenue(WithdrawData),
     Borrow(TokenData),
     ExecuteOrder(OrderExecute),
     Repay(TokenData),
     Burn(TokenData),
-    WithdrawToken(WithdrawData)
+    WithdrawToken(WithdrawData),
+    /// Pays out the liquidity-mining rewards of every market
+    ClaimRewards {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        account_id: Option<u64>,
+    },
+    /// Moves collateral between two accounts of the sender
+    TransferCollateral {
+        token_address: String,
+        amount: Uint128,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        from_account_id: Option<u64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        to_account_id: Option<u64>,
+    },
+    /// Registers the sender as a referrer under `code`
+    RegisterReferralCode {
+        code: String,
+    },
+    /// Pays out the sender's referral rebates of every market
+    ClaimReferralRebates {},
+    /// Lets `operator` trade, borrow or repay on every account of the sender,
+    /// replacing an earlier approval. Operators can never withdraw.
+    ApproveOperator {
+        operator: String,
+        permissions: Vec<OperatorPermission>,
+        /// Never expires when not set
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        expires: Option<Expiration>,
+    },
+    RevokeOperator {
+        operator: String,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
@@ -60,6 +178,11 @@ pub struct LiquidateData {
 pub struct TokenData {
     pub token_address: Addr,
     pub token_amount: Uint128,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<u64>,
+    /// Wallet an approved operator borrows or repays for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_behalf_of: Option<Addr>,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
@@ -67,9 +190,8 @@ pub struct TokenData {
 pub struct WithdrawData {
     pub token_address: Addr,
     pub token_amount: Uint128,
-    pub withdraw_type: String,
-    pub native: Option<String>,
-    pub usdc: Option<String>
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
@@ -85,13 +207,38 @@ impl fmt::Display for Cw20ReceiveMsg {
         write!(
             f,
             "sender:{} amount:{} msg:{}",
-            self.sender,
-            self.amount,
-            self.msg.to_string()
+            self.sender, self.amount, self.msg
         )
     }
 }
 
+/// Payload of a CW20 `Send`, an empty payload deposits collateral
+#[cw_serde]
+pub enum Cw20HookMsg {
+    Deposit {
+        /// Binds the referrer behind the code on the user's first deposit
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        referral_code: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        account_id: Option<u64>,
+    },
+    Repay {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        account_id: Option<u64>,
+    },
+    TopUp {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        account_id: Option<u64>,
+    },
+    Liquidate {
+        user_address: String,
+        collateral_token: String,
+        /// Sub-account of the liquidated user
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        account_id: Option<u64>,
+    },
+}
+
 #[cw_serde]
 pub struct DepositCollateralReceive {
     pub message: String,
@@ -108,11 +255,62 @@ pub struct OrderExecute {
     pub token_out: Addr,
     pub amount_in: Uint128,
     pub amount_out: Uint128,
+    /// Sub-account of `user_address` the order trades on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<u64>,
+    /// Least `token_out` vTokens the user accepts after the trading fee, so
+    /// nobody filling the order for them can fill it at a worse price
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_out: Option<Uint128>,
+}
+
+#[cw_serde]
+pub enum MigrateMsg {
+    /// Moves a contract of the first release to the current storage layout.
+    /// `tokens` needs the metadata of every token it listed, the other fields
+    /// are set up like on instantiation.
+    FromFirstRelease {
+        tokens: Vec<InitialToken>,
+        fees: Option<FeeConfig>,
+        timelock_delay: Option<u64>,
+    },
+}
+
+/// Emergency actions of chain governance, which reach the contract through `sudo`
+#[cw_serde]
+pub enum SudoMsg {
+    ForcePause {},
+    /// Replaces the owner right away, also when its key is lost
+    ReplaceOwner {
+        new_owner: String,
+    },
+    /// Sets the price sources without going through the timelock
+    UpdatePriceSources {
+        sources: Vec<PriceSourceUpdate>,
+    },
+    /// Closes all debt of the user, see `sudo_module::force_settle`
+    ForceSettle {
+        user_address: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        account_id: Option<u64>,
+    },
+    /// Ranks the next `limit` holders of the token, the call completing the
+    /// scan covers its deficit, see `sudo_module::auto_deleverage`
+    AutoDeleverage {
+        token_address: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        limit: Option<u32>,
+    },
 }
 
 #[cw_serde]
-pub enum MigrateMsg {}
+pub struct PriceSourceUpdate {
+    pub token_address: String,
+    pub source: PriceSource,
+}
 
+/// Queries of a user's balances take the same optional `account_id` as user
+/// actions, simulations act on the account of the simulated action
 #[cw_serde]
 #[derive(QueryResponses)]
 pub enum QueryMsg {
@@ -128,15 +326,357 @@ pub enum QueryMsg {
     #[returns(Uint128)]
     UserVTokenBalance(QueryTokenData),
 
+    #[returns(Uint128)]
+    UserBorrowInterest(QueryTokenData),
+
     #[returns(OrderState)]
-    UserOrders { user_address: Addr }
+    UserOrders {
+        user_address: Addr,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        account_id: Option<u64>,
+    },
+
+    #[returns(ConfigResponse)]
+    Config {},
+
+    #[returns(cw2::ContractVersion)]
+    ContractVersion {},
+
+    #[returns(RoleMembersResponse)]
+    RoleMembers {
+        role: Role,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+
+    #[returns(ListedTokensResponse)]
+    ListedTokens {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+
+    #[returns(TokenInfo)]
+    ListedToken { token_address: Addr },
+
+    #[returns(PriceResponse)]
+    Price { token_address: Addr },
+
+    #[returns(AccountHealthResponse)]
+    AccountHealth {
+        user_address: Addr,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        account_id: Option<u64>,
+    },
+
+    #[returns(MarketStateResponse)]
+    MarketState { token_address: Addr },
+
+    #[returns(MarketsResponse)]
+    AllMarkets {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+
+    #[returns(LiquidatableAccountsResponse)]
+    LiquidatableAccounts {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+
+    /// Accounts auto-deleveraging closes first when the token runs a deficit
+    #[returns(AdlRankingResponse)]
+    AdlRanking {
+        token_address: Addr,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+
+    #[returns(SimulationResponse)]
+    SimulateBorrow {
+        user_address: Addr,
+        token_data: TokenData,
+    },
+
+    #[returns(SimulationResponse)]
+    SimulateWithdraw {
+        user_address: Addr,
+        withdraw_data: WithdrawData,
+    },
+
+    #[returns(SimulationResponse)]
+    SimulateOrder(OrderExecute),
+
+    #[returns(SimulationResponse)]
+    SimulateBurn {
+        user_address: Addr,
+        token_data: TokenData,
+    },
+
+    #[returns(SimulationResponse)]
+    SimulateLiquidation {
+        liquidator: Addr,
+        user_address: Addr,
+        /// Debt token and the amount the liquidator pays
+        token_data: TokenData,
+        collateral_token: Addr,
+        /// Sub-account of the liquidated user
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        account_id: Option<u64>,
+    },
+
+    #[returns(UserAccountResponse)]
+    UserAccount {
+        user_address: Addr,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        account_id: Option<u64>,
+    },
+
+    #[returns(PendingActionsResponse)]
+    PendingActions {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+
+    #[returns(PendingAction)]
+    PendingAction { id: u64 },
+
+    /// Rewards the user would receive when claiming now
+    #[returns(PendingRewardsResponse)]
+    PendingRewards {
+        user_address: Addr,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        account_id: Option<u64>,
+    },
+
+    #[returns(RewardStateResponse)]
+    RewardState { token_address: Addr },
+
+    #[returns(ReferralStatsResponse)]
+    ReferralStats { referrer_address: Addr },
+
+    #[returns(ReferrerResponse)]
+    Referrer { user_address: Addr },
+
+    /// Sub-accounts of the owner which ever held collateral
+    #[returns(SubAccountsResponse)]
+    SubAccounts { owner: Addr },
+
+    #[returns(OperatorApprovalsResponse)]
+    OperatorApprovals {
+        owner: Addr,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
 }
 
+#[cw_serde]
+pub struct ConfigResponse {
+    pub owner: Addr,
+    pub pending_owner: Option<Addr>,
+    pub paused: bool,
+    pub timelock_delay: u64,
+    pub fees: FeeConfig,
+}
 
+#[cw_serde]
+pub struct ListedTokensResponse {
+    pub tokens: Vec<TokenInfo>,
+}
+
+#[cw_serde]
+pub struct PriceResponse {
+    pub token: String,
+    pub price: Decimal,
+}
+
+/// Values are in the quote currency of the price sources
+#[cw_serde]
+pub struct AccountHealthResponse {
+    pub collateral_value: Decimal256,
+    pub position_value: Decimal256,
+    pub debt_value: Decimal256,
+    /// Below one the account can be liquidated, `None` without debt
+    pub health_factor: Option<Decimal256>,
+    pub leverage: Option<Decimal256>,
+}
+
+/// Balances of one listed token, borrowed is the principal without interest
+#[cw_serde]
+pub struct AccountTokenBalance {
+    pub token: String,
+    pub collateral: Uint128,
+    pub wrapped: Uint128,
+    pub borrowed: Uint128,
+    pub interest: Uint128,
+    pub vtokens: Uint128,
+    pub collateral_value: Decimal256,
+    pub position_value: Decimal256,
+    pub debt_value: Decimal256,
+}
+
+#[cw_serde]
+pub struct UserAccountResponse {
+    pub user_address: Addr,
+    pub balances: Vec<AccountTokenBalance>,
+    pub health: AccountHealthResponse,
+    pub open_orders: u64,
+}
+
+#[cw_serde]
+pub struct MarketStateResponse {
+    pub token: String,
+    pub total_collateral: Uint128,
+    pub total_wrapped: Uint128,
+    pub total_borrowed: Uint128,
+    pub total_vtokens: Uint128,
+    /// Borrowed share of the wrapped supply
+    pub utilization: Decimal,
+    pub deposit_cap: Option<Uint128>,
+    pub borrow_cap: Option<Uint128>,
+    pub borrow_rate: Decimal,
+    pub reserve: Uint128,
+    pub revenue: Uint128,
+    pub trading_fees: Uint128,
+}
+
+#[cw_serde]
+pub struct MarketsResponse {
+    pub markets: Vec<MarketStateResponse>,
+}
+
+/// Largest liquidation of an account, repaying its biggest debt and seizing its biggest collateral
+#[cw_serde]
+pub struct LiquidatableAccount {
+    pub user_address: Addr,
+    pub account_id: u64,
+    pub health_factor: Decimal256,
+    pub debt_token: String,
+    pub max_repayable: Uint128,
+    pub collateral_token: String,
+    pub expected_seized: Uint128,
+}
+
+/// Profitable position in a token, ranked by auto-deleveraging
+#[cw_serde]
+pub struct AdlRank {
+    pub user_address: Addr,
+    pub account_id: u64,
+    /// vTokens held beyond the account's own debt in the token
+    pub position: Uint128,
+    /// Part of the position auto-deleveraging may close, at most the account's profit
+    pub closable: Uint128,
+    /// Value of all vTokens of the account minus its debt
+    pub pnl: Decimal256,
+    pub leverage: Decimal256,
+    /// Profit relative to the collateral times leverage, highest is closed first
+    pub score: Decimal256,
+}
+
+#[cw_serde]
+pub struct AdlRankingResponse {
+    pub token: String,
+    pub deficit: Uint128,
+    /// Ranking of the scanned holders only
+    pub ranking: Vec<AdlRank>,
+    /// Last scanned holder, `None` once all holders were scanned
+    pub next_start_after: Option<Addr>,
+}
+
+#[cw_serde]
+pub struct LiquidatableAccountsResponse {
+    pub accounts: Vec<LiquidatableAccount>,
+    /// Last scanned borrower, `None` once all borrowers were scanned
+    pub next_start_after: Option<Addr>,
+}
+
+/// State of the account after a simulated action
+#[cw_serde]
+pub struct SimulationResponse {
+    pub balances: Vec<AccountTokenBalance>,
+    pub health: AccountHealthResponse,
+    /// Fee charged by the action, in the token it charges it in
+    pub fee: Uint128,
+    /// Attributes the action would emit
+    pub attributes: Vec<Attribute>,
+}
+
+#[cw_serde]
+pub struct PendingActionsResponse {
+    pub delay: u64,
+    pub actions: Vec<PendingAction>,
+}
+
+#[cw_serde]
+pub struct RoleMembersResponse {
+    pub role: Role,
+    pub members: Vec<Addr>,
+}
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct QueryTokenData {
     pub token_address: Addr,
-    pub user_address: Addr
-}
\ No newline at end of file
+    pub user_address: Addr,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<u64>,
+}
+
+#[cw_serde]
+pub struct PendingRewardsResponse {
+    pub user: Addr,
+    pub rewards: Vec<Coin>,
+}
+
+#[cw_serde]
+pub struct RewardStateResponse {
+    pub token: String,
+    pub schedule: Option<RewardSchedule>,
+    /// Rewards per unit of collateral since the first schedule
+    pub collateral_index: Decimal256,
+    /// Rewards per unit of debt since the first schedule
+    pub borrow_index: Decimal256,
+    /// Unclaimed reward tokens of the schedule's denom, shared with other markets paying in it
+    pub funds: Uint128,
+}
+
+#[cw_serde]
+pub struct TokenRebates {
+    pub token: String,
+    pub pending: ReferralRebate,
+    pub claimed: ReferralRebate,
+}
+
+#[cw_serde]
+pub struct ReferralStatsResponse {
+    pub referrer: Addr,
+    pub code: Option<String>,
+    pub referred_users: u64,
+    pub rebates: Vec<TokenRebates>,
+}
+
+#[cw_serde]
+pub struct ReferrerResponse {
+    pub user: Addr,
+    /// `None` for users who made their first deposit without a referral code
+    pub referrer: Option<Addr>,
+}
+
+#[cw_serde]
+pub struct SubAccountsResponse {
+    pub owner: Addr,
+    pub account_ids: Vec<u64>,
+}
+
+#[cw_serde]
+pub struct OperatorApprovalInfo {
+    pub operator: Addr,
+    pub permissions: Vec<OperatorPermission>,
+    pub expires: Expiration,
+    pub expired: bool,
+}
+
+#[cw_serde]
+pub struct OperatorApprovalsResponse {
+    pub owner: Addr,
+    pub approvals: Vec<OperatorApprovalInfo>,
+}
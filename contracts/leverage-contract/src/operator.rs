@@ -0,0 +1,195 @@
+pub mod operator_approvals {
+    use cosmwasm_std::{Addr, Deps, DepsMut, Env, MessageInfo, Order, Response, StdResult};
+    use cw20::Expiration;
+    use cw_storage_plus::Bound;
+
+    use crate::error::ContractError;
+    use crate::events::leverage_events::LeverageEvent;
+    use crate::msg::{OperatorApprovalInfo, OperatorApprovalsResponse, TokenData};
+    use crate::state::{OperatorApproval, OperatorPermission, OPERATOR_APPROVALS};
+    use crate::sub_account::sub_accounts;
+
+    const DEFAULT_LIMIT: u32 = 10;
+    const MAX_LIMIT: u32 = 30;
+
+    /**
+     * Approves `operator` for `permissions` on every account of the sender.
+     *
+     * A new approval replaces the previous one of the operator as a whole, so
+     * permissions can be narrowed by approving again.
+     */
+    pub fn approve_operator(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        operator: String,
+        permissions: Vec<OperatorPermission>,
+        expires: Option<Expiration>,
+    ) -> Result<Response, ContractError> {
+        let operator = deps.api.addr_validate(&operator)?;
+        if operator == info.sender {
+            return Err(ContractError::SelfOperator {});
+        }
+        if permissions.is_empty() {
+            return Err(ContractError::NoOperatorPermissions {});
+        }
+        let expires = expires.unwrap_or_default();
+        if expires.is_expired(&env.block) {
+            return Err(ContractError::ApprovalExpired {});
+        }
+
+        let mut unique_permissions = Vec::with_capacity(permissions.len());
+        for permission in permissions {
+            if !unique_permissions.contains(&permission) {
+                unique_permissions.push(permission);
+            }
+        }
+        let approval = OperatorApproval {
+            permissions: unique_permissions,
+            expires,
+        };
+        OPERATOR_APPROVALS.save(deps.storage, (&info.sender, &operator), &approval)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "approve_operator")
+            .add_attribute("owner", info.sender.clone())
+            .add_attribute("operator", operator.clone())
+            .add_event(
+                LeverageEvent::OperatorApproved {
+                    owner: info.sender,
+                    operator,
+                    permissions: approval.permissions,
+                    expires: approval.expires,
+                }
+                .into(),
+            ))
+    }
+
+    pub fn revoke_operator(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        operator: String,
+    ) -> Result<Response, ContractError> {
+        let operator = deps.api.addr_validate(&operator)?;
+        if !OPERATOR_APPROVALS.has(deps.storage, (&info.sender, &operator)) {
+            return Err(ContractError::NoOperatorApproval {});
+        }
+        OPERATOR_APPROVALS.remove(deps.storage, (&info.sender, &operator));
+
+        Ok(Response::new()
+            .add_attribute("method", "revoke_operator")
+            .add_attribute("owner", info.sender.clone())
+            .add_attribute("operator", operator.clone())
+            .add_event(
+                LeverageEvent::OperatorRevoked {
+                    owner: info.sender,
+                    operator,
+                }
+                .into(),
+            ))
+    }
+
+    pub fn is_approved(
+        deps: Deps,
+        env: &Env,
+        owner: &Addr,
+        operator: &Addr,
+        permission: OperatorPermission,
+    ) -> StdResult<bool> {
+        Ok(
+            match OPERATOR_APPROVALS.may_load(deps.storage, (owner, operator))? {
+                Some(approval) => {
+                    approval.permissions.contains(&permission)
+                        && !approval.expires.is_expired(&env.block)
+                }
+                None => false,
+            },
+        )
+    }
+
+    pub fn assert_approved(
+        deps: Deps,
+        env: &Env,
+        owner: &Addr,
+        operator: &Addr,
+        permission: OperatorPermission,
+    ) -> Result<(), ContractError> {
+        if !is_approved(deps, env, owner, operator, permission)? {
+            return Err(ContractError::OperatorNotApproved {
+                permission: permission.as_str().to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /**
+     * Message info with the account the call acts on as sender.
+     *
+     * That's the sender's own account, unless the sender acts as an operator
+     * for the `on_behalf_of` wallet, which needs its approval for `permission`.
+     */
+    pub fn acting_account(
+        deps: Deps,
+        env: &Env,
+        info: MessageInfo,
+        token_data: &TokenData,
+        permission: OperatorPermission,
+    ) -> Result<MessageInfo, ContractError> {
+        match &token_data.on_behalf_of {
+            Some(owner) if *owner != info.sender => {
+                assert_approved(deps, env, owner, &info.sender, permission)?;
+                Ok(MessageInfo {
+                    sender: sub_accounts::account_address(owner, token_data.account_id)?,
+                    funds: info.funds,
+                })
+            }
+            _ => sub_accounts::sender_account(info, token_data.account_id),
+        }
+    }
+
+    /**
+     * Message info of the sender's own account, for actions operators can't take.
+     */
+    pub fn own_account(
+        info: MessageInfo,
+        token_data: &TokenData,
+        action: &str,
+    ) -> Result<MessageInfo, ContractError> {
+        match &token_data.on_behalf_of {
+            Some(owner) if *owner != info.sender => Err(ContractError::OwnerOnlyAction {
+                action: action.to_string(),
+            }),
+            _ => sub_accounts::sender_account(info, token_data.account_id),
+        }
+    }
+
+    pub fn fetch_operator_approvals(
+        deps: Deps,
+        env: Env,
+        owner: Addr,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    ) -> StdResult<OperatorApprovalsResponse> {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+        let start_after = start_after.map(Addr::unchecked);
+        let start = start_after.as_ref().map(Bound::exclusive);
+
+        let approvals = OPERATOR_APPROVALS
+            .prefix(&owner)
+            .range(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .map(|item| {
+                let (operator, approval) = item?;
+                Ok(OperatorApprovalInfo {
+                    operator,
+                    expired: approval.expires.is_expired(&env.block),
+                    permissions: approval.permissions,
+                    expires: approval.expires,
+                })
+            })
+            .collect::<StdResult<Vec<OperatorApprovalInfo>>>()?;
+
+        Ok(OperatorApprovalsResponse { owner, approvals })
+    }
+}
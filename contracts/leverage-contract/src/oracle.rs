@@ -0,0 +1,183 @@
+pub mod price_oracle {
+    use cosmwasm_std::{Addr, Api, Decimal, Deps, DepsMut, Env, Response, StdResult};
+
+    use crate::aggregator::price_aggregator;
+    use crate::error::ContractError;
+    use crate::events::leverage_events::LeverageEvent;
+    use crate::listing::token_listing;
+    use crate::msg::PriceResponse;
+    use crate::pyth::pyth_oracle;
+    use crate::state::{PriceSource, TokenInfo, PRICE_SOURCE};
+    use crate::twap::osmosis_twap;
+
+    /**
+     * Price of one whole token in the quote currency.
+     */
+    pub fn query_price(
+        deps: Deps,
+        _env: &Env,
+        token_info: &TokenInfo,
+    ) -> Result<Decimal, ContractError> {
+        let source = match PRICE_SOURCE.may_load(deps.storage, &token_info.token)? {
+            Some(source) => source,
+            None => {
+                return Err(ContractError::PriceNotAvailable {
+                    token: token_info.token.clone(),
+                })
+            }
+        };
+
+        source_price(deps, _env, token_info, source)
+    }
+
+    fn source_price(
+        deps: Deps,
+        env: &Env,
+        token_info: &TokenInfo,
+        source: PriceSource,
+    ) -> Result<Decimal, ContractError> {
+        let price = match source {
+            PriceSource::Fixed { price } => price,
+            PriceSource::OsmosisTwap {
+                pool_id,
+                quote_denom,
+                quote_decimals,
+                window_seconds,
+            } => osmosis_twap::query_price(
+                deps,
+                env,
+                token_info,
+                pool_id,
+                &quote_denom,
+                quote_decimals,
+                window_seconds,
+            )?,
+            PriceSource::Pyth {
+                contract_addr,
+                price_feed_id,
+                max_age_seconds,
+                max_confidence_ratio,
+            } => pyth_oracle::query_price(
+                deps,
+                env,
+                token_info,
+                &contract_addr,
+                &price_feed_id,
+                max_age_seconds,
+                max_confidence_ratio,
+            )?,
+            PriceSource::Aggregate {
+                sources,
+                max_deviation,
+                min_sources,
+            } => {
+                let prices = sources
+                    .into_iter()
+                    .map(|source| source_price(deps, env, token_info, source))
+                    .collect();
+                price_aggregator::aggregate(token_info, prices, max_deviation, min_sources)?
+            }
+        };
+
+        if price.is_zero() {
+            return Err(ContractError::PriceNotAvailable {
+                token: token_info.token.clone(),
+            });
+        }
+        Ok(price)
+    }
+
+    /**
+     * Whether pricing a token failed for lack of a usable price rather than
+     * because the request was wrong.
+     */
+    pub fn is_price_unavailable(err: &ContractError) -> bool {
+        matches!(
+            err,
+            ContractError::PriceNotAvailable { .. }
+                | ContractError::StalePrice { .. }
+                | ContractError::PriceUncertain { .. }
+                | ContractError::PriceDeviation { .. }
+        )
+    }
+
+    /**
+     * Sets the price source of a token, only reachable through an executed timelock action.
+     */
+    pub fn set_price_source(
+        deps: DepsMut,
+        _env: Env,
+        token_address: Addr,
+        source: PriceSource,
+    ) -> Result<Response, ContractError> {
+        validate_price_source(deps.as_ref(), &token_address, &source)?;
+
+        PRICE_SOURCE.save(deps.storage, token_address.as_str(), &source)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "set_price_source")
+            .add_attribute("token_address", token_address.clone())
+            .add_event(
+                LeverageEvent::PriceSourceUpdated {
+                    token: token_address.to_string(),
+                }
+                .into(),
+            ))
+    }
+
+    pub fn validate_price_source(
+        deps: Deps,
+        token_address: &Addr,
+        source: &PriceSource,
+    ) -> Result<(), ContractError> {
+        let token_info = token_listing::load_token(deps.storage, token_address)?;
+        validate_source(deps.api, &token_info, source)
+    }
+
+    fn validate_source(
+        api: &dyn Api,
+        token_info: &TokenInfo,
+        source: &PriceSource,
+    ) -> Result<(), ContractError> {
+        match source {
+            PriceSource::Fixed { .. } => {}
+            PriceSource::OsmosisTwap {
+                quote_decimals,
+                window_seconds,
+                ..
+            } => osmosis_twap::validate_source(token_info, *quote_decimals, *window_seconds)?,
+            PriceSource::Pyth {
+                contract_addr,
+                max_age_seconds,
+                max_confidence_ratio,
+                ..
+            } => pyth_oracle::validate_source(
+                api,
+                contract_addr,
+                *max_age_seconds,
+                *max_confidence_ratio,
+            )?,
+            PriceSource::Aggregate {
+                sources,
+                max_deviation,
+                min_sources,
+            } => {
+                price_aggregator::validate_source(sources, *max_deviation, *min_sources)?;
+                for source in sources {
+                    validate_source(api, token_info, source)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn fetch_price(deps: Deps, env: Env, token_address: Addr) -> StdResult<PriceResponse> {
+        let token_info = token_listing::load_token(deps.storage, &token_address)?;
+        let price = query_price(deps, &env, &token_info)?;
+
+        Ok(PriceResponse {
+            token: token_info.token,
+            price,
+        })
+    }
+}
@@ -0,0 +1,115 @@
+pub mod pyth_oracle {
+    use cosmwasm_schema::{cw_serde, QueryResponses};
+    use cosmwasm_std::{Addr, Api, Decimal, Deps, Env, Int64, Uint128, Uint64};
+
+    use crate::state::TokenInfo;
+    use crate::ContractError;
+
+    /**
+     * The part of the Pyth contract's query interface the oracle uses.
+     */
+    #[cw_serde]
+    #[derive(QueryResponses)]
+    pub enum PythQueryMsg {
+        #[returns(PriceFeedResponse)]
+        PriceFeed { id: String },
+    }
+
+    #[cw_serde]
+    pub struct PriceFeedResponse {
+        pub price_feed: PriceFeed,
+    }
+
+    #[cw_serde]
+    pub struct PriceFeed {
+        /// Hex encoded feed id
+        pub id: String,
+        pub price: PythPrice,
+        pub ema_price: PythPrice,
+    }
+
+    /// `price ± conf` scaled by `10^expo`, published at `publish_time` in unix seconds
+    #[cw_serde]
+    pub struct PythPrice {
+        pub price: Int64,
+        pub conf: Uint64,
+        pub expo: i32,
+        pub publish_time: i64,
+    }
+
+    pub fn validate_source(
+        api: &dyn Api,
+        contract_addr: &Addr,
+        max_age_seconds: u64,
+        max_confidence_ratio: Decimal,
+    ) -> Result<(), ContractError> {
+        api.addr_validate(contract_addr.as_str())?;
+        if max_age_seconds == 0 {
+            return Err(ContractError::InvalidPriceAge {});
+        }
+        if max_confidence_ratio.is_zero() || max_confidence_ratio > Decimal::one() {
+            return Err(ContractError::InvalidConfidenceRatio {});
+        }
+        Ok(())
+    }
+
+    /**
+     * Spot price of the token's Pyth feed.
+     *
+     * Prices published more than `max_age_seconds` ago and prices whose
+     * confidence interval is above `max_confidence_ratio` of the price are
+     * refused.
+     */
+    pub fn query_price(
+        deps: Deps,
+        env: &Env,
+        token_info: &TokenInfo,
+        contract_addr: &Addr,
+        price_feed_id: &str,
+        max_age_seconds: u64,
+        max_confidence_ratio: Decimal,
+    ) -> Result<Decimal, ContractError> {
+        let response: PriceFeedResponse = deps.querier.query_wasm_smart(
+            contract_addr,
+            &PythQueryMsg::PriceFeed {
+                id: String::from(price_feed_id),
+            },
+        )?;
+        let feed_price = response.price_feed.price;
+
+        // A publish time ahead of the block counts as fresh
+        let age = (env.block.time.seconds() as i64).saturating_sub(feed_price.publish_time);
+        if age > max_age_seconds as i64 {
+            return Err(ContractError::StalePrice {
+                token: token_info.token.clone(),
+            });
+        }
+
+        let not_available = || ContractError::PriceNotAvailable {
+            token: token_info.token.clone(),
+        };
+        if feed_price.price <= Int64::zero() {
+            return Err(not_available());
+        }
+        let raw_price = Uint128::from(feed_price.price.i64() as u64);
+        let conf = Uint128::from(feed_price.conf.u64());
+        if Decimal::from_ratio(conf, raw_price) > max_confidence_ratio {
+            return Err(ContractError::PriceUncertain {
+                token: token_info.token.clone(),
+            });
+        }
+
+        scale_price(raw_price, feed_price.expo).ok_or_else(not_available)
+    }
+
+    fn scale_price(raw_price: Uint128, expo: i32) -> Option<Decimal> {
+        let scale = Uint128::from(10u128)
+            .checked_pow(expo.unsigned_abs())
+            .ok()?;
+        if expo >= 0 {
+            Decimal::checked_from_ratio(raw_price.checked_mul(scale).ok()?, 1u128).ok()
+        } else {
+            Decimal::checked_from_ratio(raw_price, scale).ok()
+        }
+    }
+}
@@ -1,11 +1,16 @@
 pub mod query_module {
-    use cosmwasm_std::{Deps, Env, StdResult, Uint128, Addr};
+    use cosmwasm_std::{Addr, Deps, Env, StdResult, Uint128};
 
     use crate::error::ContractError;
-    use crate::msg::QueryTokenData;
+    use crate::interest::interest_module;
+    use crate::msg::{ConfigResponse, QueryTokenData, UserAccountResponse};
     use crate::state::{
-        TOKEN_BALANCE, USER_VTOKEN_BALANCE, WRAPPED_TOKEN_BALANCE, WRAPPED_TOKEN_BORROW_BALANCE, ORDER_STATE, OrderState
+        OrderState, Status, FEE_CONFIG, LEVERAGE_CONTRACT_OWNER, ORDER_STATE, PAUSED,
+        PENDING_OWNER, TIMELOCK_DELAY, TOKEN_BALANCE, USER_VTOKEN_BALANCE, WRAPPED_TOKEN_BALANCE,
+        WRAPPED_TOKEN_BORROW_BALANCE,
     };
+    use crate::timelock::timelock_module;
+    use crate::valuation::valuation_module;
 
     pub fn fetch_user_collateral_token_balance(
         deps: Deps,
@@ -87,15 +92,37 @@ pub mod query_module {
         }
     }
 
+    pub fn fetch_user_borrow_interest(
+        deps: Deps,
+        env: Env,
+        query_token_data: QueryTokenData,
+    ) -> StdResult<Uint128> {
+        Ok(interest_module::pending_interest(
+            deps.storage,
+            &query_token_data.token_address,
+            &query_token_data.user_address,
+            env.block.time,
+        )?)
+    }
+
+    pub fn fetch_config(deps: Deps, _env: Env) -> StdResult<ConfigResponse> {
+        Ok(ConfigResponse {
+            owner: LEVERAGE_CONTRACT_OWNER.load(deps.storage)?,
+            pending_owner: PENDING_OWNER.may_load(deps.storage)?,
+            paused: PAUSED.may_load(deps.storage)?.unwrap_or(false),
+            timelock_delay: TIMELOCK_DELAY
+                .may_load(deps.storage)?
+                .unwrap_or(timelock_module::DEFAULT_TIMELOCK_DELAY),
+            fees: FEE_CONFIG.may_load(deps.storage)?.unwrap_or_default(),
+        })
+    }
+
     pub fn fetch_user_orders(
         deps: Deps,
         _env: Env,
-        user_address: Addr
+        user_address: Addr,
     ) -> StdResult<Vec<OrderState>> {
-        match ORDER_STATE.may_load(
-            deps.storage,
-            &user_address,
-        ) {
+        match ORDER_STATE.may_load(deps.storage, &user_address) {
             Ok(opt_data) => match opt_data {
                 Some(data) => Ok(data),
                 None => Ok(vec![]),
@@ -103,4 +130,28 @@ pub mod query_module {
             Err(_) => Err(ContractError::UserOrderQueryFailed {}.into()),
         }
     }
+
+    /**
+     * Everything the frontend shows about an account, in a single query.
+     */
+    pub fn fetch_user_account(
+        deps: Deps,
+        env: Env,
+        user_address: Addr,
+    ) -> StdResult<UserAccountResponse> {
+        let balances = valuation_module::account_balances(deps, &env, &user_address)?;
+        let health = valuation_module::health_of(&balances)?;
+
+        let open_orders = fetch_user_orders(deps, env, user_address.clone())?
+            .iter()
+            .filter(|order| order.status == Status::Pending)
+            .count() as u64;
+
+        Ok(UserAccountResponse {
+            user_address,
+            balances,
+            health,
+            open_orders,
+        })
+    }
 }
@@ -0,0 +1,88 @@
+pub mod receive_cw20 {
+    use cosmwasm_std::{from_json, Addr, DepsMut, Env, MessageInfo, Response};
+
+    use crate::deposit::deposit_collateral;
+    use crate::error::ContractError;
+    use crate::liquidate::liquidate_leverage;
+    use crate::listing::token_listing;
+    use crate::msg::{Cw20HookMsg, Cw20ReceiveMsg};
+    use crate::repay::repay_leverage;
+    use crate::sub_account::sub_accounts;
+
+    /**
+     * Routes a CW20 `Send` to the action requested in its payload.
+     */
+    pub fn receive(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        msg: Cw20ReceiveMsg,
+    ) -> Result<Response, ContractError> {
+        let hook_msg = if msg.msg.is_empty() {
+            Cw20HookMsg::Deposit {
+                referral_code: None,
+                account_id: None,
+            }
+        } else {
+            from_json(&msg.msg)?
+        };
+
+        match hook_msg {
+            Cw20HookMsg::Deposit {
+                referral_code,
+                account_id,
+            } => {
+                let msg = Cw20ReceiveMsg {
+                    sender: sender_account(&msg.sender, account_id)?.to_string(),
+                    ..msg
+                };
+                deposit_collateral::fungible_token(deps, env, info, msg, referral_code)
+            }
+            Cw20HookMsg::Repay { account_id } => {
+                let token_info = token_listing::load_cw20_token(deps.storage, &info.sender)?;
+                repay_leverage::repay_fungible(
+                    deps,
+                    env,
+                    sender_account(&msg.sender, account_id)?,
+                    Addr::unchecked(token_info.token),
+                    msg.amount,
+                )
+            }
+            Cw20HookMsg::Liquidate {
+                user_address,
+                collateral_token,
+                account_id,
+            } => {
+                let token_info = token_listing::load_cw20_token(deps.storage, &info.sender)?;
+                let user_address = sub_accounts::account_address(
+                    &deps.api.addr_validate(&user_address)?,
+                    account_id,
+                )?;
+                liquidate_leverage::liquidate(
+                    deps,
+                    env,
+                    Addr::unchecked(msg.sender),
+                    Addr::unchecked(token_info.token),
+                    msg.amount,
+                    user_address,
+                    Addr::unchecked(collateral_token),
+                )
+            }
+            Cw20HookMsg::TopUp { account_id } => {
+                let token_info = token_listing::load_cw20_token(deps.storage, &info.sender)?;
+                repay_leverage::top_up_fungible(
+                    deps,
+                    env,
+                    sender_account(&msg.sender, account_id)?,
+                    Addr::unchecked(token_info.token),
+                    msg.amount,
+                )
+            }
+        }
+    }
+
+    // The CW20 sender is the wallet, the hook picks one of its accounts
+    fn sender_account(sender: &str, account_id: Option<u64>) -> Result<Addr, ContractError> {
+        sub_accounts::account_address(&Addr::unchecked(sender), account_id)
+    }
+}
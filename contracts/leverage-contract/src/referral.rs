@@ -0,0 +1,328 @@
+pub mod referral_program {
+    use std::collections::BTreeSet;
+
+    use cosmwasm_std::{
+        Addr, Deps, DepsMut, Env, MessageInfo, Order, Response, StdResult, Storage, Uint128,
+    };
+
+    use crate::error::ContractError;
+    use crate::events::leverage_events::LeverageEvent;
+    use crate::listing::token_listing;
+    use crate::market::market_module;
+    use crate::msg::{ReferralStatsResponse, ReferrerResponse, TokenRebates};
+    use crate::state::{
+        ReferralRebate, CLAIMED_REBATES, FEE_CONFIG, PENDING_REBATES, PROTOCOL_REVENUE,
+        REFERRAL_CODES, REFERRED_BY, REFERRED_USERS, REFERRER_CODE, TOTAL_VTOKENS, TRADING_FEES,
+        USER_VTOKEN_BALANCE,
+    };
+    use crate::sub_account::sub_accounts;
+    use crate::withdraw::withdraw_collateral;
+
+    pub const MIN_CODE_LENGTH: usize = 3;
+    pub const MAX_CODE_LENGTH: usize = 32;
+
+    fn validate_code(code: &str) -> Result<(), ContractError> {
+        let valid_chars = code
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+        if code.len() < MIN_CODE_LENGTH || code.len() > MAX_CODE_LENGTH || !valid_chars {
+            return Err(ContractError::InvalidReferralCode {});
+        }
+        Ok(())
+    }
+
+    /**
+     * Registers the sender as the referrer behind `code`.
+     *
+     * Each referrer has a single code, which stays theirs for good.
+     */
+    pub fn register_code(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        code: String,
+    ) -> Result<Response, ContractError> {
+        validate_code(&code)?;
+        if let Some(registered) = REFERRER_CODE.may_load(deps.storage, &info.sender)? {
+            return Err(ContractError::ReferralCodeRegistered { code: registered });
+        }
+        if REFERRAL_CODES.has(deps.storage, &code) {
+            return Err(ContractError::ReferralCodeTaken { code });
+        }
+
+        REFERRAL_CODES.save(deps.storage, &code, &info.sender)?;
+        REFERRER_CODE.save(deps.storage, &info.sender, &code)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "register_referral_code")
+            .add_attribute("referrer", info.sender.clone())
+            .add_event(
+                LeverageEvent::ReferralCodeRegistered {
+                    referrer: info.sender,
+                    code,
+                }
+                .into(),
+            ))
+    }
+
+    /**
+     * Binds the user to the referrer of `referral_code` on their first deposit.
+     *
+     * Later deposits never change the binding, whatever code they carry, and a
+     * first deposit without a code leaves the user without a referrer for good.
+     */
+    pub fn bind_referrer(
+        storage: &mut dyn Storage,
+        user_address: &Addr,
+        referral_code: Option<String>,
+    ) -> Result<Option<LeverageEvent>, ContractError> {
+        // Sub-accounts share the referrer of their wallet
+        let user_address = &sub_accounts::owner_of(user_address);
+        if REFERRED_BY.has(storage, user_address) {
+            return Ok(None);
+        }
+        let code = match referral_code {
+            Some(code) => code,
+            None => {
+                REFERRED_BY.save(storage, user_address, &None)?;
+                return Ok(None);
+            }
+        };
+
+        let referrer = REFERRAL_CODES
+            .may_load(storage, &code)?
+            .ok_or_else(|| ContractError::UnknownReferralCode { code: code.clone() })?;
+        if referrer == user_address {
+            return Err(ContractError::SelfReferral {});
+        }
+        REFERRED_BY.save(storage, user_address, &Some(referrer.clone()))?;
+        REFERRED_USERS.update(storage, &referrer, |count| -> StdResult<u64> {
+            Ok(count.unwrap_or_default() + 1)
+        })?;
+
+        Ok(Some(LeverageEvent::ReferrerBound {
+            user: user_address.clone(),
+            referrer,
+            code,
+        }))
+    }
+
+    /**
+     * Accrues the referral share of the fees the user was charged in the
+     * token to their referrer, the protocol keeps the rest.
+     */
+    pub fn share_fees(
+        storage: &mut dyn Storage,
+        user_address: &Addr,
+        token_address: &Addr,
+        borrow_fee: Uint128,
+        trading_fee: Uint128,
+    ) -> Result<(), ContractError> {
+        let owner = sub_accounts::owner_of(user_address);
+        let referrer = match REFERRED_BY.may_load(storage, &owner)?.flatten() {
+            Some(referrer) => referrer,
+            None => return Ok(()),
+        };
+        let share = FEE_CONFIG
+            .may_load(storage)?
+            .unwrap_or_default()
+            .referral_share;
+        let rebate = ReferralRebate {
+            borrow_fees: borrow_fee
+                .checked_mul_floor(share)
+                .map_err(|_| ContractError::Overflow {})?,
+            trading_fees: trading_fee
+                .checked_mul_floor(share)
+                .map_err(|_| ContractError::Overflow {})?,
+        };
+        if rebate.borrow_fees.is_zero() && rebate.trading_fees.is_zero() {
+            return Ok(());
+        }
+
+        PENDING_REBATES.update(
+            storage,
+            (&referrer, token_address),
+            |opt_pending| -> Result<ReferralRebate, ContractError> {
+                add_rebate(opt_pending.unwrap_or_default(), &rebate)
+            },
+        )?;
+        Ok(())
+    }
+
+    fn add_rebate(
+        total: ReferralRebate,
+        rebate: &ReferralRebate,
+    ) -> Result<ReferralRebate, ContractError> {
+        Ok(ReferralRebate {
+            borrow_fees: total
+                .borrow_fees
+                .checked_add(rebate.borrow_fees)
+                .map_err(|_| ContractError::Overflow {})?,
+            trading_fees: total
+                .trading_fees
+                .checked_add(rebate.trading_fees)
+                .map_err(|_| ContractError::Overflow {})?,
+        })
+    }
+
+    /**
+     * Pays out the sender's referral rebates in every token.
+     *
+     * Borrow fee rebates are paid in tokens out of the protocol revenue and
+     * trading fee rebates are credited as vTokens out of the trading fees. A
+     * borrow fee is only revenue once the borrower paid it, so the part the
+     * revenue can't cover yet stays pending.
+     */
+    pub fn claim_rebates(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+    ) -> Result<Response, ContractError> {
+        let pending_rebates = PENDING_REBATES
+            .prefix(&info.sender)
+            .range(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<(Addr, ReferralRebate)>>>()?;
+
+        let mut response = Response::new()
+            .add_attribute("method", "claim_referral_rebates")
+            .add_attribute("referrer", info.sender.clone());
+        let mut claimed_any = false;
+        for (token_address, pending) in pending_rebates {
+            let revenue = PROTOCOL_REVENUE
+                .may_load(deps.storage, &token_address)?
+                .unwrap_or_default();
+            let trading_fees = TRADING_FEES
+                .may_load(deps.storage, &token_address)?
+                .unwrap_or_default();
+            let paid = ReferralRebate {
+                borrow_fees: pending.borrow_fees.min(revenue),
+                trading_fees: pending.trading_fees.min(trading_fees),
+            };
+            if paid.borrow_fees.is_zero() && paid.trading_fees.is_zero() {
+                continue;
+            }
+            claimed_any = true;
+
+            let remaining = ReferralRebate {
+                borrow_fees: pending.borrow_fees - paid.borrow_fees,
+                trading_fees: pending.trading_fees - paid.trading_fees,
+            };
+            if remaining == ReferralRebate::default() {
+                PENDING_REBATES.remove(deps.storage, (&info.sender, &token_address));
+            } else {
+                PENDING_REBATES.save(deps.storage, (&info.sender, &token_address), &remaining)?;
+            }
+            CLAIMED_REBATES.update(
+                deps.storage,
+                (&info.sender, &token_address),
+                |opt_claimed| -> Result<ReferralRebate, ContractError> {
+                    add_rebate(opt_claimed.unwrap_or_default(), &paid)
+                },
+            )?;
+
+            market_module::decrease_total(
+                deps.storage,
+                &TRADING_FEES,
+                &token_address,
+                paid.trading_fees,
+            )?;
+            let vtoken_balance = USER_VTOKEN_BALANCE.update(
+                deps.storage,
+                (&token_address, &info.sender),
+                |opt_balance| -> Result<Uint128, ContractError> {
+                    opt_balance
+                        .unwrap_or_default()
+                        .checked_add(paid.trading_fees)
+                        .map_err(|_| ContractError::Overflow {})
+                },
+            )?;
+            market_module::increase_total(
+                deps.storage,
+                &TOTAL_VTOKENS,
+                &token_address,
+                paid.trading_fees,
+            )?;
+
+            if !paid.borrow_fees.is_zero() {
+                market_module::decrease_total(
+                    deps.storage,
+                    &PROTOCOL_REVENUE,
+                    &token_address,
+                    paid.borrow_fees,
+                )?;
+                let token_info = token_listing::load_token(deps.storage, &token_address)?;
+                response = response.add_message(withdraw_collateral::transfer_msg(
+                    &token_info.asset,
+                    paid.borrow_fees,
+                    &info.sender,
+                )?);
+            }
+
+            response = response.add_event(
+                LeverageEvent::ReferralRebatesClaimed {
+                    referrer: info.sender.clone(),
+                    token: token_address.to_string(),
+                    borrow_fees: paid.borrow_fees,
+                    trading_fees: paid.trading_fees,
+                    vtoken_balance,
+                }
+                .into(),
+            );
+        }
+
+        if !claimed_any {
+            return Err(ContractError::NoRebates {});
+        }
+        Ok(response)
+    }
+
+    pub fn fetch_referral_stats(
+        deps: Deps,
+        referrer_address: Addr,
+    ) -> StdResult<ReferralStatsResponse> {
+        let code = REFERRER_CODE.may_load(deps.storage, &referrer_address)?;
+        let referred_users = REFERRED_USERS
+            .may_load(deps.storage, &referrer_address)?
+            .unwrap_or_default();
+
+        let mut tokens = BTreeSet::new();
+        for rebates in [&PENDING_REBATES, &CLAIMED_REBATES] {
+            for token_address in
+                rebates
+                    .prefix(&referrer_address)
+                    .keys(deps.storage, None, None, Order::Ascending)
+            {
+                tokens.insert(token_address?);
+            }
+        }
+        let rebates = tokens
+            .into_iter()
+            .map(|token_address| {
+                Ok(TokenRebates {
+                    token: token_address.to_string(),
+                    pending: PENDING_REBATES
+                        .may_load(deps.storage, (&referrer_address, &token_address))?
+                        .unwrap_or_default(),
+                    claimed: CLAIMED_REBATES
+                        .may_load(deps.storage, (&referrer_address, &token_address))?
+                        .unwrap_or_default(),
+                })
+            })
+            .collect::<StdResult<Vec<TokenRebates>>>()?;
+
+        Ok(ReferralStatsResponse {
+            referrer: referrer_address,
+            code,
+            referred_users,
+            rebates,
+        })
+    }
+
+    pub fn fetch_referrer(deps: Deps, user_address: Addr) -> StdResult<ReferrerResponse> {
+        let referrer = REFERRED_BY.may_load(deps.storage, &user_address)?.flatten();
+        Ok(ReferrerResponse {
+            user: user_address,
+            referrer,
+        })
+    }
+}
@@ -1,13 +1,59 @@
 pub mod repay_leverage {
-    use cosmwasm_std::{DepsMut, Env, MessageInfo, Response, Uint128};
+    use cosmwasm_std::{
+        Addr, DepsMut, Env, Event, MessageInfo, Response, Storage, Timestamp, Uint128,
+    };
 
+    use crate::access::access_control;
+    use crate::events::leverage_events::LeverageEvent;
+    use crate::incentives::incentives_module;
+    use crate::interest::interest_module;
+    use crate::liquidate::liquidate_leverage;
+    use crate::listing::token_listing;
+    use crate::market::market_module;
     use crate::msg::TokenData;
-    use crate::state::{USER_VTOKEN_BALANCE, WRAPPED_TOKEN_BALANCE, WRAPPED_TOKEN_BORROW_BALANCE};
+    use crate::operator::operator_approvals;
+    use crate::state::{
+        OperatorPermission, BORROW_INTEREST, PROTOCOL_REVENUE, RESERVE_BALANCE, TOTAL_BORROWED,
+        TOTAL_VTOKENS, TOTAL_WRAPPED, USER_VTOKEN_BALANCE, WRAPPED_TOKEN_BALANCE,
+        WRAPPED_TOKEN_BORROW_BALANCE,
+    };
     use crate::ContractError;
 
+    /**
+     * Repays with vTokens of an account of the sender, or of the wallet the
+     * sender is an approved operator of.
+     */
+    pub fn repay_for_account(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        token_data: TokenData,
+    ) -> Result<Response, ContractError> {
+        let operator = info.sender.clone();
+        let info = operator_approvals::acting_account(
+            deps.as_ref(),
+            &env,
+            info,
+            &token_data,
+            OperatorPermission::Repay,
+        )?;
+        let response = repay(deps, env, info, token_data.clone())?;
+
+        Ok(match token_data.on_behalf_of {
+            Some(owner) if owner != operator => response.add_attribute("operator", operator),
+            _ => response,
+        })
+    }
+
+    /**
+     * Repays the borrowed amount with the user's vTokens.
+     *
+     * The repayment settles the accrued interest first and the principal afterwards.
+     * Only the principal part is released back into the wrapped token balance.
+     */
     pub fn repay(
         deps: DepsMut,
-        _env: Env,
+        env: Env,
         info: MessageInfo,
         token_data: TokenData,
     ) -> Result<Response, ContractError> {
@@ -25,21 +71,13 @@ pub mod repay_leverage {
             return Err(ContractError::InsufficientBalance {});
         }
 
-        // Load user's borrow balance
-        let wrapped_token_borrow_balance = match WRAPPED_TOKEN_BORROW_BALANCE
-            .may_load(deps.storage, (&token_data.token_address, &info.sender))
-        {
-            Ok(opt_data) => match opt_data {
-                Some(data) => data,
-                None => Uint128::zero(),
-            },
-            Err(_) => return Err(ContractError::BorrowBalanceLoadError {}),
-        };
-
-        // check if the user's borrow balance is less than the repayment amount
-        if wrapped_token_borrow_balance.lt(&token_data.token_amount) {
-            return Err(ContractError::RepayOverflow {});
-        }
+        let (interest_paid, principal_paid) = settle_debt(
+            deps.storage,
+            &token_data.token_address,
+            &info.sender,
+            token_data.token_amount,
+            env.block.time,
+        )?;
 
         USER_VTOKEN_BALANCE.update(
             deps.storage,
@@ -48,43 +86,302 @@ pub mod repay_leverage {
                 match opt_user_vtoken_balance {
                     Some(data) => match data.checked_sub(token_data.token_amount) {
                         Ok(user_vtoken_balance) => Ok(user_vtoken_balance),
-                        Err(_) => return Err(ContractError::Overflow {}),
+                        Err(_) => Err(ContractError::Overflow {}),
                     },
-                    None => return Err(ContractError::UpdateWrapTokenBorrowErr {}),
+                    None => Err(ContractError::UpdateWrapTokenBorrowErr {}),
                 }
             },
         )?;
+        market_module::decrease_total(
+            deps.storage,
+            &TOTAL_VTOKENS,
+            &token_data.token_address,
+            token_data.token_amount,
+        )?;
 
-        // Update user's borrow balance by subtracting the repayment amount
-        WRAPPED_TOKEN_BORROW_BALANCE.update(
+        let repay_event = repay_event(
             deps.storage,
-            (&token_data.token_address, &info.sender),
+            &token_data.token_address,
+            &info.sender,
+            interest_paid,
+            principal_paid,
+        )?;
+
+        Ok(Response::new()
+            .add_attribute("method", "repay")
+            .add_attribute("interest_paid", interest_paid)
+            .add_attribute("principal_paid", principal_paid)
+            .add_event(repay_event))
+    }
+
+    /**
+     * Repays the borrowed amount with native tokens sent along with the message.
+     */
+    pub fn repay_native(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        token_address: Addr,
+    ) -> Result<Response, ContractError> {
+        let token_info = token_listing::load_token(deps.storage, &token_address)?;
+        let amount = token_listing::native_payment(&info, &token_info)?;
+
+        repay_with_funds(deps, env, info.sender, token_address, amount)
+    }
+
+    /**
+     * Repays the borrowed amount with CW20 tokens received through `Send`.
+     */
+    pub fn repay_fungible(
+        deps: DepsMut,
+        env: Env,
+        user_address: Addr,
+        token_address: Addr,
+        amount: Uint128,
+    ) -> Result<Response, ContractError> {
+        repay_with_funds(deps, env, user_address, token_address, amount)
+    }
+
+    /**
+     * Credits vTokens against native tokens sent along with the message, so a
+     * position which lost vTokens while trading can still be repaid.
+     */
+    pub fn top_up_native(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        token_address: Addr,
+    ) -> Result<Response, ContractError> {
+        let token_info = token_listing::load_token(deps.storage, &token_address)?;
+        let amount = token_listing::native_payment(&info, &token_info)?;
+
+        top_up(deps, info.sender, token_address, amount)
+    }
+
+    /**
+     * Credits vTokens against CW20 tokens received through `Send`.
+     */
+    pub fn top_up_fungible(
+        deps: DepsMut,
+        _env: Env,
+        user_address: Addr,
+        token_address: Addr,
+        amount: Uint128,
+    ) -> Result<Response, ContractError> {
+        top_up(deps, user_address, token_address, amount)
+    }
+
+    fn repay_with_funds(
+        deps: DepsMut,
+        env: Env,
+        user_address: Addr,
+        token_address: Addr,
+        amount: Uint128,
+    ) -> Result<Response, ContractError> {
+        let (interest_paid, principal_paid) = settle_debt_with_funds(
+            deps.storage,
+            &token_address,
+            &user_address,
+            amount,
+            env.block.time,
+        )?;
+
+        let repay_event = repay_event(
+            deps.storage,
+            &token_address,
+            &user_address,
+            interest_paid,
+            principal_paid,
+        )?;
+
+        Ok(Response::new()
+            .add_attribute("method", "repay_with_funds")
+            .add_attribute("user", user_address)
+            .add_attribute("token_address", token_address)
+            .add_attribute("interest_paid", interest_paid)
+            .add_attribute("principal_paid", principal_paid)
+            .add_event(repay_event))
+    }
+
+    /**
+     * Settles the user's debt with real tokens, also used by liquidations.
+     *
+     * @return The paid interest and the paid principal.
+     */
+    pub fn settle_debt_with_funds(
+        storage: &mut dyn Storage,
+        token_address: &Addr,
+        user_address: &Addr,
+        amount: Uint128,
+        now: Timestamp,
+    ) -> Result<(Uint128, Uint128), ContractError> {
+        let (interest_paid, principal_paid) =
+            settle_debt(storage, token_address, user_address, amount, now)?;
+
+        // Paid interest is protocol income, the paid principal backs the vTokens the user keeps
+        market_module::increase_total(storage, &PROTOCOL_REVENUE, token_address, interest_paid)?;
+        market_module::increase_total(storage, &RESERVE_BALANCE, token_address, principal_paid)?;
+
+        Ok((interest_paid, principal_paid))
+    }
+
+    fn top_up(
+        deps: DepsMut,
+        user_address: Addr,
+        token_address: Addr,
+        amount: Uint128,
+    ) -> Result<Response, ContractError> {
+        access_control::assert_not_paused(deps.storage)?;
+
+        let vtoken_balance = USER_VTOKEN_BALANCE.update(
+            deps.storage,
+            (&token_address, &user_address),
+            |opt_balance| -> Result<Uint128, ContractError> {
+                match opt_balance {
+                    Some(balance) => match balance.checked_add(amount) {
+                        Ok(data) => Ok(data),
+                        Err(_) => Err(ContractError::Overflow {}),
+                    },
+                    None => Ok(amount),
+                }
+            },
+        )?;
+
+        market_module::increase_total(deps.storage, &TOTAL_VTOKENS, &token_address, amount)?;
+        market_module::increase_total(deps.storage, &RESERVE_BALANCE, &token_address, amount)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "top_up")
+            .add_attribute("user", user_address.clone())
+            .add_attribute("token_address", token_address.clone())
+            .add_attribute("amount", amount)
+            .add_event(
+                LeverageEvent::TopUp {
+                    user: user_address,
+                    token: token_address.to_string(),
+                    amount,
+                    vtoken_balance,
+                }
+                .into(),
+            ))
+    }
+
+    fn repay_event(
+        storage: &dyn Storage,
+        token_address: &Addr,
+        user_address: &Addr,
+        interest_paid: Uint128,
+        principal_paid: Uint128,
+    ) -> Result<Event, ContractError> {
+        Ok(LeverageEvent::Repay {
+            user: user_address.clone(),
+            token: token_address.to_string(),
+            interest_paid,
+            principal_paid,
+            borrow_balance: market_module::user_balance(
+                storage,
+                &WRAPPED_TOKEN_BORROW_BALANCE,
+                token_address,
+                user_address,
+            )?,
+            interest_balance: market_module::user_balance(
+                storage,
+                &BORROW_INTEREST,
+                token_address,
+                user_address,
+            )?,
+            vtoken_balance: market_module::user_balance(
+                storage,
+                &USER_VTOKEN_BALANCE,
+                token_address,
+                user_address,
+            )?,
+        }
+        .into())
+    }
+
+    /**
+     * Applies a repayment to the user's debt, interest first and principal afterwards.
+     *
+     * @return The paid interest and the paid principal.
+     */
+    pub fn settle_debt(
+        storage: &mut dyn Storage,
+        token_address: &Addr,
+        user_address: &Addr,
+        amount: Uint128,
+        now: Timestamp,
+    ) -> Result<(Uint128, Uint128), ContractError> {
+        if amount.is_zero() {
+            return Err(ContractError::InsufficientBalance {});
+        }
+
+        let interest = interest_module::accrue_interest(storage, token_address, user_address, now)?;
+        incentives_module::update_user_rewards(storage, token_address, user_address, now)?;
+
+        // Load user's borrow balance
+        let wrapped_token_borrow_balance =
+            match WRAPPED_TOKEN_BORROW_BALANCE.may_load(storage, (token_address, user_address)) {
+                Ok(opt_data) => match opt_data {
+                    Some(data) => data,
+                    None => Uint128::zero(),
+                },
+                Err(_) => return Err(ContractError::BorrowBalanceLoadError {}),
+            };
+
+        // check if the user's debt is less than the repayment amount
+        let debt = match wrapped_token_borrow_balance.checked_add(interest) {
+            Ok(data) => data,
+            Err(_) => return Err(ContractError::Overflow {}),
+        };
+        if debt.lt(&amount) {
+            return Err(ContractError::RepayOverflow {});
+        }
+
+        let interest_paid = amount.min(interest);
+        let principal_paid = amount - interest_paid;
+
+        BORROW_INTEREST.save(
+            storage,
+            (token_address, user_address),
+            &(interest - interest_paid),
+        )?;
+
+        // Update user's borrow balance by subtracting the repaid principal
+        WRAPPED_TOKEN_BORROW_BALANCE.update(
+            storage,
+            (token_address, user_address),
             |opt_wrapped_token_borrow_balance| -> Result<Uint128, ContractError> {
                 match opt_wrapped_token_borrow_balance {
-                    Some(data) => match data.checked_sub(token_data.token_amount) {
+                    Some(data) => match data.checked_sub(principal_paid) {
                         Ok(borror_balance) => Ok(borror_balance),
-                        Err(_) => return Err(ContractError::Overflow {}),
+                        Err(_) => Err(ContractError::Overflow {}),
                     },
-                    None => return Err(ContractError::UpdateWrapTokenBorrowErr {}),
+                    None => Ok(Uint128::zero()),
                 }
             },
         )?;
 
-        // Update user's unminted token balance by adding the repayment amount
+        // Update user's unminted token balance by adding the repaid principal
         WRAPPED_TOKEN_BALANCE.update(
-            deps.storage,
-            (&token_data.token_address, &info.sender),
+            storage,
+            (token_address, user_address),
             |opt_wrapped_token_balance| -> Result<Uint128, ContractError> {
                 match opt_wrapped_token_balance {
-                    Some(data) => match data.checked_add(token_data.token_amount) {
+                    Some(data) => match data.checked_add(principal_paid) {
                         Ok(wrapped_token_balance) => Ok(wrapped_token_balance),
-                        Err(_) => return Err(ContractError::Overflow {}),
+                        Err(_) => Err(ContractError::Overflow {}),
                     },
-                    None => Err(ContractError::UpdateWrapTokenErr {}),
+                    None => Ok(principal_paid),
                 }
             },
         )?;
 
-        Ok(Response::new().add_attribute("method", "repay"))
+        market_module::decrease_total(storage, &TOTAL_BORROWED, token_address, principal_paid)?;
+        market_module::increase_total(storage, &TOTAL_WRAPPED, token_address, principal_paid)?;
+
+        liquidate_leverage::update_borrower_index(storage, user_address)?;
+
+        Ok((interest_paid, principal_paid))
     }
 }
@@ -0,0 +1,978 @@
+#[cfg(test)]
+pub mod scenario_harness {
+    use std::collections::BTreeMap;
+
+    use cosmwasm_std::testing::MockStorage;
+    use cosmwasm_std::{
+        coins, to_json_binary, Addr, Coin, Decimal, Order, StdResult, Storage, Uint128,
+    };
+    use cw20::{BalanceResponse, Cw20ExecuteMsg, Cw20QueryMsg, Expiration, MinterResponse};
+    use cw_multi_test::error::AnyResult;
+    use cw_multi_test::{App, AppResponse, BankSudo, ContractWrapper, Executor};
+    use cw_storage_plus::Map;
+    use serde::de::DeserializeOwned;
+
+    use crate::contract::{execute, instantiate, query, sudo};
+    use crate::msg::{
+        Cw20HookMsg, ExecuteMsg, InitialToken, InstantiateMsg, OrderExecute, PriceSourceUpdate,
+        QueryMsg, QueryTokenData, SudoMsg, TokenData, WithdrawData,
+    };
+    use crate::state::{
+        AssetInfo, OperatorPermission, PriceSource, RewardSchedule, TimelockAction, BORROWERS,
+        BORROW_INTEREST, CLAIMED_REBATES, DEFICITS, LISTED_TOKEN, ORDER_STATE, PENDING_REWARDS,
+        PROTOCOL_REVENUE, RESERVE_BALANCE, REWARD_FUNDS, TOKEN_BALANCE, TOTAL_BORROWED,
+        TOTAL_COLLATERAL, TOTAL_VTOKENS, TOTAL_WRAPPED, USER_VTOKEN_BALANCE, WRAPPED_TOKEN_BALANCE,
+        WRAPPED_TOKEN_BORROW_BALANCE,
+    };
+    use crate::timelock::timelock_module::MIN_TIMELOCK_DELAY;
+
+    pub const OWNER: &str = "creator";
+    pub const USDC: &str = "USDC";
+    pub const OSMO: &str = "OSMO";
+    pub const ATOM: &str = "ATOM";
+    /// ATOM arrives over IBC, so it's listed under its IBC denom
+    pub const ATOM_DENOM: &str =
+        "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2";
+
+    const CW20_MINTER: &str = "usdc_minter";
+
+    type UserMap = Map<'static, (&'static Addr, &'static Addr), Uint128>;
+    type TotalMap = Map<'static, &'static Addr, Uint128>;
+
+    /**
+     * A leverage contract in a multi-test app with three listed tokens:
+     * USDC as a cw20-base token, OSMO and ATOM as native denoms.
+     *
+     * Every action checks the protocol invariants once it ran, failed
+     * actions included, and panics on the first violation.
+     */
+    pub struct Scenario {
+        pub app: App,
+        pub contract: Addr,
+        pub usdc: Addr,
+        next_order_id: u64,
+    }
+
+    impl Default for Scenario {
+        fn default() -> Self {
+            Scenario::new()
+        }
+    }
+
+    impl Scenario {
+        pub fn new() -> Self {
+            let mut app = App::default();
+
+            let cw20_code_id = app.store_code(Box::new(ContractWrapper::new(
+                cw20_base::contract::execute,
+                cw20_base::contract::instantiate,
+                cw20_base::contract::query,
+            )));
+            let usdc = app
+                .instantiate_contract(
+                    cw20_code_id,
+                    Addr::unchecked(OWNER),
+                    &cw20_base::msg::InstantiateMsg {
+                        name: String::from("USD Coin"),
+                        symbol: String::from("USDC"),
+                        decimals: 6,
+                        initial_balances: vec![],
+                        mint: Some(MinterResponse {
+                            minter: String::from(CW20_MINTER),
+                            cap: None,
+                        }),
+                        marketing: None,
+                    },
+                    &[],
+                    "usdc",
+                    None,
+                )
+                .unwrap();
+
+            let code_id = app.store_code(Box::new(
+                ContractWrapper::new(execute, instantiate, query).with_sudo(sudo),
+            ));
+            let contract = app
+                .instantiate_contract(
+                    code_id,
+                    Addr::unchecked(OWNER),
+                    &InstantiateMsg {
+                        owner: None,
+                        tokens: vec![
+                            initial_token(
+                                USDC,
+                                AssetInfo::Cw20 {
+                                    contract_addr: usdc.clone(),
+                                },
+                                Decimal::one(),
+                            ),
+                            initial_token(
+                                OSMO,
+                                AssetInfo::Native {
+                                    denom: String::from("uosmo"),
+                                },
+                                Decimal::one(),
+                            ),
+                            initial_token(
+                                ATOM,
+                                AssetInfo::Native {
+                                    denom: String::from(ATOM_DENOM),
+                                },
+                                Decimal::percent(1000),
+                            ),
+                        ],
+                        fees: None,
+                        timelock_delay: Some(MIN_TIMELOCK_DELAY),
+                    },
+                    &[],
+                    "leverage_contract",
+                    None,
+                )
+                .unwrap();
+
+            let scenario = Scenario {
+                app,
+                contract,
+                usdc,
+                next_order_id: 0,
+            };
+            scenario.assert_invariants();
+            scenario
+        }
+
+        pub fn user(&self, name: &str) -> Addr {
+            Addr::unchecked(name)
+        }
+
+        /**
+         * Mints `amount` of a listed token into the user's wallet.
+         */
+        pub fn fund(&mut self, user: &Addr, token: &str, amount: u128) {
+            match self.asset(token) {
+                AssetInfo::Native { denom } => {
+                    self.app
+                        .sudo(cw_multi_test::SudoMsg::Bank(BankSudo::Mint {
+                            to_address: user.to_string(),
+                            amount: coins(amount, denom),
+                        }))
+                        .unwrap();
+                }
+                AssetInfo::Cw20 { contract_addr } => {
+                    self.app
+                        .execute_contract(
+                            Addr::unchecked(CW20_MINTER),
+                            contract_addr,
+                            &Cw20ExecuteMsg::Mint {
+                                recipient: user.to_string(),
+                                amount: Uint128::from(amount),
+                            },
+                            &[],
+                        )
+                        .unwrap();
+                }
+            }
+        }
+
+        /**
+         * Creates a user holding `amount` of `token` in their wallet.
+         */
+        pub fn funded_user(&mut self, name: &str, token: &str, amount: u128) -> Addr {
+            let user = self.user(name);
+            self.fund(&user, token, amount);
+            user
+        }
+
+        /**
+         * Mock oracle, governance sets a fixed price which applies right away.
+         */
+        pub fn set_price(&mut self, token: &str, price: Decimal) {
+            self.sudo(&SudoMsg::UpdatePriceSources {
+                sources: vec![PriceSourceUpdate {
+                    token_address: String::from(token),
+                    source: PriceSource::Fixed { price },
+                }],
+            })
+            .unwrap();
+        }
+
+        pub fn set_borrow_rate(&mut self, token: &str, rate: Decimal) {
+            self.run_timelocked(TimelockAction::UpdateBorrowRate {
+                token_address: String::from(token),
+                rate,
+            });
+        }
+
+        /**
+         * Sets the schedule as the owner, funded with everything it emits
+         * from its start.
+         */
+        pub fn set_reward_schedule(&mut self, token: &str, schedule: RewardSchedule) {
+            let seconds = schedule.end_time.seconds() - schedule.start_time.seconds();
+            let funds = coins(
+                (schedule.collateral_emission + schedule.borrow_emission).u128() * seconds as u128,
+                &schedule.reward_denom,
+            );
+            self.app
+                .sudo(cw_multi_test::SudoMsg::Bank(BankSudo::Mint {
+                    to_address: String::from(OWNER),
+                    amount: funds.clone(),
+                }))
+                .unwrap();
+            self.try_run_timelocked_with_funds(
+                TimelockAction::SetRewardSchedule {
+                    token_address: String::from(token),
+                    schedule,
+                },
+                &funds,
+            )
+            .unwrap();
+        }
+
+        /**
+         * Proposes and executes an admin action as the owner, once the
+         * minimum timelock delay passed.
+         */
+        pub fn run_timelocked(&mut self, action: TimelockAction) {
+            self.try_run_timelocked(action).unwrap();
+        }
+
+        pub fn try_run_timelocked(&mut self, action: TimelockAction) -> AnyResult<AppResponse> {
+            self.try_run_timelocked_with_funds(action, &[])
+        }
+
+        /**
+         * Like `try_run_timelocked`, with `funds` escrowed when proposing.
+         */
+        pub fn try_run_timelocked_with_funds(
+            &mut self,
+            action: TimelockAction,
+            funds: &[Coin],
+        ) -> AnyResult<AppResponse> {
+            let owner = Addr::unchecked(OWNER);
+            let res = self.app.execute_contract(
+                owner.clone(),
+                self.contract.clone(),
+                &ExecuteMsg::ProposeAction { action },
+                funds,
+            )?;
+            let id = res
+                .custom_attrs(1)
+                .iter()
+                .find(|attr| attr.key == "action_id")
+                .unwrap()
+                .value
+                .parse()
+                .unwrap();
+            self.app
+                .update_block(|block| block.time = block.time.plus_seconds(MIN_TIMELOCK_DELAY));
+            let res = self.app.execute_contract(
+                owner,
+                self.contract.clone(),
+                &ExecuteMsg::ExecuteAction { id },
+                &[],
+            );
+            self.assert_invariants();
+            res
+        }
+
+        pub fn advance_time(&mut self, seconds: u64) {
+            self.app
+                .update_block(|block| block.time = block.time.plus_seconds(seconds));
+            self.assert_invariants();
+        }
+
+        pub fn deposit(
+            &mut self,
+            user: &Addr,
+            token: &str,
+            amount: u128,
+        ) -> AnyResult<AppResponse> {
+            self.deposit_with_code(user, token, amount, None)
+        }
+
+        pub fn deposit_with_code(
+            &mut self,
+            user: &Addr,
+            token: &str,
+            amount: u128,
+            referral_code: Option<&str>,
+        ) -> AnyResult<AppResponse> {
+            let referral_code = referral_code.map(String::from);
+            self.send_funds(
+                user,
+                token,
+                amount,
+                ExecuteMsg::DepositNative {
+                    token_address: String::from(token),
+                    referral_code: referral_code.clone(),
+                    account_id: None,
+                },
+                Cw20HookMsg::Deposit {
+                    referral_code,
+                    account_id: None,
+                },
+            )
+        }
+
+        pub fn deposit_to_account(
+            &mut self,
+            user: &Addr,
+            token: &str,
+            amount: u128,
+            account_id: u64,
+        ) -> AnyResult<AppResponse> {
+            self.send_funds(
+                user,
+                token,
+                amount,
+                ExecuteMsg::DepositNative {
+                    token_address: String::from(token),
+                    referral_code: None,
+                    account_id: Some(account_id),
+                },
+                Cw20HookMsg::Deposit {
+                    referral_code: None,
+                    account_id: Some(account_id),
+                },
+            )
+        }
+
+        /**
+         * Runs the call ibc-hooks makes from the intermediate `sender` once an
+         * ICS-20 transfer with a wasm memo arrived.
+         */
+        pub fn ibc_hook_deposit(
+            &mut self,
+            sender: &Addr,
+            funds: &[Coin],
+            channel: &str,
+            original_sender: &str,
+            borrow_amount: Option<u128>,
+        ) -> AnyResult<AppResponse> {
+            let res = self.app.execute_contract(
+                sender.clone(),
+                self.contract.clone(),
+                &ExecuteMsg::IbcHookDeposit {
+                    channel: String::from(channel),
+                    original_sender: String::from(original_sender),
+                    borrow_amount: borrow_amount.map(Uint128::from),
+                    account_id: None,
+                },
+                funds,
+            );
+            self.assert_invariants();
+            res
+        }
+
+        pub fn claim_rewards(&mut self, user: &Addr) -> AnyResult<AppResponse> {
+            self.execute(user, &ExecuteMsg::ClaimRewards { account_id: None })
+        }
+
+        pub fn register_referral_code(
+            &mut self,
+            user: &Addr,
+            code: &str,
+        ) -> AnyResult<AppResponse> {
+            self.execute(
+                user,
+                &ExecuteMsg::RegisterReferralCode {
+                    code: String::from(code),
+                },
+            )
+        }
+
+        pub fn claim_referral_rebates(&mut self, user: &Addr) -> AnyResult<AppResponse> {
+            self.execute(user, &ExecuteMsg::ClaimReferralRebates {})
+        }
+
+        pub fn borrow(&mut self, user: &Addr, token: &str, amount: u128) -> AnyResult<AppResponse> {
+            self.execute(user, &ExecuteMsg::Borrow(token_data(token, amount)))
+        }
+
+        pub fn borrow_on_account(
+            &mut self,
+            user: &Addr,
+            token: &str,
+            amount: u128,
+            account_id: u64,
+        ) -> AnyResult<AppResponse> {
+            let token_data = TokenData {
+                account_id: Some(account_id),
+                ..token_data(token, amount)
+            };
+            self.execute(user, &ExecuteMsg::Borrow(token_data))
+        }
+
+        pub fn transfer_collateral(
+            &mut self,
+            user: &Addr,
+            token: &str,
+            amount: u128,
+            from_account_id: u64,
+            to_account_id: u64,
+        ) -> AnyResult<AppResponse> {
+            self.execute(
+                user,
+                &ExecuteMsg::TransferCollateral {
+                    token_address: String::from(token),
+                    amount: Uint128::from(amount),
+                    from_account_id: Some(from_account_id),
+                    to_account_id: Some(to_account_id),
+                },
+            )
+        }
+
+        /**
+         * Sells `amount_in` vTokens of `token_in` for `amount_out` vTokens of `token_out`.
+         */
+        pub fn order(
+            &mut self,
+            user: &Addr,
+            token_in: &str,
+            amount_in: u128,
+            token_out: &str,
+            amount_out: u128,
+        ) -> AnyResult<AppResponse> {
+            self.order_as(user, user, token_in, amount_in, token_out, amount_out)
+        }
+
+        /**
+         * Executes an order of `user` sent by `sender`, a keeper or an operator.
+         */
+        pub fn order_as(
+            &mut self,
+            sender: &Addr,
+            user: &Addr,
+            token_in: &str,
+            amount_in: u128,
+            token_out: &str,
+            amount_out: u128,
+        ) -> AnyResult<AppResponse> {
+            self.fill_order(
+                sender, user, token_in, amount_in, token_out, amount_out, None,
+            )
+        }
+
+        /**
+         * Executes an order of `user` sent by `sender` which must pay out at
+         * least the user's `min_out`.
+         */
+        #[allow(clippy::too_many_arguments)]
+        pub fn fill_order(
+            &mut self,
+            sender: &Addr,
+            user: &Addr,
+            token_in: &str,
+            amount_in: u128,
+            token_out: &str,
+            amount_out: u128,
+            min_out: Option<u128>,
+        ) -> AnyResult<AppResponse> {
+            self.next_order_id += 1;
+            let order = OrderExecute {
+                order_id: self.next_order_id.to_string(),
+                user_address: user.clone(),
+                token_in: Addr::unchecked(token_in),
+                amount_in: Uint128::from(amount_in),
+                token_out: Addr::unchecked(token_out),
+                amount_out: Uint128::from(amount_out),
+                account_id: None,
+                min_out: min_out.map(Uint128::from),
+            };
+            self.execute(sender, &ExecuteMsg::ExecuteOrder(order))
+        }
+
+        pub fn approve_operator(
+            &mut self,
+            owner: &Addr,
+            operator: &Addr,
+            permissions: Vec<OperatorPermission>,
+            expires: Option<Expiration>,
+        ) -> AnyResult<AppResponse> {
+            self.execute(
+                owner,
+                &ExecuteMsg::ApproveOperator {
+                    operator: operator.to_string(),
+                    permissions,
+                    expires,
+                },
+            )
+        }
+
+        pub fn revoke_operator(&mut self, owner: &Addr, operator: &Addr) -> AnyResult<AppResponse> {
+            self.execute(
+                owner,
+                &ExecuteMsg::RevokeOperator {
+                    operator: operator.to_string(),
+                },
+            )
+        }
+
+        /**
+         * Borrows as an operator of `owner`.
+         */
+        pub fn borrow_for(
+            &mut self,
+            operator: &Addr,
+            owner: &Addr,
+            token: &str,
+            amount: u128,
+        ) -> AnyResult<AppResponse> {
+            let token_data = TokenData {
+                on_behalf_of: Some(owner.clone()),
+                ..token_data(token, amount)
+            };
+            self.execute(operator, &ExecuteMsg::Borrow(token_data))
+        }
+
+        /**
+         * Repays with vTokens as an operator of `owner`.
+         */
+        pub fn repay_for(
+            &mut self,
+            operator: &Addr,
+            owner: &Addr,
+            token: &str,
+            amount: u128,
+        ) -> AnyResult<AppResponse> {
+            let token_data = TokenData {
+                on_behalf_of: Some(owner.clone()),
+                ..token_data(token, amount)
+            };
+            self.execute(operator, &ExecuteMsg::Repay(token_data))
+        }
+
+        /**
+         * Repays debt with vTokens.
+         */
+        pub fn repay(&mut self, user: &Addr, token: &str, amount: u128) -> AnyResult<AppResponse> {
+            self.execute(user, &ExecuteMsg::Repay(token_data(token, amount)))
+        }
+
+        /**
+         * Repays debt with tokens from the user's wallet.
+         */
+        pub fn repay_with_funds(
+            &mut self,
+            user: &Addr,
+            token: &str,
+            amount: u128,
+        ) -> AnyResult<AppResponse> {
+            self.send_funds(
+                user,
+                token,
+                amount,
+                ExecuteMsg::RepayNative {
+                    token_address: String::from(token),
+                    account_id: None,
+                },
+                Cw20HookMsg::Repay { account_id: None },
+            )
+        }
+
+        pub fn top_up(&mut self, user: &Addr, token: &str, amount: u128) -> AnyResult<AppResponse> {
+            self.send_funds(
+                user,
+                token,
+                amount,
+                ExecuteMsg::TopUpNative {
+                    token_address: String::from(token),
+                    account_id: None,
+                },
+                Cw20HookMsg::TopUp { account_id: None },
+            )
+        }
+
+        pub fn burn(&mut self, user: &Addr, token: &str, amount: u128) -> AnyResult<AppResponse> {
+            self.execute(user, &ExecuteMsg::Burn(token_data(token, amount)))
+        }
+
+        pub fn withdraw(
+            &mut self,
+            user: &Addr,
+            token: &str,
+            amount: u128,
+        ) -> AnyResult<AppResponse> {
+            self.withdraw_from_account(user, token, amount, 0)
+        }
+
+        pub fn withdraw_from_account(
+            &mut self,
+            user: &Addr,
+            token: &str,
+            amount: u128,
+            account_id: u64,
+        ) -> AnyResult<AppResponse> {
+            self.execute(
+                user,
+                &ExecuteMsg::WithdrawToken(WithdrawData {
+                    token_address: Addr::unchecked(token),
+                    token_amount: Uint128::from(amount),
+                    account_id: Some(account_id),
+                }),
+            )
+        }
+
+        /**
+         * Repays `amount` of the user's `token` debt from the liquidator's
+         * wallet, seizing `collateral_token`.
+         */
+        pub fn liquidate(
+            &mut self,
+            liquidator: &Addr,
+            user: &Addr,
+            token: &str,
+            amount: u128,
+            collateral_token: &str,
+        ) -> AnyResult<AppResponse> {
+            self.send_funds(
+                liquidator,
+                token,
+                amount,
+                ExecuteMsg::LiquidateNative {
+                    token_address: String::from(token),
+                    user_address: user.to_string(),
+                    collateral_token: String::from(collateral_token),
+                    account_id: None,
+                },
+                Cw20HookMsg::Liquidate {
+                    user_address: user.to_string(),
+                    collateral_token: String::from(collateral_token),
+                    account_id: None,
+                },
+            )
+        }
+
+        /**
+         * Calls the contract's `sudo` entry point like chain governance does.
+         */
+        pub fn sudo(&mut self, msg: &SudoMsg) -> AnyResult<AppResponse> {
+            let res = self.app.wasm_sudo(self.contract.clone(), msg);
+            self.assert_invariants();
+            res
+        }
+
+        pub fn query<T: DeserializeOwned>(&self, msg: &QueryMsg) -> T {
+            self.app
+                .wrap()
+                .query_wasm_smart(self.contract.clone(), msg)
+                .unwrap()
+        }
+
+        pub fn collateral(&self, user: &Addr, token: &str) -> Uint128 {
+            self.query(&QueryMsg::UserCollateralTokenBalance(query_data(
+                token, user,
+            )))
+        }
+
+        pub fn wrapped(&self, user: &Addr, token: &str) -> Uint128 {
+            self.query(&QueryMsg::UserWrappedTokenBalance(query_data(token, user)))
+        }
+
+        pub fn borrowed(&self, user: &Addr, token: &str) -> Uint128 {
+            self.query(&QueryMsg::UserBorrowTokenBalance(query_data(token, user)))
+        }
+
+        pub fn vtokens(&self, user: &Addr, token: &str) -> Uint128 {
+            self.query(&QueryMsg::UserVTokenBalance(query_data(token, user)))
+        }
+
+        /**
+         * Balance of a listed token in the wallet of `address`.
+         */
+        pub fn wallet_balance(&self, address: &Addr, token: &str) -> Uint128 {
+            match self.asset(token) {
+                AssetInfo::Native { denom } => {
+                    self.app
+                        .wrap()
+                        .query_balance(address, denom)
+                        .unwrap()
+                        .amount
+                }
+                AssetInfo::Cw20 { contract_addr } => {
+                    let balance: BalanceResponse = self
+                        .app
+                        .wrap()
+                        .query_wasm_smart(
+                            contract_addr,
+                            &Cw20QueryMsg::Balance {
+                                address: address.to_string(),
+                            },
+                        )
+                        .unwrap();
+                    balance.balance
+                }
+            }
+        }
+
+        /**
+         * Real tokens held by the leverage contract.
+         */
+        pub fn holdings(&self, token: &str) -> Uint128 {
+            self.wallet_balance(&self.contract, token)
+        }
+
+        /**
+         * Checks the protocol invariants against the raw contract storage.
+         *
+         * @return A description of every violated invariant, empty if all hold.
+         */
+        pub fn check_invariants(&self) -> Vec<String> {
+            let storage = self.contract_storage();
+            let mut violations = vec![];
+
+            let listed = LISTED_TOKEN
+                .keys(&storage, None, None, Order::Ascending)
+                .collect::<StdResult<Vec<String>>>()
+                .unwrap();
+
+            let user_maps: [(&str, UserMap, TotalMap); 4] = [
+                ("collateral", TOKEN_BALANCE, TOTAL_COLLATERAL),
+                ("wrapped", WRAPPED_TOKEN_BALANCE, TOTAL_WRAPPED),
+                ("borrowed", WRAPPED_TOKEN_BORROW_BALANCE, TOTAL_BORROWED),
+                ("vtoken", USER_VTOKEN_BALANCE, TOTAL_VTOKENS),
+            ];
+            for (name, balances, totals) in user_maps {
+                let sums = sum_per_token(&storage, &balances);
+
+                // Balances only exist for listed tokens
+                for token in sums.keys().filter(|token| !listed.contains(token)) {
+                    violations.push(format!("{name} balances of unlisted token {token}"));
+                }
+
+                for token in &listed {
+                    let sum = sums.get(token).copied().unwrap_or_default();
+                    let total = totals
+                        .may_load(&storage, &Addr::unchecked(token))
+                        .unwrap()
+                        .unwrap_or_default();
+                    if sum != total {
+                        violations.push(format!(
+                            "{name} total of {token} is {total}, user balances sum up to {sum}"
+                        ));
+                    }
+                }
+            }
+
+            // Collateral and collected interest are backed by real tokens
+            let collateral = sum_per_token(&storage, &TOKEN_BALANCE);
+            for token in &listed {
+                let token_address = Addr::unchecked(token);
+                let revenue = PROTOCOL_REVENUE
+                    .may_load(&storage, &token_address)
+                    .unwrap()
+                    .unwrap_or_default();
+                let owed = collateral.get(token).copied().unwrap_or_default() + revenue;
+                let holdings = self.holdings(token);
+                if holdings < owed {
+                    violations.push(format!(
+                        "contract holds {holdings} {token} but owes {owed} of collateral and revenue"
+                    ));
+                }
+            }
+
+            // Reward funds are held by the contract and cover the pending rewards
+            let mut pending_rewards: BTreeMap<String, Uint128> = BTreeMap::new();
+            for item in PENDING_REWARDS.range(&storage, None, None, Order::Ascending) {
+                let ((_, denom), amount) = item.unwrap();
+                *pending_rewards.entry(denom).or_default() += amount;
+            }
+            for item in REWARD_FUNDS.range(&storage, None, None, Order::Ascending) {
+                let (denom, funds) = item.unwrap();
+                let holdings = self
+                    .app
+                    .wrap()
+                    .query_balance(&self.contract, &denom)
+                    .unwrap()
+                    .amount;
+                if holdings < funds {
+                    violations.push(format!(
+                        "contract holds {holdings} {denom} but has {funds} of reward funds"
+                    ));
+                }
+                let pending = pending_rewards.remove(&denom).unwrap_or_default();
+                if pending > funds {
+                    violations.push(format!(
+                        "{pending} {denom} of rewards are pending but only {funds} are funded"
+                    ));
+                }
+            }
+            for (denom, pending) in pending_rewards {
+                violations.push(format!("{pending} {denom} of rewards are pending unfunded"));
+            }
+
+            // Every vToken traces back to debt, to the reserve at the wrapping
+            // ratio, to a filled order, to a trading fee rebate or to a deficit
+            let vtokens = sum_per_token(&storage, &USER_VTOKEN_BALANCE);
+            let borrowed = sum_per_token(&storage, &WRAPPED_TOKEN_BORROW_BALANCE);
+            let mut bought: BTreeMap<String, Uint128> = BTreeMap::new();
+            for item in ORDER_STATE.range(&storage, None, None, Order::Ascending) {
+                let (_, orders) = item.unwrap();
+                for order in orders {
+                    *bought.entry(order.buy_token).or_default() += order.buy_token_amount;
+                }
+            }
+            let mut rebated: BTreeMap<String, Uint128> = BTreeMap::new();
+            for item in CLAIMED_REBATES.range(&storage, None, None, Order::Ascending) {
+                let ((_, token), rebate) = item.unwrap();
+                *rebated.entry(token.to_string()).or_default() += rebate.trading_fees;
+            }
+            for token in &listed {
+                let token_address = Addr::unchecked(token);
+                let total_of = |totals: &TotalMap| {
+                    totals
+                        .may_load(&storage, &token_address)
+                        .unwrap()
+                        .unwrap_or_default()
+                };
+                let issued = vtokens.get(token).copied().unwrap_or_default();
+                let backing = borrowed.get(token).copied().unwrap_or_default()
+                    + total_of(&RESERVE_BALANCE) * Uint128::from(10u128)
+                    + bought.get(token).copied().unwrap_or_default()
+                    + rebated.get(token).copied().unwrap_or_default()
+                    + total_of(&DEFICITS);
+                if issued > backing {
+                    violations.push(format!(
+                        "{issued} {token} vTokens are issued but only {backing} are backed"
+                    ));
+                }
+            }
+
+            // The borrower index lists exactly the accounts with debt
+            let mut debtors = vec![];
+            for (token, user) in keys(&storage, &WRAPPED_TOKEN_BORROW_BALANCE)
+                .into_iter()
+                .chain(keys(&storage, &BORROW_INTEREST))
+            {
+                let principal = WRAPPED_TOKEN_BORROW_BALANCE
+                    .may_load(&storage, (&token, &user))
+                    .unwrap()
+                    .unwrap_or_default();
+                let interest = BORROW_INTEREST
+                    .may_load(&storage, (&token, &user))
+                    .unwrap()
+                    .unwrap_or_default();
+                let has_debt = !principal.is_zero() || !interest.is_zero();
+                if has_debt && !debtors.contains(&user) {
+                    debtors.push(user);
+                }
+            }
+            let borrowers = BORROWERS
+                .keys(&storage, None, None, Order::Ascending)
+                .collect::<StdResult<Vec<Addr>>>()
+                .unwrap();
+            for user in debtors.iter().filter(|user| !borrowers.contains(user)) {
+                violations.push(format!("{user} has debt but is no indexed borrower"));
+            }
+            for user in borrowers.iter().filter(|user| !debtors.contains(user)) {
+                violations.push(format!("{user} is an indexed borrower without debt"));
+            }
+
+            violations
+        }
+
+        pub fn assert_invariants(&self) {
+            let violations = self.check_invariants();
+            assert!(
+                violations.is_empty(),
+                "protocol invariants violated:\n{}",
+                violations.join("\n")
+            );
+        }
+
+        fn contract_storage(&self) -> MockStorage {
+            let mut storage = MockStorage::new();
+            for (key, value) in self.app.dump_wasm_raw(&self.contract) {
+                storage.set(&key, &value);
+            }
+            storage
+        }
+
+        fn asset(&self, token: &str) -> AssetInfo {
+            let storage = self.contract_storage();
+            LISTED_TOKEN.load(&storage, token).unwrap().asset
+        }
+
+        fn execute(&mut self, sender: &Addr, msg: &ExecuteMsg) -> AnyResult<AppResponse> {
+            let res = self
+                .app
+                .execute_contract(sender.clone(), self.contract.clone(), msg, &[]);
+            self.assert_invariants();
+            res
+        }
+
+        // Native tokens go along with `native_msg`, CW20 tokens through `Send` with `hook`
+        fn send_funds(
+            &mut self,
+            sender: &Addr,
+            token: &str,
+            amount: u128,
+            native_msg: ExecuteMsg,
+            hook: Cw20HookMsg,
+        ) -> AnyResult<AppResponse> {
+            let res = match self.asset(token) {
+                AssetInfo::Native { denom } => self.app.execute_contract(
+                    sender.clone(),
+                    self.contract.clone(),
+                    &native_msg,
+                    &coins(amount, denom),
+                ),
+                AssetInfo::Cw20 { contract_addr } => self.app.execute_contract(
+                    sender.clone(),
+                    contract_addr,
+                    &Cw20ExecuteMsg::Send {
+                        contract: self.contract.to_string(),
+                        amount: Uint128::from(amount),
+                        msg: to_json_binary(&hook)?,
+                    },
+                    &[],
+                ),
+            };
+            self.assert_invariants();
+            res
+        }
+    }
+
+    fn initial_token(token: &str, asset: AssetInfo, price: Decimal) -> InitialToken {
+        InitialToken {
+            token_address: String::from(token),
+            asset,
+            decimals: Some(6),
+            symbol: String::from(token),
+            price_source: Some(PriceSource::Fixed { price }),
+        }
+    }
+
+    fn token_data(token: &str, amount: u128) -> TokenData {
+        TokenData {
+            token_address: Addr::unchecked(token),
+            token_amount: Uint128::from(amount),
+            account_id: None,
+            on_behalf_of: None,
+        }
+    }
+
+    fn query_data(token: &str, user: &Addr) -> QueryTokenData {
+        QueryTokenData {
+            token_address: Addr::unchecked(token),
+            user_address: user.clone(),
+            account_id: None,
+        }
+    }
+
+    fn keys(storage: &dyn Storage, balances: &UserMap) -> Vec<(Addr, Addr)> {
+        balances
+            .keys(storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<(Addr, Addr)>>>()
+            .unwrap()
+    }
+
+    fn sum_per_token(storage: &dyn Storage, balances: &UserMap) -> BTreeMap<String, Uint128> {
+        let mut sums = BTreeMap::new();
+        for item in balances.range(storage, None, None, Order::Ascending) {
+            let ((token, _), balance) = item.unwrap();
+            *sums.entry(token.to_string()).or_insert_with(Uint128::zero) += balance;
+        }
+        sums
+    }
+}
@@ -0,0 +1,236 @@
+pub mod simulation_module {
+    use std::collections::BTreeMap;
+    use std::ops::Bound as RangeBound;
+
+    use cosmwasm_std::{
+        Addr, Deps, DepsMut, Env, MessageInfo, Order, Record, Response, StdResult, Storage, Uint128,
+    };
+
+    use crate::borrow::borrow_leverage;
+    use crate::burn::burn_tokens;
+    use crate::error::ContractError;
+    use crate::exchange::exchange_tokens;
+    use crate::liquidate::liquidate_leverage;
+    use crate::msg::{OrderExecute, SimulationResponse, TokenData, WithdrawData};
+    use crate::operator::operator_approvals;
+    use crate::state::OperatorPermission;
+    use crate::sub_account::sub_accounts;
+    use crate::valuation::valuation_module;
+    use crate::withdraw::withdraw_collateral;
+
+    /**
+     * Storage which keeps every write in memory on top of a read-only store.
+     *
+     * Lets queries run the execute handlers without persisting anything.
+     */
+    struct OverlayStorage<'a> {
+        base: &'a dyn Storage,
+        // `None` marks a key removed in the overlay
+        changes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    }
+
+    impl<'a> OverlayStorage<'a> {
+        fn new(base: &'a dyn Storage) -> Self {
+            OverlayStorage {
+                base,
+                changes: BTreeMap::new(),
+            }
+        }
+    }
+
+    impl Storage for OverlayStorage<'_> {
+        fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+            match self.changes.get(key) {
+                Some(value) => value.clone(),
+                None => self.base.get(key),
+            }
+        }
+
+        fn range<'b>(
+            &'b self,
+            start: Option<&[u8]>,
+            end: Option<&[u8]>,
+            order: Order,
+        ) -> Box<dyn Iterator<Item = Record> + 'b> {
+            let mut records: BTreeMap<Vec<u8>, Vec<u8>> =
+                self.base.range(start, end, Order::Ascending).collect();
+
+            let lower = start.map_or(RangeBound::Unbounded, |key| {
+                RangeBound::Included(key.to_vec())
+            });
+            let upper = end.map_or(RangeBound::Unbounded, |key| {
+                RangeBound::Excluded(key.to_vec())
+            });
+            for (key, value) in self.changes.range((lower, upper)) {
+                match value {
+                    Some(value) => records.insert(key.clone(), value.clone()),
+                    None => records.remove(key),
+                };
+            }
+
+            match order {
+                Order::Ascending => Box::new(records.into_iter()),
+                Order::Descending => Box::new(records.into_iter().rev()),
+            }
+        }
+
+        fn set(&mut self, key: &[u8], value: &[u8]) {
+            self.changes.insert(key.to_vec(), Some(value.to_vec()));
+        }
+
+        fn remove(&mut self, key: &[u8]) {
+            self.changes.insert(key.to_vec(), None);
+        }
+    }
+
+    /**
+     * Runs an execute handler on a copy-on-write view of the storage and reports
+     * the account of `user_address` afterwards.
+     *
+     * Errors of the handler are returned as they are, so a simulation fails
+     * exactly like the real call would.
+     */
+    fn simulate<F>(
+        deps: Deps,
+        env: &Env,
+        user_address: &Addr,
+        handler: F,
+    ) -> Result<SimulationResponse, ContractError>
+    where
+        F: FnOnce(DepsMut) -> Result<Response, ContractError>,
+    {
+        let mut storage = OverlayStorage::new(deps.storage);
+
+        let response = handler(DepsMut {
+            storage: &mut storage,
+            api: deps.api,
+            querier: deps.querier,
+        })?;
+
+        let simulated_deps = Deps {
+            storage: &storage,
+            api: deps.api,
+            querier: deps.querier,
+        };
+        let balances = valuation_module::account_balances(simulated_deps, env, user_address)?;
+        let health = valuation_module::health_of(&balances)?;
+
+        // Actions report the fees they charge in their `fee` attribute
+        let fee = response
+            .attributes
+            .iter()
+            .filter(|attr| attr.key == "fee")
+            .filter_map(|attr| attr.value.parse::<Uint128>().ok())
+            .sum();
+
+        Ok(SimulationResponse {
+            balances,
+            health,
+            fee,
+            attributes: response.attributes,
+        })
+    }
+
+    fn info_of(sender: &Addr) -> MessageInfo {
+        MessageInfo {
+            sender: sender.clone(),
+            funds: vec![],
+        }
+    }
+
+    /**
+     * Simulates a borrow sent by `user_address`, reporting the account it acts
+     * on, which is the `on_behalf_of` wallet's for an approved operator.
+     */
+    pub fn simulate_borrow(
+        deps: Deps,
+        env: Env,
+        user_address: Addr,
+        token_data: TokenData,
+    ) -> StdResult<SimulationResponse> {
+        let account = operator_approvals::acting_account(
+            deps,
+            &env,
+            info_of(&user_address),
+            &token_data,
+            OperatorPermission::Borrow,
+        )?
+        .sender;
+
+        Ok(simulate(deps, &env, &account, |deps| {
+            borrow_leverage::borrow_for_account(
+                deps,
+                env.clone(),
+                info_of(&user_address),
+                token_data,
+            )
+        })?)
+    }
+
+    pub fn simulate_withdraw(
+        deps: Deps,
+        env: Env,
+        user_address: Addr,
+        withdraw_data: WithdrawData,
+    ) -> StdResult<SimulationResponse> {
+        let account = sub_accounts::account_address(&user_address, withdraw_data.account_id)?;
+
+        Ok(simulate(deps, &env, &account, |deps| {
+            withdraw_collateral::withdraw(deps, env.clone(), info_of(&account), withdraw_data)
+        })?)
+    }
+
+    pub fn simulate_order(
+        deps: Deps,
+        env: Env,
+        order: OrderExecute,
+    ) -> StdResult<SimulationResponse> {
+        let user_address = order.user_address.clone();
+        let account = sub_accounts::account_address(&user_address, order.account_id)?;
+
+        Ok(simulate(deps, &env, &account, |deps| {
+            exchange_tokens::execute_order(deps, env.clone(), info_of(&user_address), order)
+        })?)
+    }
+
+    pub fn simulate_burn(
+        deps: Deps,
+        env: Env,
+        user_address: Addr,
+        token_data: TokenData,
+    ) -> StdResult<SimulationResponse> {
+        let info = operator_approvals::own_account(info_of(&user_address), &token_data, "burn")?;
+        let account = info.sender.clone();
+
+        Ok(simulate(deps, &env, &account, |deps| {
+            burn_tokens::burn(deps, env.clone(), info, token_data)
+        })?)
+    }
+
+    /**
+     * Simulates a liquidation paying `token_data`, reporting the liquidated account.
+     */
+    pub fn simulate_liquidation(
+        deps: Deps,
+        env: Env,
+        liquidator: Addr,
+        user_address: Addr,
+        account_id: Option<u64>,
+        token_data: TokenData,
+        collateral_token: Addr,
+    ) -> StdResult<SimulationResponse> {
+        let account = sub_accounts::account_address(&user_address, account_id)?;
+
+        Ok(simulate(deps, &env, &account, |deps| {
+            liquidate_leverage::liquidate(
+                deps,
+                env.clone(),
+                liquidator,
+                token_data.token_address,
+                token_data.token_amount,
+                account.clone(),
+                collateral_token,
+            )
+        })?)
+    }
+}
@@ -1,9 +1,182 @@
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::{Addr, Timestamp, Uint128};
+use cosmwasm_std::{Addr, Coin, Decimal, Decimal256, Empty, Timestamp, Uint128};
+use cw20::Expiration;
 use cw_storage_plus::{Item, Map};
 
 pub const LEVERAGE_CONTRACT_OWNER: Item<Addr> = Item::new("leverage_contract_owner");
-pub const LISTED_TOKEN: Item<Vec<String>> = Item::new("listed_chain");
+
+#[cw_serde]
+pub enum AssetInfo {
+    Native { denom: String },
+    Cw20 { contract_addr: Addr },
+}
+
+impl AssetInfo {
+    /// Denom or contract address the asset is received as
+    pub fn reference(&self) -> &str {
+        match self {
+            AssetInfo::Native { denom } => denom,
+            AssetInfo::Cw20 { contract_addr } => contract_addr.as_str(),
+        }
+    }
+}
+
+#[cw_serde]
+pub enum TokenStatus {
+    Active,
+    /// Positions can only be closed, nothing adds to them
+    ReduceOnly,
+    /// Closed for good, users can still repay and withdraw
+    Delisted,
+}
+
+impl TokenStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenStatus::Active => "active",
+            TokenStatus::ReduceOnly => "reduce_only",
+            TokenStatus::Delisted => "delisted",
+        }
+    }
+}
+
+#[cw_serde]
+pub struct TokenInfo {
+    /// Key the user balances of the token are stored under
+    pub token: String,
+    pub asset: AssetInfo,
+    pub decimals: u8,
+    pub symbol: String,
+    pub listed_at: Timestamp,
+    pub status: TokenStatus,
+}
+
+/// Where the price of a token comes from, quoted per whole token
+#[cw_serde]
+pub enum PriceSource {
+    /// Manually set price
+    Fixed { price: Decimal },
+    /// Arithmetic TWAP of the token's denom in `quote_denom` from an Osmosis pool
+    OsmosisTwap {
+        pool_id: u64,
+        quote_denom: String,
+        quote_decimals: u8,
+        window_seconds: u64,
+    },
+    /// Spot price of a Pyth price feed, refused when stale or too uncertain
+    Pyth {
+        contract_addr: Addr,
+        price_feed_id: String,
+        max_age_seconds: u64,
+        max_confidence_ratio: Decimal,
+    },
+    /// Median of several sources which are checked against each other
+    Aggregate {
+        sources: Vec<PriceSource>,
+        max_deviation: Decimal,
+        /// Sources that need a price before the token is priced
+        min_sources: u32,
+    },
+}
+
+pub const PRICE_SOURCE: Map<&str, PriceSource> = Map::new("price source");
+
+pub const LISTED_TOKEN: Map<&str, TokenInfo> = Map::new("listed tokens");
+// Denom or CW20 address of a listed asset to its token key
+pub const ASSET_TOKEN: Map<&str, String> = Map::new("asset token");
+pub const PENDING_OWNER: Item<Addr> = Item::new("pending_owner");
+pub const PAUSED: Item<bool> = Item::new("paused");
+
+/// Fees as a share of the borrowed amount and of the bought amount of an order
+#[cw_serde]
+#[derive(Default)]
+pub struct FeeConfig {
+    pub borrow_fee: Decimal,
+    pub trading_fee: Decimal,
+    /// Share of a referred user's fees rebated to their referrer
+    #[serde(default)]
+    pub referral_share: Decimal,
+}
+
+pub const FEE_CONFIG: Item<FeeConfig> = Item::new("fee_config");
+
+#[cw_serde]
+#[derive(Copy)]
+pub enum Role {
+    /// Manages listings and grants every other role
+    Admin,
+    /// Tunes risk parameters such as borrow rates
+    RiskManager,
+    /// Pauses the protocol in an emergency
+    Guardian,
+    /// Executes orders on behalf of users
+    Keeper,
+    /// Collects the protocol revenue
+    FeeCollector,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::RiskManager => "risk_manager",
+            Role::Guardian => "guardian",
+            Role::Keeper => "keeper",
+            Role::FeeCollector => "fee_collector",
+        }
+    }
+}
+
+pub const ROLE_MEMBERS: Map<(&str, &Addr), Empty> = Map::new("role members");
+
+/// Risk-sensitive admin actions which only take effect after the timelock delay
+#[cw_serde]
+pub enum TimelockAction {
+    ListToken {
+        token_address: String,
+        asset: AssetInfo,
+        /// Queried from the CW20 token info when not set
+        decimals: Option<u8>,
+        symbol: String,
+    },
+    SetPriceSource {
+        token_address: String,
+        source: PriceSource,
+    },
+    UpdateBorrowRate {
+        token_address: String,
+        rate: Decimal,
+    },
+    UpdateMarketCaps {
+        token_address: String,
+        caps: MarketCaps,
+    },
+    UpdateFees {
+        fees: FeeConfig,
+    },
+    UpdateTimelockDelay {
+        delay: u64,
+    },
+    SetRewardSchedule {
+        token_address: String,
+        schedule: RewardSchedule,
+    },
+}
+
+#[cw_serde]
+pub struct PendingAction {
+    pub id: u64,
+    pub action: TimelockAction,
+    pub proposer: Addr,
+    pub eta: Timestamp,
+    // Escrowed with the proposal, paid back to the proposer if it's cancelled
+    pub funds: Vec<Coin>,
+}
+
+// Seconds between proposing and executing an action
+pub const TIMELOCK_DELAY: Item<u64> = Item::new("timelock_delay");
+pub const NEXT_ACTION_ID: Item<u64> = Item::new("next_action_id");
+pub const PENDING_ACTIONS: Map<u64, PendingAction> = Map::new("pending actions");
 
 pub const TOKEN_BALANCE: Map<(&Addr, &Addr), Uint128> = Map::new("user token colateral balance");
 pub const WRAPPED_TOKEN_BALANCE: Map<(&Addr, &Addr), Uint128> = Map::new("wrapped token balance");
@@ -11,6 +184,156 @@ pub const WRAPPED_TOKEN_BORROW_BALANCE: Map<(&Addr, &Addr), Uint128> =
     Map::new("wrapped token borrow balance");
 pub const USER_VTOKEN_BALANCE: Map<(&Addr, &Addr), Uint128> = Map::new("user vtoken balance");
 
+// Interest accrued on top of the borrowed principal, settled before the principal on repay
+pub const BORROW_INTEREST: Map<(&Addr, &Addr), Uint128> = Map::new("wrapped token borrow interest");
+pub const BORROW_INTEREST_TIMESTAMP: Map<(&Addr, &Addr), Timestamp> =
+    Map::new("wrapped token borrow interest timestamp");
+// Annual borrow rate per token, defaults to zero when the owner never set one
+pub const BORROW_RATE: Map<&Addr, Decimal> = Map::new("token borrow rate");
+
+// Real tokens received through repayments and top-ups which back outstanding vTokens
+pub const RESERVE_BALANCE: Map<&Addr, Uint128> = Map::new("token reserve balance");
+// Interest paid in real tokens
+pub const PROTOCOL_REVENUE: Map<&Addr, Uint128> = Map::new("protocol revenue");
+// vTokens kept back from orders as trading fees
+pub const TRADING_FEES: Map<&Addr, Uint128> = Map::new("trading fees");
+// Written-off debt, in vTokens, which neither the insurance nor auto-deleveraging covered yet
+pub const DEFICITS: Map<&Addr, Uint128> = Map::new("deficits");
+
+/// Auto-deleveraging scan over the holders of a token which isn't complete yet
+#[cw_serde]
+pub struct AdlScan {
+    /// The next page of holders starts after it
+    pub last_holder: Addr,
+    /// Best ranked accounts of the scanned pages
+    pub candidates: Vec<Addr>,
+}
+
+pub const ADL_SCANS: Map<&Addr, AdlScan> = Map::new("adl scans");
+
+// Sub-accounts which held collateral, keyed by owner and account id
+pub const SUB_ACCOUNTS: Map<(&Addr, u64), Empty> = Map::new("sub accounts");
+
+/// What an operator may do on the accounts of the wallet which approved it.
+/// Withdrawing is deliberately not among them.
+#[cw_serde]
+#[derive(Copy)]
+pub enum OperatorPermission {
+    /// Executes orders
+    Trade,
+    /// Borrows against the collateral
+    Borrow,
+    /// Repays debt with vTokens
+    Repay,
+}
+
+impl OperatorPermission {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OperatorPermission::Trade => "trade",
+            OperatorPermission::Borrow => "borrow",
+            OperatorPermission::Repay => "repay",
+        }
+    }
+}
+
+#[cw_serde]
+pub struct OperatorApproval {
+    pub permissions: Vec<OperatorPermission>,
+    pub expires: Expiration,
+}
+
+// Keyed by owner and operator, covers every sub-account of the owner
+pub const OPERATOR_APPROVALS: Map<(&Addr, &Addr), OperatorApproval> =
+    Map::new("operator approvals");
+
+// Users with open debt, scanned by keepers looking for liquidations
+pub const BORROWERS: Map<&Addr, Empty> = Map::new("borrowers");
+
+// Running totals of the user balances above, per token
+pub const TOTAL_COLLATERAL: Map<&Addr, Uint128> = Map::new("total collateral");
+pub const TOTAL_WRAPPED: Map<&Addr, Uint128> = Map::new("total wrapped");
+pub const TOTAL_BORROWED: Map<&Addr, Uint128> = Map::new("total borrowed");
+pub const TOTAL_VTOKENS: Map<&Addr, Uint128> = Map::new("total vtokens");
+
+/// Limits of the total deposits and borrows of a token, unlimited when not set
+#[cw_serde]
+#[derive(Default)]
+pub struct MarketCaps {
+    pub deposit_cap: Option<Uint128>,
+    pub borrow_cap: Option<Uint128>,
+}
+
+pub const MARKET_CAPS: Map<&Addr, MarketCaps> = Map::new("market caps");
+
+/// Rewards a market emits per second between `start_time` and `end_time`
+#[cw_serde]
+pub struct RewardSchedule {
+    /// Native denom the rewards are paid in, the contract must hold enough of it
+    pub reward_denom: String,
+    pub start_time: Timestamp,
+    pub end_time: Timestamp,
+    /// Shared by all collateral of the market
+    pub collateral_emission: Uint128,
+    /// Shared by all debt of the market
+    pub borrow_emission: Uint128,
+}
+
+/// Balance a market's rewards are shared by
+#[cw_serde]
+#[derive(Copy)]
+pub enum RewardSide {
+    Collateral,
+    Borrow,
+}
+
+impl RewardSide {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RewardSide::Collateral => "collateral",
+            RewardSide::Borrow => "borrow",
+        }
+    }
+}
+
+/// Rewards emitted per unit of balance since the schedule was set
+#[cw_serde]
+pub struct RewardIndex {
+    pub index: Decimal256,
+    pub updated_at: Timestamp,
+}
+
+pub const REWARD_SCHEDULE: Map<&Addr, RewardSchedule> = Map::new("reward schedule");
+// Keyed by token and reward side
+pub const REWARD_INDEX: Map<(&Addr, &str), RewardIndex> = Map::new("reward index");
+// Index a user's rewards were last accrued at, keyed by token, reward side and user
+pub const USER_REWARD_INDEX: Map<(&Addr, &str, &Addr), Decimal256> = Map::new("user reward index");
+// Accrued and unclaimed rewards, keyed by user and reward denom
+pub const PENDING_REWARDS: Map<(&Addr, &str), Uint128> = Map::new("pending rewards");
+// Reward tokens sent with the schedules and not claimed yet, keyed by reward denom
+pub const REWARD_FUNDS: Map<&str, Uint128> = Map::new("reward funds");
+
+// Referral code to the referrer who registered it, one code per referrer
+pub const REFERRAL_CODES: Map<&str, Addr> = Map::new("referral codes");
+pub const REFERRER_CODE: Map<&Addr, String> = Map::new("referrer code");
+// Set on a user's first deposit, to `None` when they came without a referral code
+pub const REFERRED_BY: Map<&Addr, Option<Addr>> = Map::new("referred by");
+pub const REFERRED_USERS: Map<&Addr, u64> = Map::new("referred users");
+
+/// Fee rebates of a referrer in one token
+#[cw_serde]
+#[derive(Default)]
+pub struct ReferralRebate {
+    /// Share of borrow fees, paid in tokens out of the protocol revenue
+    pub borrow_fees: Uint128,
+    /// Share of trading fees, paid in vTokens out of the trading fees
+    pub trading_fees: Uint128,
+}
+
+// Keyed by referrer and token
+pub const PENDING_REBATES: Map<(&Addr, &Addr), ReferralRebate> = Map::new("pending rebates");
+pub const CLAIMED_REBATES: Map<(&Addr, &Addr), ReferralRebate> = Map::new("claimed rebates");
+
 #[cw_serde]
 pub struct OrderState {
     pub order_id: String,
@@ -29,4 +352,4 @@ pub enum Status {
     Rejected,
 }
 
-pub const ORDER_STATE: Map<&Addr, Vec<OrderState>> = Map::new("order data");
\ No newline at end of file
+pub const ORDER_STATE: Map<&Addr, Vec<OrderState>> = Map::new("order data");
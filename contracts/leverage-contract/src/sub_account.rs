@@ -0,0 +1,169 @@
+pub mod sub_accounts {
+    use cosmwasm_std::{
+        Addr, Deps, DepsMut, Empty, Env, MessageInfo, Order, Response, StdResult, Storage, Uint128,
+    };
+
+    use crate::access::access_control;
+    use crate::deposit::deposit_collateral;
+    use crate::error::ContractError;
+    use crate::events::leverage_events::LeverageEvent;
+    use crate::listing::token_listing;
+    use crate::msg::{QueryTokenData, SubAccountsResponse};
+    use crate::state::SUB_ACCOUNTS;
+    use crate::valuation::valuation_module;
+    use crate::withdraw::withdraw_collateral;
+
+    pub const MAX_ACCOUNT_ID: u64 = 64;
+
+    // Wallet addresses never contain it, so derived addresses can't collide with a wallet
+    const SEPARATOR: char = '/';
+
+    /**
+     * Address the balances of a sub-account are kept under.
+     *
+     * Account 0, the default, is the wallet itself, so balances from before
+     * sub-accounts existed stay where they are. Sub-account `n` of a wallet is
+     * `<owner>/<n>`.
+     */
+    pub fn account_address(owner: &Addr, account_id: Option<u64>) -> Result<Addr, ContractError> {
+        match account_id.unwrap_or_default() {
+            0 => Ok(owner.clone()),
+            account_id if account_id <= MAX_ACCOUNT_ID => {
+                Ok(Addr::unchecked(format!("{owner}{SEPARATOR}{account_id}")))
+            }
+            account_id => Err(ContractError::InvalidAccountId { account_id }),
+        }
+    }
+
+    /**
+     * Splits an account address into its owner and account id.
+     */
+    pub fn parse(account: &Addr) -> (Addr, u64) {
+        match account.as_str().rsplit_once(SEPARATOR) {
+            Some((owner, account_id)) => match account_id.parse::<u64>() {
+                Ok(account_id) => (Addr::unchecked(owner), account_id),
+                Err(_) => (account.clone(), 0),
+            },
+            None => (account.clone(), 0),
+        }
+    }
+
+    /**
+     * Wallet which receives whatever is paid out of the account.
+     */
+    pub fn owner_of(account: &Addr) -> Addr {
+        parse(account).0
+    }
+
+    /**
+     * Message info with the sender's sub-account as sender, so handlers act
+     * on its balances.
+     */
+    pub fn sender_account(
+        info: MessageInfo,
+        account_id: Option<u64>,
+    ) -> Result<MessageInfo, ContractError> {
+        Ok(MessageInfo {
+            sender: account_address(&info.sender, account_id)?,
+            funds: info.funds,
+        })
+    }
+
+    /**
+     * Token balance query on the account of the queried user.
+     */
+    pub fn query_account(
+        query_token_data: QueryTokenData,
+    ) -> Result<QueryTokenData, ContractError> {
+        Ok(QueryTokenData {
+            user_address: account_address(
+                &query_token_data.user_address,
+                query_token_data.account_id,
+            )?,
+            account_id: None,
+            ..query_token_data
+        })
+    }
+
+    /**
+     * Keeps track of the sub-accounts of an owner once they hold collateral.
+     */
+    pub fn record(storage: &mut dyn Storage, account: &Addr) -> StdResult<()> {
+        let (owner, account_id) = parse(account);
+        if account_id != 0 && !SUB_ACCOUNTS.has(storage, (&owner, account_id)) {
+            SUB_ACCOUNTS.save(storage, (&owner, account_id), &Empty {})?;
+        }
+        Ok(())
+    }
+
+    /**
+     * Moves collateral between two accounts of the sender.
+     *
+     * Moving collateral out of an account works like withdrawing it: the
+     * account can't owe the token and has to stay healthy afterwards.
+     */
+    pub fn transfer_collateral(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        token_address: Addr,
+        amount: Uint128,
+        from_account_id: Option<u64>,
+        to_account_id: Option<u64>,
+    ) -> Result<Response, ContractError> {
+        access_control::assert_not_paused(deps.storage)?;
+        token_listing::load_token(deps.storage, &token_address)?;
+
+        if amount.is_zero() {
+            return Err(ContractError::ZeroAmount {});
+        }
+        let from = account_address(&info.sender, from_account_id)?;
+        let to = account_address(&info.sender, to_account_id)?;
+        if from == to {
+            return Err(ContractError::SameAccount {});
+        }
+
+        let (from_collateral_balance, _) = withdraw_collateral::debit_collateral(
+            deps.storage,
+            env.block.time,
+            &token_address,
+            &from,
+            amount,
+        )?;
+        let (to_collateral_balance, _) = deposit_collateral::credit_collateral(
+            deps.storage,
+            env.block.time,
+            &token_address,
+            &to,
+            amount,
+        )?;
+
+        valuation_module::assert_healthy(deps.as_ref(), &env, &from)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "transfer_collateral")
+            .add_attribute("owner", info.sender.clone())
+            .add_attribute("token_address", token_address.clone())
+            .add_attribute("amount", amount)
+            .add_event(
+                LeverageEvent::CollateralTransferred {
+                    owner: info.sender,
+                    token: token_address.to_string(),
+                    amount,
+                    from_account: from_account_id.unwrap_or_default(),
+                    to_account: to_account_id.unwrap_or_default(),
+                    from_collateral_balance,
+                    to_collateral_balance,
+                }
+                .into(),
+            ))
+    }
+
+    pub fn fetch_sub_accounts(deps: Deps, owner: Addr) -> StdResult<SubAccountsResponse> {
+        let account_ids = SUB_ACCOUNTS
+            .prefix(&owner)
+            .keys(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<u64>>>()?;
+        Ok(SubAccountsResponse { owner, account_ids })
+    }
+}
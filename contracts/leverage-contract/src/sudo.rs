@@ -0,0 +1,454 @@
+pub mod sudo_module {
+    use cosmwasm_std::{
+        Addr, Decimal, Decimal256, DepsMut, Env, Event, Order, Response, StdResult, Storage,
+        Uint128,
+    };
+
+    use crate::deleverage::auto_deleverage;
+    use crate::events::leverage_events::LeverageEvent;
+    use crate::incentives::incentives_module;
+    use crate::interest::interest_module;
+    use crate::liquidate::liquidate_leverage;
+    use crate::listing::token_listing;
+    use crate::market::market_module;
+    use crate::msg::PriceSourceUpdate;
+    use crate::oracle::price_oracle;
+    use crate::repay::repay_leverage;
+    use crate::state::{
+        TokenInfo, BORROW_INTEREST, DEFICITS, LEVERAGE_CONTRACT_OWNER, LISTED_TOKEN, PAUSED,
+        PENDING_OWNER, RESERVE_BALANCE, TOKEN_BALANCE, TOTAL_BORROWED, TOTAL_VTOKENS,
+        USER_VTOKEN_BALANCE, WRAPPED_TOKEN_BORROW_BALANCE,
+    };
+    use crate::sub_account::sub_accounts;
+    use crate::valuation::valuation_module;
+    use crate::ContractError;
+
+    /**
+     * Pauses the protocol like a guardian would.
+     *
+     * Sudo calls come from the chain itself, so the contract stands in as sender.
+     */
+    pub fn force_pause(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+        PAUSED.save(deps.storage, &true)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "sudo_force_pause")
+            .add_event(
+                LeverageEvent::Paused {
+                    sender: env.contract.address,
+                }
+                .into(),
+            ))
+    }
+
+    /**
+     * Replaces the owner without the acceptance step, a pending transfer is dropped.
+     */
+    pub fn replace_owner(
+        deps: DepsMut,
+        _env: Env,
+        new_owner: String,
+    ) -> Result<Response, ContractError> {
+        let new_owner = deps.api.addr_validate(&new_owner)?;
+        let previous_owner = LEVERAGE_CONTRACT_OWNER.load(deps.storage)?;
+
+        LEVERAGE_CONTRACT_OWNER.save(deps.storage, &new_owner)?;
+        PENDING_OWNER.remove(deps.storage);
+
+        Ok(Response::new()
+            .add_attribute("method", "sudo_replace_owner")
+            .add_attribute("owner", new_owner.clone())
+            .add_event(
+                LeverageEvent::OwnerReplaced {
+                    previous_owner,
+                    owner: new_owner,
+                }
+                .into(),
+            ))
+    }
+
+    pub fn update_price_sources(
+        mut deps: DepsMut,
+        env: Env,
+        sources: Vec<PriceSourceUpdate>,
+    ) -> Result<Response, ContractError> {
+        let mut response = Response::new().add_attribute("method", "sudo_update_price_sources");
+
+        for update in sources {
+            let updated = price_oracle::set_price_source(
+                deps.branch(),
+                env.clone(),
+                Addr::unchecked(update.token_address),
+                update.source,
+            )?;
+            response = response.add_events(updated.events);
+        }
+
+        Ok(response)
+    }
+
+    /**
+     * Closes every debt of the user, also while the protocol is paused.
+     *
+     * The debts are settled by `settle_account`, the insurance covers the
+     * deficit the bad debt leaves right away. Positions are only deleveraged
+     * for the rest by `auto_deleverage`, once all holders were ranked.
+     */
+    pub fn force_settle(
+        mut deps: DepsMut,
+        env: Env,
+        user_address: String,
+        account_id: Option<u64>,
+    ) -> Result<Response, ContractError> {
+        let user_address =
+            sub_accounts::account_address(&deps.api.addr_validate(&user_address)?, account_id)?;
+
+        let (settled, deficit_tokens) = settle_account(deps.branch(), &env, &user_address)?;
+        let mut response = Response::new()
+            .add_attribute("method", "sudo_force_settle")
+            .add_attribute("user", user_address.clone())
+            .add_events(settled.into_iter().map(Event::from));
+
+        for token_address in deficit_tokens {
+            let covered =
+                auto_deleverage::cover_deficit(deps.branch(), &env, &token_address, vec![])?;
+            response = response.add_events(covered.into_iter().map(Event::from));
+        }
+
+        Ok(response)
+    }
+
+    /**
+     * Closes every debt of the account with what it holds.
+     *
+     * For each token the debt is repaid with the account's vTokens first. What
+     * they don't cover is taken from its collateral in the same token, kept in
+     * the reserve. A shortfall left after that is covered with the account's
+     * vTokens and collateral in the other tokens. Collateral is valued against
+     * debt at oracle prices throughout, as liquidations value it, so one unit
+     * of collateral covers one unit of debt in its own token. Only debt the
+     * whole account can't cover is written off as bad debt and recorded as the
+     * token's deficit.
+     *
+     * @return The settlement events and the tokens left with a deficit.
+     */
+    pub fn settle_account(
+        mut deps: DepsMut,
+        env: &Env,
+        user_address: &Addr,
+    ) -> Result<(Vec<LeverageEvent>, Vec<Addr>), ContractError> {
+        let tokens = LISTED_TOKEN
+            .keys(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<String>>>()?;
+
+        // Every debt takes from its own token first, before other tokens are seized
+        let mut settlements = vec![];
+        for token in tokens {
+            let token_address = Addr::unchecked(token);
+            if let Some(settlement) = settle_token(deps.storage, env, &token_address, user_address)?
+            {
+                settlements.push(settlement);
+            }
+        }
+
+        let mut events = vec![];
+        let mut deficit_tokens = vec![];
+        for settlement in settlements {
+            let mut seizures = vec![];
+            let mut bad_debt = settlement.shortfall;
+            if !bad_debt.is_zero() {
+                let (covered, seized_events) = seize_other_assets(
+                    deps.branch(),
+                    env,
+                    user_address,
+                    &settlement.token_address,
+                    bad_debt,
+                )?;
+                bad_debt -= covered;
+                seizures = seized_events;
+            }
+            auto_deleverage::record_deficit(deps.storage, &settlement.token_address, bad_debt)?;
+
+            events.push(LeverageEvent::ForceSettled {
+                user: user_address.clone(),
+                token: settlement.token_address.to_string(),
+                repaid: settlement.repaid,
+                seized: settlement.seized,
+                bad_debt,
+                collateral_balance: market_module::user_balance(
+                    deps.storage,
+                    &TOKEN_BALANCE,
+                    &settlement.token_address,
+                    user_address,
+                )?,
+                vtoken_balance: market_module::user_balance(
+                    deps.storage,
+                    &USER_VTOKEN_BALANCE,
+                    &settlement.token_address,
+                    user_address,
+                )?,
+            });
+            events.extend(seizures);
+            if !bad_debt.is_zero() {
+                deficit_tokens.push(settlement.token_address);
+            }
+        }
+
+        liquidate_leverage::update_borrower_index(deps.storage, user_address)?;
+        Ok((events, deficit_tokens))
+    }
+
+    /**
+     * Covers the recorded deficit of the token with the insurance and the
+     * best ranked positions of all its holders.
+     *
+     * Every call ranks the next `limit` holders. While the `next_start_after`
+     * attribute is set governance calls again, the positions are closed by the
+     * call which completes the scan.
+     */
+    pub fn auto_deleverage(
+        mut deps: DepsMut,
+        env: Env,
+        token_address: String,
+        limit: Option<u32>,
+    ) -> Result<Response, ContractError> {
+        let token_address = Addr::unchecked(token_address);
+        token_listing::load_token(deps.storage, &token_address)?;
+        if !DEFICITS.has(deps.storage, &token_address) {
+            return Err(ContractError::NoDeficit {
+                token: token_address.to_string(),
+            });
+        }
+
+        let (ranking, next_start_after) =
+            auto_deleverage::scan_positions(deps.branch(), &env, &token_address, limit)?;
+
+        let response = Response::new()
+            .add_attribute("method", "sudo_auto_deleverage")
+            .add_attribute("token", token_address.clone());
+        if let Some(next_start_after) = next_start_after {
+            return Ok(response.add_attribute("next_start_after", next_start_after));
+        }
+
+        let covered = auto_deleverage::cover_deficit(deps, &env, &token_address, ranking)?;
+        Ok(response.add_events(covered.into_iter().map(Event::from)))
+    }
+
+    // What settling a debt with the user's assets in its own token left open
+    struct Settlement {
+        token_address: Addr,
+        repaid: Uint128,
+        seized: Uint128,
+        shortfall: Uint128,
+    }
+
+    fn settle_token(
+        storage: &mut dyn Storage,
+        env: &Env,
+        token_address: &Addr,
+        user_address: &Addr,
+    ) -> Result<Option<Settlement>, ContractError> {
+        let interest = interest_module::pending_interest(
+            storage,
+            token_address,
+            user_address,
+            env.block.time,
+        )?;
+        let principal = market_module::user_balance(
+            storage,
+            &WRAPPED_TOKEN_BORROW_BALANCE,
+            token_address,
+            user_address,
+        )?;
+        let debt = principal
+            .checked_add(interest)
+            .map_err(|_| ContractError::Overflow {})?;
+        if debt.is_zero() {
+            return Ok(None);
+        }
+        interest_module::accrue_interest(storage, token_address, user_address, env.block.time)?;
+        incentives_module::update_user_rewards(
+            storage,
+            token_address,
+            user_address,
+            env.block.time,
+        )?;
+
+        let vtokens = market_module::user_balance(
+            storage,
+            &USER_VTOKEN_BALANCE,
+            token_address,
+            user_address,
+        )?;
+        let repaid = vtokens.min(debt);
+        if !repaid.is_zero() {
+            repay_leverage::settle_debt(
+                storage,
+                token_address,
+                user_address,
+                repaid,
+                env.block.time,
+            )?;
+            USER_VTOKEN_BALANCE.save(
+                storage,
+                (token_address, user_address),
+                &(vtokens - repaid),
+            )?;
+            market_module::decrease_total(storage, &TOTAL_VTOKENS, token_address, repaid)?;
+        }
+
+        let shortfall = debt - repaid;
+        let mut seized = Uint128::zero();
+        if !shortfall.is_zero() {
+            // Valued at the oracle price like in `liquidate` and `seize_other_assets`,
+            // which in the debt's own token is one collateral unit per unit of debt
+            let collateral =
+                market_module::user_balance(storage, &TOKEN_BALANCE, token_address, user_address)?;
+            seized = shortfall.min(collateral);
+            liquidate_leverage::seize_collateral(storage, token_address, user_address, seized)?;
+            market_module::increase_total(storage, &RESERVE_BALANCE, token_address, seized)?;
+
+            let remaining_principal = market_module::user_balance(
+                storage,
+                &WRAPPED_TOKEN_BORROW_BALANCE,
+                token_address,
+                user_address,
+            )?;
+            WRAPPED_TOKEN_BORROW_BALANCE.save(
+                storage,
+                (token_address, user_address),
+                &Uint128::zero(),
+            )?;
+            BORROW_INTEREST.save(storage, (token_address, user_address), &Uint128::zero())?;
+            market_module::decrease_total(
+                storage,
+                &TOTAL_BORROWED,
+                token_address,
+                remaining_principal,
+            )?;
+        }
+
+        Ok(Some(Settlement {
+            token_address: token_address.clone(),
+            repaid,
+            seized,
+            shortfall: shortfall - seized,
+        }))
+    }
+
+    /**
+     * Covers a shortfall in one token with the user's vTokens and collateral in
+     * the other tokens, valued at oracle prices.
+     *
+     * vTokens go first, they're what the debt was traded into. The protocol is
+     * their counterparty, so seized vTokens are burned, while seized collateral
+     * stays in the reserve of its token.
+     *
+     * @return The covered part of the shortfall and an event per token seized from.
+     */
+    fn seize_other_assets(
+        deps: DepsMut,
+        env: &Env,
+        user_address: &Addr,
+        debt_token: &Addr,
+        shortfall: Uint128,
+    ) -> Result<(Uint128, Vec<LeverageEvent>), ContractError> {
+        let debt_info = token_listing::load_token(deps.storage, debt_token)?;
+        let debt_price = price_oracle::query_price(deps.as_ref(), env, &debt_info)?;
+        let mut remaining = valuation_module::token_value(shortfall, &debt_info, debt_price)?;
+
+        let balances = valuation_module::account_balances(deps.as_ref(), env, user_address)?;
+        let mut events = vec![];
+        for balance in balances {
+            if remaining.is_zero() {
+                break;
+            }
+            if balance.token == debt_token.as_str()
+                || (balance.vtokens.is_zero() && balance.collateral.is_zero())
+            {
+                continue;
+            }
+            let token_address = Addr::unchecked(&balance.token);
+            let token_info = token_listing::load_token(deps.storage, &token_address)?;
+            let price = price_oracle::query_price(deps.as_ref(), env, &token_info)?;
+
+            let vtokens = amount_worth(remaining, &token_info, price)?.min(balance.vtokens);
+            let mut seized_value = valuation_module::token_value(vtokens, &token_info, price)?;
+            let collateral =
+                amount_worth(remaining.saturating_sub(seized_value), &token_info, price)?
+                    .min(balance.collateral);
+            seized_value = seized_value
+                .checked_add(valuation_module::token_value(
+                    collateral,
+                    &token_info,
+                    price,
+                )?)
+                .map_err(|_| ContractError::Overflow {})?;
+            remaining = remaining.saturating_sub(seized_value);
+
+            if !vtokens.is_zero() {
+                USER_VTOKEN_BALANCE.save(
+                    deps.storage,
+                    (&token_address, user_address),
+                    &(balance.vtokens - vtokens),
+                )?;
+                market_module::decrease_total(
+                    deps.storage,
+                    &TOTAL_VTOKENS,
+                    &token_address,
+                    vtokens,
+                )?;
+            }
+            if !collateral.is_zero() {
+                incentives_module::update_user_rewards(
+                    deps.storage,
+                    &token_address,
+                    user_address,
+                    env.block.time,
+                )?;
+                liquidate_leverage::seize_collateral(
+                    deps.storage,
+                    &token_address,
+                    user_address,
+                    collateral,
+                )?;
+                market_module::increase_total(
+                    deps.storage,
+                    &RESERVE_BALANCE,
+                    &token_address,
+                    collateral,
+                )?;
+            }
+
+            events.push(LeverageEvent::SettlementSeized {
+                user: user_address.clone(),
+                token: balance.token.clone(),
+                debt_token: debt_token.to_string(),
+                vtokens,
+                collateral,
+                covered: valuation_module::token_amount(seized_value, &debt_info, debt_price)?
+                    .min(shortfall),
+                collateral_balance: balance.collateral - collateral,
+                vtoken_balance: balance.vtokens - vtokens,
+            });
+        }
+
+        let uncovered = amount_worth(remaining, &debt_info, debt_price)?.min(shortfall);
+        Ok((shortfall - uncovered, events))
+    }
+
+    // Smallest amount of the token worth at least `value`
+    fn amount_worth(
+        value: Decimal256,
+        token_info: &TokenInfo,
+        price: Decimal,
+    ) -> Result<Uint128, ContractError> {
+        let amount = valuation_module::token_amount(value, token_info, price)?;
+        if valuation_module::token_value(amount, token_info, price)? < value {
+            return amount
+                .checked_add(Uint128::one())
+                .map_err(|_| ContractError::Overflow {});
+        }
+        Ok(amount)
+    }
+}
@@ -0,0 +1,304 @@
+pub mod timelock_module {
+    use cosmwasm_std::{
+        Addr, BankMsg, Coin, Deps, DepsMut, Env, MessageInfo, Order, Response, StdResult, Storage,
+        Uint128,
+    };
+    use cw_storage_plus::Bound;
+
+    use crate::access::access_control;
+    use crate::error::ContractError;
+    use crate::events::leverage_events::LeverageEvent;
+    use crate::execute::execute_module;
+    use crate::incentives::incentives_module;
+    use crate::listing::token_listing;
+    use crate::market::market_module;
+    use crate::msg::PendingActionsResponse;
+    use crate::oracle::price_oracle;
+    use crate::state::{
+        PendingAction, Role, TimelockAction, NEXT_ACTION_ID, PENDING_ACTIONS, TIMELOCK_DELAY,
+    };
+
+    pub const DEFAULT_TIMELOCK_DELAY: u64 = 2 * 24 * 60 * 60;
+    // Lower bound of every delay, so no action ever applies in the block it's proposed
+    pub const MIN_TIMELOCK_DELAY: u64 = 60 * 60;
+    // Actions not executed within this window after their ETA go stale
+    pub const GRACE_PERIOD: u64 = 14 * 24 * 60 * 60;
+
+    const DEFAULT_LIMIT: u32 = 10;
+    const MAX_LIMIT: u32 = 30;
+
+    fn required_role(action: &TimelockAction) -> Role {
+        match action {
+            TimelockAction::ListToken { .. } => Role::Admin,
+            TimelockAction::UpdateBorrowRate { .. } => Role::RiskManager,
+            TimelockAction::SetPriceSource { .. } => Role::RiskManager,
+            TimelockAction::UpdateMarketCaps { .. } => Role::RiskManager,
+            TimelockAction::UpdateFees { .. } => Role::Admin,
+            TimelockAction::UpdateTimelockDelay { .. } => Role::Admin,
+            TimelockAction::SetRewardSchedule { .. } => Role::RiskManager,
+        }
+    }
+
+    fn timelock_delay(storage: &dyn Storage) -> StdResult<u64> {
+        Ok(TIMELOCK_DELAY
+            .may_load(storage)?
+            .unwrap_or(DEFAULT_TIMELOCK_DELAY)
+            .max(MIN_TIMELOCK_DELAY))
+    }
+
+    // Sum of the escrowed coins, all of them are in the reward denom
+    fn escrowed_amount(funds: &[Coin]) -> Uint128 {
+        funds.iter().map(|coin| coin.amount).sum()
+    }
+
+    // Runs the checks of applying the action against the state at proposal
+    fn validate_action(
+        deps: Deps,
+        env: &Env,
+        action: &TimelockAction,
+        funds: &[Coin],
+    ) -> Result<(), ContractError> {
+        match action {
+            TimelockAction::ListToken {
+                token_address,
+                asset,
+                decimals,
+                ..
+            } => {
+                token_listing::validate_listing(deps, token_address, asset, *decimals)?;
+            }
+            TimelockAction::UpdateBorrowRate { token_address, .. }
+            | TimelockAction::UpdateMarketCaps { token_address, .. } => {
+                token_listing::load_token(deps.storage, &Addr::unchecked(token_address))?;
+            }
+            TimelockAction::SetPriceSource {
+                token_address,
+                source,
+            } => {
+                price_oracle::validate_price_source(deps, &Addr::unchecked(token_address), source)?
+            }
+            TimelockAction::UpdateFees { fees } => execute_module::assert_fees(fees)?,
+            TimelockAction::UpdateTimelockDelay { delay } => assert_timelock_delay(*delay)?,
+            TimelockAction::SetRewardSchedule {
+                token_address,
+                schedule,
+            } => incentives_module::validate_reward_schedule(
+                deps.storage,
+                env.block.time,
+                &Addr::unchecked(token_address),
+                schedule,
+                escrowed_amount(funds),
+            )?,
+        }
+        Ok(())
+    }
+
+    // Only a reward schedule is funded, in its reward denom
+    fn assert_action_funds(action: &TimelockAction, funds: &[Coin]) -> Result<(), ContractError> {
+        let accepted = match action {
+            TimelockAction::SetRewardSchedule { schedule, .. } => {
+                funds.iter().all(|coin| coin.denom == schedule.reward_denom)
+            }
+            _ => funds.is_empty(),
+        };
+        if !accepted {
+            return Err(ContractError::UnexpectedFunds {});
+        }
+        Ok(())
+    }
+
+    pub fn assert_timelock_delay(delay: u64) -> Result<(), ContractError> {
+        if delay < MIN_TIMELOCK_DELAY {
+            return Err(ContractError::TimelockDelayTooShort {
+                min: MIN_TIMELOCK_DELAY,
+            });
+        }
+        Ok(())
+    }
+
+    /**
+     * Queues an admin action which becomes executable once the timelock delay passed.
+     *
+     * The action is checked right away, and again when it's applied since the
+     * state may change in between. A reward schedule is funded here, the funds
+     * are escrowed with the action.
+     */
+    pub fn propose_action(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        action: TimelockAction,
+    ) -> Result<Response, ContractError> {
+        access_control::assert_role(deps.storage, required_role(&action), &info.sender)?;
+        assert_action_funds(&action, &info.funds)?;
+        validate_action(deps.as_ref(), &env, &action, &info.funds)?;
+
+        let id = NEXT_ACTION_ID.may_load(deps.storage)?.unwrap_or_default() + 1;
+        NEXT_ACTION_ID.save(deps.storage, &id)?;
+
+        let eta = env.block.time.plus_seconds(timelock_delay(deps.storage)?);
+        PENDING_ACTIONS.save(
+            deps.storage,
+            id,
+            &PendingAction {
+                id,
+                action,
+                proposer: info.sender.clone(),
+                eta,
+                funds: info.funds,
+            },
+        )?;
+
+        Ok(Response::new()
+            .add_attribute("method", "propose_action")
+            .add_attribute("action_id", id.to_string())
+            .add_attribute("proposer", info.sender.clone())
+            .add_attribute("eta", eta.seconds().to_string())
+            .add_event(
+                LeverageEvent::ActionProposed {
+                    id,
+                    proposer: info.sender,
+                    eta: eta.seconds(),
+                }
+                .into(),
+            ))
+    }
+
+    /**
+     * Drops a queued action, admins and guardians can veto during the delay.
+     * Escrowed funds go back to the proposer.
+     */
+    pub fn cancel_action(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        id: u64,
+    ) -> Result<Response, ContractError> {
+        if !access_control::has_role(deps.storage, Role::Admin, &info.sender)?
+            && !access_control::has_role(deps.storage, Role::Guardian, &info.sender)?
+        {
+            return Err(ContractError::Unauthorized {});
+        }
+
+        let pending_action = match PENDING_ACTIONS.may_load(deps.storage, id)? {
+            Some(pending_action) => pending_action,
+            None => return Err(ContractError::ActionNotFound { id }),
+        };
+        PENDING_ACTIONS.remove(deps.storage, id);
+
+        let mut response = Response::new()
+            .add_attribute("method", "cancel_action")
+            .add_attribute("action_id", id.to_string())
+            .add_event(LeverageEvent::ActionCancelled { id }.into());
+        if !pending_action.funds.is_empty() {
+            response = response.add_message(BankMsg::Send {
+                to_address: pending_action.proposer.to_string(),
+                amount: pending_action.funds,
+            });
+        }
+        Ok(response)
+    }
+
+    /**
+     * Applies a queued action once its ETA passed, anyone can trigger it.
+     * Funds are only taken when proposing, none can be sent along here.
+     */
+    pub fn execute_action(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        id: u64,
+    ) -> Result<Response, ContractError> {
+        let pending_action = match PENDING_ACTIONS.may_load(deps.storage, id)? {
+            Some(pending_action) => pending_action,
+            None => return Err(ContractError::ActionNotFound { id }),
+        };
+        if !info.funds.is_empty() {
+            return Err(ContractError::UnexpectedFunds {});
+        }
+
+        if env.block.time < pending_action.eta {
+            return Err(ContractError::TimelockNotExpired {
+                eta: pending_action.eta.seconds(),
+            });
+        }
+        if env.block.time > pending_action.eta.plus_seconds(GRACE_PERIOD) {
+            return Err(ContractError::ActionExpired { id });
+        }
+
+        PENDING_ACTIONS.remove(deps.storage, id);
+
+        let response = match pending_action.action {
+            TimelockAction::ListToken {
+                token_address,
+                asset,
+                decimals,
+                symbol,
+            } => token_listing::list_token(deps, env, token_address, asset, decimals, symbol)?,
+            TimelockAction::UpdateBorrowRate {
+                token_address,
+                rate,
+            } => {
+                execute_module::update_borrow_rate(deps, env, Addr::unchecked(token_address), rate)?
+            }
+            TimelockAction::SetPriceSource {
+                token_address,
+                source,
+            } => price_oracle::set_price_source(deps, env, Addr::unchecked(token_address), source)?,
+            TimelockAction::UpdateMarketCaps {
+                token_address,
+                caps,
+            } => {
+                market_module::update_market_caps(deps, env, Addr::unchecked(token_address), caps)?
+            }
+            TimelockAction::UpdateFees { fees } => execute_module::update_fees(deps, env, fees)?,
+            TimelockAction::UpdateTimelockDelay { delay } => {
+                assert_timelock_delay(delay)?;
+                TIMELOCK_DELAY.save(deps.storage, &delay)?;
+                Response::new()
+                    .add_attribute("method", "update_timelock_delay")
+                    .add_attribute("delay", delay.to_string())
+                    .add_event(LeverageEvent::TimelockDelayUpdated { delay }.into())
+            }
+            TimelockAction::SetRewardSchedule {
+                token_address,
+                schedule,
+            } => incentives_module::set_reward_schedule(
+                deps,
+                env,
+                Addr::unchecked(token_address),
+                schedule,
+                escrowed_amount(&pending_action.funds),
+            )?,
+        };
+
+        Ok(response
+            .add_attribute("action_id", id.to_string())
+            .add_event(LeverageEvent::ActionExecuted { id }.into()))
+    }
+
+    pub fn fetch_pending_actions(
+        deps: Deps,
+        _env: Env,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    ) -> StdResult<PendingActionsResponse> {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+        let start = start_after.map(Bound::exclusive);
+
+        let actions = PENDING_ACTIONS
+            .range(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .map(|item| item.map(|(_, pending_action)| pending_action))
+            .collect::<StdResult<Vec<PendingAction>>>()?;
+
+        Ok(PendingActionsResponse {
+            delay: timelock_delay(deps.storage)?,
+            actions,
+        })
+    }
+
+    pub fn fetch_pending_action(deps: Deps, _env: Env, id: u64) -> StdResult<PendingAction> {
+        PENDING_ACTIONS.load(deps.storage, id)
+    }
+}
@@ -0,0 +1,125 @@
+pub mod osmosis_twap {
+    use cosmwasm_schema::cw_serde;
+    use cosmwasm_std::{Binary, Decimal, Deps, Env, QueryRequest, Timestamp};
+
+    use crate::listing::token_listing;
+    use crate::state::{AssetInfo, TokenInfo};
+    use crate::ContractError;
+
+    pub const ARITHMETIC_TWAP_TO_NOW_PATH: &str = "/osmosis.twap.v1beta1.Query/ArithmeticTwapToNow";
+
+    /// Osmosis prunes TWAP records older than 48 hours
+    pub const MAX_TWAP_WINDOW: u64 = 48 * 60 * 60;
+
+    /**
+     * Response of the TWAP query, Osmosis answers Stargate queries in JSON.
+     */
+    #[cw_serde]
+    pub struct ArithmeticTwapToNowResponse {
+        pub arithmetic_twap: Decimal,
+    }
+
+    pub fn validate_source(
+        token_info: &TokenInfo,
+        quote_decimals: u8,
+        window_seconds: u64,
+    ) -> Result<(), ContractError> {
+        if let AssetInfo::Cw20 { .. } = token_info.asset {
+            return Err(ContractError::TwapNeedsNativeToken {
+                token: token_info.token.clone(),
+            });
+        }
+        if window_seconds == 0 || window_seconds > MAX_TWAP_WINDOW {
+            return Err(ContractError::InvalidTwapWindow {});
+        }
+        if quote_decimals > token_listing::MAX_DECIMALS {
+            return Err(ContractError::DecimalsTooLarge {
+                max: token_listing::MAX_DECIMALS,
+            });
+        }
+        Ok(())
+    }
+
+    /**
+     * Arithmetic TWAP of the token's denom in `quote_denom` over the last
+     * `window_seconds`, scaled from base units to one whole token.
+     */
+    pub fn query_price(
+        deps: Deps,
+        env: &Env,
+        token_info: &TokenInfo,
+        pool_id: u64,
+        quote_denom: &str,
+        quote_decimals: u8,
+        window_seconds: u64,
+    ) -> Result<Decimal, ContractError> {
+        let start_time = env.block.time.minus_seconds(window_seconds);
+        let response: ArithmeticTwapToNowResponse =
+            deps.querier.query(&QueryRequest::Stargate {
+                path: String::from(ARITHMETIC_TWAP_TO_NOW_PATH),
+                data: encode_request(
+                    pool_id,
+                    token_info.asset.reference(),
+                    quote_denom,
+                    start_time,
+                ),
+            })?;
+
+        // The TWAP prices one base unit in quote units
+        let twap = response.arithmetic_twap;
+        if token_info.decimals >= quote_decimals {
+            let scale = 10u128.pow((token_info.decimals - quote_decimals) as u32);
+            twap.checked_mul(Decimal::from_ratio(scale, 1u128))
+                .map_err(|_| ContractError::Overflow {})
+        } else {
+            let scale = 10u128.pow((quote_decimals - token_info.decimals) as u32);
+            twap.checked_div(Decimal::from_ratio(scale, 1u128))
+                .map_err(|_| ContractError::Overflow {})
+        }
+    }
+
+    /**
+     * Protobuf encoding of `ArithmeticTwapToNowRequest`.
+     */
+    pub fn encode_request(
+        pool_id: u64,
+        base_asset: &str,
+        quote_asset: &str,
+        start_time: Timestamp,
+    ) -> Binary {
+        let mut timestamp = vec![];
+        encode_varint_field(&mut timestamp, 1, start_time.seconds());
+        encode_varint_field(&mut timestamp, 2, start_time.subsec_nanos());
+
+        let mut request = vec![];
+        encode_varint_field(&mut request, 1, pool_id);
+        encode_bytes_field(&mut request, 2, base_asset.as_bytes());
+        encode_bytes_field(&mut request, 3, quote_asset.as_bytes());
+        encode_bytes_field(&mut request, 4, &timestamp);
+        Binary::from(request)
+    }
+
+    // Default values are left out, like protobuf encoders do
+    fn encode_varint_field(buf: &mut Vec<u8>, field: u8, value: u64) {
+        if value != 0 {
+            buf.push(field << 3);
+            encode_varint(buf, value);
+        }
+    }
+
+    fn encode_bytes_field(buf: &mut Vec<u8>, field: u8, bytes: &[u8]) {
+        if !bytes.is_empty() {
+            buf.push(field << 3 | 2);
+            encode_varint(buf, bytes.len() as u64);
+            buf.extend_from_slice(bytes);
+        }
+    }
+
+    fn encode_varint(buf: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            buf.push((value as u8 & 0x7f) | 0x80);
+            value >>= 7;
+        }
+        buf.push(value as u8);
+    }
+}
@@ -0,0 +1,230 @@
+pub mod valuation_module {
+    use cosmwasm_std::{Addr, Decimal, Decimal256, Deps, Env, Order, StdResult, Uint128, Uint256};
+
+    use crate::error::ContractError;
+    use crate::interest::interest_module;
+    use crate::msg::{AccountHealthResponse, AccountTokenBalance};
+    use crate::oracle::price_oracle;
+    use crate::state::{
+        TokenInfo, LISTED_TOKEN, TOKEN_BALANCE, USER_VTOKEN_BALANCE, WRAPPED_TOKEN_BALANCE,
+        WRAPPED_TOKEN_BORROW_BALANCE,
+    };
+
+    // Equity an account keeps on top of its debt before it can be liquidated
+    pub const MAINTENANCE_MARGIN: Decimal = Decimal::percent(5);
+    // Keeper fills may pay out slightly above the oracle price to absorb price lag
+    pub const ORDER_PRICE_TOLERANCE: Decimal = Decimal::percent(1);
+
+    /**
+     * Value of a raw token amount in the quote currency.
+     *
+     * The amount is normalized by the token's decimals first, so tokens of
+     * different precision are valued on the same scale.
+     */
+    pub fn token_value(
+        amount: Uint128,
+        token_info: &TokenInfo,
+        price: Decimal,
+    ) -> Result<Decimal256, ContractError> {
+        let normalized = Decimal256::from_atomics(amount, token_info.decimals as u32)
+            .map_err(|_| ContractError::Overflow {})?;
+
+        normalized
+            .checked_mul(Decimal256::from(price))
+            .map_err(|_| ContractError::Overflow {})
+    }
+
+    /**
+     * Raw token amount worth `value` in the quote currency, rounded down.
+     */
+    pub fn token_amount(
+        value: Decimal256,
+        token_info: &TokenInfo,
+        price: Decimal,
+    ) -> Result<Uint128, ContractError> {
+        let normalized = value
+            .checked_div(Decimal256::from(price))
+            .map_err(|_| ContractError::Overflow {})?;
+        let scale = Uint256::from(10u128.pow(18 - token_info.decimals as u32));
+
+        Uint128::try_from(normalized.atomics() / scale).map_err(|_| ContractError::Overflow {})
+    }
+
+    /**
+     * Checks that an order doesn't pay out more than the sold tokens are worth,
+     * give or take `tolerance` of their value.
+     */
+    pub fn assert_order_price(
+        deps: Deps,
+        env: &Env,
+        token_in: &TokenInfo,
+        amount_in: Uint128,
+        token_out: &TokenInfo,
+        amount_out: Uint128,
+        tolerance: Decimal,
+    ) -> Result<(), ContractError> {
+        let value_in = token_value(
+            amount_in,
+            token_in,
+            price_oracle::query_price(deps, env, token_in)?,
+        )?;
+        let value_out = token_value(
+            amount_out,
+            token_out,
+            price_oracle::query_price(deps, env, token_out)?,
+        )?;
+
+        let max_value_out = value_in
+            .checked_mul(Decimal256::one() + Decimal256::from(tolerance))
+            .map_err(|_| ContractError::Overflow {})?;
+
+        if value_out > max_value_out {
+            return Err(ContractError::OrderAbovePrice {});
+        }
+        Ok(())
+    }
+
+    /**
+     * Balances and values of the user for every listed token.
+     *
+     * Tokens the user holds nothing of are not priced, so an account is only
+     * exposed to the price sources of the tokens it actually holds.
+     */
+    pub fn account_balances(
+        deps: Deps,
+        env: &Env,
+        user_address: &Addr,
+    ) -> Result<Vec<AccountTokenBalance>, ContractError> {
+        let tokens = LISTED_TOKEN
+            .range(deps.storage, None, None, Order::Ascending)
+            .map(|item| item.map(|(_, token_info)| token_info))
+            .collect::<StdResult<Vec<TokenInfo>>>()?;
+
+        let mut balances = vec![];
+        for token_info in tokens {
+            let token_address = Addr::unchecked(&token_info.token);
+
+            let collateral = TOKEN_BALANCE
+                .may_load(deps.storage, (&token_address, user_address))?
+                .unwrap_or_default();
+            let wrapped = WRAPPED_TOKEN_BALANCE
+                .may_load(deps.storage, (&token_address, user_address))?
+                .unwrap_or_default();
+            let vtokens = USER_VTOKEN_BALANCE
+                .may_load(deps.storage, (&token_address, user_address))?
+                .unwrap_or_default();
+            let borrowed = WRAPPED_TOKEN_BORROW_BALANCE
+                .may_load(deps.storage, (&token_address, user_address))?
+                .unwrap_or_default();
+            let interest = interest_module::pending_interest(
+                deps.storage,
+                &token_address,
+                user_address,
+                env.block.time,
+            )?;
+            let debt = borrowed
+                .checked_add(interest)
+                .map_err(|_| ContractError::Overflow {})?;
+
+            let mut balance = AccountTokenBalance {
+                token: token_info.token.clone(),
+                collateral,
+                wrapped,
+                borrowed,
+                interest,
+                vtokens,
+                collateral_value: Decimal256::zero(),
+                position_value: Decimal256::zero(),
+                debt_value: Decimal256::zero(),
+            };
+
+            if !collateral.is_zero() || !vtokens.is_zero() || !debt.is_zero() {
+                let price = price_oracle::query_price(deps, env, &token_info)?;
+                balance.collateral_value = token_value(collateral, &token_info, price)?;
+                balance.position_value = token_value(vtokens, &token_info, price)?;
+                balance.debt_value = token_value(debt, &token_info, price)?;
+            }
+
+            balances.push(balance);
+        }
+
+        Ok(balances)
+    }
+
+    /**
+     * Values every balance of the user across the listed tokens.
+     */
+    pub fn account_health(
+        deps: Deps,
+        env: &Env,
+        user_address: &Addr,
+    ) -> Result<AccountHealthResponse, ContractError> {
+        health_of(&account_balances(deps, env, user_address)?)
+    }
+
+    /**
+     * Sums up the token values of an account into its health.
+     */
+    pub fn health_of(
+        balances: &[AccountTokenBalance],
+    ) -> Result<AccountHealthResponse, ContractError> {
+        let mut collateral_value = Decimal256::zero();
+        let mut position_value = Decimal256::zero();
+        let mut debt_value = Decimal256::zero();
+
+        for balance in balances {
+            collateral_value = add_value(collateral_value, balance.collateral_value)?;
+            position_value = add_value(position_value, balance.position_value)?;
+            debt_value = add_value(debt_value, balance.debt_value)?;
+        }
+
+        let assets_value = add_value(collateral_value, position_value)?;
+
+        let (health_factor, leverage) = if debt_value.is_zero() {
+            (None, None)
+        } else {
+            let required_value = debt_value
+                .checked_mul(Decimal256::one() + Decimal256::from(MAINTENANCE_MARGIN))
+                .map_err(|_| ContractError::Overflow {})?;
+            let health_factor = assets_value
+                .checked_div(required_value)
+                .map_err(|_| ContractError::Overflow {})?;
+
+            // Without equity left the leverage is unbounded
+            let leverage = match assets_value.checked_sub(debt_value) {
+                Ok(equity) if !equity.is_zero() => debt_value
+                    .checked_div(equity)
+                    .map_err(|_| ContractError::Overflow {})?,
+                _ => Decimal256::MAX,
+            };
+
+            (Some(health_factor), Some(leverage))
+        };
+
+        Ok(AccountHealthResponse {
+            collateral_value,
+            position_value,
+            debt_value,
+            health_factor,
+            leverage,
+        })
+    }
+
+    /**
+     * Fails when the account's assets don't cover its debt plus the maintenance margin.
+     */
+    pub fn assert_healthy(deps: Deps, env: &Env, user_address: &Addr) -> Result<(), ContractError> {
+        match account_health(deps, env, user_address)?.health_factor {
+            Some(health_factor) if health_factor < Decimal256::one() => {
+                Err(ContractError::UnhealthyAccount {})
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn add_value(value: Decimal256, other: Decimal256) -> Result<Decimal256, ContractError> {
+        value
+            .checked_add(other)
+            .map_err(|_| ContractError::Overflow {})
+    }
+}
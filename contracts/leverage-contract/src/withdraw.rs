@@ -1,39 +1,125 @@
 pub mod withdraw_collateral {
     use cosmwasm_std::{
-        to_json_binary, BankMsg, Coin, CosmosMsg, DepsMut, Empty, Env, MessageInfo, Response,
-        Uint128, WasmMsg,
+        to_json_binary, Addr, BankMsg, Coin, CosmosMsg, DepsMut, Empty, Env, MessageInfo, Response,
+        Storage, Timestamp, Uint128, WasmMsg,
     };
-    
+
+    use crate::access::access_control;
     use crate::error::ContractError;
+    use crate::events::leverage_events::LeverageEvent;
+    use crate::incentives::incentives_module;
+    use crate::interest::interest_module;
+    use crate::listing::token_listing;
+    use crate::market::market_module;
     use crate::msg::WithdrawData;
-    use crate::state::{TOKEN_BALANCE, WRAPPED_TOKEN_BALANCE, WRAPPED_TOKEN_BORROW_BALANCE};
+    use crate::state::{
+        AssetInfo, TOKEN_BALANCE, TOTAL_COLLATERAL, TOTAL_WRAPPED, WRAPPED_TOKEN_BALANCE,
+        WRAPPED_TOKEN_BORROW_BALANCE,
+    };
+    use crate::sub_account::sub_accounts;
+    use crate::valuation::valuation_module;
 
     pub fn withdraw(
         deps: DepsMut,
-        _env: Env,
+        env: Env,
         info: MessageInfo,
         withdraw_data: WithdrawData,
     ) -> Result<Response, ContractError> {
+        access_control::assert_not_paused(deps.storage)?;
+
+        if withdraw_data.token_amount.is_zero() {
+            return Err(ContractError::ZeroAmount {});
+        }
+
+        let (collateral_balance, wrapped_balance) = debit_collateral(
+            deps.storage,
+            env.block.time,
+            &withdraw_data.token_address,
+            &info.sender,
+            withdraw_data.token_amount,
+        )?;
+        let remove_wrapped_token = withdraw_data
+            .token_amount
+            .checked_mul(Uint128::from(10u128))
+            .unwrap_or_default();
+
+        market_module::decrease_total(
+            deps.storage,
+            &TOTAL_COLLATERAL,
+            &withdraw_data.token_address,
+            withdraw_data.token_amount,
+        )?;
+        market_module::decrease_total(
+            deps.storage,
+            &TOTAL_WRAPPED,
+            &withdraw_data.token_address,
+            remove_wrapped_token,
+        )?;
+
+        // Debt in other tokens must stay covered by the remaining collateral
+        valuation_module::assert_healthy(deps.as_ref(), &env, &info.sender)?;
+
+        let token_info = token_listing::load_token(deps.storage, &withdraw_data.token_address)?;
+        let cosmos_msg = transfer_msg(
+            &token_info.asset,
+            withdraw_data.token_amount,
+            &sub_accounts::owner_of(&info.sender),
+        )?;
+
+        Ok(Response::new()
+            .add_attribute("method", "token_withdraw")
+            .add_attribute("token_address", withdraw_data.token_address.clone())
+            .add_attribute("user", info.sender.clone())
+            .add_event(
+                LeverageEvent::Withdraw {
+                    user: info.sender,
+                    token: withdraw_data.token_address.to_string(),
+                    amount: withdraw_data.token_amount,
+                    collateral_balance,
+                    wrapped_balance,
+                }
+                .into(),
+            )
+            .add_message(cosmos_msg))
+    }
+
+    /**
+     * Takes collateral and its wrapped tokens off the user's balances, without
+     * touching the market totals.
+     *
+     * The user can't owe the token, since the wrapped tokens backing a debt
+     * can't leave the account.
+     *
+     * @return The new collateral and wrapped token balances.
+     */
+    pub fn debit_collateral(
+        storage: &mut dyn Storage,
+        now: Timestamp,
+        token_address: &Addr,
+        user_address: &Addr,
+        amount: Uint128,
+    ) -> Result<(Uint128, Uint128), ContractError> {
         // Load the user's borrow balance from storage
-        let user_borrow_balance = match WRAPPED_TOKEN_BORROW_BALANCE
-            .may_load(deps.storage, (&withdraw_data.token_address, &info.sender))
-        {
-            Ok(opt_data) => match opt_data {
-                Some(data) => data,
-                None => Uint128::zero(),
-            },
-            Err(_) => return Err(ContractError::BorrowBalanceLoadError {}),
-        };
+        let user_borrow_balance =
+            match WRAPPED_TOKEN_BORROW_BALANCE.may_load(storage, (token_address, user_address)) {
+                Ok(opt_data) => match opt_data {
+                    Some(data) => data,
+                    None => Uint128::zero(),
+                },
+                Err(_) => return Err(ContractError::BorrowBalanceLoadError {}),
+            };
 
         // Check if the user has any borrow balance
-        if user_borrow_balance.gt(&Uint128::zero()) {
+        // Interest still owed counts as borrowed as well
+        let user_borrow_interest =
+            interest_module::pending_interest(storage, token_address, user_address, now)?;
+
+        if user_borrow_balance.gt(&Uint128::zero()) || user_borrow_interest.gt(&Uint128::zero()) {
             return Err(ContractError::BorrowAmountIsNotZero {});
         }
 
         // Load user's token balance
-        let token_balance = match TOKEN_BALANCE
-            .may_load(deps.storage, (&withdraw_data.token_address, &info.sender))
-        {
+        let token_balance = match TOKEN_BALANCE.may_load(storage, (token_address, user_address)) {
             Ok(opt_balance) => match opt_balance {
                 Some(balance) => balance,
                 None => Uint128::zero(),
@@ -46,17 +132,19 @@ pub mod withdraw_collateral {
         };
 
         // Check if user has sufficient balance for withdrawal
-        if token_balance.lt(&withdraw_data.token_amount) {
+        if token_balance.lt(&amount) {
             return Err(ContractError::InsufficientBalance {});
         }
 
+        incentives_module::update_user_rewards(storage, token_address, user_address, now)?;
+
         // Update user's token balance
-        TOKEN_BALANCE.update(
-            deps.storage,
-            (&withdraw_data.token_address, &info.sender),
+        let collateral_balance = TOKEN_BALANCE.update(
+            storage,
+            (token_address, user_address),
             |opt_balance| -> Result<Uint128, ContractError> {
                 match opt_balance {
-                    Some(balance) => match balance.checked_sub(withdraw_data.token_amount) {
+                    Some(balance) => match balance.checked_sub(amount) {
                         Ok(data) => Ok(data),
                         Err(_) => Err(ContractError::Overflow {}),
                     },
@@ -66,18 +154,15 @@ pub mod withdraw_collateral {
         )?;
 
         // Calculate the amount of unminted tokens to remove
-        let remove_wrapped_token = match withdraw_data
-            .token_amount
-            .checked_mul(Uint128::from(10u128))
-        {
+        let remove_wrapped_token = match amount.checked_mul(Uint128::from(10u128)) {
             Ok(data) => data,
             Err(_) => Uint128::zero(),
         };
 
         // Update user's unminted token balance
-        WRAPPED_TOKEN_BALANCE.update(
-            deps.storage,
-            (&withdraw_data.token_address, &info.sender),
+        let wrapped_balance = WRAPPED_TOKEN_BALANCE.update(
+            storage,
+            (token_address, user_address),
             |opt_balance| -> Result<Uint128, ContractError> {
                 match opt_balance {
                     Some(balance) => match balance.checked_sub(remove_wrapped_token) {
@@ -89,34 +174,33 @@ pub mod withdraw_collateral {
             },
         )?;
 
-        let cosmos_msg: CosmosMsg<Empty> =
-            if withdraw_data.withdraw_type == String::from("fungible") {
-                CosmosMsg::Wasm(WasmMsg::Execute {
-                    contract_addr: withdraw_data.usdc.unwrap(),
-                    msg: to_json_binary(&cw20::Cw20ExecuteMsg::Transfer {
-                        recipient: info.sender.to_string(),
-                        amount: withdraw_data.token_amount,
-                    })?,
-                    funds: vec![],
-                })
-            } else if withdraw_data.withdraw_type == String::from("native") {
-                CosmosMsg::Bank(BankMsg::Send {
-                    to_address: info.sender.to_string(),
-                    amount: vec![Coin {
-                        denom: withdraw_data.native.unwrap(),
-                        amount: withdraw_data.token_amount,
-                    }],
-                })
-            } else {
-                return Err(ContractError::GenericError {
-                    error: String::from("Undefined withdraw type"),
-                });
-            };
+        Ok((collateral_balance, wrapped_balance))
+    }
 
-        Ok(Response::new()
-            .add_attribute("method", "token_withdraw")
-            .add_attribute("withdraw_data.token_address", withdraw_data.token_address)
-            .add_attribute("user", info.sender)
-            .add_message(cosmos_msg))
+    /**
+     * Builds the message sending `amount` of the asset to the recipient.
+     */
+    pub fn transfer_msg(
+        asset: &AssetInfo,
+        amount: Uint128,
+        recipient: &Addr,
+    ) -> Result<CosmosMsg<Empty>, ContractError> {
+        match asset {
+            AssetInfo::Cw20 { contract_addr } => Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: contract_addr.to_string(),
+                msg: to_json_binary(&cw20::Cw20ExecuteMsg::Transfer {
+                    recipient: recipient.to_string(),
+                    amount,
+                })?,
+                funds: vec![],
+            })),
+            AssetInfo::Native { denom } => Ok(CosmosMsg::Bank(BankMsg::Send {
+                to_address: recipient.to_string(),
+                amount: vec![Coin {
+                    denom: denom.clone(),
+                    amount,
+                }],
+            })),
+        }
     }
 }
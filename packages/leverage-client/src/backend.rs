@@ -0,0 +1,70 @@
+pub mod client_backend {
+    use cosmwasm_std::{Binary, Coin, Event};
+
+    use crate::error::ClientError;
+
+    /**
+     * A message for a contract, ready to be signed by any backend.
+     */
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct ContractCall {
+        pub contract: String,
+        pub msg: Binary,
+        pub funds: Vec<Coin>,
+    }
+
+    /**
+     * Outcome of an executed message, with the events as the chain reports them.
+     */
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct TxResult {
+        // Not known for in-process backends
+        pub tx_hash: Option<String>,
+        pub events: Vec<Event>,
+    }
+
+    impl TxResult {
+        /**
+         * First `wasm-leverage-<kind>` event of the transaction.
+         */
+        pub fn leverage_event(&self, kind: &str) -> Option<&Event> {
+            let ty = format!("wasm-leverage-{kind}");
+            self.events.iter().find(|event| event.ty == ty)
+        }
+
+        pub fn leverage_attribute(&self, kind: &str, key: &str) -> Option<&str> {
+            self.leverage_event(kind)?
+                .attributes
+                .iter()
+                .find(|attr| attr.key == key)
+                .map(|attr| attr.value.as_str())
+        }
+    }
+
+    /**
+     * Signs, broadcasts and queries contract messages.
+     *
+     * Senders are plain strings, an address for in-process backends and a key
+     * name for chain backends.
+     */
+    pub trait Backend {
+        fn execute(&mut self, sender: &str, call: ContractCall) -> Result<TxResult, ClientError>;
+
+        /**
+         * @return The address of the new contract and the instantiate transaction.
+         */
+        fn instantiate(
+            &mut self,
+            sender: &str,
+            code_id: u64,
+            msg: Binary,
+            funds: Vec<Coin>,
+            label: &str,
+        ) -> Result<(String, TxResult), ClientError>;
+
+        /**
+         * Runs a smart query, returning the raw JSON response of the contract.
+         */
+        fn query(&self, contract: &str, msg: Binary) -> Result<Binary, ClientError>;
+    }
+}
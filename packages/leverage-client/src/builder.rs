@@ -0,0 +1,259 @@
+pub mod msg_builder {
+    use cosmwasm_std::{to_json_binary, Addr, Coin, Uint128};
+    use cw20::Cw20ExecuteMsg;
+    use leverage_contract::msg::{Cw20HookMsg, ExecuteMsg, OrderExecute, TokenData};
+
+    use crate::backend::client_backend::ContractCall;
+    use crate::error::ClientError;
+
+    /**
+     * Anything which turns into a call, given the leverage contract address.
+     */
+    pub trait LeverageAction {
+        fn into_call(self, contract: &str) -> Result<ContractCall, ClientError>;
+    }
+
+    // Raw messages go to the leverage contract without funds
+    impl LeverageAction for ExecuteMsg {
+        fn into_call(self, contract: &str) -> Result<ContractCall, ClientError> {
+            Ok(ContractCall {
+                contract: contract.to_string(),
+                msg: to_json_binary(&self)?,
+                funds: vec![],
+            })
+        }
+    }
+
+    /**
+     * Deposits native tokens as collateral of a listed token.
+     */
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Deposit {
+        pub token_address: String,
+        pub funds: Vec<Coin>,
+        pub referral_code: Option<String>,
+        pub account_id: Option<u64>,
+    }
+
+    impl Deposit {
+        pub fn native(token_address: impl Into<String>, coin: Coin) -> Self {
+            Deposit {
+                token_address: token_address.into(),
+                funds: vec![coin],
+                referral_code: None,
+                account_id: None,
+            }
+        }
+
+        /// Only binds the referrer when this is the user's first deposit
+        pub fn referral_code(mut self, code: impl Into<String>) -> Self {
+            self.referral_code = Some(code.into());
+            self
+        }
+
+        pub fn account_id(mut self, account_id: u64) -> Self {
+            self.account_id = Some(account_id);
+            self
+        }
+    }
+
+    impl LeverageAction for Deposit {
+        fn into_call(self, contract: &str) -> Result<ContractCall, ClientError> {
+            Ok(ContractCall {
+                contract: contract.to_string(),
+                msg: to_json_binary(&ExecuteMsg::DepositNative {
+                    token_address: self.token_address,
+                    referral_code: self.referral_code,
+                    account_id: self.account_id,
+                })?,
+                funds: self.funds,
+            })
+        }
+    }
+
+    /**
+     * A CW20 `Send` to the leverage contract, carrying a hook payload.
+     *
+     * The call goes to the CW20 contract, which forwards the tokens.
+     */
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Cw20Send {
+        pub cw20_contract: String,
+        pub amount: Uint128,
+        pub hook: Cw20HookMsg,
+    }
+
+    impl Cw20Send {
+        pub fn deposit(cw20_contract: impl Into<String>, amount: impl Into<Uint128>) -> Self {
+            Cw20Send {
+                cw20_contract: cw20_contract.into(),
+                amount: amount.into(),
+                hook: Cw20HookMsg::Deposit {
+                    referral_code: None,
+                    account_id: None,
+                },
+            }
+        }
+
+        pub fn repay(cw20_contract: impl Into<String>, amount: impl Into<Uint128>) -> Self {
+            Cw20Send {
+                cw20_contract: cw20_contract.into(),
+                amount: amount.into(),
+                hook: Cw20HookMsg::Repay { account_id: None },
+            }
+        }
+
+        pub fn top_up(cw20_contract: impl Into<String>, amount: impl Into<Uint128>) -> Self {
+            Cw20Send {
+                cw20_contract: cw20_contract.into(),
+                amount: amount.into(),
+                hook: Cw20HookMsg::TopUp { account_id: None },
+            }
+        }
+
+        pub fn liquidate(
+            cw20_contract: impl Into<String>,
+            amount: impl Into<Uint128>,
+            user_address: impl Into<String>,
+            collateral_token: impl Into<String>,
+        ) -> Self {
+            Cw20Send {
+                cw20_contract: cw20_contract.into(),
+                amount: amount.into(),
+                hook: Cw20HookMsg::Liquidate {
+                    user_address: user_address.into(),
+                    collateral_token: collateral_token.into(),
+                    account_id: None,
+                },
+            }
+        }
+    }
+
+    impl LeverageAction for Cw20Send {
+        fn into_call(self, contract: &str) -> Result<ContractCall, ClientError> {
+            Ok(ContractCall {
+                contract: self.cw20_contract,
+                msg: to_json_binary(&Cw20ExecuteMsg::Send {
+                    contract: contract.to_string(),
+                    amount: self.amount,
+                    msg: to_json_binary(&self.hook)?,
+                })?,
+                funds: vec![],
+            })
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Borrow {
+        pub token_address: String,
+        pub amount: Uint128,
+        pub account_id: Option<u64>,
+        pub on_behalf_of: Option<String>,
+    }
+
+    impl Borrow {
+        pub fn new(token_address: impl Into<String>, amount: impl Into<Uint128>) -> Self {
+            Borrow {
+                token_address: token_address.into(),
+                amount: amount.into(),
+                account_id: None,
+                on_behalf_of: None,
+            }
+        }
+
+        pub fn account_id(mut self, account_id: u64) -> Self {
+            self.account_id = Some(account_id);
+            self
+        }
+
+        /// Borrows as an operator approved by `owner`
+        pub fn on_behalf_of(mut self, owner: impl Into<String>) -> Self {
+            self.on_behalf_of = Some(owner.into());
+            self
+        }
+    }
+
+    impl LeverageAction for Borrow {
+        fn into_call(self, contract: &str) -> Result<ContractCall, ClientError> {
+            ExecuteMsg::Borrow(TokenData {
+                token_address: Addr::unchecked(self.token_address),
+                token_amount: self.amount,
+                account_id: self.account_id,
+                on_behalf_of: self.on_behalf_of.map(Addr::unchecked),
+            })
+            .into_call(contract)
+        }
+    }
+
+    /**
+     * Swaps vTokens of `user_address`, reads as "sell `amount_in` of
+     * `token_in` for `amount_out` of `token_out`".
+     */
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct Order {
+        order_id: String,
+        user_address: Option<String>,
+        sell: Option<(String, Uint128)>,
+        buy: Option<(String, Uint128)>,
+        min_out: Option<Uint128>,
+    }
+
+    impl Order {
+        pub fn new(order_id: impl Into<String>) -> Self {
+            Order {
+                order_id: order_id.into(),
+                ..Order::default()
+            }
+        }
+
+        pub fn user(mut self, user_address: impl Into<String>) -> Self {
+            self.user_address = Some(user_address.into());
+            self
+        }
+
+        pub fn sell(mut self, token_in: impl Into<String>, amount_in: impl Into<Uint128>) -> Self {
+            self.sell = Some((token_in.into(), amount_in.into()));
+            self
+        }
+
+        pub fn buy(mut self, token_out: impl Into<String>, amount_out: impl Into<Uint128>) -> Self {
+            self.buy = Some((token_out.into(), amount_out.into()));
+            self
+        }
+
+        /**
+         * Least `token_out` the user accepts after the trading fee.
+         */
+        pub fn min_out(mut self, min_out: impl Into<Uint128>) -> Self {
+            self.min_out = Some(min_out.into());
+            self
+        }
+
+        pub fn build(self) -> Result<OrderExecute, ClientError> {
+            let missing = |field: &str| ClientError::IncompleteOrder {
+                missing: field.to_string(),
+            };
+
+            let user_address = self.user_address.ok_or_else(|| missing("user"))?;
+            let (token_in, amount_in) = self.sell.ok_or_else(|| missing("sell side"))?;
+            let (token_out, amount_out) = self.buy.ok_or_else(|| missing("buy side"))?;
+
+            Ok(OrderExecute {
+                order_id: self.order_id,
+                user_address: Addr::unchecked(user_address),
+                token_in: Addr::unchecked(token_in),
+                token_out: Addr::unchecked(token_out),
+                amount_in,
+                amount_out,
+                account_id: None,
+                min_out: self.min_out,
+            })
+        }
+    }
+
+    impl LeverageAction for Order {
+        fn into_call(self, contract: &str) -> Result<ContractCall, ClientError> {
+            ExecuteMsg::ExecuteOrder(self.build()?).into_call(contract)
+        }
+    }
+}
@@ -0,0 +1,318 @@
+pub mod chain_backend {
+    use std::process::Command;
+    use std::thread;
+    use std::time::Duration;
+
+    use cosmwasm_std::{to_json_binary, Attribute, Binary, Coin, Event};
+    use serde_json::Value;
+
+    use crate::backend::client_backend::{Backend, ContractCall, TxResult};
+    use crate::error::ClientError;
+
+    /**
+     * Runs the client against a chain through its node binary, e.g. `wasmd`
+     * or `osmosisd`.
+     *
+     * Senders are key names of the configured keyring.
+     */
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct ChainCliBackend {
+        pub binary: String,
+        pub node: String,
+        pub chain_id: String,
+        pub gas_prices: String,
+        pub gas_adjustment: String,
+        pub keyring_backend: String,
+        pub home: Option<String>,
+        // Waiting for a broadcast transaction to land in a block
+        pub poll_interval: Duration,
+        pub max_polls: u32,
+    }
+
+    impl ChainCliBackend {
+        pub fn new(
+            binary: impl Into<String>,
+            node: impl Into<String>,
+            chain_id: impl Into<String>,
+        ) -> Self {
+            ChainCliBackend {
+                binary: binary.into(),
+                node: node.into(),
+                chain_id: chain_id.into(),
+                gas_prices: String::from("0.025uosmo"),
+                gas_adjustment: String::from("1.3"),
+                keyring_backend: String::from("test"),
+                home: None,
+                poll_interval: Duration::from_secs(1),
+                max_polls: 30,
+            }
+        }
+
+        pub fn gas_prices(mut self, gas_prices: impl Into<String>) -> Self {
+            self.gas_prices = gas_prices.into();
+            self
+        }
+
+        pub fn keyring_backend(mut self, keyring_backend: impl Into<String>) -> Self {
+            self.keyring_backend = keyring_backend.into();
+            self
+        }
+
+        pub fn home(mut self, home: impl Into<String>) -> Self {
+            self.home = Some(home.into());
+            self
+        }
+
+        pub fn execute_args(&self, sender: &str, call: &ContractCall) -> Vec<String> {
+            let mut args = vec![
+                String::from("tx"),
+                String::from("wasm"),
+                String::from("execute"),
+                call.contract.clone(),
+                String::from_utf8_lossy(call.msg.as_slice()).into_owned(),
+            ];
+            if let Some(amount) = funds_arg(&call.funds) {
+                args.extend([String::from("--amount"), amount]);
+            }
+            args.extend(self.tx_flags(sender));
+            args
+        }
+
+        pub fn instantiate_args(
+            &self,
+            sender: &str,
+            code_id: u64,
+            msg: &Binary,
+            funds: &[Coin],
+            label: &str,
+        ) -> Vec<String> {
+            let mut args = vec![
+                String::from("tx"),
+                String::from("wasm"),
+                String::from("instantiate"),
+                code_id.to_string(),
+                String::from_utf8_lossy(msg.as_slice()).into_owned(),
+                String::from("--label"),
+                label.to_string(),
+                String::from("--no-admin"),
+            ];
+            if let Some(amount) = funds_arg(funds) {
+                args.extend([String::from("--amount"), amount]);
+            }
+            args.extend(self.tx_flags(sender));
+            args
+        }
+
+        pub fn query_args(&self, contract: &str, msg: &Binary) -> Vec<String> {
+            let mut args = vec![
+                String::from("query"),
+                String::from("wasm"),
+                String::from("contract-state"),
+                String::from("smart"),
+                contract.to_string(),
+                String::from_utf8_lossy(msg.as_slice()).into_owned(),
+            ];
+            args.extend(self.query_flags());
+            args
+        }
+
+        fn query_flags(&self) -> Vec<String> {
+            let mut flags = vec![
+                String::from("--node"),
+                self.node.clone(),
+                String::from("--output"),
+                String::from("json"),
+            ];
+            if let Some(home) = &self.home {
+                flags.extend([String::from("--home"), home.clone()]);
+            }
+            flags
+        }
+
+        fn tx_flags(&self, sender: &str) -> Vec<String> {
+            let mut flags = vec![
+                String::from("--from"),
+                sender.to_string(),
+                String::from("--chain-id"),
+                self.chain_id.clone(),
+                String::from("--gas"),
+                String::from("auto"),
+                String::from("--gas-adjustment"),
+                self.gas_adjustment.clone(),
+                String::from("--gas-prices"),
+                self.gas_prices.clone(),
+                String::from("--keyring-backend"),
+                self.keyring_backend.clone(),
+                String::from("--broadcast-mode"),
+                String::from("sync"),
+                String::from("-y"),
+            ];
+            flags.extend(self.query_flags());
+            flags
+        }
+
+        fn run(&self, args: &[String]) -> Result<Value, ClientError> {
+            let output = Command::new(&self.binary).args(args).output()?;
+            if !output.status.success() {
+                return Err(ClientError::Command {
+                    status: output.status.to_string(),
+                    stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+                });
+            }
+            Ok(serde_json::from_slice(&output.stdout)?)
+        }
+
+        /**
+         * Broadcasts a transaction and waits until it's part of a block.
+         */
+        fn broadcast(&self, args: &[String]) -> Result<TxResult, ClientError> {
+            let tx = self.run(args)?;
+            assert_tx_success(&tx)?;
+            let tx_hash = match tx.get("txhash").and_then(Value::as_str) {
+                Some(tx_hash) => tx_hash.to_string(),
+                None => {
+                    return Err(ClientError::Execute {
+                        msg: String::from("broadcast returned no txhash"),
+                    })
+                }
+            };
+
+            let mut query_args = vec![String::from("query"), String::from("tx"), tx_hash.clone()];
+            query_args.extend(self.query_flags());
+            for _ in 0..self.max_polls {
+                thread::sleep(self.poll_interval);
+
+                // The node answers with an error until the transaction is included
+                if let Ok(tx) = self.run(&query_args) {
+                    assert_tx_success(&tx)?;
+                    return Ok(TxResult {
+                        tx_hash: Some(tx_hash),
+                        events: tx_events(&tx),
+                    });
+                }
+            }
+
+            Err(ClientError::TxTimeout { tx_hash })
+        }
+    }
+
+    impl Backend for ChainCliBackend {
+        fn execute(&mut self, sender: &str, call: ContractCall) -> Result<TxResult, ClientError> {
+            self.broadcast(&self.execute_args(sender, &call))
+        }
+
+        fn instantiate(
+            &mut self,
+            sender: &str,
+            code_id: u64,
+            msg: Binary,
+            funds: Vec<Coin>,
+            label: &str,
+        ) -> Result<(String, TxResult), ClientError> {
+            let result =
+                self.broadcast(&self.instantiate_args(sender, code_id, &msg, &funds, label))?;
+
+            let contract = result
+                .events
+                .iter()
+                .filter(|event| event.ty == "instantiate")
+                .flat_map(|event| event.attributes.iter())
+                .find(|attr| attr.key == "_contract_address")
+                .map(|attr| attr.value.clone())
+                .ok_or(ClientError::MissingContractAddress {})?;
+
+            Ok((contract, result))
+        }
+
+        fn query(&self, contract: &str, msg: Binary) -> Result<Binary, ClientError> {
+            let response = self.run(&self.query_args(contract, &msg))?;
+            match response.get("data") {
+                Some(data) => Ok(to_json_binary(data)?),
+                None => Err(ClientError::Query {
+                    msg: response.to_string(),
+                }),
+            }
+        }
+    }
+
+    /**
+     * Formats funds the way the `--amount` flag expects, e.g. `100uosmo,5uatom`.
+     */
+    pub fn funds_arg(funds: &[Coin]) -> Option<String> {
+        if funds.is_empty() {
+            return None;
+        }
+        Some(
+            funds
+                .iter()
+                .map(|coin| format!("{}{}", coin.amount, coin.denom))
+                .collect::<Vec<String>>()
+                .join(","),
+        )
+    }
+
+    fn assert_tx_success(tx: &Value) -> Result<(), ClientError> {
+        match tx.get("code").and_then(Value::as_u64).unwrap_or_default() {
+            0 => Ok(()),
+            code => Err(ClientError::TxFailed {
+                code,
+                log: tx
+                    .get("raw_log")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+            }),
+        }
+    }
+
+    /**
+     * Events of a `TxResponse`, from the message logs of older nodes or the
+     * top-level events of newer ones.
+     */
+    pub fn tx_events(tx: &Value) -> Vec<Event> {
+        let log_events: Vec<&Value> = tx
+            .get("logs")
+            .and_then(Value::as_array)
+            .map(|logs| {
+                logs.iter()
+                    .filter_map(|log| log.get("events").and_then(Value::as_array))
+                    .flatten()
+                    .collect()
+            })
+            .unwrap_or_default();
+        let events = if log_events.is_empty() {
+            tx.get("events")
+                .and_then(Value::as_array)
+                .map(|events| events.iter().collect())
+                .unwrap_or_default()
+        } else {
+            log_events
+        };
+
+        events
+            .into_iter()
+            .filter_map(|event| {
+                let ty = event.get("type").and_then(Value::as_str)?;
+                let mut chain_event = Event::new(ty);
+                // Built directly, the chain reports reserved keys like `_contract_address`
+                chain_event.attributes = event
+                    .get("attributes")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|attr| {
+                        Some(Attribute {
+                            key: attr.get("key").and_then(Value::as_str)?.to_string(),
+                            value: attr
+                                .get("value")
+                                .and_then(Value::as_str)
+                                .unwrap_or_default()
+                                .to_string(),
+                        })
+                    })
+                    .collect();
+                Some(chain_event)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,356 @@
+pub mod leverage_client {
+    use cosmwasm_std::{from_json, to_json_binary, Addr, Coin, Uint128};
+    use cw20::Expiration;
+    use leverage_contract::msg::{
+        AccountHealthResponse, ConfigResponse, ExecuteMsg, InstantiateMsg,
+        LiquidatableAccountsResponse, MarketStateResponse, MarketsResponse, OrderExecute,
+        PendingRewardsResponse, PriceResponse, QueryMsg, QueryTokenData, ReferralStatsResponse,
+        SimulationResponse, TokenData, UserAccountResponse, WithdrawData,
+    };
+    use leverage_contract::state::{OperatorPermission, OrderState, TokenInfo};
+    use serde::de::DeserializeOwned;
+
+    use crate::backend::client_backend::{Backend, TxResult};
+    use crate::builder::msg_builder::{Borrow, Deposit, LeverageAction, Order};
+    use crate::error::ClientError;
+
+    /**
+     * Typed access to one deployed leverage contract.
+     */
+    pub struct LeverageClient<B: Backend> {
+        backend: B,
+        contract: String,
+    }
+
+    impl<B: Backend> LeverageClient<B> {
+        pub fn new(backend: B, contract: impl Into<String>) -> Self {
+            LeverageClient {
+                backend,
+                contract: contract.into(),
+            }
+        }
+
+        /**
+         * Instantiates a stored leverage code and returns a client for the new contract.
+         */
+        pub fn instantiate(
+            mut backend: B,
+            sender: &str,
+            code_id: u64,
+            msg: &InstantiateMsg,
+            label: &str,
+        ) -> Result<Self, ClientError> {
+            let (contract, _) =
+                backend.instantiate(sender, code_id, to_json_binary(msg)?, vec![], label)?;
+            Ok(LeverageClient::new(backend, contract))
+        }
+
+        pub fn contract(&self) -> &str {
+            &self.contract
+        }
+
+        pub fn backend(&self) -> &B {
+            &self.backend
+        }
+
+        pub fn backend_mut(&mut self) -> &mut B {
+            &mut self.backend
+        }
+
+        pub fn execute<A: LeverageAction>(
+            &mut self,
+            sender: &str,
+            action: A,
+        ) -> Result<TxResult, ClientError> {
+            let call = action.into_call(&self.contract)?;
+            self.backend.execute(sender, call)
+        }
+
+        pub fn deposit_native(
+            &mut self,
+            sender: &str,
+            token_address: &str,
+            coin: Coin,
+        ) -> Result<TxResult, ClientError> {
+            self.execute(sender, Deposit::native(token_address, coin))
+        }
+
+        pub fn borrow(
+            &mut self,
+            sender: &str,
+            token_address: &str,
+            amount: impl Into<Uint128>,
+        ) -> Result<TxResult, ClientError> {
+            self.execute(sender, Borrow::new(token_address, amount))
+        }
+
+        pub fn execute_order(
+            &mut self,
+            sender: &str,
+            order: Order,
+        ) -> Result<TxResult, ClientError> {
+            self.execute(sender, order)
+        }
+
+        pub fn repay(
+            &mut self,
+            sender: &str,
+            token_address: &str,
+            amount: impl Into<Uint128>,
+        ) -> Result<TxResult, ClientError> {
+            self.execute(sender, ExecuteMsg::Repay(token_data(token_address, amount)))
+        }
+
+        pub fn burn(
+            &mut self,
+            sender: &str,
+            token_address: &str,
+            amount: impl Into<Uint128>,
+        ) -> Result<TxResult, ClientError> {
+            self.execute(sender, ExecuteMsg::Burn(token_data(token_address, amount)))
+        }
+
+        pub fn withdraw(
+            &mut self,
+            sender: &str,
+            token_address: &str,
+            amount: impl Into<Uint128>,
+        ) -> Result<TxResult, ClientError> {
+            self.execute(
+                sender,
+                ExecuteMsg::WithdrawToken(WithdrawData {
+                    token_address: Addr::unchecked(token_address),
+                    token_amount: amount.into(),
+                    account_id: None,
+                }),
+            )
+        }
+
+        pub fn claim_rewards(&mut self, sender: &str) -> Result<TxResult, ClientError> {
+            self.execute(sender, ExecuteMsg::ClaimRewards { account_id: None })
+        }
+
+        pub fn register_referral_code(
+            &mut self,
+            sender: &str,
+            code: &str,
+        ) -> Result<TxResult, ClientError> {
+            self.execute(
+                sender,
+                ExecuteMsg::RegisterReferralCode {
+                    code: code.to_string(),
+                },
+            )
+        }
+
+        pub fn claim_referral_rebates(&mut self, sender: &str) -> Result<TxResult, ClientError> {
+            self.execute(sender, ExecuteMsg::ClaimReferralRebates {})
+        }
+
+        /**
+         * Lets `operator`, e.g. a trading bot, act on the sender's accounts
+         * with the given permissions until `expires`.
+         */
+        pub fn approve_operator(
+            &mut self,
+            sender: &str,
+            operator: &str,
+            permissions: Vec<OperatorPermission>,
+            expires: Option<Expiration>,
+        ) -> Result<TxResult, ClientError> {
+            self.execute(
+                sender,
+                ExecuteMsg::ApproveOperator {
+                    operator: operator.to_string(),
+                    permissions,
+                    expires,
+                },
+            )
+        }
+
+        pub fn revoke_operator(
+            &mut self,
+            sender: &str,
+            operator: &str,
+        ) -> Result<TxResult, ClientError> {
+            self.execute(
+                sender,
+                ExecuteMsg::RevokeOperator {
+                    operator: operator.to_string(),
+                },
+            )
+        }
+
+        pub fn query<T: DeserializeOwned>(&self, msg: &QueryMsg) -> Result<T, ClientError> {
+            let response = self.backend.query(&self.contract, to_json_binary(msg)?)?;
+            Ok(from_json(&response)?)
+        }
+
+        pub fn config(&self) -> Result<ConfigResponse, ClientError> {
+            self.query(&QueryMsg::Config {})
+        }
+
+        pub fn listed_token(&self, token_address: &str) -> Result<TokenInfo, ClientError> {
+            self.query(&QueryMsg::ListedToken {
+                token_address: Addr::unchecked(token_address),
+            })
+        }
+
+        pub fn price(&self, token_address: &str) -> Result<PriceResponse, ClientError> {
+            self.query(&QueryMsg::Price {
+                token_address: Addr::unchecked(token_address),
+            })
+        }
+
+        pub fn collateral_balance(
+            &self,
+            token_address: &str,
+            user_address: &str,
+        ) -> Result<Uint128, ClientError> {
+            self.query(&QueryMsg::UserCollateralTokenBalance(query_token_data(
+                token_address,
+                user_address,
+            )))
+        }
+
+        pub fn borrow_balance(
+            &self,
+            token_address: &str,
+            user_address: &str,
+        ) -> Result<Uint128, ClientError> {
+            self.query(&QueryMsg::UserBorrowTokenBalance(query_token_data(
+                token_address,
+                user_address,
+            )))
+        }
+
+        pub fn vtoken_balance(
+            &self,
+            token_address: &str,
+            user_address: &str,
+        ) -> Result<Uint128, ClientError> {
+            self.query(&QueryMsg::UserVTokenBalance(query_token_data(
+                token_address,
+                user_address,
+            )))
+        }
+
+        pub fn user_orders(&self, user_address: &str) -> Result<OrderState, ClientError> {
+            self.query(&QueryMsg::UserOrders {
+                user_address: Addr::unchecked(user_address),
+                account_id: None,
+            })
+        }
+
+        pub fn user_account(&self, user_address: &str) -> Result<UserAccountResponse, ClientError> {
+            self.query(&QueryMsg::UserAccount {
+                user_address: Addr::unchecked(user_address),
+                account_id: None,
+            })
+        }
+
+        pub fn pending_rewards(
+            &self,
+            user_address: &str,
+        ) -> Result<PendingRewardsResponse, ClientError> {
+            self.query(&QueryMsg::PendingRewards {
+                user_address: Addr::unchecked(user_address),
+                account_id: None,
+            })
+        }
+
+        pub fn referral_stats(
+            &self,
+            referrer_address: &str,
+        ) -> Result<ReferralStatsResponse, ClientError> {
+            self.query(&QueryMsg::ReferralStats {
+                referrer_address: Addr::unchecked(referrer_address),
+            })
+        }
+
+        pub fn account_health(
+            &self,
+            user_address: &str,
+        ) -> Result<AccountHealthResponse, ClientError> {
+            self.query(&QueryMsg::AccountHealth {
+                user_address: Addr::unchecked(user_address),
+                account_id: None,
+            })
+        }
+
+        pub fn market_state(
+            &self,
+            token_address: &str,
+        ) -> Result<MarketStateResponse, ClientError> {
+            self.query(&QueryMsg::MarketState {
+                token_address: Addr::unchecked(token_address),
+            })
+        }
+
+        pub fn all_markets(
+            &self,
+            start_after: Option<String>,
+            limit: Option<u32>,
+        ) -> Result<MarketsResponse, ClientError> {
+            self.query(&QueryMsg::AllMarkets { start_after, limit })
+        }
+
+        pub fn liquidatable_accounts(
+            &self,
+            start_after: Option<String>,
+            limit: Option<u32>,
+        ) -> Result<LiquidatableAccountsResponse, ClientError> {
+            self.query(&QueryMsg::LiquidatableAccounts { start_after, limit })
+        }
+
+        pub fn simulate_borrow(
+            &self,
+            user_address: &str,
+            token_address: &str,
+            amount: impl Into<Uint128>,
+        ) -> Result<SimulationResponse, ClientError> {
+            self.query(&QueryMsg::SimulateBorrow {
+                user_address: Addr::unchecked(user_address),
+                token_data: token_data(token_address, amount),
+            })
+        }
+
+        pub fn simulate_order(&self, order: Order) -> Result<SimulationResponse, ClientError> {
+            let order: OrderExecute = order.build()?;
+            self.query(&QueryMsg::SimulateOrder(order))
+        }
+
+        pub fn simulate_withdraw(
+            &self,
+            user_address: &str,
+            token_address: &str,
+            amount: impl Into<Uint128>,
+        ) -> Result<SimulationResponse, ClientError> {
+            self.query(&QueryMsg::SimulateWithdraw {
+                user_address: Addr::unchecked(user_address),
+                withdraw_data: WithdrawData {
+                    token_address: Addr::unchecked(token_address),
+                    token_amount: amount.into(),
+                    account_id: None,
+                },
+            })
+        }
+    }
+
+    fn token_data(token_address: &str, amount: impl Into<Uint128>) -> TokenData {
+        TokenData {
+            token_address: Addr::unchecked(token_address),
+            token_amount: amount.into(),
+            account_id: None,
+            on_behalf_of: None,
+        }
+    }
+
+    fn query_token_data(token_address: &str, user_address: &str) -> QueryTokenData {
+        QueryTokenData {
+            token_address: Addr::unchecked(token_address),
+            user_address: Addr::unchecked(user_address),
+            account_id: None,
+        }
+    }
+}
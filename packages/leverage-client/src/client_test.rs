@@ -0,0 +1,268 @@
+#[cfg(test)]
+mod test {
+    use cosmwasm_std::{coin, coins, from_json, Addr, Binary, Decimal, Uint128};
+    use cw20::Cw20Coin;
+    use cw_multi_test::{App, AppBuilder, ContractWrapper, Executor};
+    use leverage_contract::msg::{ExecuteMsg, InitialToken, InstantiateMsg};
+    use leverage_contract::state::{AssetInfo, PriceSource};
+    use leverage_contract::ContractError;
+    use serde_json::{json, Value};
+
+    use crate::backend::client_backend::ContractCall;
+    use crate::builder::msg_builder::{Borrow, Cw20Send, Deposit, LeverageAction, Order};
+    use crate::chain::chain_backend::{self, ChainCliBackend};
+    use crate::multi_test::multi_test_backend::MultiTestBackend;
+    use crate::{ClientError, LeverageClient};
+
+    const USER: &str = "user_one";
+
+    fn initial_token(token: &str, asset: AssetInfo, price: Decimal) -> InitialToken {
+        InitialToken {
+            token_address: String::from(token),
+            asset,
+            decimals: Some(6),
+            symbol: String::from(token),
+            price_source: Some(PriceSource::Fixed { price }),
+        }
+    }
+
+    fn setup_client() -> (LeverageClient<MultiTestBackend>, Addr) {
+        let mut app: App = AppBuilder::new().build(|router, _, storage| {
+            router
+                .bank
+                .init_balance(storage, &Addr::unchecked(USER), coins(1000000, "uosmo"))
+                .unwrap();
+        });
+
+        let cw20_code_id = app.store_code(Box::new(ContractWrapper::new(
+            cw20_base::contract::execute,
+            cw20_base::contract::instantiate,
+            cw20_base::contract::query,
+        )));
+        let usdc = app
+            .instantiate_contract(
+                cw20_code_id,
+                Addr::unchecked("creator"),
+                &cw20_base::msg::InstantiateMsg {
+                    name: String::from("USD Coin"),
+                    symbol: String::from("USDC"),
+                    decimals: 6,
+                    initial_balances: vec![Cw20Coin {
+                        address: String::from(USER),
+                        amount: Uint128::from(1000000u128),
+                    }],
+                    mint: None,
+                    marketing: None,
+                },
+                &[],
+                "usdc",
+                None,
+            )
+            .unwrap();
+
+        let mut backend = MultiTestBackend::new(app);
+        let code_id = backend.store_leverage_code();
+        let client = LeverageClient::instantiate(
+            backend,
+            "creator",
+            code_id,
+            &InstantiateMsg {
+                owner: None,
+                tokens: vec![
+                    initial_token(
+                        "USDC",
+                        AssetInfo::Cw20 {
+                            contract_addr: usdc.clone(),
+                        },
+                        Decimal::one(),
+                    ),
+                    initial_token(
+                        "OSMO",
+                        AssetInfo::Native {
+                            denom: String::from("uosmo"),
+                        },
+                        Decimal::one(),
+                    ),
+                    initial_token(
+                        "ATOM",
+                        AssetInfo::Native {
+                            denom: String::from("uatom"),
+                        },
+                        Decimal::percent(1000),
+                    ),
+                ],
+                fees: None,
+                timelock_delay: None,
+            },
+            "leverage_contract",
+        )
+        .unwrap();
+
+        (client, usdc)
+    }
+
+    #[test]
+    fn drives_the_contract_through_multi_test() {
+        let (mut client, usdc) = setup_client();
+
+        let res = client
+            .deposit_native(USER, "OSMO", coin(1000, "uosmo"))
+            .unwrap();
+        assert_eq!(
+            res.leverage_attribute("deposit", "collateral_balance"),
+            Some("1000")
+        );
+
+        // CW20 deposits go through the token contract
+        client
+            .execute(USER, Cw20Send::deposit(usdc.as_str(), 500u128))
+            .unwrap();
+        assert_eq!(
+            client.collateral_balance("USDC", USER).unwrap(),
+            Uint128::from(500u128)
+        );
+
+        client.borrow(USER, "OSMO", 4000u128).unwrap();
+        assert_eq!(
+            client.borrow_balance("OSMO", USER).unwrap(),
+            Uint128::from(4000u128)
+        );
+
+        let order = Order::new("1")
+            .user(USER)
+            .sell("OSMO", 400u128)
+            .buy("ATOM", 40u128);
+        let simulated = client.simulate_order(order.clone()).unwrap();
+        assert!(simulated.health.health_factor.is_some());
+        assert_eq!(
+            client.vtoken_balance("ATOM", USER).unwrap(),
+            Uint128::zero()
+        );
+
+        let res = client.execute_order(USER, order).unwrap();
+        assert_eq!(res.leverage_attribute("order", "order_id"), Some("1"));
+        assert_eq!(
+            client.vtoken_balance("ATOM", USER).unwrap(),
+            Uint128::from(40u128)
+        );
+
+        let account = client.user_account(USER).unwrap();
+        assert_eq!(account.user_address, Addr::unchecked(USER));
+        assert!(client.account_health(USER).unwrap().health_factor.is_some());
+        assert_eq!(client.price("ATOM").unwrap().price, Decimal::percent(1000));
+        assert_eq!(
+            client.market_state("OSMO").unwrap().total_borrowed,
+            Uint128::from(4000u128)
+        );
+        assert_eq!(client.all_markets(None, None).unwrap().markets.len(), 3);
+
+        // Repaying CW20 debt with a hook payload
+        client.borrow(USER, "USDC", 100u128).unwrap();
+        client
+            .execute(USER, Cw20Send::repay(usdc.as_str(), 100u128))
+            .unwrap();
+        assert_eq!(
+            client.borrow_balance("USDC", USER).unwrap(),
+            Uint128::zero()
+        );
+    }
+
+    #[test]
+    fn surfaces_contract_errors() {
+        let (mut client, _) = setup_client();
+
+        let err = client.borrow(USER, "OSMO", 100u128).unwrap_err();
+        match err {
+            ClientError::Execute { msg } => {
+                assert_eq!(msg, ContractError::InsufficientUnmintedToken {}.to_string())
+            }
+            err => panic!("unexpected error {err}"),
+        }
+
+        let err = client.price("DOGE").unwrap_err();
+        assert!(matches!(err, ClientError::Query { .. }));
+
+        let err = client
+            .execute_order(USER, Order::new("1").user(USER).sell("OSMO", 1u128))
+            .unwrap_err();
+        assert!(matches!(err, ClientError::IncompleteOrder { .. }));
+    }
+
+    #[test]
+    fn builders_produce_contract_messages() {
+        let call = Borrow::new("OSMO", 10u128).into_call("leverage").unwrap();
+        assert_eq!(
+            from_json::<Value>(&call.msg).unwrap(),
+            json!({
+                "borrow": {"token_address": "OSMO", "token_amount": "10"}
+            })
+        );
+
+        let call = Cw20Send::liquidate("usdc", 5u128, "user_one", "OSMO")
+            .into_call("leverage")
+            .unwrap();
+        assert_eq!(call.contract, "usdc");
+        let send = from_json::<Value>(&call.msg).unwrap();
+        assert_eq!(send["send"]["contract"], "leverage");
+        assert_eq!(send["send"]["amount"], "5");
+        let hook: Binary = serde_json::from_value(send["send"]["msg"].clone()).unwrap();
+        assert_eq!(
+            from_json::<Value>(&hook).unwrap(),
+            json!({
+                "liquidate": {"user_address": "user_one", "collateral_token": "OSMO"}
+            })
+        );
+
+        let call = ExecuteMsg::Pause {}.into_call("leverage").unwrap();
+        assert!(call.funds.is_empty());
+    }
+
+    #[test]
+    fn chain_backend_builds_node_commands() {
+        let backend = ChainCliBackend::new("osmosisd", "http://localhost:26657", "localosmosis")
+            .home("/tmp/osmosis");
+
+        let call: ContractCall = Deposit::native("OSMO", coin(1000, "uosmo"))
+            .into_call("osmo1leverage")
+            .unwrap();
+        let args = backend.execute_args("trader", &call);
+        assert_eq!(
+            args[..5],
+            [
+                "tx",
+                "wasm",
+                "execute",
+                "osmo1leverage",
+                r#"{"deposit_native":{"token_address":"OSMO"}}"#
+            ]
+        );
+        assert!(args
+            .windows(2)
+            .any(|pair| pair == ["--amount", "1000uosmo"]));
+        assert!(args.windows(2).any(|pair| pair == ["--from", "trader"]));
+        assert!(args
+            .windows(2)
+            .any(|pair| pair == ["--home", "/tmp/osmosis"]));
+
+        assert_eq!(
+            chain_backend::funds_arg(&[coin(1, "uosmo"), coin(2, "uatom")]),
+            Some(String::from("1uosmo,2uatom"))
+        );
+        assert_eq!(chain_backend::funds_arg(&[]), None);
+
+        let events = chain_backend::tx_events(&json!({
+            "txhash": "AB",
+            "logs": [],
+            "events": [
+                {"type": "message", "attributes": [{"key": "sender", "value": "osmo1user"}]},
+                {"type": "wasm-leverage-deposit", "attributes": [
+                    {"key": "_contract_address", "value": "osmo1leverage"},
+                    {"key": "amount", "value": "1000"}
+                ]}
+            ]
+        }));
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].ty, "wasm-leverage-deposit");
+        assert_eq!(events[1].attributes[1].value, "1000");
+    }
+}
@@ -0,0 +1,36 @@
+use cosmwasm_std::StdError;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error("{0}")]
+    Std(#[from] StdError),
+
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("{0}")]
+    Json(#[from] serde_json::Error),
+
+    /// The contract or the chain rejected the message, carries the root cause
+    #[error("{msg}")]
+    Execute { msg: String },
+
+    #[error("Query failed: {msg}")]
+    Query { msg: String },
+
+    #[error("Command failed with {status}: {stderr}")]
+    Command { status: String, stderr: String },
+
+    #[error("Transaction failed with code {code}: {log}")]
+    TxFailed { code: u64, log: String },
+
+    #[error("Transaction {tx_hash} was not included in time")]
+    TxTimeout { tx_hash: String },
+
+    #[error("Order misses {missing}")]
+    IncompleteOrder { missing: String },
+
+    #[error("No contract address in the instantiate events")]
+    MissingContractAddress {},
+}
@@ -0,0 +1,13 @@
+pub mod backend;
+pub mod builder;
+pub mod chain;
+pub mod client;
+mod error;
+#[cfg(feature = "multi-test")]
+pub mod multi_test;
+
+#[cfg(all(test, feature = "multi-test"))]
+mod client_test;
+
+pub use crate::client::leverage_client::LeverageClient;
+pub use crate::error::ClientError;
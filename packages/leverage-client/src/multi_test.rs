@@ -0,0 +1,126 @@
+pub mod multi_test_backend {
+    use cosmwasm_std::{
+        to_json_vec, Addr, Binary, Coin, ContractResult, CosmosMsg, Empty, QueryRequest,
+        SystemResult, WasmMsg, WasmQuery,
+    };
+    use cw_multi_test::{App, ContractWrapper, Executor};
+    use leverage_contract::contract::{execute, instantiate, query, sudo};
+
+    use crate::backend::client_backend::{Backend, ContractCall, TxResult};
+    use crate::error::ClientError;
+
+    /**
+     * Runs the client against an in-process `cw_multi_test::App`.
+     */
+    pub struct MultiTestBackend {
+        app: App,
+    }
+
+    impl MultiTestBackend {
+        pub fn new(app: App) -> Self {
+            MultiTestBackend { app }
+        }
+
+        pub fn app(&self) -> &App {
+            &self.app
+        }
+
+        pub fn app_mut(&mut self) -> &mut App {
+            &mut self.app
+        }
+
+        /**
+         * Stores the leverage contract code in the app.
+         *
+         * @return The code id to instantiate.
+         */
+        pub fn store_leverage_code(&mut self) -> u64 {
+            self.app.store_code(Box::new(
+                ContractWrapper::new(execute, instantiate, query).with_sudo(sudo),
+            ))
+        }
+    }
+
+    // Keeps the root cause, which is the contract error for contract failures
+    fn execute_error(err: anyhow::Error) -> ClientError {
+        ClientError::Execute {
+            msg: err.root_cause().to_string(),
+        }
+    }
+
+    impl Backend for MultiTestBackend {
+        fn execute(&mut self, sender: &str, call: ContractCall) -> Result<TxResult, ClientError> {
+            let response = self
+                .app
+                .execute(
+                    Addr::unchecked(sender),
+                    CosmosMsg::Wasm(WasmMsg::Execute {
+                        contract_addr: call.contract,
+                        msg: call.msg,
+                        funds: call.funds,
+                    }),
+                )
+                .map_err(execute_error)?;
+
+            Ok(TxResult {
+                tx_hash: None,
+                events: response.events,
+            })
+        }
+
+        fn instantiate(
+            &mut self,
+            sender: &str,
+            code_id: u64,
+            msg: Binary,
+            funds: Vec<Coin>,
+            label: &str,
+        ) -> Result<(String, TxResult), ClientError> {
+            let response = self
+                .app
+                .execute(
+                    Addr::unchecked(sender),
+                    CosmosMsg::Wasm(WasmMsg::Instantiate {
+                        admin: None,
+                        code_id,
+                        msg,
+                        funds,
+                        label: label.to_string(),
+                    }),
+                )
+                .map_err(execute_error)?;
+
+            let contract = response
+                .events
+                .iter()
+                .filter(|event| event.ty == "instantiate")
+                .flat_map(|event| event.attributes.iter())
+                .find(|attr| attr.key == "_contract_address")
+                .map(|attr| attr.value.clone())
+                .ok_or(ClientError::MissingContractAddress {})?;
+
+            Ok((
+                contract,
+                TxResult {
+                    tx_hash: None,
+                    events: response.events,
+                },
+            ))
+        }
+
+        fn query(&self, contract: &str, msg: Binary) -> Result<Binary, ClientError> {
+            let request: QueryRequest<Empty> = QueryRequest::Wasm(WasmQuery::Smart {
+                contract_addr: contract.to_string(),
+                msg,
+            });
+
+            match self.app.wrap().raw_query(&to_json_vec(&request)?) {
+                SystemResult::Ok(ContractResult::Ok(response)) => Ok(response),
+                SystemResult::Ok(ContractResult::Err(msg)) => Err(ClientError::Query { msg }),
+                SystemResult::Err(err) => Err(ClientError::Query {
+                    msg: err.to_string(),
+                }),
+            }
+        }
+    }
+}
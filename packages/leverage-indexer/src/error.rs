@@ -0,0 +1,22 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum IndexerError {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("{0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("{0}")]
+    Sqlite(#[from] rusqlite::Error),
+
+    #[error("Invalid transaction log: {reason}")]
+    InvalidTxLog { reason: String },
+
+    #[error("Event leverage-{kind} misses the {key} attribute")]
+    MissingAttribute { kind: String, key: String },
+
+    #[error("Invalid amount: {value}")]
+    InvalidAmount { value: String },
+}
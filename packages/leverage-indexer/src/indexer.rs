@@ -0,0 +1,381 @@
+pub mod event_indexer {
+    use std::fs;
+    use std::path::Path;
+
+    use rusqlite::Connection;
+
+    use crate::error::IndexerError;
+    use crate::store::sqlite_store::{self, MarketField, OrderRecord, PositionField};
+    use crate::tx_log::tx_parser::{self, LeverageLog};
+
+    // Attributes naming the account an event belongs to, by priority
+    const ACCOUNT_KEYS: [&str; 7] = [
+        "user",
+        "collector",
+        "address",
+        "sender",
+        "proposer",
+        "owner",
+        "pending_owner",
+    ];
+
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub struct IndexSummary {
+        pub indexed: u64,
+        // Events which were already in the store
+        pub skipped: u64,
+    }
+
+    /**
+     * Replays a recorded transaction log file into the store.
+     */
+    pub fn index_file(conn: &mut Connection, path: &Path) -> Result<IndexSummary, IndexerError> {
+        let logs = tx_parser::parse_tx_logs(&fs::read_to_string(path)?)?;
+        index_logs(conn, &logs)
+    }
+
+    /**
+     * Applies leverage events in chain order within one database transaction.
+     *
+     * Events already indexed are skipped, so overlapping logs can be replayed.
+     */
+    pub fn index_logs(
+        conn: &mut Connection,
+        logs: &[LeverageLog],
+    ) -> Result<IndexSummary, IndexerError> {
+        let transaction = conn.transaction()?;
+        let mut summary = IndexSummary::default();
+
+        for log in logs {
+            let account = ACCOUNT_KEYS
+                .iter()
+                .find_map(|key| log.attributes.get(*key))
+                .map(String::as_str);
+            let counterparty = log.attributes.get("liquidator").map(String::as_str);
+
+            if !sqlite_store::insert_event(&transaction, log, account, counterparty)? {
+                summary.skipped += 1;
+                continue;
+            }
+            for address in account.into_iter().chain(counterparty) {
+                sqlite_store::touch_account(&transaction, address, log.height)?;
+            }
+
+            apply_event(&transaction, log)?;
+            summary.indexed += 1;
+        }
+
+        transaction.commit()?;
+        Ok(summary)
+    }
+
+    fn apply_event(conn: &Connection, log: &LeverageLog) -> Result<(), IndexerError> {
+        let height = log.height;
+
+        match log.kind.as_str() {
+            "deposit" => {
+                let (user, token) = (log.attr("user")?, log.attr("token")?);
+                sqlite_store::set_position(
+                    conn,
+                    user,
+                    token,
+                    PositionField::Collateral,
+                    log.amount("collateral_balance")?,
+                    height,
+                )?;
+                sqlite_store::add_to_market(
+                    conn,
+                    token,
+                    MarketField::Deposited,
+                    log.amount("amount")?,
+                    height,
+                )?;
+            }
+            "borrow" => {
+                let (user, token) = (log.attr("user")?, log.attr("token")?);
+                sqlite_store::set_position(
+                    conn,
+                    user,
+                    token,
+                    PositionField::Borrowed,
+                    log.amount("borrow_balance")?,
+                    height,
+                )?;
+                sqlite_store::set_position(
+                    conn,
+                    user,
+                    token,
+                    PositionField::VTokens,
+                    log.amount("vtoken_balance")?,
+                    height,
+                )?;
+                sqlite_store::add_to_market(
+                    conn,
+                    token,
+                    MarketField::Borrowed,
+                    log.amount("amount")?,
+                    height,
+                )?;
+                sqlite_store::add_to_market(
+                    conn,
+                    token,
+                    MarketField::BorrowFees,
+                    log.amount("fee")?,
+                    height,
+                )?;
+            }
+            "order" => {
+                let order = OrderRecord {
+                    order_id: log.attr("order_id")?.to_string(),
+                    account: log.attr("user")?.to_string(),
+                    token_in: log.attr("token_in")?.to_string(),
+                    amount_in: log.amount("amount_in")?,
+                    token_out: log.attr("token_out")?.to_string(),
+                    amount_out: log.amount("amount_out")?,
+                    fee: log.amount("fee")?,
+                    height,
+                    tx_hash: log.tx_hash.clone(),
+                    timestamp: log.timestamp.clone(),
+                };
+                sqlite_store::set_position(
+                    conn,
+                    &order.account,
+                    &order.token_in,
+                    PositionField::VTokens,
+                    log.amount("vtoken_in_balance")?,
+                    height,
+                )?;
+                sqlite_store::set_position(
+                    conn,
+                    &order.account,
+                    &order.token_out,
+                    PositionField::VTokens,
+                    log.amount("vtoken_out_balance")?,
+                    height,
+                )?;
+                // Trading fees are kept back from the output token
+                sqlite_store::add_to_market(
+                    conn,
+                    &order.token_out,
+                    MarketField::TradingFees,
+                    order.fee,
+                    height,
+                )?;
+                sqlite_store::insert_order(conn, log, &order)?;
+            }
+            "repay" => {
+                let (user, token) = (log.attr("user")?, log.attr("token")?);
+                sqlite_store::set_position(
+                    conn,
+                    user,
+                    token,
+                    PositionField::Borrowed,
+                    log.amount("borrow_balance")?,
+                    height,
+                )?;
+                sqlite_store::set_position(
+                    conn,
+                    user,
+                    token,
+                    PositionField::VTokens,
+                    log.amount("vtoken_balance")?,
+                    height,
+                )?;
+                sqlite_store::add_to_market(
+                    conn,
+                    token,
+                    MarketField::Repaid,
+                    log.amount("principal_paid")?,
+                    height,
+                )?;
+                sqlite_store::add_to_market(
+                    conn,
+                    token,
+                    MarketField::InterestPaid,
+                    log.amount("interest_paid")?,
+                    height,
+                )?;
+            }
+            "top_up" => {
+                sqlite_store::set_position(
+                    conn,
+                    log.attr("user")?,
+                    log.attr("token")?,
+                    PositionField::VTokens,
+                    log.amount("vtoken_balance")?,
+                    height,
+                )?;
+            }
+            "burn" => {
+                let (user, token) = (log.attr("user")?, log.attr("token")?);
+                sqlite_store::set_position(
+                    conn,
+                    user,
+                    token,
+                    PositionField::Collateral,
+                    log.amount("collateral_balance")?,
+                    height,
+                )?;
+                sqlite_store::set_position(
+                    conn,
+                    user,
+                    token,
+                    PositionField::VTokens,
+                    log.amount("vtoken_balance")?,
+                    height,
+                )?;
+            }
+            "withdraw" => {
+                let (user, token) = (log.attr("user")?, log.attr("token")?);
+                sqlite_store::set_position(
+                    conn,
+                    user,
+                    token,
+                    PositionField::Collateral,
+                    log.amount("collateral_balance")?,
+                    height,
+                )?;
+                sqlite_store::add_to_market(
+                    conn,
+                    token,
+                    MarketField::Withdrawn,
+                    log.amount("amount")?,
+                    height,
+                )?;
+            }
+            "liquidate" => {
+                let user = log.attr("user")?;
+                let (token, collateral_token) = (log.attr("token")?, log.attr("collateral_token")?);
+                sqlite_store::set_position(
+                    conn,
+                    user,
+                    token,
+                    PositionField::Borrowed,
+                    log.amount("borrow_balance")?,
+                    height,
+                )?;
+                sqlite_store::set_position(
+                    conn,
+                    user,
+                    collateral_token,
+                    PositionField::Collateral,
+                    log.amount("collateral_balance")?,
+                    height,
+                )?;
+                sqlite_store::add_to_market(
+                    conn,
+                    token,
+                    MarketField::Liquidated,
+                    log.amount("amount")?,
+                    height,
+                )?;
+                sqlite_store::add_to_market(
+                    conn,
+                    collateral_token,
+                    MarketField::Seized,
+                    log.amount("seized")?,
+                    height,
+                )?;
+            }
+            // Governance closing all debt of a user in one token
+            "force_settled" => {
+                let (user, token) = (log.attr("user")?, log.attr("token")?);
+                sqlite_store::set_position(
+                    conn,
+                    user,
+                    token,
+                    PositionField::Borrowed,
+                    log.amount("borrow_balance")?,
+                    height,
+                )?;
+                sqlite_store::set_position(
+                    conn,
+                    user,
+                    token,
+                    PositionField::Collateral,
+                    log.amount("collateral_balance")?,
+                    height,
+                )?;
+                sqlite_store::set_position(
+                    conn,
+                    user,
+                    token,
+                    PositionField::VTokens,
+                    log.amount("vtoken_balance")?,
+                    height,
+                )?;
+            }
+            // Other tokens of the user covering a force settled debt
+            "settlement_seized" => {
+                let (user, token) = (log.attr("user")?, log.attr("token")?);
+                sqlite_store::set_position(
+                    conn,
+                    user,
+                    token,
+                    PositionField::Collateral,
+                    log.amount("collateral_balance")?,
+                    height,
+                )?;
+                sqlite_store::set_position(
+                    conn,
+                    user,
+                    token,
+                    PositionField::VTokens,
+                    log.amount("vtoken_balance")?,
+                    height,
+                )?;
+            }
+            // Closed positions are credited collateral for what they didn't absorb
+            "auto_deleveraged" => {
+                let (user, token) = (log.attr("user")?, log.attr("token")?);
+                sqlite_store::set_position(
+                    conn,
+                    user,
+                    token,
+                    PositionField::Collateral,
+                    log.amount("collateral_balance")?,
+                    height,
+                )?;
+                sqlite_store::set_position(
+                    conn,
+                    user,
+                    token,
+                    PositionField::VTokens,
+                    log.amount("vtoken_balance")?,
+                    height,
+                )?;
+            }
+            "collect_revenue" => {
+                sqlite_store::add_to_market(
+                    conn,
+                    log.attr("token")?,
+                    MarketField::RevenueCollected,
+                    log.amount("amount")?,
+                    height,
+                )?;
+            }
+            "token_listed" => {
+                sqlite_store::set_market_listing(
+                    conn,
+                    log.attr("token")?,
+                    Some(log.attr("symbol")?),
+                    "active",
+                    height,
+                )?;
+            }
+            "token_status_updated" => {
+                sqlite_store::set_market_listing(
+                    conn,
+                    log.attr("token")?,
+                    None,
+                    log.attr("status")?,
+                    height,
+                )?;
+            }
+            // Admin events only show up in the history
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,257 @@
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use rusqlite::Connection;
+
+    use crate::indexer::event_indexer::{self, IndexSummary};
+    use crate::store::sqlite_store::{self, PositionRecord};
+    use crate::tx_log::tx_parser;
+    use crate::IndexerError;
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures")
+            .join(name)
+    }
+
+    fn indexed_fixtures() -> Connection {
+        let mut conn = sqlite_store::open_in_memory().unwrap();
+        event_indexer::index_file(&mut conn, &fixture("legacy_logs.json")).unwrap();
+        event_indexer::index_file(&mut conn, &fixture("tx_responses.jsonl")).unwrap();
+        conn
+    }
+
+    fn position(conn: &Connection, account: &str, token: &str) -> PositionRecord {
+        sqlite_store::fetch_positions(conn, account)
+            .unwrap()
+            .into_iter()
+            .find(|position| position.token == token)
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_legacy_and_current_tx_formats() {
+        let legacy = tx_parser::parse_tx_logs(
+            &std::fs::read_to_string(fixture("legacy_logs.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(legacy.len(), 6);
+        assert_eq!(legacy[0].kind, "token_listed");
+        assert_eq!(legacy[0].height, 100);
+        // Indexes count every event of the transaction, not just ours
+        assert_eq!(legacy[0].event_index, 2);
+        assert_eq!(legacy[0].contract.as_deref(), Some("osmo1leveragecontract"));
+        assert!(!legacy[0].attributes.contains_key("_contract_address"));
+
+        let current = tx_parser::parse_tx_logs(
+            &std::fs::read_to_string(fixture("tx_responses.jsonl")).unwrap(),
+        )
+        .unwrap();
+        // The failed deposit is left out
+        assert_eq!(
+            current
+                .iter()
+                .map(|log| log.kind.as_str())
+                .collect::<Vec<&str>>(),
+            vec![
+                "repay",
+                "liquidate",
+                "collect_revenue",
+                "repay",
+                "token_status_updated"
+            ]
+        );
+
+        let err = tx_parser::parse_tx_logs(r#"{"txhash": "C1", "events": []}"#).unwrap_err();
+        assert!(matches!(err, IndexerError::InvalidTxLog { .. }));
+    }
+
+    #[test]
+    fn replays_positions_orders_and_markets() {
+        let conn = indexed_fixtures();
+
+        let osmo = position(&conn, "osmo1userone", "OSMO");
+        assert_eq!(osmo.collateral, 454);
+        assert_eq!(osmo.borrowed, 520);
+        assert_eq!(osmo.vtokens, 0);
+        assert_eq!(osmo.updated_height, 112);
+        assert_eq!(position(&conn, "osmo1userone", "ATOM").vtokens, 99);
+        assert_eq!(position(&conn, "osmo1usertwo", "ATOM").collateral, 500);
+
+        let orders = sqlite_store::fetch_orders(&conn, "osmo1userone").unwrap();
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].order_id, "order-1");
+        assert_eq!(orders[0].amount_in, 1000);
+        assert_eq!(orders[0].amount_out, 99);
+        assert_eq!(orders[0].timestamp.as_deref(), Some("2024-03-01T10:00:18Z"));
+
+        let osmo = sqlite_store::fetch_market(&conn, "OSMO").unwrap().unwrap();
+        assert_eq!(osmo.symbol.as_deref(), Some("OSMO"));
+        assert_eq!(osmo.status.as_deref(), Some("active"));
+        assert_eq!(osmo.total_collateral, 454);
+        assert_eq!(osmo.total_borrowed, 520);
+        assert_eq!(osmo.total_vtokens, 0);
+        assert_eq!(osmo.deposited, 1000);
+        assert_eq!(osmo.borrowed, 4000);
+        assert_eq!(osmo.borrow_fees, 40);
+        assert_eq!(osmo.repaid, 2960);
+        assert_eq!(osmo.interest_paid, 40);
+        assert_eq!(osmo.liquidated, 520);
+        assert_eq!(osmo.seized, 546);
+        assert_eq!(osmo.revenue_collected, 40);
+
+        let atom = sqlite_store::fetch_market(&conn, "ATOM").unwrap().unwrap();
+        assert_eq!(atom.status.as_deref(), Some("reduce_only"));
+        assert_eq!(atom.total_collateral, 500);
+        assert_eq!(atom.total_vtokens, 99);
+        assert_eq!(atom.trading_fees, 1);
+
+        assert_eq!(sqlite_store::fetch_markets(&conn).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn account_history_follows_chain_order() {
+        let conn = indexed_fixtures();
+
+        let history = sqlite_store::account_history(&conn, "osmo1userone", None, None).unwrap();
+        assert_eq!(
+            history
+                .iter()
+                .map(|entry| entry.kind.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["deposit", "borrow", "order", "repay", "liquidate"]
+        );
+        assert_eq!(history[3].attributes["principal_paid"], "2960");
+
+        // Liquidators see the liquidations they performed
+        let history = sqlite_store::account_history(&conn, "osmo1liquidator", None, None).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].attributes["user"], "osmo1userone");
+
+        let history =
+            sqlite_store::account_history(&conn, "osmo1userone", Some("borrow"), None).unwrap();
+        assert_eq!(history.len(), 1);
+        let history = sqlite_store::account_history(&conn, "osmo1userone", None, Some(2)).unwrap();
+        assert_eq!(history.len(), 2);
+
+        let account = sqlite_store::fetch_account(&conn, "osmo1userone")
+            .unwrap()
+            .unwrap();
+        assert_eq!(account.first_height, 101);
+        assert_eq!(account.last_height, 112);
+        assert_eq!(account.event_count, 5);
+    }
+
+    #[test]
+    fn replaying_a_log_twice_changes_nothing() {
+        let mut conn = indexed_fixtures();
+        let markets = sqlite_store::fetch_markets(&conn).unwrap();
+
+        let summary = event_indexer::index_file(&mut conn, &fixture("tx_responses.jsonl")).unwrap();
+        assert_eq!(
+            summary,
+            IndexSummary {
+                indexed: 0,
+                skipped: 5
+            }
+        );
+        assert_eq!(sqlite_store::fetch_markets(&conn).unwrap(), markets);
+    }
+
+    #[test]
+    fn malformed_events_roll_back_the_batch() {
+        let mut conn = sqlite_store::open_in_memory().unwrap();
+        let logs = tx_parser::parse_tx_logs(
+            r#"{"height": "5", "txhash": "D1", "events": [
+                {"type": "wasm-leverage-deposit", "attributes": [
+                    {"key": "user", "value": "osmo1userone"},
+                    {"key": "token", "value": "OSMO"},
+                    {"key": "amount", "value": "10"},
+                    {"key": "collateral_balance", "value": "10"}
+                ]},
+                {"type": "wasm-leverage-withdraw", "attributes": [
+                    {"key": "user", "value": "osmo1userone"},
+                    {"key": "token", "value": "OSMO"},
+                    {"key": "amount", "value": "-1"},
+                    {"key": "collateral_balance", "value": "0"}
+                ]}
+            ]}"#,
+        )
+        .unwrap();
+
+        let err = event_indexer::index_logs(&mut conn, &logs).unwrap_err();
+        assert!(matches!(err, IndexerError::InvalidAmount { .. }));
+        assert!(sqlite_store::fetch_positions(&conn, "osmo1userone")
+            .unwrap()
+            .is_empty());
+        assert!(
+            sqlite_store::account_history(&conn, "osmo1userone", None, None)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn force_settlements_close_the_position() {
+        let mut conn = indexed_fixtures();
+        let logs = tx_parser::parse_tx_logs(
+            r#"{"height": "200", "txhash": "F1", "events": [
+                {"type": "wasm-leverage-force_settled", "attributes": [
+                    {"key": "user", "value": "osmo1userone"},
+                    {"key": "token", "value": "OSMO"},
+                    {"key": "repaid", "value": "0"},
+                    {"key": "seized", "value": "480"},
+                    {"key": "bad_debt", "value": "0"},
+                    {"key": "borrow_balance", "value": "0"},
+                    {"key": "collateral_balance", "value": "0"},
+                    {"key": "vtoken_balance", "value": "0"}
+                ]},
+                {"type": "wasm-leverage-settlement_seized", "attributes": [
+                    {"key": "user", "value": "osmo1userone"},
+                    {"key": "token", "value": "ATOM"},
+                    {"key": "debt_token", "value": "OSMO"},
+                    {"key": "vtokens", "value": "40"},
+                    {"key": "collateral", "value": "0"},
+                    {"key": "covered", "value": "400"},
+                    {"key": "collateral_balance", "value": "0"},
+                    {"key": "vtoken_balance", "value": "60"}
+                ]}
+            ]}"#,
+        )
+        .unwrap();
+        event_indexer::index_logs(&mut conn, &logs).unwrap();
+
+        let osmo = position(&conn, "osmo1userone", "OSMO");
+        assert_eq!((osmo.collateral, osmo.borrowed, osmo.vtokens), (0, 0, 0));
+        assert_eq!(osmo.updated_height, 200);
+        let atom = position(&conn, "osmo1userone", "ATOM");
+        assert_eq!((atom.collateral, atom.vtokens), (0, 60));
+        assert_eq!(atom.updated_height, 200);
+    }
+
+    #[test]
+    fn auto_deleveraging_shrinks_the_position() {
+        let mut conn = indexed_fixtures();
+        let logs = tx_parser::parse_tx_logs(
+            r#"{"height": "210", "txhash": "A1", "events": [
+                {"type": "wasm-leverage-auto_deleveraged", "attributes": [
+                    {"key": "user", "value": "osmo1userone"},
+                    {"key": "token", "value": "OSMO"},
+                    {"key": "rank", "value": "1"},
+                    {"key": "closed", "value": "100"},
+                    {"key": "absorbed", "value": "20"},
+                    {"key": "credited", "value": "8"},
+                    {"key": "collateral_balance", "value": "8"},
+                    {"key": "vtoken_balance", "value": "380"}
+                ]}
+            ]}"#,
+        )
+        .unwrap();
+        event_indexer::index_logs(&mut conn, &logs).unwrap();
+
+        let osmo = position(&conn, "osmo1userone", "OSMO");
+        assert_eq!((osmo.collateral, osmo.vtokens), (8, 380));
+        assert_eq!(osmo.updated_height, 210);
+    }
+}
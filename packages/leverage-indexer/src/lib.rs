@@ -0,0 +1,9 @@
+pub mod error;
+pub mod indexer;
+pub mod store;
+pub mod tx_log;
+
+#[cfg(test)]
+mod indexer_test;
+
+pub use crate::error::IndexerError;
@@ -0,0 +1,98 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+
+use leverage_indexer::indexer::event_indexer;
+use leverage_indexer::store::sqlite_store;
+use leverage_indexer::IndexerError;
+
+/// Replays leverage contract events into SQLite and queries the result.
+#[derive(Parser)]
+#[command(name = "leverage-indexer", version)]
+struct Cli {
+    /// SQLite database, created on first use
+    #[arg(long, default_value = "leverage.sqlite")]
+    db: PathBuf,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Index recorded transaction logs (TxResponse JSON, arrays or JSON lines)
+    Index { files: Vec<PathBuf> },
+    /// Events of an account in chain order
+    History {
+        account: String,
+        /// Only events of this kind, e.g. `deposit` or `liquidate`
+        #[arg(long)]
+        kind: Option<String>,
+        #[arg(long)]
+        limit: Option<u32>,
+    },
+    /// Activity and open positions of an account
+    Account { account: String },
+    /// Orders executed for an account
+    Orders { account: String },
+    /// Totals and cumulative flows of every market
+    Markets,
+}
+
+#[derive(Serialize)]
+struct AccountOutput {
+    account: Option<sqlite_store::AccountRecord>,
+    positions: Vec<sqlite_store::PositionRecord>,
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), IndexerError> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+fn run(cli: Cli) -> Result<(), IndexerError> {
+    let mut conn = sqlite_store::open(&cli.db)?;
+
+    match cli.command {
+        Command::Index { files } => {
+            for file in files {
+                let summary = event_indexer::index_file(&mut conn, &file)?;
+                println!(
+                    "{}: indexed {} events, skipped {} already indexed",
+                    file.display(),
+                    summary.indexed,
+                    summary.skipped
+                );
+            }
+            Ok(())
+        }
+        Command::History {
+            account,
+            kind,
+            limit,
+        } => print_json(&sqlite_store::account_history(
+            &conn,
+            &account,
+            kind.as_deref(),
+            limit,
+        )?),
+        Command::Account { account } => print_json(&AccountOutput {
+            account: sqlite_store::fetch_account(&conn, &account)?,
+            positions: sqlite_store::fetch_positions(&conn, &account)?,
+        }),
+        Command::Orders { account } => print_json(&sqlite_store::fetch_orders(&conn, &account)?),
+        Command::Markets => print_json(&sqlite_store::fetch_markets(&conn)?),
+    }
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
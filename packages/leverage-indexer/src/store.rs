@@ -0,0 +1,622 @@
+pub mod sqlite_store {
+    use std::collections::BTreeMap;
+    use std::path::Path;
+
+    use rusqlite::{params, Connection, OptionalExtension};
+    use serde::Serialize;
+
+    use crate::error::IndexerError;
+    use crate::tx_log::tx_parser::{parse_amount, LeverageLog};
+
+    // Amounts are kept as decimal text, SQLite integers can't hold a Uint128
+    const SCHEMA: &str = "
+        CREATE TABLE IF NOT EXISTS events (
+            tx_hash TEXT NOT NULL,
+            event_index INTEGER NOT NULL,
+            height INTEGER NOT NULL,
+            timestamp TEXT,
+            contract TEXT,
+            kind TEXT NOT NULL,
+            account TEXT,
+            counterparty TEXT,
+            attributes TEXT NOT NULL,
+            PRIMARY KEY (tx_hash, event_index)
+        );
+        CREATE INDEX IF NOT EXISTS events_account ON events (account);
+        CREATE INDEX IF NOT EXISTS events_counterparty ON events (counterparty);
+
+        CREATE TABLE IF NOT EXISTS accounts (
+            address TEXT PRIMARY KEY,
+            first_height INTEGER NOT NULL,
+            last_height INTEGER NOT NULL,
+            event_count INTEGER NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS positions (
+            account TEXT NOT NULL,
+            token TEXT NOT NULL,
+            collateral TEXT NOT NULL DEFAULT '0',
+            borrowed TEXT NOT NULL DEFAULT '0',
+            vtokens TEXT NOT NULL DEFAULT '0',
+            updated_height INTEGER NOT NULL,
+            PRIMARY KEY (account, token)
+        );
+
+        CREATE TABLE IF NOT EXISTS orders (
+            tx_hash TEXT NOT NULL,
+            event_index INTEGER NOT NULL,
+            order_id TEXT NOT NULL,
+            account TEXT NOT NULL,
+            token_in TEXT NOT NULL,
+            amount_in TEXT NOT NULL,
+            token_out TEXT NOT NULL,
+            amount_out TEXT NOT NULL,
+            fee TEXT NOT NULL,
+            height INTEGER NOT NULL,
+            timestamp TEXT,
+            PRIMARY KEY (tx_hash, event_index)
+        );
+        CREATE INDEX IF NOT EXISTS orders_account ON orders (account);
+
+        CREATE TABLE IF NOT EXISTS markets (
+            token TEXT PRIMARY KEY,
+            symbol TEXT,
+            status TEXT,
+            total_collateral TEXT NOT NULL DEFAULT '0',
+            total_borrowed TEXT NOT NULL DEFAULT '0',
+            total_vtokens TEXT NOT NULL DEFAULT '0',
+            deposited TEXT NOT NULL DEFAULT '0',
+            withdrawn TEXT NOT NULL DEFAULT '0',
+            borrowed TEXT NOT NULL DEFAULT '0',
+            repaid TEXT NOT NULL DEFAULT '0',
+            interest_paid TEXT NOT NULL DEFAULT '0',
+            borrow_fees TEXT NOT NULL DEFAULT '0',
+            trading_fees TEXT NOT NULL DEFAULT '0',
+            liquidated TEXT NOT NULL DEFAULT '0',
+            seized TEXT NOT NULL DEFAULT '0',
+            revenue_collected TEXT NOT NULL DEFAULT '0',
+            updated_height INTEGER NOT NULL
+        );
+    ";
+
+    /**
+     * Balances of an account which the events report after every change.
+     */
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum PositionField {
+        Collateral,
+        Borrowed,
+        VTokens,
+    }
+
+    impl PositionField {
+        fn column(&self) -> &'static str {
+            match self {
+                PositionField::Collateral => "collateral",
+                PositionField::Borrowed => "borrowed",
+                PositionField::VTokens => "vtokens",
+            }
+        }
+
+        // Market total which sums this balance over all accounts
+        fn total(&self) -> MarketField {
+            match self {
+                PositionField::Collateral => MarketField::TotalCollateral,
+                PositionField::Borrowed => MarketField::TotalBorrowed,
+                PositionField::VTokens => MarketField::TotalVTokens,
+            }
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum MarketField {
+        TotalCollateral,
+        TotalBorrowed,
+        TotalVTokens,
+        Deposited,
+        Withdrawn,
+        Borrowed,
+        Repaid,
+        InterestPaid,
+        BorrowFees,
+        TradingFees,
+        Liquidated,
+        Seized,
+        RevenueCollected,
+    }
+
+    impl MarketField {
+        fn column(&self) -> &'static str {
+            match self {
+                MarketField::TotalCollateral => "total_collateral",
+                MarketField::TotalBorrowed => "total_borrowed",
+                MarketField::TotalVTokens => "total_vtokens",
+                MarketField::Deposited => "deposited",
+                MarketField::Withdrawn => "withdrawn",
+                MarketField::Borrowed => "borrowed",
+                MarketField::Repaid => "repaid",
+                MarketField::InterestPaid => "interest_paid",
+                MarketField::BorrowFees => "borrow_fees",
+                MarketField::TradingFees => "trading_fees",
+                MarketField::Liquidated => "liquidated",
+                MarketField::Seized => "seized",
+                MarketField::RevenueCollected => "revenue_collected",
+            }
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq, Serialize)]
+    pub struct HistoryEntry {
+        pub height: u64,
+        pub tx_hash: String,
+        pub event_index: u32,
+        pub timestamp: Option<String>,
+        pub kind: String,
+        pub attributes: BTreeMap<String, String>,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Serialize)]
+    pub struct AccountRecord {
+        pub address: String,
+        pub first_height: u64,
+        pub last_height: u64,
+        pub event_count: u64,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Serialize)]
+    pub struct PositionRecord {
+        pub account: String,
+        pub token: String,
+        pub collateral: u128,
+        pub borrowed: u128,
+        pub vtokens: u128,
+        pub updated_height: u64,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Serialize)]
+    pub struct OrderRecord {
+        pub order_id: String,
+        pub account: String,
+        pub token_in: String,
+        pub amount_in: u128,
+        pub token_out: String,
+        pub amount_out: u128,
+        pub fee: u128,
+        pub height: u64,
+        pub tx_hash: String,
+        pub timestamp: Option<String>,
+    }
+
+    #[derive(Clone, Debug, Default, PartialEq, Serialize)]
+    pub struct MarketRecord {
+        pub token: String,
+        pub symbol: Option<String>,
+        pub status: Option<String>,
+        pub total_collateral: u128,
+        pub total_borrowed: u128,
+        pub total_vtokens: u128,
+        pub deposited: u128,
+        pub withdrawn: u128,
+        pub borrowed: u128,
+        pub repaid: u128,
+        pub interest_paid: u128,
+        pub borrow_fees: u128,
+        pub trading_fees: u128,
+        pub liquidated: u128,
+        pub seized: u128,
+        pub revenue_collected: u128,
+        pub updated_height: u64,
+    }
+
+    pub fn open(path: &Path) -> Result<Connection, IndexerError> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(conn)
+    }
+
+    pub fn open_in_memory() -> Result<Connection, IndexerError> {
+        let conn = Connection::open_in_memory()?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(conn)
+    }
+
+    /**
+     * Records the raw event for the history of `account`.
+     *
+     * @return false if the event was indexed before, so replays stay idempotent.
+     */
+    pub fn insert_event(
+        conn: &Connection,
+        log: &LeverageLog,
+        account: Option<&str>,
+        counterparty: Option<&str>,
+    ) -> Result<bool, IndexerError> {
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO events
+                (tx_hash, event_index, height, timestamp, contract, kind, account, counterparty, attributes)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                log.tx_hash,
+                log.event_index,
+                log.height,
+                log.timestamp,
+                log.contract,
+                log.kind,
+                account,
+                counterparty,
+                serde_json::to_string(&log.attributes)?,
+            ],
+        )?;
+
+        Ok(inserted > 0)
+    }
+
+    pub fn touch_account(
+        conn: &Connection,
+        address: &str,
+        height: u64,
+    ) -> Result<(), IndexerError> {
+        conn.execute(
+            "INSERT INTO accounts (address, first_height, last_height, event_count)
+             VALUES (?1, ?2, ?2, 1)
+             ON CONFLICT (address) DO UPDATE SET
+                first_height = MIN(first_height, excluded.first_height),
+                last_height = MAX(last_height, excluded.last_height),
+                event_count = event_count + 1",
+            params![address, height],
+        )?;
+        Ok(())
+    }
+
+    /**
+     * Sets a balance of a position and moves the market total by the difference.
+     */
+    pub fn set_position(
+        conn: &Connection,
+        account: &str,
+        token: &str,
+        field: PositionField,
+        balance: u128,
+        height: u64,
+    ) -> Result<(), IndexerError> {
+        let previous: Option<String> = conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM positions WHERE account = ?1 AND token = ?2",
+                    field.column()
+                ),
+                params![account, token],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let previous = match previous {
+            Some(previous) => parse_amount(&previous)?,
+            None => 0,
+        };
+
+        conn.execute(
+            &format!(
+                "INSERT INTO positions (account, token, {column}, updated_height)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (account, token) DO UPDATE SET
+                    {column} = excluded.{column},
+                    updated_height = excluded.updated_height",
+                column = field.column()
+            ),
+            params![account, token, balance.to_string(), height],
+        )?;
+
+        let total = market_value(conn, token, field.total())?;
+        let total = if balance >= previous {
+            total.saturating_add(balance - previous)
+        } else {
+            total.saturating_sub(previous - balance)
+        };
+        set_market_value(conn, token, field.total(), total, height)
+    }
+
+    pub fn add_to_market(
+        conn: &Connection,
+        token: &str,
+        field: MarketField,
+        amount: u128,
+        height: u64,
+    ) -> Result<(), IndexerError> {
+        let value = market_value(conn, token, field)?.saturating_add(amount);
+        set_market_value(conn, token, field, value, height)
+    }
+
+    pub fn set_market_listing(
+        conn: &Connection,
+        token: &str,
+        symbol: Option<&str>,
+        status: &str,
+        height: u64,
+    ) -> Result<(), IndexerError> {
+        conn.execute(
+            "INSERT INTO markets (token, symbol, status, updated_height)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (token) DO UPDATE SET
+                symbol = COALESCE(excluded.symbol, symbol),
+                status = excluded.status,
+                updated_height = excluded.updated_height",
+            params![token, symbol, status, height],
+        )?;
+        Ok(())
+    }
+
+    pub fn insert_order(
+        conn: &Connection,
+        log: &LeverageLog,
+        order: &OrderRecord,
+    ) -> Result<(), IndexerError> {
+        conn.execute(
+            "INSERT OR IGNORE INTO orders
+                (tx_hash, event_index, order_id, account, token_in, amount_in, token_out,
+                 amount_out, fee, height, timestamp)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                log.tx_hash,
+                log.event_index,
+                order.order_id,
+                order.account,
+                order.token_in,
+                order.amount_in.to_string(),
+                order.token_out,
+                order.amount_out.to_string(),
+                order.fee.to_string(),
+                order.height,
+                order.timestamp,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn market_value(
+        conn: &Connection,
+        token: &str,
+        field: MarketField,
+    ) -> Result<u128, IndexerError> {
+        let value: Option<String> = conn
+            .query_row(
+                &format!("SELECT {} FROM markets WHERE token = ?1", field.column()),
+                params![token],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        match value {
+            Some(value) => parse_amount(&value),
+            None => Ok(0),
+        }
+    }
+
+    fn set_market_value(
+        conn: &Connection,
+        token: &str,
+        field: MarketField,
+        value: u128,
+        height: u64,
+    ) -> Result<(), IndexerError> {
+        conn.execute(
+            &format!(
+                "INSERT INTO markets (token, {column}, updated_height)
+                 VALUES (?1, ?2, ?3)
+                 ON CONFLICT (token) DO UPDATE SET
+                    {column} = excluded.{column},
+                    updated_height = excluded.updated_height",
+                column = field.column()
+            ),
+            params![token, value.to_string(), height],
+        )?;
+        Ok(())
+    }
+
+    /**
+     * Events of an account in chain order, including liquidations it performed.
+     */
+    pub fn account_history(
+        conn: &Connection,
+        account: &str,
+        kind: Option<&str>,
+        limit: Option<u32>,
+    ) -> Result<Vec<HistoryEntry>, IndexerError> {
+        let mut statement = conn.prepare(
+            "SELECT height, tx_hash, event_index, timestamp, kind, attributes FROM events
+             WHERE (account = ?1 OR counterparty = ?1) AND (?2 IS NULL OR kind = ?2)
+             ORDER BY height, rowid
+             LIMIT ?3",
+        )?;
+        let rows =
+            statement.query_map(params![account, kind, limit.map_or(-1, i64::from)], |row| {
+                Ok((
+                    row.get::<_, u64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, u32>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, String>(5)?,
+                ))
+            })?;
+
+        let mut history = vec![];
+        for row in rows {
+            let (height, tx_hash, event_index, timestamp, kind, attributes) = row?;
+            history.push(HistoryEntry {
+                height,
+                tx_hash,
+                event_index,
+                timestamp,
+                kind,
+                attributes: serde_json::from_str(&attributes)?,
+            });
+        }
+        Ok(history)
+    }
+
+    pub fn fetch_account(
+        conn: &Connection,
+        address: &str,
+    ) -> Result<Option<AccountRecord>, IndexerError> {
+        Ok(conn
+            .query_row(
+                "SELECT address, first_height, last_height, event_count FROM accounts
+                 WHERE address = ?1",
+                params![address],
+                |row| {
+                    Ok(AccountRecord {
+                        address: row.get(0)?,
+                        first_height: row.get(1)?,
+                        last_height: row.get(2)?,
+                        event_count: row.get(3)?,
+                    })
+                },
+            )
+            .optional()?)
+    }
+
+    pub fn fetch_positions(
+        conn: &Connection,
+        account: &str,
+    ) -> Result<Vec<PositionRecord>, IndexerError> {
+        let mut statement = conn.prepare(
+            "SELECT account, token, collateral, borrowed, vtokens, updated_height FROM positions
+             WHERE account = ?1
+             ORDER BY token",
+        )?;
+        let rows = statement.query_map(params![account], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, u64>(5)?,
+            ))
+        })?;
+
+        let mut positions = vec![];
+        for row in rows {
+            let (account, token, collateral, borrowed, vtokens, updated_height) = row?;
+            positions.push(PositionRecord {
+                account,
+                token,
+                collateral: parse_amount(&collateral)?,
+                borrowed: parse_amount(&borrowed)?,
+                vtokens: parse_amount(&vtokens)?,
+                updated_height,
+            });
+        }
+        Ok(positions)
+    }
+
+    pub fn fetch_orders(
+        conn: &Connection,
+        account: &str,
+    ) -> Result<Vec<OrderRecord>, IndexerError> {
+        let mut statement = conn.prepare(
+            "SELECT order_id, account, token_in, amount_in, token_out, amount_out, fee, height,
+                    tx_hash, timestamp
+             FROM orders
+             WHERE account = ?1
+             ORDER BY height, rowid",
+        )?;
+        let rows = statement.query_map(params![account], |row| {
+            Ok((
+                (
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                ),
+                (
+                    row.get::<_, String>(5)?,
+                    row.get::<_, String>(6)?,
+                    row.get::<_, u64>(7)?,
+                    row.get::<_, String>(8)?,
+                    row.get::<_, Option<String>>(9)?,
+                ),
+            ))
+        })?;
+
+        let mut orders = vec![];
+        for row in rows {
+            let (
+                (order_id, account, token_in, amount_in, token_out),
+                (amount_out, fee, height, tx_hash, timestamp),
+            ) = row?;
+            orders.push(OrderRecord {
+                order_id,
+                account,
+                token_in,
+                amount_in: parse_amount(&amount_in)?,
+                token_out,
+                amount_out: parse_amount(&amount_out)?,
+                fee: parse_amount(&fee)?,
+                height,
+                tx_hash,
+                timestamp,
+            });
+        }
+        Ok(orders)
+    }
+
+    pub fn fetch_markets(conn: &Connection) -> Result<Vec<MarketRecord>, IndexerError> {
+        let mut statement = conn.prepare("SELECT token FROM markets ORDER BY token")?;
+        let tokens = statement
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+
+        let mut markets = vec![];
+        for token in tokens {
+            if let Some(market) = fetch_market(conn, &token)? {
+                markets.push(market);
+            }
+        }
+        Ok(markets)
+    }
+
+    pub fn fetch_market(
+        conn: &Connection,
+        token: &str,
+    ) -> Result<Option<MarketRecord>, IndexerError> {
+        let listing = conn
+            .query_row(
+                "SELECT symbol, status, updated_height FROM markets WHERE token = ?1",
+                params![token],
+                |row| {
+                    Ok((
+                        row.get::<_, Option<String>>(0)?,
+                        row.get::<_, Option<String>>(1)?,
+                        row.get::<_, u64>(2)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let (symbol, status, updated_height) = match listing {
+            Some(listing) => listing,
+            None => return Ok(None),
+        };
+
+        Ok(Some(MarketRecord {
+            token: token.to_string(),
+            symbol,
+            status,
+            total_collateral: market_value(conn, token, MarketField::TotalCollateral)?,
+            total_borrowed: market_value(conn, token, MarketField::TotalBorrowed)?,
+            total_vtokens: market_value(conn, token, MarketField::TotalVTokens)?,
+            deposited: market_value(conn, token, MarketField::Deposited)?,
+            withdrawn: market_value(conn, token, MarketField::Withdrawn)?,
+            borrowed: market_value(conn, token, MarketField::Borrowed)?,
+            repaid: market_value(conn, token, MarketField::Repaid)?,
+            interest_paid: market_value(conn, token, MarketField::InterestPaid)?,
+            borrow_fees: market_value(conn, token, MarketField::BorrowFees)?,
+            trading_fees: market_value(conn, token, MarketField::TradingFees)?,
+            liquidated: market_value(conn, token, MarketField::Liquidated)?,
+            seized: market_value(conn, token, MarketField::Seized)?,
+            revenue_collected: market_value(conn, token, MarketField::RevenueCollected)?,
+            updated_height,
+        }))
+    }
+}
@@ -0,0 +1,172 @@
+pub mod tx_parser {
+    use std::collections::BTreeMap;
+
+    use serde_json::Value;
+
+    use crate::error::IndexerError;
+
+    // The chain prefixes custom contract events with `wasm-`
+    pub const EVENT_PREFIX: &str = "wasm-leverage-";
+
+    /**
+     * A leverage event of a transaction, in the order the chain emitted it.
+     */
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct LeverageLog {
+        pub height: u64,
+        pub tx_hash: String,
+        pub timestamp: Option<String>,
+        // Position of the event within its transaction
+        pub event_index: u32,
+        pub contract: Option<String>,
+        pub kind: String,
+        pub attributes: BTreeMap<String, String>,
+    }
+
+    impl LeverageLog {
+        pub fn attr(&self, key: &str) -> Result<&str, IndexerError> {
+            match self.attributes.get(key) {
+                Some(value) => Ok(value),
+                None => Err(IndexerError::MissingAttribute {
+                    kind: self.kind.clone(),
+                    key: key.to_string(),
+                }),
+            }
+        }
+
+        pub fn amount(&self, key: &str) -> Result<u128, IndexerError> {
+            parse_amount(self.attr(key)?)
+        }
+    }
+
+    pub fn parse_amount(value: &str) -> Result<u128, IndexerError> {
+        match value.parse::<u128>() {
+            Ok(amount) => Ok(amount),
+            Err(_) => Err(IndexerError::InvalidAmount {
+                value: value.to_string(),
+            }),
+        }
+    }
+
+    /**
+     * Parses recorded transactions into their leverage events.
+     *
+     * Accepts the `TxResponse` JSON of a node, either as a single object, an
+     * array of them, or one object per line. Failed transactions are skipped.
+     */
+    pub fn parse_tx_logs(input: &str) -> Result<Vec<LeverageLog>, IndexerError> {
+        let trimmed = input.trim();
+        let txs: Vec<Value> = if trimmed.is_empty() {
+            vec![]
+        } else if trimmed.starts_with('[') {
+            serde_json::from_str(trimmed)?
+        } else {
+            match serde_json::from_str::<Value>(trimmed) {
+                Ok(tx) => vec![tx],
+                Err(_) => trimmed
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(serde_json::from_str)
+                    .collect::<Result<Vec<Value>, _>>()?,
+            }
+        };
+
+        let mut logs = vec![];
+        for tx in &txs {
+            logs.extend(leverage_logs(tx)?);
+        }
+        Ok(logs)
+    }
+
+    fn leverage_logs(tx: &Value) -> Result<Vec<LeverageLog>, IndexerError> {
+        // Some nodes wrap the response, e.g. `{"tx_response": {...}}`
+        let tx = tx.get("tx_response").unwrap_or(tx);
+
+        if tx.get("code").and_then(Value::as_u64).unwrap_or_default() != 0 {
+            return Ok(vec![]);
+        }
+
+        let height = match tx.get("height") {
+            Some(Value::String(height)) => height.parse::<u64>().ok(),
+            Some(Value::Number(height)) => height.as_u64(),
+            _ => None,
+        };
+        let height = match height {
+            Some(height) => height,
+            None => {
+                return Err(IndexerError::InvalidTxLog {
+                    reason: String::from("missing height"),
+                })
+            }
+        };
+        let tx_hash = match tx.get("txhash").and_then(Value::as_str) {
+            Some(tx_hash) => tx_hash.to_string(),
+            None => {
+                return Err(IndexerError::InvalidTxLog {
+                    reason: String::from("missing txhash"),
+                })
+            }
+        };
+        let timestamp = tx
+            .get("timestamp")
+            .and_then(Value::as_str)
+            .map(String::from);
+
+        // Older nodes report the events per message in `logs`, newer ones only in `events`
+        let log_events: Vec<&Value> = tx
+            .get("logs")
+            .and_then(Value::as_array)
+            .map(|logs| {
+                logs.iter()
+                    .filter_map(|log| log.get("events").and_then(Value::as_array))
+                    .flatten()
+                    .collect()
+            })
+            .unwrap_or_default();
+        let events = if log_events.is_empty() {
+            tx.get("events")
+                .and_then(Value::as_array)
+                .map(|events| events.iter().collect())
+                .unwrap_or_default()
+        } else {
+            log_events
+        };
+
+        let mut logs = vec![];
+        for (index, event) in events.into_iter().enumerate() {
+            let kind = match event
+                .get("type")
+                .and_then(Value::as_str)
+                .and_then(|ty| ty.strip_prefix(EVENT_PREFIX))
+            {
+                Some(kind) => kind.to_string(),
+                None => continue,
+            };
+
+            let mut attributes = BTreeMap::new();
+            for attribute in event
+                .get("attributes")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+            {
+                let key = attribute.get("key").and_then(Value::as_str);
+                let value = attribute.get("value").and_then(Value::as_str);
+                if let (Some(key), Some(value)) = (key, value) {
+                    attributes.insert(key.to_string(), value.to_string());
+                }
+            }
+
+            logs.push(LeverageLog {
+                height,
+                tx_hash: tx_hash.clone(),
+                timestamp: timestamp.clone(),
+                event_index: index as u32,
+                contract: attributes.remove("_contract_address"),
+                kind,
+                attributes,
+            });
+        }
+        Ok(logs)
+    }
+}